@@ -0,0 +1,39 @@
+"""Replays a few swaps against a pool snapshot using the Rust swap math.
+
+Build the extension first:
+
+    pip install maturin
+    maturin develop --release
+
+then run `python example.py ../swap-sdk/tests/fixtures/pool_legacy_numbers.json`.
+"""
+
+import sys
+
+import cetus_swap_sdk as sdk
+
+
+def main(path: str) -> None:
+    with open(path) as f:
+        pool = sdk.Pool.from_json(f.read())
+    print(pool)
+
+    quote = pool.quote_exact_amount_in(10_000, True, 1_700_000_000)
+    print("quote a2b 10000:", quote["amount_out"], "fee", quote["fee"])
+
+    for i, (amount, a2b) in enumerate([(10_000, True), (25_000, False), (5_000, True)]):
+        try:
+            result = pool.swap_exact_amount_in(amount, a2b, 1_700_000_000 + i * 30)
+        except sdk.DlmmError as e:
+            print(f"swap {i} failed: {type(e).__name__}: {e}")
+            continue
+        bins = [step["bin_id"] for step in result["steps"]]
+        print(f"swap {i}: in={result['amount_in']} out={result['amount_out']} bins={bins}")
+    print("active id:", pool.active_id)
+
+    price = sdk.get_price_from_id(pool.active_id, pool.bin_step)
+    print("active price (Q64.64):", price, "~", price / 2**64)
+
+
+if __name__ == "__main__":
+    main(sys.argv[1] if len(sys.argv) > 1 else "../swap-sdk/tests/fixtures/pool_legacy_numbers.json")
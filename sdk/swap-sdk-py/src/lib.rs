@@ -0,0 +1,308 @@
+//! Python bindings for `cetus-swap-sdk`, exposed as the `cetus_swap_sdk`
+//! extension module. Build with maturin, see `pyproject.toml`.
+//!
+//! Swap results are returned as dicts with the same keys as [`SwapResult`].
+//! Errors raise `cetus_swap_sdk.DlmmError`, or the subclass named after the
+//! [`DlmmError`](error::DlmmError) variant when one is available.
+
+use pyo3::{
+    create_exception,
+    exceptions::PyException,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+
+use cetus_swap_sdk::{
+    error,
+    math::price_math,
+    pool::{Pool, SwapResult},
+};
+
+create_exception!(cetus_swap_sdk, DlmmError, PyException);
+create_exception!(cetus_swap_sdk, InvalidStartBinIndex, DlmmError);
+create_exception!(cetus_swap_sdk, InvalidBinId, DlmmError);
+create_exception!(cetus_swap_sdk, InvalidInput, DlmmError);
+create_exception!(cetus_swap_sdk, MathOverflow, DlmmError);
+create_exception!(cetus_swap_sdk, ZeroLiquidity, DlmmError);
+create_exception!(cetus_swap_sdk, InvalidLayout, DlmmError);
+create_exception!(cetus_swap_sdk, SwapFailed, DlmmError);
+create_exception!(cetus_swap_sdk, InvalidConfig, DlmmError);
+create_exception!(cetus_swap_sdk, StaleState, DlmmError);
+create_exception!(cetus_swap_sdk, TimestampInPast, DlmmError);
+create_exception!(cetus_swap_sdk, InvalidFeeRate, DlmmError);
+create_exception!(cetus_swap_sdk, PriceOutOfRange, DlmmError);
+create_exception!(cetus_swap_sdk, StepFailed, DlmmError);
+create_exception!(cetus_swap_sdk, Aborted, DlmmError);
+create_exception!(cetus_swap_sdk, OnChainAbort, DlmmError);
+create_exception!(cetus_swap_sdk, BaselineMismatch, DlmmError);
+
+fn dlmm_err(err: &error::DlmmError) -> PyErr {
+    let msg = err.to_string();
+    match err {
+        error::DlmmError::InvalidStartBinIndex => InvalidStartBinIndex::new_err(msg),
+        error::DlmmError::InvalidBinId => InvalidBinId::new_err(msg),
+        error::DlmmError::InvalidInput => InvalidInput::new_err(msg),
+        error::DlmmError::MathOverflow => MathOverflow::new_err(msg),
+        error::DlmmError::ZeroLiquidity => ZeroLiquidity::new_err(msg),
+        error::DlmmError::InvalidLayout(_) => InvalidLayout::new_err(msg),
+        error::DlmmError::SwapFailed(_) => SwapFailed::new_err(msg),
+        error::DlmmError::InvalidConfig(_) => InvalidConfig::new_err(msg),
+        error::DlmmError::StaleState { .. } => StaleState::new_err(msg),
+        error::DlmmError::TimestampInPast { .. } => TimestampInPast::new_err(msg),
+        error::DlmmError::InvalidFeeRate { .. } => InvalidFeeRate::new_err(msg),
+        error::DlmmError::PriceOutOfRange { .. } => PriceOutOfRange::new_err(msg),
+        error::DlmmError::StepFailed { .. } => StepFailed::new_err(msg),
+        error::DlmmError::Aborted(_) => Aborted::new_err(msg),
+        error::DlmmError::OnChainAbort { .. } => OnChainAbort::new_err(msg),
+        error::DlmmError::BaselineMismatch { .. } => BaselineMismatch::new_err(msg),
+    }
+}
+
+fn anyhow_err(err: anyhow::Error) -> PyErr {
+    match err.downcast_ref::<error::DlmmError>() {
+        Some(err) => dlmm_err(err),
+        None => DlmmError::new_err(format!("{err:#}")),
+    }
+}
+
+fn swap_result_dict<'py>(py: Python<'py>, result: &SwapResult) -> PyResult<Bound<'py, PyDict>> {
+    let steps = PyList::empty(py);
+    for step in &result.steps {
+        let dict = PyDict::new(py);
+        dict.set_item("bin_id", step.bin_id)?;
+        dict.set_item("amount_in", step.amount_in)?;
+        dict.set_item("amount_out", step.amount_out)?;
+        dict.set_item("fee", step.fee)?;
+        dict.set_item("var_fee_rate", step.var_fee_rate)?;
+        dict.set_item("protocol_fee", step.protocol_fee)?;
+        dict.set_item("price", step.price)?;
+        dict.set_item("execution_price", step.execution_price)?;
+        dict.set_item("fee_a", step.fee_a)?;
+        dict.set_item("fee_b", step.fee_b)?;
+        dict.set_item("protocol_fee_a", step.protocol_fee_a)?;
+        dict.set_item("protocol_fee_b", step.protocol_fee_b)?;
+        steps.append(dict)?;
+    }
+    let dict = PyDict::new(py);
+    dict.set_item("amount_in", result.amount_in)?;
+    dict.set_item("amount_out", result.amount_out)?;
+    dict.set_item("fee", result.fee)?;
+    dict.set_item("ref_fee", result.ref_fee)?;
+    dict.set_item("protocol_fee", result.protocol_fee)?;
+    dict.set_item("steps", steps)?;
+    dict.set_item("is_exceed", result.is_exceed)?;
+    dict.set_item(
+        "end_volatility_accumulator",
+        result.end_volatility_accumulator,
+    )?;
+    dict.set_item("end_volatility_reference", result.end_volatility_reference)?;
+    dict.set_item("end_index_reference", result.end_index_reference)?;
+    dict.set_item("end_active_id", result.end_active_id)?;
+    dict.set_item(
+        "end_last_update_timestamp",
+        result.end_last_update_timestamp,
+    )?;
+    dict.set_item("amount_remaining", result.amount_remaining)?;
+    dict.set_item("fee_a", result.fee_a)?;
+    dict.set_item("fee_b", result.fee_b)?;
+    dict.set_item("protocol_fee_a", result.protocol_fee_a)?;
+    dict.set_item("protocol_fee_b", result.protocol_fee_b)?;
+    dict.set_item("max_rounding_shortfall", result.max_rounding_shortfall)?;
+    Ok(dict)
+}
+
+/// A pool snapshot. `swap_*` methods mutate it like the contract would;
+/// `quote_*` methods leave it untouched.
+#[pyclass(name = "Pool")]
+#[derive(Clone)]
+pub struct PyPool {
+    inner: Pool,
+}
+
+#[pymethods]
+impl PyPool {
+    /// Loads a pool from the SDK's JSON representation.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let inner: Pool = serde_json::from_str(json)
+            .map_err(|e| dlmm_err(&error::DlmmError::InvalidLayout(e.to_string())))?;
+        inner.validate().map_err(anyhow_err)?;
+        Ok(Self { inner })
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.inner).map_err(|e| DlmmError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn active_id(&self) -> i32 {
+        self.inner.active_id
+    }
+
+    #[getter]
+    fn base_fee_rate(&self) -> u64 {
+        self.inner.base_fee_rate
+    }
+
+    #[getter]
+    fn bin_step(&self) -> u16 {
+        self.inner.v_parameters.bin_step_config.bin_step
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.bins().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Pool(active_id={}, base_fee_rate={}, bins={})",
+            self.inner.active_id,
+            self.inner.base_fee_rate,
+            self.inner.bins().len()
+        )
+    }
+
+    fn swap_exact_amount_in<'py>(
+        &mut self,
+        py: Python<'py>,
+        amount_in: u64,
+        a2b: bool,
+        timestamp: u64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let result = self
+            .inner
+            .swap_exact_amount_in(amount_in, a2b, timestamp)
+            .map_err(anyhow_err)?;
+        swap_result_dict(py, &result)
+    }
+
+    fn swap_exact_amount_out<'py>(
+        &mut self,
+        py: Python<'py>,
+        amount_out: u64,
+        a2b: bool,
+        timestamp: u64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let result = self
+            .inner
+            .swap_exact_amount_out(amount_out, a2b, timestamp)
+            .map_err(anyhow_err)?;
+        swap_result_dict(py, &result)
+    }
+
+    fn quote_exact_amount_in<'py>(
+        &self,
+        py: Python<'py>,
+        amount_in: u64,
+        a2b: bool,
+        timestamp: u64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let result = self
+            .inner
+            .quote_exact_amount_in(amount_in, a2b, timestamp)
+            .map_err(anyhow_err)?;
+        swap_result_dict(py, &result)
+    }
+
+    fn quote_exact_amount_out<'py>(
+        &self,
+        py: Python<'py>,
+        amount_out: u64,
+        a2b: bool,
+        timestamp: u64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let result = self
+            .inner
+            .quote_exact_amount_out(amount_out, a2b, timestamp)
+            .map_err(anyhow_err)?;
+        swap_result_dict(py, &result)
+    }
+}
+
+/// Q64.64 price of `bin_id`.
+#[pyfunction]
+fn get_price_from_id(bin_id: i32, bin_step: u16) -> PyResult<u128> {
+    price_math::get_price_from_id(bin_id, bin_step).map_err(|e| dlmm_err(&e))
+}
+
+/// Largest bin id whose price does not exceed the Q64.64 `price`.
+#[pyfunction]
+fn get_id_from_price(price: u128, bin_step: u16) -> PyResult<i32> {
+    price_math::get_id_from_price(price, bin_step).map_err(|e| dlmm_err(&e))
+}
+
+#[pymodule]
+#[pyo3(name = "cetus_swap_sdk")]
+fn cetus_swap_sdk_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<PyPool>()?;
+    m.add_function(wrap_pyfunction!(get_price_from_id, m)?)?;
+    m.add_function(wrap_pyfunction!(get_id_from_price, m)?)?;
+    m.add("DlmmError", py.get_type::<DlmmError>())?;
+    m.add(
+        "InvalidStartBinIndex",
+        py.get_type::<InvalidStartBinIndex>(),
+    )?;
+    m.add("InvalidBinId", py.get_type::<InvalidBinId>())?;
+    m.add("InvalidInput", py.get_type::<InvalidInput>())?;
+    m.add("MathOverflow", py.get_type::<MathOverflow>())?;
+    m.add("ZeroLiquidity", py.get_type::<ZeroLiquidity>())?;
+    m.add("InvalidLayout", py.get_type::<InvalidLayout>())?;
+    m.add("SwapFailed", py.get_type::<SwapFailed>())?;
+    m.add("InvalidConfig", py.get_type::<InvalidConfig>())?;
+    m.add("StaleState", py.get_type::<StaleState>())?;
+    m.add("TimestampInPast", py.get_type::<TimestampInPast>())?;
+    m.add("InvalidFeeRate", py.get_type::<InvalidFeeRate>())?;
+    m.add("PriceOutOfRange", py.get_type::<PriceOutOfRange>())?;
+    m.add("StepFailed", py.get_type::<StepFailed>())?;
+    m.add("Aborted", py.get_type::<Aborted>())?;
+    m.add("OnChainAbort", py.get_type::<OnChainAbort>())?;
+    m.add("BaselineMismatch", py.get_type::<BaselineMismatch>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    const LEGACY_POOL: &str =
+        include_str!("../../swap-sdk/tests/fixtures/pool_legacy_numbers.json");
+
+    #[test]
+    fn module_exposes_pool_and_errors() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "cetus_swap_sdk").unwrap();
+            cetus_swap_sdk_py(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("sdk", module).unwrap();
+            globals.set_item("pool_json", LEGACY_POOL).unwrap();
+            let script = CString::new(
+                r#"
+pool = sdk.Pool.from_json(pool_json)
+before = pool.to_json()
+quote = pool.quote_exact_amount_in(1000, True, 10)
+assert pool.to_json() == before
+swap = pool.swap_exact_amount_in(1000, True, 10)
+assert quote == swap, (quote, swap)
+assert swap["amount_in"] == sum(step["amount_in"] for step in swap["steps"])
+assert sdk.get_id_from_price(sdk.get_price_from_id(-7, pool.bin_step), pool.bin_step) == -7
+try:
+    sdk.get_id_from_price(0, 25)
+    raise AssertionError("expected InvalidInput")
+except sdk.InvalidInput as e:
+    assert isinstance(e, sdk.DlmmError)
+try:
+    sdk.Pool.from_json("{}")
+    raise AssertionError("expected InvalidLayout")
+except sdk.InvalidLayout:
+    pass
+"#,
+            )
+            .unwrap();
+            py.run(&script, Some(&globals), None).unwrap();
+        });
+    }
+}
@@ -0,0 +1,59 @@
+//! Batch quoting throughput against the same requests quoted serially.
+//!
+//! Run with `cargo bench --features rayon`.
+
+use cetus_swap_sdk::{
+    batch::{quote_many, QuoteRequest, SwapMode},
+    math::price_math::get_price_from_id,
+    Bin, BinStepConfig, Pool, Timestamp, VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn pool(bin_step: u16, width: i32) -> Pool {
+    let config = BinStepConfig::new(bin_step, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    let bins = (-width..=width)
+        .map(|id| Bin {
+            id,
+            amount_a: if id >= 0 { 1_000_000 } else { 0 },
+            amount_b: if id <= 0 { 1_000_000 } else { 0 },
+            price: get_price_from_id(id, bin_step).unwrap().into(),
+            ..Default::default()
+        })
+        .collect();
+    Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+}
+
+fn batch(c: &mut Criterion) {
+    let pools: Vec<Pool> = [1, 10, 25, 100]
+        .iter()
+        .map(|step| pool(*step, 200))
+        .collect();
+    let mut group = c.benchmark_group("quote_many");
+    group.sample_size(20);
+    for count in [100usize, 2_000] {
+        let requests: Vec<QuoteRequest> = (0..count)
+            .map(|i| QuoteRequest {
+                pool: &pools[i % pools.len()],
+                amount: 10_000 + (i as u64 % 50) * 200_000,
+                a2b: i % 2 == 0,
+                mode: SwapMode::ExactIn,
+                timestamp: Timestamp::from_seconds(1_000),
+            })
+            .collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(
+            BenchmarkId::new("serial", count),
+            &requests,
+            |b, requests| b.iter(|| requests.iter().map(QuoteRequest::quote).collect::<Vec<_>>()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("rayon", count),
+            &requests,
+            |b, requests| b.iter(|| quote_many(requests)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, batch);
+criterion_main!(benches);
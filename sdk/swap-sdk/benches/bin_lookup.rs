@@ -0,0 +1,60 @@
+//! Lookup-heavy workload on a 20,000-bin pool: building a cloned map with
+//! `bins_map` versus borrowing lookups with `bins_by_id`.
+//!
+//! Run with `cargo bench --bench bin_lookup`.
+
+use cetus_swap_sdk::{Bin, BinStepConfig, Pool, PriceQ64, VariableParameters};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const BINS: i32 = 20_000;
+
+fn pool() -> Pool {
+    let config = BinStepConfig::new(1, 1, 60, 600, 9000, 0, 350_000, 30_000);
+    let bins = (0..BINS)
+        .map(|i| Bin {
+            id: i * 2 - BINS,
+            amount_a: 1_000,
+            amount_b: 1_000,
+            price: PriceQ64::ONE,
+            rewards_growth_global: vec![0; 3],
+            ..Default::default()
+        })
+        .collect();
+    Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+}
+
+/// Ids to look up; half of them fall between bins.
+fn queries() -> Vec<i32> {
+    (0..5_000).map(|i| (i * 7919) % (2 * BINS) - BINS).collect()
+}
+
+fn bin_lookup(c: &mut Criterion) {
+    let pool = pool();
+    let queries = queries();
+    let mut group = c.benchmark_group("lookup_5000_ids");
+    group.bench_function("bins_map", |b| {
+        b.iter(|| {
+            #[allow(deprecated)]
+            let map = pool.bins_map();
+            queries
+                .iter()
+                .filter_map(|id| map.get(id))
+                .map(|bin| bin.amount_a)
+                .sum::<u64>()
+        })
+    });
+    group.bench_function("bins_by_id", |b| {
+        b.iter(|| {
+            let lookup = pool.bins_by_id();
+            queries
+                .iter()
+                .filter_map(|id| lookup(*id))
+                .map(|bin| bin.amount_a)
+                .sum::<u64>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bin_lookup);
+criterion_main!(benches);
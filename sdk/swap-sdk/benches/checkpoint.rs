@@ -0,0 +1,67 @@
+//! Undoing a swap on a 10,000-bin pool: restoring a checkpoint versus
+//! swapping on a full clone.
+//!
+//! Run with `cargo bench --bench checkpoint`.
+
+use cetus_swap_sdk::{
+    math::price_math::get_price_from_id, Bin, BinStepConfig, Pool, VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const WIDTH: i32 = 5_000;
+const BIN_AMOUNT: u64 = 10_000;
+const TIMESTAMP: u64 = 1_000;
+
+fn pool() -> Pool {
+    let config = BinStepConfig::new(1, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    let bins = (-WIDTH..WIDTH)
+        .map(|id| Bin {
+            id,
+            amount_a: if id >= 0 { BIN_AMOUNT } else { 0 },
+            amount_b: if id <= 0 { BIN_AMOUNT } else { 0 },
+            price: get_price_from_id(id, 1).unwrap().into(),
+            liquidity_supply: 2 * BIN_AMOUNT as u128,
+            rewards_growth_global: vec![0; 3],
+            ..Default::default()
+        })
+        .collect();
+    Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+}
+
+fn checkpoint(c: &mut Criterion) {
+    let mut pool = pool();
+    let mut group = c.benchmark_group("swap_then_undo");
+    for crossed in [1u64, 10, 100] {
+        let amount_out = crossed * BIN_AMOUNT - BIN_AMOUNT / 2;
+        group.bench_with_input(
+            BenchmarkId::new("clone", crossed),
+            &amount_out,
+            |b, amount_out| {
+                b.iter(|| {
+                    let mut branch = pool.clone();
+                    branch
+                        .swap_exact_amount_out(*amount_out, true, TIMESTAMP)
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("checkpoint", crossed),
+            &amount_out,
+            |b, amount_out| {
+                b.iter(|| {
+                    let mut checkpoint = pool.checkpoint();
+                    let result = pool
+                        .swap_tracked(*amount_out, true, false, TIMESTAMP, &mut checkpoint)
+                        .unwrap();
+                    pool.restore(&checkpoint);
+                    result
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, checkpoint);
+criterion_main!(benches);
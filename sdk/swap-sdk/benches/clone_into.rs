@@ -0,0 +1,92 @@
+//! Copying a 5,000-bin pool with three rewarders into a scratch pool: a
+//! fresh clone each time versus [`Pool::clone_into`] reusing the scratch
+//! pool's allocations. The allocations per copy are counted and printed
+//! before the timings.
+//!
+//! Run with `cargo bench --bench clone_into`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use cetus_swap_sdk::{
+    math::price_math::get_price_from_id, Bin, BinStepConfig, Pool, VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const WIDTH: i32 = 2_500;
+const BIN_AMOUNT: u64 = 10_000;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn pool() -> Pool {
+    let config = BinStepConfig::new(1, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    let bins = (-WIDTH..WIDTH)
+        .map(|id| Bin {
+            id,
+            amount_a: if id >= 0 { BIN_AMOUNT } else { 0 },
+            amount_b: if id <= 0 { BIN_AMOUNT } else { 0 },
+            price: get_price_from_id(id, 1).unwrap().into(),
+            liquidity_supply: 2 * BIN_AMOUNT as u128,
+            rewards_growth_global: vec![0; 3],
+            ..Default::default()
+        })
+        .collect();
+    Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+}
+
+fn allocations(copy: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    copy();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn clone_into(c: &mut Criterion) {
+    let source = pool();
+    let mut scratch = source.clone();
+    println!(
+        "allocations per copy: clone {}, clone_into {}",
+        allocations(|| drop(source.clone())),
+        allocations(|| source.clone_into(&mut scratch)),
+    );
+
+    let mut group = c.benchmark_group("copy_pool");
+    group.bench_function("clone", |b| {
+        b.iter(|| {
+            scratch = source.clone();
+            scratch.active_id
+        })
+    });
+    group.bench_function("clone_into", |b| {
+        b.iter(|| {
+            source.clone_into(&mut scratch);
+            scratch.active_id
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, clone_into);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+//! Quotes on a dense 2,000-bin pool that find their first bin with a
+//! binary search versus a `DenseBinIndex`.
+//!
+//! Run with `cargo bench --bench dense_index`.
+
+use cetus_swap_sdk::{
+    math::price_math::get_price_from_id, pool::SwapOptions, Bin, BinStepConfig, DenseBinIndex,
+    Pool, VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const BIN_AMOUNT: u64 = 10_000;
+const TIMESTAMP: u64 = 1_000;
+
+fn pool() -> Pool {
+    let bins = (-1_000..1_000)
+        .map(|id| Bin {
+            id,
+            amount_a: if id >= 0 { BIN_AMOUNT } else { 0 },
+            amount_b: if id <= 0 { BIN_AMOUNT } else { 0 },
+            price: get_price_from_id(id, 1).unwrap().into(),
+            liquidity_supply: 2 * BIN_AMOUNT as u128,
+            ..Default::default()
+        })
+        .collect();
+    let config = BinStepConfig::new(1, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+}
+
+fn dense_index(c: &mut Criterion) {
+    let pool = pool();
+    let index = DenseBinIndex::new(&pool).unwrap();
+    let options = SwapOptions::default();
+    let mut group = c.benchmark_group("quote_2000_bins");
+    // Output within one bin and across ten bins.
+    for (crossed, amount_out) in [(1, BIN_AMOUNT / 4), (10, 10 * BIN_AMOUNT - BIN_AMOUNT / 2)] {
+        group.bench_with_input(
+            BenchmarkId::new("binary_search", crossed),
+            &amount_out,
+            |b, &amount_out| {
+                b.iter(|| {
+                    pool.quote_exact_amount_out_with_options(amount_out, true, TIMESTAMP, options)
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("dense_index", crossed),
+            &amount_out,
+            |b, &amount_out| {
+                b.iter(|| {
+                    pool.quote_with_index(&index, amount_out, true, false, TIMESTAMP, options)
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dense_index);
+criterion_main!(benches);
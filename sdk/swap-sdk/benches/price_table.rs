@@ -0,0 +1,29 @@
+//! Pricing the 10,000 bins around id 0 at bin step 25, one
+//! `get_price_from_id` call per bin versus one `PriceTable`.
+//!
+//! Run with `cargo bench --bench price_table`.
+
+use cetus_swap_sdk::{math::price_math::get_price_from_id, price::PriceTable};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const BIN_STEP: u16 = 25;
+const LO_ID: i32 = -5_000;
+const HI_ID: i32 = 4_999;
+
+fn price_table(c: &mut Criterion) {
+    let mut group = c.benchmark_group("price_10000_bins");
+    group.bench_function("get_price_from_id", |b| {
+        b.iter(|| {
+            (LO_ID..=HI_ID)
+                .map(|id| get_price_from_id(black_box(id), BIN_STEP).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("price_table", |b| {
+        b.iter(|| PriceTable::build(BIN_STEP, black_box(LO_ID), HI_ID).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, price_table);
+criterion_main!(benches);
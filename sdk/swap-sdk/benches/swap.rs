@@ -0,0 +1,108 @@
+//! Swap loop and pool construction benchmarks.
+//!
+//! Run with `cargo bench --bench swap`.
+
+use cetus_swap_sdk::{
+    math::price_math::get_price_from_id, Bin, BinStepConfig, Pool, VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+const BIN_AMOUNT: u64 = 10_000;
+const TIMESTAMP: u64 = 1_000;
+
+fn bins(width: i32) -> Vec<Bin> {
+    (-width..=width)
+        .map(|id| Bin {
+            id,
+            amount_a: if id >= 0 { BIN_AMOUNT } else { 0 },
+            amount_b: if id <= 0 { BIN_AMOUNT } else { 0 },
+            price: get_price_from_id(id, 1).unwrap().into(),
+            liquidity_supply: 2 * BIN_AMOUNT as u128,
+            rewards_growth_global: vec![0; 3],
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn params() -> VariableParameters {
+    let config = BinStepConfig::new(1, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    VariableParameters::new(config, 0, 0)
+}
+
+/// A swap of `B -> A` output worth `bins - 0.5` full bins crosses exactly
+/// `bins` bins.
+fn output_for(bins: u64) -> u64 {
+    bins * BIN_AMOUNT - BIN_AMOUNT / 2
+}
+
+fn swap(c: &mut Criterion) {
+    let pool = Pool::new(0, 2_500_000, params(), bins(1_100));
+
+    let mut group = c.benchmark_group("swap_exact_in");
+    for crossed in [1u64, 10, 100, 1_000] {
+        let amount_in = pool
+            .quote_exact_amount_out(output_for(crossed), true, TIMESTAMP)
+            .unwrap()
+            .amount_in;
+        group.bench_with_input(
+            BenchmarkId::from_parameter(crossed),
+            &amount_in,
+            |b, amount_in| {
+                b.iter_batched_ref(
+                    || pool.clone(),
+                    |pool| {
+                        pool.swap_exact_amount_in(*amount_in, true, TIMESTAMP)
+                            .unwrap()
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("swap_exact_out");
+    for crossed in [1u64, 100] {
+        let amount_out = output_for(crossed);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(crossed),
+            &amount_out,
+            |b, amount_out| {
+                b.iter_batched_ref(
+                    || pool.clone(),
+                    |pool| {
+                        pool.swap_exact_amount_out(*amount_out, true, TIMESTAMP)
+                            .unwrap()
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn construction(c: &mut Criterion) {
+    let bins = bins(5_000);
+    let json = serde_json::to_string(&Pool::new(0, 2_500_000, params(), bins.clone())).unwrap();
+
+    let mut group = c.benchmark_group("pool_10k_bins");
+    group.bench_function("new_and_validate", |b| {
+        b.iter_batched(
+            || bins.clone(),
+            |bins| {
+                let pool = Pool::new(0, 2_500_000, params(), bins);
+                pool.validate().unwrap();
+                pool
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("from_json", |b| {
+        b.iter(|| serde_json::from_str::<Pool>(&json).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, swap, construction);
+criterion_main!(benches);
@@ -0,0 +1,57 @@
+//! Encoding and decoding a `SwapResult` of 200 steps with
+//! `SwapResult::to_bytes` versus `serde_json`.
+//!
+//! Encoded sizes are printed before the timings. Run with
+//! `cargo bench --bench swap_result_bytes`.
+
+use cetus_swap_sdk::{
+    math::price_math::get_price_from_id, Bin, BinStepConfig, Pool, SwapResult, VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const BINS: i32 = 200;
+const BIN_AMOUNT: u64 = 1_000_000;
+
+fn result() -> SwapResult {
+    let config = BinStepConfig::new(10, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    let bins = (-BINS..=0)
+        .map(|id| Bin {
+            id,
+            amount_b: BIN_AMOUNT,
+            price: get_price_from_id(id, 10).unwrap().into(),
+            ..Default::default()
+        })
+        .collect();
+    let pool = Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins);
+    let amount_out = BINS as u64 * BIN_AMOUNT - BIN_AMOUNT / 2;
+    let result = pool.quote_exact_amount_out(amount_out, true, 0).unwrap();
+    assert_eq!(result.steps.len(), BINS as usize);
+    result
+}
+
+fn swap_result_bytes(c: &mut Criterion) {
+    let result = result();
+    let bytes = result.to_bytes();
+    let json = serde_json::to_vec(&result).unwrap();
+    println!(
+        "to_bytes: {} bytes, serde_json: {} bytes",
+        bytes.len(),
+        json.len()
+    );
+
+    let mut group = c.benchmark_group("swap_result_200_steps");
+    group.bench_function("to_bytes", |b| b.iter(|| result.to_bytes()));
+    group.bench_function("serde_json_encode", |b| {
+        b.iter(|| serde_json::to_vec(&result).unwrap())
+    });
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| SwapResult::from_bytes(&bytes).unwrap())
+    });
+    group.bench_function("serde_json_decode", |b| {
+        b.iter(|| serde_json::from_slice::<SwapResult>(&json).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, swap_result_bytes);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+//! A swap crossing 1,000 bins, with and without per-bin steps. The
+//! volatility accumulator is capped after 35 bins, so the remaining steps
+//! reuse the fee rates of the previous one.
+//!
+//! Allocated bytes per swap are printed before the timings. Run with
+//! `cargo bench --bench swap_steps`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use cetus_swap_sdk::{
+    math::price_math::get_price_from_id, pool::SwapOptions, Bin, BinStepConfig, Pool,
+    VariableParameters,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Counts bytes handed out by the system allocator.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const AMOUNT_IN: u64 = 1_000 * 10_000;
+
+fn pool() -> Pool {
+    let config = BinStepConfig::new(1, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+    let bins = (-1_100..=1_100)
+        .map(|id| Bin {
+            id,
+            amount_a: if id >= 0 { 10_000 } else { 0 },
+            amount_b: if id <= 0 { 10_000 } else { 0 },
+            price: get_price_from_id(id, 1).unwrap().into(),
+            ..Default::default()
+        })
+        .collect();
+    Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+}
+
+fn allocated_by(f: impl FnOnce()) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    f();
+    ALLOCATED.load(Ordering::Relaxed) - before
+}
+
+fn swap_steps(c: &mut Criterion) {
+    let pool = pool();
+    let no_steps = SwapOptions {
+        collect_steps: false,
+        ..Default::default()
+    };
+    let crossed = pool
+        .quote_exact_amount_in(AMOUNT_IN, true, 1_000)
+        .unwrap()
+        .steps
+        .len();
+
+    for (name, options) in [("steps", SwapOptions::default()), ("no_steps", no_steps)] {
+        let bytes = allocated_by(|| {
+            pool.quote_exact_amount_in_with_options(AMOUNT_IN, true, 1_000, options)
+                .unwrap();
+        });
+        println!("quote across {crossed} bins, {name}: {bytes} bytes allocated");
+    }
+
+    let mut group = c.benchmark_group("quote_1000_bins");
+    group.bench_function("steps", |b| {
+        b.iter(|| pool.quote_exact_amount_in(AMOUNT_IN, true, 1_000).unwrap())
+    });
+    group.bench_function("no_steps", |b| {
+        b.iter(|| {
+            pool.quote_exact_amount_in_with_options(AMOUNT_IN, true, 1_000, no_steps)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, swap_steps);
+criterion_main!(benches);
@@ -0,0 +1,232 @@
+//! The abort conditions of the DLMM contract's `pool`, `bin` and `config`
+//! modules, see [`AbortReason`].
+//!
+//! The contract declares its errors as Move 2024 clever errors
+//! (`#[error] const EName: vector<u8> = b"message"`). The u64 code of such
+//! an abort is not a fixed number per error: it packs the source line and
+//! the indices of the constant in the deployed module, see [`CleverAbort`].
+//! Errors are therefore identified by their constant name, which is what
+//! explorers and the Sui CLI print for a clever abort.
+
+use core::fmt;
+
+macro_rules! abort_reasons {
+    ($($module:literal { $($variant:ident = $message:literal,)* })*) => {
+        /// An abort condition of the DLMM contract. Each variant is the
+        /// `E`-prefixed error constant of its module without the prefix.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum AbortReason {
+            $($($variant,)*)*
+        }
+
+        impl AbortReason {
+            /// Every abort reason, grouped by module in declaration order.
+            pub const ALL: &'static [AbortReason] = &[$($(AbortReason::$variant,)*)*];
+
+            /// The module declaring the error constant, e.g. `"pool"`.
+            pub fn module(self) -> &'static str {
+                match self {
+                    $($(AbortReason::$variant => $module,)*)*
+                }
+            }
+
+            /// The name of the error constant, e.g. `"EPoolIsBlocked"`.
+            pub fn constant(self) -> &'static str {
+                match self {
+                    $($(AbortReason::$variant => concat!("E", stringify!($variant)),)*)*
+                }
+            }
+
+            /// The message the contract attaches to the error.
+            pub fn message(self) -> &'static str {
+                match self {
+                    $($(AbortReason::$variant => $message,)*)*
+                }
+            }
+        }
+    };
+}
+
+abort_reasons! {
+    "pool" {
+        PoolIsBlocked = "Pool is blocked",
+        PositionPoolNotMatch = "Position pool not match",
+        PositionNotMatch = "Position not match",
+        InvalidAmountsOrBinsLength = "Invalid amounts or bins length",
+        InvalidRepayAmount = "Invalid repay amount",
+        RewardTypeNotPermitted = "Reward type not permitted",
+        FlashSwapRepayNotMatch = "Flash swap repay not match",
+        FlashSwapRepayAmountNotCorrect = "Flash swap repay amount not correct",
+        NotEnoughLiquidity = "Not enough liquidity",
+        AmountInZero = "Amount in zero",
+        AmountOutIsZero = "Amount out is zero",
+        InvalidRefFeeRate = "Invalid ref fee rate",
+        InvalidBins = "Invalid bins",
+        BinNotExistsButLiquidityNotZero = "Bin not exists but liquidity not zero",
+        InvalidLiquiditySharesOrBinsLength = "Invalid liquidity shares or bins length",
+        RewardDurationLessThanMinRewardDuration = "Reward duration less than min reward duration",
+        RemainingRewardSlotsReservedForManager = "Remaining reward slots reserved for manager",
+        InvalidRewardStartTime = "Invalid reward start time",
+        InvalidRewardEndTime = "Invalid reward end time",
+        ClosePositionCertNotMatchWithPool = "Close position cert not match with pool",
+        BinIdOutOfPositionRange = "Bin id out of position range",
+        ActiveIdAlreadyFilled = "Active id already filled",
+        PositionLengthOverMax = "Position length over max",
+        OpenPositionCountNotEmpty = "Open position count not empty",
+        BinGroupRefNotMatch = "Bin group ref not match",
+        InvalidBinRange = "Invalid bin range",
+        InvalidPercent = "Invalid percent",
+        CannotOpenEmptyPosition = "Cannot open empty position",
+        InvalidBaseFeeRate = "Invalid base fee rate",
+        ActiveIdNotFilled = "Active id not filled",
+        ActiveIdNotIncluded = "Active id not included",
+        InvalidWidth = "Invalid width",
+    }
+    "bin" {
+        BinNotExists = "Bin not exists",
+        InvalidBinId = "Invalid bin id",
+        BinLiquidityUnderflow = "Bin liquidity underflow",
+        BinNotEmpty = "Bin not empty",
+        FeeAmountOverflow = "Fee amount overflow",
+        BinLiquiditySupplyZero = "Bin liquidity supply zero",
+        BinLiquidityShareOverflow = "Bin liquidity share overflow",
+        GroupNotExists = "Group not exists",
+        BinNotInGroup = "Bin not in group",
+        GroupNotEmpty = "Group not empty",
+        OffsetOverflow = "Offset overflow",
+    }
+    "config" {
+        InvalidRoleCode = "Invalid role code",
+        NoRestrictionManagerPermission = "No restriction manager permission",
+        UserIsBlocked = "User is blocked",
+        PositionIsBlocked = "Position is blocked",
+        BinConfigAlreadyExist = "Bin config already exist",
+        BinConfigNotExists = "Bin config not exists",
+        InvalidBinStep = "Invalid bin step",
+        CoinAlreadyExistsInList = "Coin already exists in list",
+        CoinNotExistsInList = "Coin not exists in list",
+        NoPoolManagerPermission = "No pool manager permission",
+        NoConfigManagerPermission = "No config manager permission",
+        NoProtocolFeeManagerPermission = "No protocol fee manager permission",
+        NoPartnerManagerPermission = "No partner manager permission",
+        NoRewardManagerPermission = "No reward manager permission",
+        InvalidProtocolFeeRate = "Invalid protocol fee rate",
+        InvalidBinCfg = "Invalid bin cfg",
+        RewardExistsInWhiteList = "Reward exists in white list",
+        RewardNotInWhiteList = "Reward not in white list",
+        NoEmergencyPauseManagerPermission = "No emergency pause manager permission",
+        ProtocolAlreadyEmergencyPause = "Protocol already emergency pause",
+        ProtocolNotEmergencyPause = "Protocol not emergency pause",
+        InvalidPackageVersion = "Invalid package version",
+    }
+}
+
+impl AbortReason {
+    /// Looks up an error constant by name, e.g. `"EPoolIsBlocked"`.
+    pub fn from_constant(constant: &str) -> Option<AbortReason> {
+        AbortReason::ALL
+            .iter()
+            .copied()
+            .find(|reason| reason.constant() == constant)
+    }
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}::{}: {}",
+            self.module(),
+            self.constant(),
+            self.message()
+        )
+    }
+}
+
+/// The fields packed into the u64 code of a clever error abort:
+///
+/// ```text
+/// | tag (1 bit) | reserved (15 bits) | line (16) | identifier index (16) | constant index (16) |
+/// ```
+///
+/// The indices point into the identifier and constant pools of the deployed
+/// module, so resolving them to an [`AbortReason`] needs its bytecode. They
+/// are `u16::MAX` for an abort without an error constant, such as a bare
+/// `assert!(cond)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CleverAbort {
+    pub line_number: u16,
+    pub identifier_index: u16,
+    pub constant_index: u16,
+}
+
+impl CleverAbort {
+    const TAG: u64 = 1 << 63;
+
+    /// Unpacks a clever error code, or `None` for a plain numeric code,
+    /// which has the tag bit clear.
+    pub fn from_code(code: u64) -> Option<CleverAbort> {
+        if code & Self::TAG == 0 {
+            return None;
+        }
+        Some(CleverAbort {
+            line_number: (code >> 32) as u16,
+            identifier_index: (code >> 16) as u16,
+            constant_index: code as u16,
+        })
+    }
+
+    /// The abort code, the inverse of [`CleverAbort::from_code`].
+    pub fn code(&self) -> u64 {
+        Self::TAG
+            | (self.line_number as u64) << 32
+            | (self.identifier_index as u64) << 16
+            | self.constant_index as u64
+    }
+
+    /// Whether the abort names an error constant.
+    pub fn has_constant(&self) -> bool {
+        self.identifier_index != u16::MAX && self.constant_index != u16::MAX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_round_trip_through_their_constant() {
+        assert_eq!(AbortReason::ALL.len(), 65);
+        for &reason in AbortReason::ALL {
+            assert_eq!(AbortReason::from_constant(reason.constant()), Some(reason));
+            assert!(["pool", "bin", "config"].contains(&reason.module()));
+        }
+        assert_eq!(AbortReason::PoolIsBlocked.constant(), "EPoolIsBlocked");
+        assert_eq!(AbortReason::BinNotExists.module(), "bin");
+        assert_eq!(
+            AbortReason::InvalidProtocolFeeRate.to_string(),
+            "config::EInvalidProtocolFeeRate: Invalid protocol fee rate"
+        );
+        assert_eq!(AbortReason::from_constant("PoolIsBlocked"), None);
+        // dlmm_math is not covered.
+        assert_eq!(AbortReason::from_constant("EInvalidFeeRate"), None);
+    }
+
+    #[test]
+    fn clever_codes_unpack() {
+        let abort = CleverAbort {
+            line_number: 512,
+            identifier_index: 7,
+            constant_index: 3,
+        };
+        assert_eq!(abort.code(), 0x8000_0200_0007_0003);
+        assert_eq!(CleverAbort::from_code(abort.code()), Some(abort));
+        assert!(abort.has_constant());
+
+        let bare = CleverAbort::from_code(0x8000_002a_ffff_ffff).unwrap();
+        assert_eq!(bare.line_number, 42);
+        assert!(!bare.has_constant());
+
+        assert_eq!(CleverAbort::from_code(5), None);
+    }
+}
@@ -0,0 +1,1001 @@
+//! LP analytics derived from pool snapshots.
+//!
+//! Fee growth is accumulated per unit of liquidity as a Q128 value (see
+//! [`calculate_growth_by_amount`](crate::math::dlmm_math::calculate_growth_by_amount)),
+//! so the growth delta between two snapshots of a bin, divided by `2^128`,
+//! is the fee each unit of its liquidity earned in between. The growth
+//! counters wrap on overflow, so deltas are taken modulo `2^128`.
+//!
+//! The APR results are `f64`s meant for dashboards, not for accounting.
+//! Token amounts are exact integers, rounded down.
+//!
+//! [`sandwich_impact`] simulates a frontrun and backrun around a trade, to
+//! measure what the trade can lose to one.
+
+use alloc::{format, vec::Vec};
+use core::ops::RangeInclusive;
+
+use anyhow::{Context, Error};
+use ruint::aliases::{U256, U512};
+
+use crate::{
+    bin::Bin,
+    error::DlmmError,
+    math::{
+        full_math::mul_div, price_math::get_price_from_id, q64x64_math::ONE, Rounding,
+        BASIS_POINT_MAX,
+    },
+    pool::{Pool, SwapResult},
+    scenario::SwapInstruction,
+    timestamp::Timestamp,
+};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 86_400.0;
+/// `2^128`, the scale of fee growth.
+const GROWTH_ONE: f64 = ONE as f64 * ONE as f64;
+
+/// Fees earned by one unit of liquidity over a period, see [`bin_fee_apr`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AprEstimate {
+    /// Token A base units earned per unit of liquidity.
+    pub fee_a_per_liquidity: f64,
+    /// Token B base units earned per unit of liquidity.
+    pub fee_b_per_liquidity: f64,
+    /// Both fees valued in token B base units at the bin price.
+    pub fee_value_in_b_per_liquidity: f64,
+    /// The fee value relative to the value of the liquidity, annualized
+    /// without compounding, in basis points.
+    pub apr_bps: f64,
+}
+
+/// Estimates the fee APR of a bin from two snapshots of it taken
+/// `elapsed_seconds` apart.
+///
+/// The liquidity is valued from `after`'s reserves at its price. Fails with
+/// [`DlmmError::InvalidInput`] if the snapshots are of different bins or
+/// `elapsed_seconds` is zero, and with [`DlmmError::ZeroLiquidity`] if
+/// `after` holds no liquidity to earn fees on.
+pub fn bin_fee_apr(before: &Bin, after: &Bin, elapsed_seconds: u64) -> Result<AprEstimate, Error> {
+    if before.id != after.id {
+        return Err(DlmmError::InvalidInput)
+            .with_context(|| format!("snapshots of bins {} and {}", before.id, after.id));
+    }
+    if elapsed_seconds == 0 {
+        return Err(DlmmError::InvalidInput).context("elapsed_seconds is zero");
+    }
+    // Value of one unit of liquidity in token B.
+    let price = after.price.as_raw() as f64 / ONE as f64;
+    let reserves_value = after.amount_a as f64 * price + after.amount_b as f64;
+    if after.liquidity_supply == 0 || reserves_value == 0.0 {
+        return Err(DlmmError::ZeroLiquidity).with_context(|| format!("bin {}", after.id));
+    }
+    let liquidity_value = reserves_value / after.liquidity_supply as f64;
+
+    let fee_a = growth_delta(
+        before.fee_amount_a_growth_global,
+        after.fee_amount_a_growth_global,
+    );
+    let fee_b = growth_delta(
+        before.fee_amount_b_growth_global,
+        after.fee_amount_b_growth_global,
+    );
+    let fee_value = fee_a * price + fee_b;
+    Ok(AprEstimate {
+        fee_a_per_liquidity: fee_a,
+        fee_b_per_liquidity: fee_b,
+        fee_value_in_b_per_liquidity: fee_value,
+        apr_bps: fee_value / liquidity_value * SECONDS_PER_YEAR / elapsed_seconds as f64 * 10_000.0,
+    })
+}
+
+/// [`bin_fee_apr`] over the bins with ids in `range`, averaged with weights
+/// of each bin's `liquidity_supply` in `after`.
+///
+/// Liquidity is denominated in token B at the bin price, so the weights are
+/// the bins' values. Bins missing from either snapshot or without liquidity
+/// are left out; fails with [`DlmmError::ZeroLiquidity`] if that leaves
+/// none.
+pub fn pool_fee_apr(
+    before: &Pool,
+    after: &Pool,
+    range: RangeInclusive<i32>,
+    elapsed_seconds: u64,
+) -> Result<AprEstimate, Error> {
+    let mut total = AprEstimate::default();
+    let mut total_liquidity = 0.0;
+    for bin in after.bins.iter().filter(|bin| range.contains(&bin.id)) {
+        let Some(previous) = before.get_bin(bin.id) else {
+            continue;
+        };
+        if bin.liquidity_supply == 0 {
+            continue;
+        }
+        let apr = match bin_fee_apr(previous, bin, elapsed_seconds) {
+            Ok(apr) => apr,
+            Err(err) if matches!(err.downcast_ref(), Some(DlmmError::ZeroLiquidity)) => continue,
+            Err(err) => return Err(err),
+        };
+        let weight = bin.liquidity_supply as f64;
+        total.fee_a_per_liquidity += apr.fee_a_per_liquidity * weight;
+        total.fee_b_per_liquidity += apr.fee_b_per_liquidity * weight;
+        total.fee_value_in_b_per_liquidity += apr.fee_value_in_b_per_liquidity * weight;
+        total.apr_bps += apr.apr_bps * weight;
+        total_liquidity += weight;
+    }
+    if total_liquidity == 0.0 {
+        return Err(DlmmError::ZeroLiquidity)
+            .with_context(|| format!("no liquidity in bins {range:?}"));
+    }
+    Ok(AprEstimate {
+        fee_a_per_liquidity: total.fee_a_per_liquidity / total_liquidity,
+        fee_b_per_liquidity: total.fee_b_per_liquidity / total_liquidity,
+        fee_value_in_b_per_liquidity: total.fee_value_in_b_per_liquidity / total_liquidity,
+        apr_bps: total.apr_bps / total_liquidity,
+    })
+}
+
+/// How a position spread over several bins fares against holding its
+/// tokens, see [`divergence_loss`]. Amounts are in base units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DivergenceReport {
+    pub start_amount_a: u128,
+    pub start_amount_b: u128,
+    pub end_amount_a: u128,
+    pub end_amount_b: u128,
+    /// The start amounts valued at the end price, in token B.
+    pub hodl_value_in_b: u128,
+    /// The end amounts valued at the end price, in token B.
+    pub position_value_in_b: u128,
+    /// `position_value_in_b - hodl_value_in_b`; negative for a loss.
+    pub divergence_in_b: i128,
+    /// `divergence_in_b` relative to `hodl_value_in_b`, in basis points;
+    /// zero if the position is worthless.
+    pub divergence_bps: i64,
+}
+
+/// Compares a position holding `share` of the `liquidity_supply` of each
+/// `(bin_id, share)` bin, when the active id moves from `start_active_id` to
+/// `end_active_id`, with holding the tokens it started with.
+///
+/// Crossing a bin converts it at its own price: at each active id, bins
+/// below it hold only token B and bins above only token A, worth what the
+/// bin holds now valued at [`get_price_from_id`]. The active bin keeps the
+/// mix of its reserves in `bins`, as the swap that moved the active id may
+/// have stopped anywhere inside it. Both values use the price of
+/// `end_active_id`, so the report only depends on the two ids, and moving
+/// back to the start id gives no divergence.
+///
+/// `bins` must be sorted by id, as [`Pool::bins`] is. Fails with
+/// [`DlmmError::InvalidBinId`] if a bin is missing or an id is out of range,
+/// with [`DlmmError::InvalidInput`] if a share exceeds the bin's
+/// `liquidity_supply` and with [`DlmmError::MathOverflow`] if a total does
+/// not fit.
+pub fn divergence_loss(
+    bins: &[Bin],
+    share_per_bin: &[(i32, u128)],
+    start_active_id: i32,
+    end_active_id: i32,
+    bin_step: u16,
+) -> Result<DivergenceReport, Error> {
+    let mut start = (0u128, 0u128);
+    let mut end = (0u128, 0u128);
+    for &(bin_id, share) in share_per_bin {
+        let bin = bins
+            .binary_search_by_key(&bin_id, |bin| bin.id)
+            .map(|index| &bins[index])
+            .map_err(|_| DlmmError::InvalidBinId)
+            .with_context(|| format!("no bin {bin_id}"))?;
+        if share > bin.liquidity_supply {
+            return Err(DlmmError::InvalidInput).with_context(|| {
+                format!(
+                    "share {share} of bin {bin_id} exceeds its liquidity {}",
+                    bin.liquidity_supply
+                )
+            });
+        }
+        if share == 0 {
+            continue;
+        }
+        let amount_a = mul_div(
+            bin.amount_a as u128,
+            share,
+            bin.liquidity_supply,
+            Rounding::Down,
+        )
+        .ok_or(DlmmError::MathOverflow)?;
+        let amount_b = mul_div(
+            bin.amount_b as u128,
+            share,
+            bin.liquidity_supply,
+            Rounding::Down,
+        )
+        .ok_or(DlmmError::MathOverflow)?;
+        let price = get_price_from_id(bin_id, bin_step).with_context(|| format!("bin {bin_id}"))?;
+        for (total, active_id) in [(&mut start, start_active_id), (&mut end, end_active_id)] {
+            let (a, b) = composition_at(bin_id, amount_a, amount_b, price, active_id)?;
+            total.0 = total.0.checked_add(a).ok_or(DlmmError::MathOverflow)?;
+            total.1 = total.1.checked_add(b).ok_or(DlmmError::MathOverflow)?;
+        }
+    }
+
+    let end_price = get_price_from_id(end_active_id, bin_step).context("end_active_id")?;
+    let hodl_value_in_b = value_in_b(start.0, start.1, end_price)?;
+    let position_value_in_b = value_in_b(end.0, end.1, end_price)?;
+    let divergence_in_b = i128::try_from(position_value_in_b)
+        .ok()
+        .zip(i128::try_from(hodl_value_in_b).ok())
+        .map(|(position, hodl)| position - hodl)
+        .ok_or(DlmmError::MathOverflow)?;
+    let divergence_bps = if hodl_value_in_b == 0 {
+        0
+    } else {
+        let bps = mul_div(
+            divergence_in_b.unsigned_abs(),
+            10_000,
+            hodl_value_in_b,
+            Rounding::Down,
+        )
+        .and_then(|bps| i64::try_from(bps).ok())
+        .ok_or(DlmmError::MathOverflow)?;
+        if divergence_in_b < 0 {
+            -bps
+        } else {
+            bps
+        }
+    };
+    Ok(DivergenceReport {
+        start_amount_a: start.0,
+        start_amount_b: start.1,
+        end_amount_a: end.0,
+        end_amount_b: end.1,
+        hodl_value_in_b,
+        position_value_in_b,
+        divergence_in_b,
+        divergence_bps,
+    })
+}
+
+/// The amounts a bin's `(amount_a, amount_b)` turn into once the active id
+/// is `active_id`.
+fn composition_at(
+    bin_id: i32,
+    amount_a: u128,
+    amount_b: u128,
+    price: u128,
+    active_id: i32,
+) -> Result<(u128, u128), Error> {
+    Ok(if bin_id < active_id {
+        (0, value_in_b(amount_a, amount_b, price)?)
+    } else if bin_id > active_id {
+        let value_in_a =
+            mul_div(amount_b, ONE, price, Rounding::Down).ok_or(DlmmError::MathOverflow)?;
+        (
+            amount_a
+                .checked_add(value_in_a)
+                .ok_or(DlmmError::MathOverflow)?,
+            0,
+        )
+    } else {
+        (amount_a, amount_b)
+    })
+}
+
+/// `amount_a * price + amount_b`, rounded down.
+fn value_in_b(amount_a: u128, amount_b: u128, price: u128) -> Result<u128, Error> {
+    mul_div(amount_a, price, ONE, Rounding::Down)
+        .and_then(|value| value.checked_add(amount_b))
+        .ok_or(DlmmError::MathOverflow)
+        .context("value in token B")
+}
+
+impl Pool {
+    /// Every bin's reserves valued in token B at the bin's price, i.e. the
+    /// sum of `amount_b + amount_a * price`, rounded down per bin.
+    ///
+    /// Fails with [`DlmmError::InvalidInput`] if a bin has a zero price and
+    /// with [`DlmmError::MathOverflow`] if the total does not fit in a
+    /// `u128`.
+    pub fn value_in_b(&self) -> Result<u128, Error> {
+        total_value(self.bins.iter().map(|bin| bin_value(bin, true)))
+    }
+
+    /// Like [`value_in_b`](Self::value_in_b), valued in token A as
+    /// `amount_a + amount_b / price`.
+    pub fn value_in_a(&self) -> Result<u128, Error> {
+        total_value(self.bins.iter().map(|bin| bin_value(bin, false)))
+    }
+}
+
+/// The value of a position holding `share` of the `liquidity_supply` of
+/// each bin, in token B if `in_token_b` and in token A otherwise.
+///
+/// Each bin's value is computed as in [`Pool::value_in_b`] and the share
+/// of it rounded down. Fails like [`Pool::value_in_b`], with
+/// [`DlmmError::ZeroLiquidity`] for a share of a bin without liquidity and
+/// with [`DlmmError::InvalidInput`] for a share exceeding the bin's
+/// `liquidity_supply`.
+pub fn position_value(bins: &[(&Bin, u128)], in_token_b: bool) -> Result<u128, Error> {
+    total_value(bins.iter().map(|&(bin, share)| {
+        if share == 0 {
+            return Ok(U256::ZERO);
+        }
+        if bin.liquidity_supply == 0 {
+            return Err(DlmmError::ZeroLiquidity).with_context(|| format!("bin {}", bin.id));
+        }
+        if share > bin.liquidity_supply {
+            return Err(DlmmError::InvalidInput).with_context(|| {
+                format!(
+                    "share {share} of bin {} exceeds its liquidity {}",
+                    bin.id, bin.liquidity_supply
+                )
+            });
+        }
+        let value = bin_value(bin, in_token_b)?
+            .checked_mul(U256::from(share))
+            .ok_or(DlmmError::MathOverflow)?;
+        Ok(value / U256::from(bin.liquidity_supply))
+    }))
+}
+
+/// A bin's reserves valued in one token at its price, rounded down. At most
+/// `2^64 + 2^128`, so it cannot overflow a `U256`.
+fn bin_value(bin: &Bin, in_token_b: bool) -> Result<U256, Error> {
+    if bin.price.as_raw() == 0 {
+        return Err(DlmmError::InvalidInput)
+            .with_context(|| format!("bin {} has zero price", bin.id));
+    }
+    let amount_a = U256::from(bin.amount_a);
+    let amount_b = U256::from(bin.amount_b);
+    let price = U256::from(bin.price.as_raw());
+    Ok(if in_token_b {
+        amount_b + ((amount_a * price) >> 64usize)
+    } else {
+        amount_a + (amount_b << 64usize) / price
+    })
+}
+
+fn total_value(values: impl Iterator<Item = Result<U256, Error>>) -> Result<u128, Error> {
+    let mut total = U256::ZERO;
+    for value in values {
+        total = total.checked_add(value?).ok_or(DlmmError::MathOverflow)?;
+    }
+    total
+        .try_into()
+        .map_err(|_| DlmmError::MathOverflow)
+        .context("total value")
+}
+
+/// How much of a pool's value sits near the active bin, see
+/// [`concentration`]. Shares are in basis points of the pool's value in
+/// token B, rounded down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConcentrationReport {
+    /// The share of bins within 1, 5 and 10 ids of the active id.
+    pub within_1_bps: u32,
+    pub within_5_bps: u32,
+    pub within_10_bps: u32,
+    /// The caller's window and the share within it.
+    pub window: u32,
+    pub within_window_bps: u32,
+    /// The Herfindahl-Hirschman index of the bins' shares, i.e. the sum of
+    /// their squares: 10_000 if one bin holds everything, `10_000 / n` if
+    /// `n` bins hold the same value.
+    pub hhi_bps: u32,
+    /// The bin holding the most value, the lowest id on a tie; `None` if
+    /// the pool is worthless.
+    pub largest_bin_id: Option<i32>,
+}
+
+/// Measures how concentrated `pool`'s liquidity is around its active id,
+/// with each bin valued in token B as in [`Pool::value_in_b`].
+///
+/// A pool without value reports zero shares. Fails with
+/// [`DlmmError::InvalidInput`] if a bin has a zero price.
+pub fn concentration(pool: &Pool, window: u32) -> Result<ConcentrationReport, Error> {
+    let mut values = Vec::with_capacity(pool.bins.len());
+    let mut total = U256::ZERO;
+    let mut largest: Option<(i32, U256)> = None;
+    for bin in &pool.bins {
+        let value = bin_value(bin, true)?;
+        // At most `2^129` per bin, so the sum cannot overflow.
+        total += value;
+        if value > U256::ZERO && largest.is_none_or(|(_, max)| value > max) {
+            largest = Some((bin.id, value));
+        }
+        values.push((bin.id, value));
+    }
+    if total == U256::ZERO {
+        return Ok(ConcentrationReport {
+            window,
+            ..Default::default()
+        });
+    }
+
+    let bps = U256::from(BASIS_POINT_MAX);
+    let share_within = |window: u32| {
+        let value: U256 = values
+            .iter()
+            .filter(|(id, _)| (*id as i64 - pool.active_id as i64).unsigned_abs() <= window as u64)
+            .map(|&(_, value)| value)
+            .sum();
+        (value * bps / total).to::<u32>()
+    };
+    // Squares of values up to `2^129` need more than 256 bits.
+    let squares: U512 = values
+        .iter()
+        .map(|&(_, value)| U512::from(value) * U512::from(value))
+        .sum();
+    let total = U512::from(total);
+    let hhi = squares * U512::from(BASIS_POINT_MAX) / (total * total);
+
+    Ok(ConcentrationReport {
+        within_1_bps: share_within(1),
+        within_5_bps: share_within(5),
+        within_10_bps: share_within(10),
+        window,
+        within_window_bps: share_within(window),
+        hhi_bps: hhi.to::<u32>(),
+        largest_bin_id: largest.map(|(id, _)| id),
+    })
+}
+
+/// A victim trade sandwiched by an attacker of one size, see
+/// [`sandwich_impact`]. Amounts are in base units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SandwichOutcome {
+    /// The input of the attacker's frontrun, in the victim's input token.
+    pub attacker_size: u64,
+    /// What the victim pays and receives after the frontrun.
+    pub victim_amount_in: u64,
+    pub victim_amount_out: u64,
+    /// What the frontrun costs the victim against trading on the
+    /// undisturbed pool: output lost by an exact-input trade, in the output
+    /// token, or input added to an exact-output one, in the input token.
+    pub victim_loss: i128,
+    /// Whether the victim's trade ran out of liquidity after the frontrun.
+    pub victim_is_exceed: bool,
+    /// What the backrun returns minus what the frontrun paid, both fees
+    /// included, in the victim's input token; negative for a loss.
+    pub attacker_profit: i128,
+    /// The smallest of the simulated sizes from which on every larger size
+    /// loses the attacker money too, the same in every outcome of a call;
+    /// `None` if the largest size still breaks even or better.
+    pub unprofitable_from: Option<u64>,
+}
+
+/// Simulates `victim` sandwiched by an attacker of each of
+/// `attacker_sizes`, returning one outcome per size in the same order.
+///
+/// The attacker swaps its size in the victim's direction, the victim
+/// trades, and the attacker swaps everything the frontrun bought back. All
+/// three legs run at `timestamp`, in place of the victim's own, as they
+/// would within one block, so the volatility accumulator carries over from
+/// one leg to the next and each pays the escalated fee the previous one
+/// left. Every simulation runs on a clone; `pool` is never modified.
+///
+/// Fails if the victim's trade fails on the undisturbed pool or any leg
+/// fails, naming the attacker size.
+pub fn sandwich_impact(
+    pool: &Pool,
+    victim: &SwapInstruction,
+    attacker_sizes: &[u64],
+    timestamp: impl Into<Timestamp>,
+) -> Result<Vec<SandwichOutcome>, Error> {
+    let timestamp = timestamp.into();
+    let a2b = victim.a2b;
+    let victim_swap = |pool: &mut Pool| {
+        if victim.by_amount_in {
+            pool.swap_exact_amount_in(victim.amount, a2b, timestamp)
+        } else {
+            pool.swap_exact_amount_out(victim.amount, a2b, timestamp)
+        }
+    };
+    let baseline = victim_swap(&mut pool.clone()).context("victim trade")?;
+
+    let mut outcomes = Vec::with_capacity(attacker_sizes.len());
+    for &attacker_size in attacker_sizes {
+        let sandwich = |pool: &mut Pool| -> Result<_, Error> {
+            let frontrun = pool.swap_exact_amount_in(attacker_size, a2b, timestamp)?;
+            let victim = victim_swap(pool)?;
+            let backrun = pool.swap_exact_amount_in(frontrun.amount_out, !a2b, timestamp)?;
+            Ok((frontrun, victim, backrun))
+        };
+        let (frontrun, victim_result, backrun): (SwapResult, SwapResult, SwapResult) =
+            sandwich(&mut pool.clone())
+                .with_context(|| format!("attacker size {attacker_size}"))?;
+        let victim_loss = if victim.by_amount_in {
+            baseline.amount_out as i128 - victim_result.amount_out as i128
+        } else {
+            victim_result.amount_in as i128 - baseline.amount_in as i128
+        };
+        outcomes.push(SandwichOutcome {
+            attacker_size,
+            victim_amount_in: victim_result.amount_in,
+            victim_amount_out: victim_result.amount_out,
+            victim_loss,
+            victim_is_exceed: victim_result.is_exceed,
+            attacker_profit: backrun.amount_out as i128 - frontrun.amount_in as i128,
+            unprofitable_from: None,
+        });
+    }
+
+    let mut by_size: Vec<_> = outcomes
+        .iter()
+        .map(|outcome| (outcome.attacker_size, outcome.attacker_profit))
+        .collect();
+    by_size.sort_unstable();
+    let unprofitable_from = by_size
+        .iter()
+        .rposition(|&(_, profit)| profit >= 0)
+        .map_or(by_size.first(), |last_profitable| {
+            by_size.get(last_profitable + 1)
+        })
+        .map(|&(size, _)| size);
+    for outcome in &mut outcomes {
+        outcome.unprofitable_from = unprofitable_from;
+    }
+    Ok(outcomes)
+}
+
+/// Fee per unit of liquidity between two growth readings, allowing for the
+/// counter wrapping.
+fn growth_delta(before: u128, after: u128) -> f64 {
+    after.wrapping_sub(before) as f64 / GROWTH_ONE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BinStepConfig, VariableParameters},
+        math::dlmm_math::calculate_growth_by_amount,
+        price::PriceQ64,
+    };
+
+    const DAY: u64 = 86_400;
+
+    /// A bin at price 2 worth 4_000_000 B, with liquidity in the on-chain
+    /// unit of `value << 64`.
+    fn bin(id: i32) -> Bin {
+        Bin {
+            id,
+            amount_a: 1_000_000,
+            amount_b: 2_000_000,
+            price: (2 * ONE).into(),
+            liquidity_supply: 4_000_000 * ONE,
+            ..Default::default()
+        }
+    }
+
+    fn earned(mut bin: Bin, fee_a: u64, fee_b: u64) -> Bin {
+        bin.fee_amount_a_growth_global = bin
+            .fee_amount_a_growth_global
+            .wrapping_add(calculate_growth_by_amount(fee_a, bin.liquidity_supply).unwrap());
+        bin.fee_amount_b_growth_global = bin
+            .fee_amount_b_growth_global
+            .wrapping_add(calculate_growth_by_amount(fee_b, bin.liquidity_supply).unwrap());
+        bin
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-9,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn bin_apr_values_both_fees_at_the_bin_price() {
+        let before = bin(3);
+        // 100 A (worth 200 B) and 200 B a day on 4_000_000 B: 0.01% a day.
+        let after = earned(before.clone(), 100, 200);
+        let apr = bin_fee_apr(&before, &after, DAY).unwrap();
+        let liquidity = before.liquidity_supply as f64;
+        assert_close(apr.fee_a_per_liquidity * liquidity, 100.0);
+        assert_close(apr.fee_b_per_liquidity * liquidity, 200.0);
+        assert_close(apr.fee_value_in_b_per_liquidity * liquidity, 400.0);
+        assert_close(apr.apr_bps, 365.0);
+
+        // The same fees over two days are half the rate.
+        assert_close(
+            bin_fee_apr(&before, &after, 2 * DAY).unwrap().apr_bps,
+            182.5,
+        );
+        assert_eq!(bin_fee_apr(&before, &before, DAY).unwrap().apr_bps, 0.0);
+    }
+
+    #[test]
+    fn growth_wrapping_around_is_a_small_delta() {
+        let mut before = bin(0);
+        before.fee_amount_a_growth_global = u128::MAX - 5;
+        before.fee_amount_b_growth_global = u128::MAX;
+        let after = earned(before.clone(), 100, 200);
+        assert!(after.fee_amount_b_growth_global < before.fee_amount_b_growth_global);
+        assert_close(bin_fee_apr(&before, &after, DAY).unwrap().apr_bps, 365.0);
+    }
+
+    #[test]
+    fn degenerate_inputs_fail() {
+        let before = bin(0);
+        let after = earned(before.clone(), 100, 200);
+        let kind = |result: Result<AprEstimate, Error>| {
+            let err = result.unwrap_err();
+            match err.downcast_ref::<DlmmError>() {
+                Some(DlmmError::InvalidInput) => "InvalidInput",
+                Some(DlmmError::ZeroLiquidity) => "ZeroLiquidity",
+                _ => panic!("{err:#}"),
+            }
+        };
+        assert_eq!(kind(bin_fee_apr(&before, &after, 0)), "InvalidInput");
+        assert_eq!(kind(bin_fee_apr(&bin(1), &after, DAY)), "InvalidInput");
+
+        let mut withdrawn = after.clone();
+        withdrawn.liquidity_supply = 0;
+        assert_eq!(kind(bin_fee_apr(&before, &withdrawn, DAY)), "ZeroLiquidity");
+        let mut drained = after;
+        drained.amount_a = 0;
+        drained.amount_b = 0;
+        assert_eq!(kind(bin_fee_apr(&before, &drained, DAY)), "ZeroLiquidity");
+    }
+
+    /// A position over bins -3..=3 around active bin 0, holding half of
+    /// each bin.
+    fn position() -> (Vec<Bin>, Vec<(i32, u128)>) {
+        let bins: Vec<Bin> = (-3..=3)
+            .map(|id| {
+                let price = get_price_from_id(id, 25).unwrap();
+                let amount_a = if id >= 0 { 1_000_000 } else { 0 };
+                let amount_b = if id <= 0 { 1_000_000 } else { 0 };
+                Bin {
+                    id,
+                    amount_a,
+                    amount_b,
+                    price: price.into(),
+                    liquidity_supply: value_in_b(amount_a as u128, amount_b as u128, price)
+                        .unwrap()
+                        * ONE,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let shares = bins
+            .iter()
+            .map(|bin| (bin.id, bin.liquidity_supply / 2))
+            .collect();
+        (bins, shares)
+    }
+
+    #[test]
+    fn divergence_is_zero_when_the_price_returns() {
+        let (bins, shares) = position();
+        let report = divergence_loss(&bins, &shares, 0, 0, 25).unwrap();
+        assert_eq!(report.start_amount_a, 2_000_000);
+        assert_eq!(report.start_amount_b, 2_000_000);
+        assert_eq!(
+            (report.end_amount_a, report.end_amount_b),
+            (report.start_amount_a, report.start_amount_b)
+        );
+        assert_eq!(report.divergence_in_b, 0);
+        assert_eq!(report.divergence_bps, 0);
+
+        // The report only depends on the two ids: there and back is the
+        // same as staying.
+        let there = divergence_loss(&bins, &shares, 0, 2, 25).unwrap();
+        let back = divergence_loss(&bins, &shares, 2, 0, 25).unwrap();
+        assert!(there.divergence_in_b < 0);
+        assert_eq!(
+            (back.end_amount_a, back.end_amount_b),
+            (there.start_amount_a, there.start_amount_b)
+        );
+    }
+
+    #[test]
+    fn crossing_the_whole_range_converts_every_bin() {
+        let (bins, shares) = position();
+        let price = |id| get_price_from_id(id, 25).unwrap();
+
+        // Up through every bin: all the A is sold for B at the bins' prices.
+        let up = divergence_loss(&bins, &shares, 0, 10, 25).unwrap();
+        assert_eq!(up.end_amount_a, 0);
+        let sold: u128 = (0..=3)
+            .map(|id| mul_div(500_000, price(id), ONE, Rounding::Down).unwrap())
+            .sum();
+        assert_eq!(up.end_amount_b, 1_500_000 + 500_000 + sold);
+        assert_eq!(up.position_value_in_b, up.end_amount_b);
+        assert_eq!(
+            up.hodl_value_in_b,
+            mul_div(2_000_000, price(10), ONE, Rounding::Down).unwrap() + 2_000_000
+        );
+        assert_eq!(
+            up.divergence_in_b,
+            up.position_value_in_b as i128 - up.hodl_value_in_b as i128
+        );
+        assert!(up.divergence_bps < 0);
+
+        // Down through every bin: all the B is bought into A.
+        let down = divergence_loss(&bins, &shares, 0, -10, 25).unwrap();
+        assert_eq!(down.end_amount_b, 0);
+        assert!(down.end_amount_a > down.start_amount_a);
+        assert!(down.divergence_in_b < 0);
+
+        // A move inside the range converts only the bins crossed.
+        let partial = divergence_loss(&bins, &shares, 0, 2, 25).unwrap();
+        assert!(partial.end_amount_a > 0 && partial.end_amount_b > up.start_amount_b);
+        assert!(partial.divergence_bps > up.divergence_bps);
+    }
+
+    #[test]
+    fn divergence_rejects_bad_positions() {
+        let (bins, mut shares) = position();
+        let err = divergence_loss(&bins, &[(7, 1)], 0, 1, 25).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidBinId)));
+        shares[0].1 = bins[0].liquidity_supply + 1;
+        let err = divergence_loss(&bins, &shares, 0, 1, 25).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidInput)));
+        let err = divergence_loss(&bins, &[], 0, crate::MAX_BIN_ID + 1, 25).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidBinId)));
+    }
+
+    fn valued_pool() -> Pool {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 0);
+        let bin = |id, amount_a, amount_b, price| Bin {
+            id,
+            amount_a,
+            amount_b,
+            price: PriceQ64::from_raw(price),
+            liquidity_supply: 1_000,
+            ..Default::default()
+        };
+        Pool::new(
+            0,
+            0,
+            VariableParameters::new(config, 0, 0),
+            // At price 2 and 0.5.
+            vec![bin(0, 10, 5, 2 * ONE), bin(1, 7, 3, ONE / 2)],
+        )
+    }
+
+    #[test]
+    fn pool_value_in_either_token() {
+        let mut pool = valued_pool();
+        // 5 + 10 * 2 = 25 and 3 + 7 * 0.5 = 6.5, rounded down.
+        assert_eq!(pool.value_in_b().unwrap(), 25 + 6);
+        // 10 + 5 / 2 = 12.5 and 7 + 3 / 0.5 = 13.
+        assert_eq!(pool.value_in_a().unwrap(), 12 + 13);
+
+        let position = [(&pool.bins[0], 500), (&pool.bins[1], 0)];
+        assert_eq!(position_value(&position, true).unwrap(), 12);
+        assert_eq!(position_value(&position, false).unwrap(), 6);
+        assert_eq!(position_value(&[], true).unwrap(), 0);
+
+        pool.bins[1].price = PriceQ64::from_raw(0);
+        for result in [pool.value_in_b(), pool.value_in_a()] {
+            assert!(matches!(
+                result.unwrap_err().downcast_ref(),
+                Some(DlmmError::InvalidInput)
+            ));
+        }
+    }
+
+    #[test]
+    fn value_overflow_and_bad_shares_fail() {
+        let mut pool = valued_pool();
+        for bin in &mut pool.bins {
+            bin.amount_a = u64::MAX;
+            bin.price = PriceQ64::from_raw(u128::MAX);
+        }
+        // (2^64 - 1) * (2^128 - 1) >> 64 = 2^128 - 2^64 - 1, so one bin fits
+        // in a u128 but two do not.
+        let whole = [(&pool.bins[0], 1_000)];
+        assert_eq!(
+            position_value(&whole, true).unwrap(),
+            u128::MAX - (1 << 64) + 5
+        );
+        for result in [
+            pool.value_in_b(),
+            position_value(&[whole[0], (&pool.bins[1], 1_000)], true),
+        ] {
+            assert!(matches!(
+                result.unwrap_err().downcast_ref(),
+                Some(DlmmError::MathOverflow)
+            ));
+        }
+
+        let pool = valued_pool();
+        let err = position_value(&[(&pool.bins[0], 1_001)], true).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidInput)));
+        let empty = Bin {
+            liquidity_supply: 0,
+            ..pool.bins[0].clone()
+        };
+        let err = position_value(&[(&empty, 1)], true).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::ZeroLiquidity)));
+    }
+
+    #[test]
+    fn pool_apr_weights_bins_by_liquidity() {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 0);
+        let pool = |bins| Pool::new(0, 0, VariableParameters::new(config.clone(), 0, 0), bins);
+
+        // Bin 1 is three times the size of bin 0 and earns at twice the rate.
+        let mut large = bin(1);
+        large.amount_a *= 3;
+        large.amount_b *= 3;
+        large.liquidity_supply *= 3;
+        let empty = Bin {
+            liquidity_supply: 0,
+            amount_a: 0,
+            amount_b: 0,
+            ..bin(2)
+        };
+        let before = pool(vec![bin(0), large.clone(), empty.clone()]);
+        let after = pool(vec![
+            earned(bin(0), 0, 400),
+            earned(large, 0, 2_400),
+            empty,
+            earned(bin(3), 0, 1_000_000),
+        ]);
+
+        let apr = pool_fee_apr(&before, &after, 0..=3, DAY).unwrap();
+        assert_close(apr.apr_bps, (365.0 + 3.0 * 730.0) / 4.0);
+        // Only bin 0 is in range.
+        assert_close(
+            pool_fee_apr(&before, &after, -5..=0, DAY).unwrap().apr_bps,
+            365.0,
+        );
+        assert!(matches!(
+            pool_fee_apr(&before, &after, 2..=3, DAY)
+                .unwrap_err()
+                .downcast_ref(),
+            Some(DlmmError::ZeroLiquidity)
+        ));
+    }
+
+    #[test]
+    fn concentration_of_one_bin_and_of_a_uniform_pool() {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 0);
+        let pool = |bins| Pool::new(0, 0, VariableParameters::new(config.clone(), 0, 0), bins);
+
+        let single = concentration(&pool(vec![bin(7)]), 3).unwrap();
+        assert_eq!(single.hhi_bps, 10_000);
+        assert_eq!(single.largest_bin_id, Some(7));
+        assert_eq!((single.within_5_bps, single.within_10_bps), (0, 10_000));
+        assert_eq!(single.within_window_bps, 0);
+
+        // 100 bins holding 1_000 B each, around active id 0.
+        let uniform = (-50..50)
+            .map(|id| Bin {
+                id,
+                amount_a: 0,
+                amount_b: 1_000,
+                price: get_price_from_id(id, 25).unwrap().into(),
+                ..Default::default()
+            })
+            .collect();
+        let report = concentration(&pool(uniform), 20).unwrap();
+        assert_eq!(
+            report,
+            ConcentrationReport {
+                within_1_bps: 300,
+                within_5_bps: 1_100,
+                within_10_bps: 2_100,
+                window: 20,
+                within_window_bps: 4_100,
+                hhi_bps: 100,
+                largest_bin_id: Some(-50),
+            }
+        );
+        assert_eq!(
+            concentration(&pool(vec![]), 1).unwrap().largest_bin_id,
+            None
+        );
+    }
+
+    /// 1,000 of each token per bin at bin step 25, with a 0.1% base fee
+    /// and the variable fee on.
+    fn thin_pool() -> Pool {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 40_000, 350_000, 0);
+        crate::PoolBuilder::new()
+            .config(config)
+            .base_fee_rate(1_000_000)
+            .uniform_liquidity(-100, 100, 1_000, 1_000)
+            .build()
+            .unwrap()
+    }
+
+    fn sell(amount: u64) -> SwapInstruction {
+        SwapInstruction {
+            amount,
+            a2b: true,
+            by_amount_in: true,
+            timestamp: 0.into(),
+        }
+    }
+
+    #[test]
+    fn sandwiching_a_tiny_trade_loses_money() {
+        let pool = thin_pool();
+        let before = pool.clone();
+        let sizes = [5_000, 0, 100, 80_000, 1];
+        let outcomes = sandwich_impact(&pool, &sell(10), &sizes, 10).unwrap();
+        assert_eq!(pool, before);
+        assert_eq!(
+            outcomes.iter().map(|o| o.attacker_size).collect::<Vec<_>>(),
+            sizes
+        );
+        // Without a frontrun the victim trades as on the undisturbed pool.
+        assert_eq!(outcomes[1].attacker_profit, 0);
+        assert_eq!(outcomes[1].victim_loss, 0);
+        assert_eq!(
+            outcomes[1].victim_amount_out,
+            pool.quote_exact_amount_in(10, true, 10).unwrap().amount_out
+        );
+        for outcome in &outcomes {
+            if outcome.attacker_size > 0 {
+                assert!(outcome.attacker_profit < 0, "{outcome:?}");
+            }
+            assert_eq!(outcome.unprofitable_from, Some(1));
+        }
+    }
+
+    #[test]
+    fn sandwiching_a_large_trade_on_thin_liquidity_pays() {
+        let pool = thin_pool();
+        let sizes = [0, 100, 1_000, 5_000, 20_000, 80_000];
+        let outcomes = sandwich_impact(&pool, &sell(50_000), &sizes, 10).unwrap();
+        assert!(outcomes[1..].iter().all(|o| o.attacker_profit > 0));
+        // The victim loses more the larger the frontrun.
+        assert!(outcomes
+            .windows(2)
+            .all(|pair| pair[0].victim_loss < pair[1].victim_loss));
+        assert_eq!(outcomes[0].unprofitable_from, None);
+        // The largest frontrun leaves the victim short of liquidity.
+        assert!(outcomes[5].victim_is_exceed);
+
+        // A smaller victim pays a mid-sized attacker only.
+        let outcomes = sandwich_impact(&pool, &sell(10_000), &sizes, 10).unwrap();
+        assert!(outcomes[3].attacker_profit > 0);
+        assert!(outcomes[5].attacker_profit < 0);
+        assert_eq!(outcomes[0].unprofitable_from, Some(80_000));
+
+        // An exact-output victim pays more input instead.
+        let buy = SwapInstruction {
+            amount: 20_000,
+            a2b: false,
+            by_amount_in: false,
+            timestamp: 0.into(),
+        };
+        let outcomes = sandwich_impact(&pool, &buy, &[0, 5_000], 10).unwrap();
+        assert_eq!(outcomes[1].victim_amount_out, 20_000);
+        assert_eq!(
+            outcomes[1].victim_loss,
+            outcomes[1].victim_amount_in as i128 - outcomes[0].victim_amount_in as i128
+        );
+        assert!(outcomes[1].victim_loss > 0);
+        assert!(outcomes[1].attacker_profit > 0);
+    }
+
+    #[test]
+    fn concentration_handles_whale_bins() {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 0);
+        let whale = |id| Bin {
+            id,
+            amount_a: u64::MAX,
+            amount_b: u64::MAX,
+            price: u128::MAX.into(),
+            ..Default::default()
+        };
+        let mut bins = vec![whale(-1), whale(0), whale(1)];
+        bins[2].amount_a = 0;
+        let report = concentration(
+            &Pool::new(0, 0, VariableParameters::new(config, 0, 0), bins),
+            0,
+        )
+        .unwrap();
+        assert_eq!(report.within_1_bps, 10_000);
+        // Nearly half each in the two full bins, a sliver in the third.
+        assert_eq!(report.within_window_bps, 4_999);
+        assert_eq!(report.hhi_bps, 4_999);
+        assert_eq!(report.largest_bin_id, Some(-1));
+    }
+}
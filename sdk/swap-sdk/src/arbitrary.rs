@@ -0,0 +1,240 @@
+//! `proptest` strategies that only produce states the SDK accepts.
+//!
+//! Every generated [`Pool`] passes [`Pool::validate`]: bins are strictly
+//! sorted by id, each price is derived from its id and the pool's bin step,
+//! and fee rates are within protocol bounds. Bins below the active id hold
+//! only token B and bins above it only token A, as on chain. Ids stay close
+//! enough to 0 that prices are within `e^±10` of 1, and reserves are capped
+//! at [`MAX_BIN_RESERVE`], so swaps of up to that many tokens neither
+//! overflow the amount conversions nor the `u64` totals of a [`SwapResult`].
+//!
+//! [`SwapResult`]: crate::SwapResult
+
+use proptest::{collection, prelude::*, sample::select};
+
+use crate::{
+    bin::Bin,
+    config::{BinStepConfig, VariableParameters},
+    math::{
+        price_math::{get_price_from_id, BIN_BOUND},
+        q64x64_math::SCALE_OFFSET,
+        BASIS_POINT_MAX,
+    },
+    pool::Pool,
+    FEE_PRECISION, MAX_FEE_RATE,
+};
+
+/// Bin steps the strategies draw from.
+pub const BIN_STEPS: [u16; 10] = [1, 2, 5, 10, 20, 25, 50, 80, 100, 200];
+
+/// Largest reserve of either token in a generated bin.
+pub const MAX_BIN_RESERVE: u64 = 1 << 48;
+
+/// Bound on generated fee and reward growth values, leaving headroom for
+/// further accrual.
+const MAX_GROWTH: u128 = 1 << 100;
+
+/// Largest `|id|` generated for `bin_step`; the price of such a bin is
+/// within `e^±10` of 1.
+pub fn max_bin_id(bin_step: u16) -> i32 {
+    let per_bin = (bin_step as f64 / BASIS_POINT_MAX as f64).ln_1p();
+    ((10.0 / per_bin) as i32).min(BIN_BOUND as i32)
+}
+
+impl Arbitrary for BinStepConfig {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            select(BIN_STEPS.to_vec()),
+            1u16..=20_000,
+            0u16..=600,
+            0u16..=3_600,
+            0..=BASIS_POINT_MAX as u16,
+            0u32..=500_000,
+            0u32..=1_000_000,
+            0..=FEE_PRECISION,
+        )
+            .prop_map(
+                |(
+                    bin_step,
+                    base_factor,
+                    filter_period,
+                    decay_extra,
+                    reduction_factor,
+                    variable_fee_control,
+                    max_volatility_accumulator,
+                    protocol_fee_rate,
+                )| {
+                    BinStepConfig::new(
+                        bin_step,
+                        base_factor,
+                        filter_period,
+                        filter_period + decay_extra,
+                        reduction_factor,
+                        variable_fee_control,
+                        max_volatility_accumulator,
+                        protocol_fee_rate,
+                    )
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for VariableParameters {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<BinStepConfig>()
+            .prop_flat_map(|config| {
+                let max_accumulator = config.max_volatility_accumulator;
+                let bound = max_bin_id(config.bin_step);
+                (
+                    0..=max_accumulator,
+                    0..=max_accumulator,
+                    -bound..=bound,
+                    0u64..=1 << 40,
+                    Just(config),
+                )
+            })
+            .prop_map(
+                |(accumulator, reference, index_reference, timestamp, config)| VariableParameters {
+                    volatility_accumulator: accumulator,
+                    volatility_reference: reference,
+                    index_reference,
+                    last_update_timestamp: timestamp,
+                    bin_step_config: config,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Bin {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (select(BIN_STEPS.to_vec()), 0usize..=3)
+            .prop_flat_map(|(bin_step, rewards)| {
+                let bound = max_bin_id(bin_step);
+                (-bound..=bound).prop_flat_map(move |id| bin(id, bin_step, id, rewards))
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Pool {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![4 => small_pool(), 1 => sparse_pool()].boxed()
+    }
+}
+
+/// Up to 32 bins within 40 ids of the active id.
+pub fn small_pool() -> impl Strategy<Value = Pool> {
+    pool(32, 40)
+}
+
+/// Up to 1,000 bins scattered over as wide an id range as the bin step
+/// allows.
+pub fn sparse_pool() -> impl Strategy<Value = Pool> {
+    pool(1_000, BIN_BOUND as i32)
+}
+
+/// Pools of 1 to `max_bins` bins with ids within `spread` of the active id.
+pub fn pool(max_bins: usize, spread: i32) -> impl Strategy<Value = Pool> {
+    (any::<VariableParameters>(), 0..=MAX_FEE_RATE, 0usize..=3).prop_flat_map(
+        move |(v_parameters, base_fee_rate, rewards)| {
+            let bin_step = v_parameters.bin_step_config.bin_step;
+            let bound = max_bin_id(bin_step);
+            let spread = spread.min(bound);
+            let max_bins = max_bins.min(2 * spread as usize + 1);
+            (-bound + spread..=bound - spread)
+                .prop_flat_map(move |active_id| {
+                    let ids = collection::btree_set(
+                        active_id - spread..=active_id + spread,
+                        1..=max_bins,
+                    );
+                    (Just(active_id), ids)
+                })
+                .prop_flat_map(move |(active_id, ids)| {
+                    let bins: Vec<_> = ids
+                        .into_iter()
+                        .map(|id| bin(id, bin_step, active_id, rewards))
+                        .collect();
+                    (Just(active_id), bins)
+                })
+                .prop_map(move |(active_id, bins)| {
+                    Pool::new(active_id, base_fee_rate, v_parameters.clone(), bins)
+                })
+        },
+    )
+}
+
+/// A bin priced for `bin_step`, holding the reserves a bin at `id` would
+/// hold while `active_id` is active.
+fn bin(id: i32, bin_step: u16, active_id: i32, rewards: usize) -> BoxedStrategy<Bin> {
+    (
+        0..=MAX_BIN_RESERVE,
+        0..=MAX_BIN_RESERVE,
+        collection::vec(0..MAX_GROWTH, rewards),
+        0..MAX_GROWTH,
+        0..MAX_GROWTH,
+    )
+        .prop_map(move |(amount_a, amount_b, rewards, fee_a, fee_b)| {
+            let price = get_price_from_id(id, bin_step).expect("id is within max_bin_id");
+            let amount_a = if id < active_id { 0 } else { amount_a };
+            let amount_b = if id > active_id { 0 } else { amount_b };
+            Bin {
+                id,
+                amount_a,
+                amount_b,
+                price: price.into(),
+                liquidity_supply: ((amount_a as u128 * price) >> SCALE_OFFSET) + amount_b as u128,
+                rewards_growth_global: rewards,
+                fee_amount_a_growth_global: fee_a,
+                fee_amount_b_growth_global: fee_b,
+            }
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn generated_pools_are_valid(pool in any::<Pool>()) {
+            prop_assert!(pool.validate().is_ok());
+            prop_assert!(!pool.bins.is_empty());
+        }
+
+        #[test]
+        fn amount_out_is_bounded_by_reserves(
+            pool in prop_oneof![small_pool(), sparse_pool()],
+            amount in 1..=MAX_BIN_RESERVE,
+            a2b in any::<bool>(),
+            timestamp in 0u64..=1 << 41,
+        ) {
+            let reserve_out = if a2b {
+                pool.total_amount_b()
+            } else {
+                pool.total_amount_a()
+            };
+            let quote = pool.quote_exact_amount_in(amount, a2b, timestamp).unwrap();
+            prop_assert!(quote.amount_out as u128 <= reserve_out);
+            prop_assert!(quote.amount_in <= amount);
+
+            let mut swapped = pool.clone();
+            prop_assert_eq!(swapped.swap_exact_amount_in(amount, a2b, timestamp).unwrap(), quote);
+            prop_assert!(swapped.validate().is_ok());
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Quoting many pool/amount pairs at once on the rayon thread pool.
+
+use anyhow::Error;
+use rayon::prelude::*;
+
+pub use crate::pool::SwapMode;
+use crate::{
+    pool::{Pool, SwapResult},
+    timestamp::Timestamp,
+};
+
+/// One quote to compute. The pool is only borrowed; it is never modified.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteRequest<'a> {
+    pub pool: &'a Pool,
+    pub amount: u64,
+    pub a2b: bool,
+    pub mode: SwapMode,
+    pub timestamp: Timestamp,
+}
+
+impl QuoteRequest<'_> {
+    /// Computes this quote on the current thread.
+    pub fn quote(&self) -> Result<SwapResult, Error> {
+        match self.mode {
+            SwapMode::ExactIn => {
+                self.pool
+                    .quote_exact_amount_in(self.amount, self.a2b, self.timestamp)
+            }
+            SwapMode::ExactOut => {
+                self.pool
+                    .quote_exact_amount_out(self.amount, self.a2b, self.timestamp)
+            }
+        }
+    }
+}
+
+/// Quotes every request in parallel. Results are in request order, and each
+/// is identical to what [`QuoteRequest::quote`] returns for that request.
+pub fn quote_many(requests: &[QuoteRequest]) -> Vec<Result<SwapResult, Error>> {
+    requests.par_iter().map(QuoteRequest::quote).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY_POOL: &str = include_str!("../tests/fixtures/pool_legacy_numbers.json");
+
+    #[test]
+    fn batch_matches_sequential_quotes() {
+        let pool: Pool = serde_json::from_str(LEGACY_POOL).unwrap();
+        let mut drained = pool.clone();
+        drained.bins.clear();
+        let pools = [pool.clone(), drained];
+
+        let requests: Vec<QuoteRequest> = (0..500u64)
+            .map(|i| QuoteRequest {
+                pool: &pools[(i % 7 == 0) as usize],
+                amount: 1 + i * 37,
+                a2b: i % 2 == 0,
+                mode: if i % 3 == 0 {
+                    SwapMode::ExactOut
+                } else {
+                    SwapMode::ExactIn
+                },
+                timestamp: Timestamp::from_seconds(10 + i),
+            })
+            .collect();
+
+        let batch = quote_many(&requests);
+        assert_eq!(batch.len(), requests.len());
+        for (request, result) in requests.iter().zip(batch) {
+            assert_eq!(result.unwrap(), request.quote().unwrap());
+        }
+        assert_eq!(pools[0], pool);
+    }
+}
@@ -0,0 +1,363 @@
+//! BCS decoding of the on-chain pool object.
+//!
+//! The structs below follow the field order of the Move definitions in
+//! `packages/dlmm/sources` exactly, since BCS is not self-describing: any
+//! reordering or missing field shows up as a decoding error rather than as
+//! garbage values. Only the fields relevant to swap math are converted into
+//! SDK types; the rest are decoded to keep the layout honest.
+//!
+//! Bins are stored in dynamic fields under the bin manager, so
+//! [`Pool::from_bcs_bytes`] returns a pool without bins. Decode them with
+//! [`BcsBin`] and attach them afterwards.
+
+use anyhow::Error;
+use serde::Deserialize;
+
+use crate::{
+    bin::Bin,
+    config::{BinStepConfig, VariableParameters},
+    error::DlmmError,
+    metadata::PoolMetadata,
+    pool::Pool,
+};
+
+type Address = [u8; 32];
+
+/// `sui::object::UID`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsUid {
+    pub id: Address,
+}
+
+/// `move_stl::option_u64::OptionU64`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsOptionU64 {
+    pub is_none: bool,
+    pub v: u64,
+}
+
+/// `move_stl::skip_list::SkipList<V>`; entries live in dynamic fields. The
+/// single-field `Random { seed }` struct is flattened into `random_seed`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsSkipList {
+    pub id: BcsUid,
+    pub head: Vec<BcsOptionU64>,
+    pub tail: BcsOptionU64,
+    pub level: u64,
+    pub max_level: u64,
+    pub list_p: u64,
+    pub size: u64,
+    pub random_seed: u64,
+}
+
+/// `sui::bag::Bag` and `sui::table::Table` share this layout.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsTable {
+    pub id: BcsUid,
+    pub size: u64,
+}
+
+/// `cetusdlmm::config::BinStepConfig`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsBinStepConfig {
+    pub bin_step: u16,
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    pub max_volatility_accumulator: u32,
+    pub protocol_fee_rate: u64,
+}
+
+/// `cetusdlmm::parameters::VariableParameters`; `index_reference` holds the
+/// raw `I32` bits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsVariableParameters {
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    pub index_reference: u32,
+    pub last_update_timestamp: u64,
+    pub bin_step_config: BcsBinStepConfig,
+}
+
+/// `cetusdlmm::reward::Reward`. `reward_released` is a `u256`, kept as
+/// little-endian bytes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsReward {
+    pub reward_coin: String,
+    pub current_emission_rate: u128,
+    pub period_emission_rates: BcsSkipList,
+    pub reward_released: [u8; 32],
+    pub reward_refunded: u128,
+    pub reward_harvested: u128,
+}
+
+/// `cetusdlmm::reward::RewardManager`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsRewardManager {
+    pub is_public: bool,
+    pub vault: BcsTable,
+    pub rewards: Vec<BcsReward>,
+    pub last_updated_time: u64,
+    pub emergency_reward_pause: bool,
+}
+
+/// `cetusdlmm::bin::BinManager`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsBinManager {
+    pub pool_id: Address,
+    pub bin_step: u16,
+    pub bins: BcsSkipList,
+}
+
+/// `cetusdlmm::position::PositionManager`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsPositionManager {
+    pub bin_step: u16,
+    pub position_index: u64,
+    pub positions: BcsTable,
+}
+
+/// `cetusdlmm::pool::Permissions`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsPermissions {
+    pub disable_add: bool,
+    pub disable_remove: bool,
+    pub disable_swap: bool,
+    pub disable_collect_fee: bool,
+    pub disable_collect_reward: bool,
+    pub disable_add_reward: bool,
+}
+
+/// `cetusdlmm::pool::Pool<A, B>`. `active_id` holds the raw `I32` bits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsPool {
+    pub id: BcsUid,
+    pub index: u64,
+    pub v_parameters: BcsVariableParameters,
+    pub active_id: u32,
+    pub base_fee_rate: u64,
+    pub balance_a: u64,
+    pub balance_b: u64,
+    pub protocol_fee_a: u64,
+    pub protocol_fee_b: u64,
+    pub reward_manager: BcsRewardManager,
+    pub bin_manager: BcsBinManager,
+    pub position_manager: BcsPositionManager,
+    pub url: String,
+    pub permissions: BcsPermissions,
+    pub active_open_positions: u64,
+}
+
+/// `cetusdlmm::bin::Bin`. `id` holds the raw `I32` bits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BcsBin {
+    pub id: u32,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub price: u128,
+    pub liquidity_share: u128,
+    pub rewards_growth_global: Vec<u128>,
+    pub fee_a_growth_global: u128,
+    pub fee_b_growth_global: u128,
+}
+
+impl BcsPool {
+    /// Decodes the full object; trailing or missing bytes are rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DlmmError> {
+        ::bcs::from_bytes(bytes).map_err(|e| DlmmError::InvalidLayout(e.to_string()))
+    }
+}
+
+impl BcsBin {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DlmmError> {
+        ::bcs::from_bytes(bytes).map_err(|e| DlmmError::InvalidLayout(e.to_string()))
+    }
+}
+
+impl From<BcsBinStepConfig> for BinStepConfig {
+    fn from(raw: BcsBinStepConfig) -> Self {
+        BinStepConfig::new(
+            raw.bin_step,
+            raw.base_factor,
+            raw.filter_period,
+            raw.decay_period,
+            raw.reduction_factor,
+            raw.variable_fee_control,
+            raw.max_volatility_accumulator,
+            raw.protocol_fee_rate,
+        )
+    }
+}
+
+impl From<BcsVariableParameters> for VariableParameters {
+    fn from(raw: BcsVariableParameters) -> Self {
+        VariableParameters {
+            volatility_accumulator: raw.volatility_accumulator,
+            volatility_reference: raw.volatility_reference,
+            index_reference: raw.index_reference as i32,
+            last_update_timestamp: raw.last_update_timestamp,
+            bin_step_config: raw.bin_step_config.into(),
+        }
+    }
+}
+
+impl From<BcsBin> for Bin {
+    fn from(raw: BcsBin) -> Self {
+        Bin {
+            id: raw.id as i32,
+            amount_a: raw.amount_a,
+            amount_b: raw.amount_b,
+            price: raw.price.into(),
+            liquidity_supply: raw.liquidity_share,
+            rewards_growth_global: raw.rewards_growth_global,
+            fee_amount_a_growth_global: raw.fee_a_growth_global,
+            fee_amount_b_growth_global: raw.fee_b_growth_global,
+        }
+    }
+}
+
+/// The pool, with metadata holding its id and bin step. BCS bytes carry no
+/// type, so the coin types and decimals are left unset.
+impl From<BcsPool> for Pool {
+    fn from(raw: BcsPool) -> Self {
+        let metadata = PoolMetadata {
+            pool_id: format!("0x{}", hex(&raw.id.id)),
+            bin_step: raw.v_parameters.bin_step_config.bin_step,
+            ..Default::default()
+        };
+        Pool::new(
+            raw.active_id as i32,
+            raw.base_fee_rate,
+            raw.v_parameters.into(),
+            Vec::new(),
+        )
+        .with_metadata(metadata)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl Pool {
+    /// Decodes the BCS bytes of an on-chain pool object. The returned pool has
+    /// no bins; they must be loaded from the bin manager's dynamic fields.
+    pub fn from_bcs_bytes(bytes: &[u8]) -> Result<Pool, Error> {
+        let pool = Pool::from(BcsPool::from_bytes(bytes)?);
+        pool.validate()?;
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hand-encoded from the Move struct definitions, not captured from
+    /// chain, so it checks the decoder against our reading of the layout.
+    const POOL_OBJECT_HEX: &str = include_str!("../tests/fixtures/synthetic_pool_object.bcs.hex");
+
+    fn fixture_bytes() -> Vec<u8> {
+        let hex: String = POOL_OBJECT_HEX
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .flat_map(str::split_whitespace)
+            .collect();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_pool_object_fixture() {
+        let bytes = fixture_bytes();
+        let raw = BcsPool::from_bytes(&bytes).unwrap();
+        assert_eq!(raw.index, 14);
+        assert_eq!(raw.balance_a, 35_187_461_822);
+        assert_eq!(raw.balance_b, 121_803_229_540);
+        assert_eq!(raw.reward_manager.rewards.len(), 1);
+        assert_eq!(
+            raw.reward_manager.rewards[0].reward_coin,
+            "0000000000000000000000000000000000000000000000000000000000000002::sui::SUI"
+        );
+        assert_eq!(raw.bin_manager.bin_step, 25);
+        assert_eq!(raw.bin_manager.bins.size, 37);
+        assert_eq!(raw.position_manager.position_index, 211);
+        assert!(!raw.permissions.disable_swap);
+        assert_eq!(raw.active_open_positions, 0);
+
+        let pool = Pool::from_bcs_bytes(&bytes).unwrap();
+        assert_eq!(pool.active_id, -12);
+        assert_eq!(pool.base_fee_rate, 2_500_000);
+        assert_eq!(pool.v_parameters.volatility_accumulator, 20_000);
+        assert_eq!(pool.v_parameters.volatility_reference, 10_000);
+        assert_eq!(pool.v_parameters.index_reference, -10);
+        assert_eq!(pool.v_parameters.last_update_timestamp, 1_726_017_305);
+        let config = &pool.v_parameters.bin_step_config;
+        assert_eq!(config.bin_step, 25);
+        assert_eq!(config.base_factor, 10_000);
+        assert_eq!(config.filter_period, 30);
+        assert_eq!(config.decay_period, 600);
+        assert_eq!(config.reduction_factor, 5_000);
+        assert_eq!(config.variable_fee_control, 40_000);
+        assert_eq!(config.max_volatility_accumulator, 350_000);
+        assert_eq!(config.protocol_fee_rate, 200_000_000);
+        assert!(pool.bins.is_empty());
+
+        let metadata = pool.metadata().unwrap();
+        assert_eq!(
+            metadata.pool_id,
+            "0x4fe3a2a0f4e1ac2e4a0e5d4fc2bdb46a3f1c3c05a6b0c7e8e7bba45b1b6d1c2e"
+        );
+        assert_eq!(metadata.bin_step, 25);
+        assert_eq!(
+            (metadata.coin_type_a.as_deref(), metadata.decimals()),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn layout_mismatch_is_typed() {
+        let mut bytes = fixture_bytes();
+        bytes.pop();
+        let err = Pool::from_bcs_bytes(&bytes).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DlmmError>(),
+            Some(DlmmError::InvalidLayout(_))
+        ));
+
+        let mut bytes = fixture_bytes();
+        bytes.push(0);
+        assert!(matches!(
+            BcsPool::from_bytes(&bytes),
+            Err(DlmmError::InvalidLayout(_))
+        ));
+    }
+
+    #[test]
+    fn decodes_bin() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(-3i32 as u32).to_le_bytes());
+        bytes.extend_from_slice(&7u64.to_le_bytes());
+        bytes.extend_from_slice(&9u64.to_le_bytes());
+        bytes.extend_from_slice(&(1u128 << 64).to_le_bytes());
+        bytes.extend_from_slice(&1_000u128.to_le_bytes());
+        bytes.push(2);
+        bytes.extend_from_slice(&5u128.to_le_bytes());
+        bytes.extend_from_slice(&6u128.to_le_bytes());
+        bytes.extend_from_slice(&11u128.to_le_bytes());
+        bytes.extend_from_slice(&12u128.to_le_bytes());
+
+        let bin = Bin::from(BcsBin::from_bytes(&bytes).unwrap());
+        assert_eq!(bin.id, -3);
+        assert_eq!((bin.amount_a, bin.amount_b), (7, 9));
+        assert_eq!(bin.price.as_raw(), 1 << 64);
+        assert_eq!(bin.liquidity_supply, 1_000);
+        assert_eq!(bin.rewards_growth_global, vec![5, 6]);
+        assert_eq!(bin.fee_amount_a_growth_global, 11);
+        assert_eq!(bin.fee_amount_b_growth_global, 12);
+    }
+}
@@ -1,87 +1,161 @@
+use alloc::vec::Vec;
+
 use anyhow::{Error, anyhow};
 use serde::{Deserialize, Serialize};
 
-use crate::math::dlmm_math::{
-    calculate_amount_in,
-    calculate_amount_out,
-    calculate_fee_exclusive,
-    calculate_fee_inclusive,
+use crate::{
+    error::DlmmError,
+    price::PriceQ64,
+    math::{
+        dlmm_math::{
+            calculate_amount_in_u128,
+            calculate_amount_out_u128,
+            calculate_fee_exclusive_u128,
+            calculate_fee_inclusive_u128,
+        },
+        full_math::mul_div,
+        Rounding, BASIS_POINT_MAX,
+    },
+    FEE_PRECISION,
 };
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// `(amount_in, amount_out, fee, protocol_fee)` of a fill in one bin;
+/// `amount_in` includes the fee.
+type Fill<T> = (T, T, T, T);
+
+/// What a taker's exact input fills against one bin, seen from the liquidity
+/// in it, see [`Bin::fill_quote`]. Amounts are in base units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FillQuote {
+    /// Input taken from the taker, fee included.
+    pub amount_in: u64,
+    /// Reserve paid out to the taker.
+    pub amount_out: u64,
+    pub fee: u64,
+    pub protocol_fee: u64,
+    /// The part of the taker's input the bin does not take, once its
+    /// reserve is gone.
+    pub amount_unfilled: u64,
+    /// What the bin's liquidity receives in the input token,
+    /// `amount_in - protocol_fee`: the input added to the reserve and the
+    /// fee share left to LPs. A maker holding part of `liquidity_supply`
+    /// receives that part of it.
+    pub maker_proceeds: u64,
+    /// `amount_out` relative to the reserve it came from, in basis points,
+    /// rounded down, so only a drained reserve reports 10,000. Zero for a
+    /// bin without the output token.
+    pub reserve_filled_bps: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Bin {
     pub id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub amount_a: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub amount_b: u64,
-    pub price: u128,
+    /// Serialized as the raw `u128`, see [`PriceQ64`].
+    pub price: PriceQ64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(alias = "liquidity_share")]
     pub liquidity_supply: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::vec_u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string_array")
+    )]
+    #[serde(default)]
     pub rewards_growth_global: Vec<u128>,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(alias = "fee_a_growth_global", default)]
     pub fee_amount_a_growth_global: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(alias = "fee_b_growth_global", default)]
     pub fee_amount_b_growth_global: u128,
 }
 
-impl Default for Bin {
-    fn default() -> Self {
-        Self {
-            id: 0,
-            amount_a: 0,
-            amount_b: 0,
-            price: 0,
-            liquidity_supply: 0,
-            rewards_growth_global: vec![],
-            fee_amount_a_growth_global: 0,
-            fee_amount_b_growth_global: 0,
-        }
+impl Bin {
+    /// Makes `target` equal to `self.clone()`, reusing its
+    /// `rewards_growth_global` allocation.
+    pub fn clone_into(&self, target: &mut Bin) {
+        let Bin {
+            id,
+            amount_a,
+            amount_b,
+            price,
+            liquidity_supply,
+            rewards_growth_global,
+            fee_amount_a_growth_global,
+            fee_amount_b_growth_global,
+        } = self;
+        target.id = *id;
+        target.amount_a = *amount_a;
+        target.amount_b = *amount_b;
+        target.price = *price;
+        target.liquidity_supply = *liquidity_supply;
+        target
+            .rewards_growth_global
+            .clone_from(rewards_growth_global);
+        target.fee_amount_a_growth_global = *fee_amount_a_growth_global;
+        target.fee_amount_b_growth_global = *fee_amount_b_growth_global;
     }
-}
 
-impl Bin {
     pub fn swap_exact_amount_in(
         &mut self,
         amount_in: u64,
         a2b: bool,
         fee_rate: u64,
         protocol_fee_rate: u64,
-    ) -> Result<(u64, u64, u64, u64), Error> {
-        if a2b {
-            let fee_amount = calculate_fee_inclusive(amount_in, fee_rate)?;
-            let amount_out = calculate_amount_out(amount_in - fee_amount, self.price, a2b)?;
-
-            let (amount_in, amount_out, fee_amount) = if amount_out <= self.amount_b {
-                (amount_in, amount_out, fee_amount)
-            } else {
-                let amount_in_without_fee = calculate_amount_in(self.amount_b, self.price, a2b)?;
-                let fee_amount = calculate_fee_exclusive(amount_in_without_fee, fee_rate)?;
-                let amount_in_with_fee = amount_in_without_fee + fee_amount;
-                if amount_in_with_fee > amount_in {
-                    return Err(anyhow!("amount_in_with_fee is greater than amount_in"));
-                }
-                (amount_in_with_fee, self.amount_b, fee_amount)
-            };
-            let protocol_fee = calculate_fee_inclusive(fee_amount, protocol_fee_rate)?;
-            self.amount_a = self.amount_a + amount_in - fee_amount;
-            self.amount_b = self.amount_b - amount_out;
-            Ok((amount_in, amount_out, fee_amount, protocol_fee))
-        } else {
-            let fee_amount = calculate_fee_inclusive(amount_in, fee_rate)?;
-            let amount_out = calculate_amount_out(amount_in - fee_amount, self.price, a2b)?;
-
-            let (amount_in, amount_out, fee_amount) = if amount_out <= self.amount_a {
-                (amount_in, amount_out, fee_amount)
-            } else {
-                let amount_in_without_fee = calculate_amount_in(self.amount_a, self.price, a2b)?;
-                let fee_amount = calculate_fee_exclusive(amount_in_without_fee, fee_rate)?;
-                let amount_in_with_fee = amount_in_without_fee + fee_amount;
-                if amount_in_with_fee > amount_in {
-                    return Err(anyhow!("amount_in_with_fee is greater than amount_in"));
-                }
-                (amount_in_with_fee, self.amount_a, fee_amount)
-            };
-            let protocol_fee = calculate_fee_inclusive(fee_amount, protocol_fee_rate)?;
-            self.amount_a = self.amount_a - amount_out;
-            self.amount_b = self.amount_b + amount_in - fee_amount;
-            Ok((amount_in, amount_out, fee_amount, protocol_fee))
-        }
+    ) -> Result<Fill<u64>, Error> {
+        narrow_fill(self.swap_exact_amount_in_u128(
+            amount_in as u128,
+            a2b,
+            fee_rate,
+            protocol_fee_rate,
+        )?)
     }
 
     pub fn swap_exact_amount_out(
@@ -90,53 +164,281 @@ impl Bin {
         a2b: bool,
         fee_rate: u64,
         protocol_fee_rate: u64,
-    ) -> Result<(u64, u64, u64, u64), Error> {
+    ) -> Result<Fill<u64>, Error> {
+        narrow_fill(self.swap_exact_amount_out_u128(
+            amount_out as u128,
+            a2b,
+            fee_rate,
+            protocol_fee_rate,
+        )?)
+    }
+
+    /// Same as [`Bin::swap_exact_amount_in`] without updating the reserves.
+    pub fn quote_exact_amount_in(
+        &self,
+        amount_in: u64,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<Fill<u64>, Error> {
+        narrow_fill(self.quote_exact_amount_in_u128(
+            amount_in as u128,
+            a2b,
+            fee_rate,
+            protocol_fee_rate,
+        )?)
+    }
+
+    /// Same as [`Bin::swap_exact_amount_out`] without updating the reserves.
+    pub fn quote_exact_amount_out(
+        &self,
+        amount_out: u64,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<Fill<u64>, Error> {
+        narrow_fill(self.quote_exact_amount_out_u128(
+            amount_out as u128,
+            a2b,
+            fee_rate,
+            protocol_fee_rate,
+        )?)
+    }
+
+    /// [`Bin::quote_exact_amount_in`] as a limit order placed in this bin
+    /// would see it: how much of the reserve a taker's `taker_amount_in`
+    /// fills and what the bin's liquidity receives for it. Fails as
+    /// [`Bin::quote_exact_amount_in`] does.
+    pub fn fill_quote(
+        &self,
+        taker_amount_in: u64,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<FillQuote, Error> {
+        let (amount_in, amount_out, fee, protocol_fee) =
+            self.quote_exact_amount_in(taker_amount_in, a2b, fee_rate, protocol_fee_rate)?;
+        let reserve_out = if a2b { self.amount_b } else { self.amount_a };
+        let reserve_filled_bps = if reserve_out == 0 {
+            0
+        } else {
+            // At most `BASIS_POINT_MAX`, as the output is within the reserve.
+            (amount_out as u128 * BASIS_POINT_MAX as u128 / reserve_out as u128) as u32
+        };
+        Ok(FillQuote {
+            amount_in,
+            amount_out,
+            fee,
+            protocol_fee,
+            amount_unfilled: taker_amount_in - amount_in,
+            maker_proceeds: amount_in - protocol_fee,
+            reserve_filled_bps,
+        })
+    }
+
+    /// [`Bin::swap_exact_amount_in`] with `u128` amounts. Fails without
+    /// touching the bin if the new reserves do not fit in `u64`.
+    pub fn swap_exact_amount_in_u128(
+        &mut self,
+        amount_in: u128,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<Fill<u128>, Error> {
+        let fill = self.quote_exact_amount_in_u128(amount_in, a2b, fee_rate, protocol_fee_rate)?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(bin_id = self.id, a2b, amount_in, fee_rate, fill = ?fill, "bin swap exact in");
+        (self.amount_a, self.amount_b) = self.reserves_after_swap(a2b, fill.0 - fill.2, fill.1)?;
+        Ok(fill)
+    }
+
+    /// [`Bin::swap_exact_amount_out`] with `u128` amounts. Fails without
+    /// touching the bin if the new reserves do not fit in `u64`.
+    pub fn swap_exact_amount_out_u128(
+        &mut self,
+        amount_out: u128,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<Fill<u128>, Error> {
+        let fill =
+            self.quote_exact_amount_out_u128(amount_out, a2b, fee_rate, protocol_fee_rate)?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(bin_id = self.id, a2b, amount_out, fee_rate, fill = ?fill, "bin swap exact out");
+        (self.amount_a, self.amount_b) = self.reserves_after_swap(a2b, fill.0 - fill.2, fill.1)?;
+        Ok(fill)
+    }
+
+    /// [`Bin::quote_exact_amount_in`] with `u128` amounts.
+    ///
+    /// Like every fill, fails with [`DlmmError::InvalidFeeRate`] unless
+    /// `fee_rate` is below [`FEE_PRECISION`] and `protocol_fee_rate` at most
+    /// it.
+    pub fn quote_exact_amount_in_u128(
+        &self,
+        amount_in: u128,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<Fill<u128>, Error> {
+        check_fee_rates(fee_rate, protocol_fee_rate)?;
+        let reserve_out = if a2b { self.amount_b } else { self.amount_a } as u128;
+        let fee_amount = calculate_fee_inclusive_u128(amount_in, fee_rate)?;
+        let amount_out = calculate_amount_out_u128(amount_in - fee_amount, self.price, a2b)?;
+
+        let (amount_in, amount_out, fee_amount) = if amount_out <= reserve_out {
+            (amount_in, amount_out, fee_amount)
+        } else {
+            // The input buys more than the reserve, so the fill is what the
+            // whole reserve costs. Both steps round up, but the cost is still
+            // at most `amount_in`: the net input is
+            // `floor(amount_in * (1 - fee))` and buys more than the reserve.
+            // Should rounding ever say otherwise, all of `amount_in` is taken
+            // for the reserve rather than failing the swap.
+            let amount_in_without_fee = calculate_amount_in_u128(reserve_out, self.price, a2b)?;
+            let reserve_fee = calculate_fee_exclusive_u128(amount_in_without_fee, fee_rate)?;
+            match amount_in_without_fee.checked_add(reserve_fee) {
+                Some(cost) if cost <= amount_in => (cost, reserve_out, reserve_fee),
+                _ => (amount_in, reserve_out, fee_amount),
+            }
+        };
+        let protocol_fee = calculate_fee_inclusive_u128(fee_amount, protocol_fee_rate)?;
+        debug_assert!(protocol_fee <= fee_amount);
+        Ok((amount_in, amount_out, fee_amount, protocol_fee))
+    }
+
+    /// [`Bin::quote_exact_amount_out`] with `u128` amounts.
+    pub fn quote_exact_amount_out_u128(
+        &self,
+        amount_out: u128,
+        a2b: bool,
+        fee_rate: u64,
+        protocol_fee_rate: u64,
+    ) -> Result<Fill<u128>, Error> {
+        check_fee_rates(fee_rate, protocol_fee_rate)?;
+        let reserve_out = if a2b { self.amount_b } else { self.amount_a } as u128;
+        let allow_amount_out = reserve_out.min(amount_out);
+        let amount_in_without_fee = calculate_amount_in_u128(allow_amount_out, self.price, a2b)?;
+        let fee_amount = calculate_fee_exclusive_u128(amount_in_without_fee, fee_rate)?;
+        let amount_in_with_fee = amount_in_without_fee
+            .checked_add(fee_amount)
+            .ok_or_else(|| anyhow!("amount_in overflow"))?;
+
+        let protocol_fee = calculate_fee_inclusive_u128(fee_amount, protocol_fee_rate)?;
+        debug_assert!(protocol_fee <= fee_amount);
+        Ok((
+            amount_in_with_fee,
+            allow_amount_out,
+            fee_amount,
+            protocol_fee,
+        ))
+    }
+
+    /// The largest exact input the bin takes whole at `fee_rate`. A larger
+    /// input only buys the reserve and leaves the rest unfilled.
+    ///
+    /// An input is taken whole while its output after the fee, rounded
+    /// down, stays within the reserve, so this is at least the cost of the
+    /// reserve and can exceed it by the input that rounds to no output.
+    pub fn max_exact_amount_in_u128(&self, a2b: bool, fee_rate: u64) -> Result<u128, Error> {
+        check_fee_rates(fee_rate, 0)?;
+        let reserve_out = if a2b { self.amount_b } else { self.amount_a } as u128;
+        // The smallest net input buying more than the reserve. The net of
+        // an input is `floor(amount_in * (1 - fee))`, which first reaches
+        // it at `ceil(net / (1 - fee))`.
+        let net = calculate_amount_in_u128(reserve_out + 1, self.price, a2b)?;
+        let first_over = mul_div(
+            net,
+            FEE_PRECISION as u128,
+            (FEE_PRECISION - fee_rate) as u128,
+            Rounding::Up,
+        )
+        .ok_or_else(|| anyhow!("amount_in overflow"))?;
+        let (cost, ..) = self.quote_exact_amount_out_u128(reserve_out, a2b, fee_rate, 0)?;
+        Ok((first_over - 1).max(cost))
+    }
+
+    /// Moves a quoted fill into the reserves. The fee stays out of the bin.
+    pub fn apply_swap(&mut self, a2b: bool, amount_in_without_fee: u64, amount_out: u64) {
         if a2b {
-            let allow_amount_out = self.amount_b.min(amount_out);
-            let amount_in_without_fee = calculate_amount_in(allow_amount_out, self.price, a2b)?;
-            let fee_amount = calculate_fee_exclusive(amount_in_without_fee, fee_rate)?;
-            let amount_in_with_fee = amount_in_without_fee + fee_amount;
-
-            let protocol_fee = calculate_fee_inclusive(fee_amount, protocol_fee_rate)?;
-            self.amount_a = self.amount_a + amount_in_without_fee;
-            self.amount_b = self.amount_b - allow_amount_out;
-
-            Ok((
-                amount_in_with_fee,
-                allow_amount_out,
-                fee_amount,
-                protocol_fee,
-            ))
+            self.amount_a += amount_in_without_fee;
+            self.amount_b -= amount_out;
         } else {
-            let allow_amount_out = self.amount_a.min(amount_out);
-            let amount_in_without_fee = calculate_amount_in(allow_amount_out, self.price, a2b)?;
-            let fee_amount = calculate_fee_exclusive(amount_in_without_fee, fee_rate)?;
-            let amount_in_with_fee = amount_in_without_fee + fee_amount;
-
-            let protocol_fee = calculate_fee_inclusive(fee_amount, protocol_fee_rate)?;
-            self.amount_a = self.amount_a - allow_amount_out;
-            self.amount_b = self.amount_b + amount_in_without_fee;
-
-            Ok((
-                amount_in_with_fee,
-                allow_amount_out,
-                fee_amount,
-                protocol_fee,
-            ))
+            self.amount_a -= amount_out;
+            self.amount_b += amount_in_without_fee;
         }
     }
+
+    /// `(amount_a, amount_b)` after a quoted fill, or an error if the input
+    /// side would no longer fit in `u64`.
+    pub fn reserves_after_swap(
+        &self,
+        a2b: bool,
+        amount_in_without_fee: u128,
+        amount_out: u128,
+    ) -> Result<(u64, u64), Error> {
+        let (reserve_in, reserve_out) = if a2b {
+            (self.amount_a, self.amount_b)
+        } else {
+            (self.amount_b, self.amount_a)
+        };
+        let reserve_in = (reserve_in as u128)
+            .checked_add(amount_in_without_fee)
+            .and_then(|reserve| u64::try_from(reserve).ok())
+            .ok_or_else(|| anyhow!("bin {} reserve overflow", self.id))?;
+        let reserve_out = (reserve_out as u128)
+            .checked_sub(amount_out)
+            .ok_or_else(|| anyhow!("bin {} reserve underflow", self.id))?
+            as u64;
+        Ok(if a2b {
+            (reserve_in, reserve_out)
+        } else {
+            (reserve_out, reserve_in)
+        })
+    }
+}
+
+/// A fill needs some input left after the fee, so `fee_rate` must be below
+/// `FEE_PRECISION`; the protocol can take at most the whole fee.
+fn check_fee_rates(fee_rate: u64, protocol_fee_rate: u64) -> Result<(), DlmmError> {
+    if fee_rate >= FEE_PRECISION {
+        return Err(DlmmError::InvalidFeeRate {
+            which: "fee_rate",
+            value: fee_rate,
+        });
+    }
+    if protocol_fee_rate > FEE_PRECISION {
+        return Err(DlmmError::InvalidFeeRate {
+            which: "protocol_fee_rate",
+            value: protocol_fee_rate,
+        });
+    }
+    Ok(())
+}
+
+/// Narrows a fill to `u64`. The output is bounded by a `u64` reserve and the
+/// fees by the input, so only the input of an exact-output fill can overflow.
+fn narrow_fill((amount_in, amount_out, fee, protocol_fee): Fill<u128>) -> Result<Fill<u64>, Error> {
+    let amount_in = u64::try_from(amount_in).map_err(|_| anyhow!("amount_in overflow"))?;
+    Ok((
+        amount_in,
+        amount_out as u64,
+        fee as u64,
+        protocol_fee as u64,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::Bin;
+    use crate::{error::DlmmError, FEE_PRECISION};
 
     fn make_bin(amount_a: u64, amount_b: u64, price: u128) -> Bin {
         Bin {
             id: 0,
             amount_a,
             amount_b,
-            price,
+            price: price.into(),
             liquidity_supply: 0,
             rewards_growth_global: vec![],
             fee_amount_a_growth_global: 0,
@@ -155,4 +457,256 @@ mod tests {
         assert_eq!(bin.amount_b, 500_000 - amount_out);
         assert_eq!(bin.amount_a, 1_000_000 + amount_in - fee);
     }
+
+    #[test]
+    fn quote_matches_swap_and_leaves_reserves() {
+        let bin = make_bin(1_000_000, 500_000, 1 << 64);
+        let quote = bin
+            .quote_exact_amount_in(800_000, true, 300_000, 1000)
+            .unwrap();
+        assert_eq!(bin, make_bin(1_000_000, 500_000, 1 << 64));
+
+        let mut swapped = bin.clone();
+        assert_eq!(
+            swapped
+                .swap_exact_amount_in(800_000, true, 300_000, 1000)
+                .unwrap(),
+            quote
+        );
+        assert_eq!(quote.1, 500_000);
+        assert_eq!(swapped.amount_b, 0);
+    }
+
+    #[test]
+    fn fill_quotes_report_the_maker_side() {
+        let bin = make_bin(0, 1_000_000, 1 << 64);
+        let (fee_rate, protocol_fee_rate) = (3_000_000, 200_000_000);
+
+        // A partial fill takes the whole input.
+        let fill = bin
+            .fill_quote(100_000, true, fee_rate, protocol_fee_rate)
+            .unwrap();
+        assert_eq!(fill.amount_in, 100_000);
+        assert_eq!(fill.fee, 300);
+        assert_eq!(fill.protocol_fee, 60);
+        assert_eq!(fill.amount_out, 99_700);
+        assert_eq!(fill.amount_unfilled, 0);
+        assert_eq!(fill.maker_proceeds, 100_000 - 60);
+        assert_eq!(fill.reserve_filled_bps, 997);
+
+        // The input the whole reserve costs fills it exactly.
+        let (cost, ..) = bin
+            .quote_exact_amount_out(1_000_000, true, fee_rate, protocol_fee_rate)
+            .unwrap();
+        let full = bin
+            .fill_quote(cost, true, fee_rate, protocol_fee_rate)
+            .unwrap();
+        assert_eq!(
+            (full.amount_in, full.amount_out, full.amount_unfilled),
+            (cost, 1_000_000, 0)
+        );
+        assert_eq!(full.reserve_filled_bps, 10_000);
+        assert_eq!(
+            bin.fill_quote(cost - 1, true, fee_rate, protocol_fee_rate)
+                .unwrap()
+                .reserve_filled_bps,
+            9_999
+        );
+
+        // A larger input leaves the rest unfilled.
+        let oversize = bin
+            .fill_quote(5_000_000, true, fee_rate, protocol_fee_rate)
+            .unwrap();
+        assert_eq!(oversize.amount_out, 1_000_000);
+        assert_eq!(oversize.amount_in, cost);
+        assert_eq!(oversize.amount_unfilled, 5_000_000 - cost);
+        assert_eq!(oversize.maker_proceeds, cost - oversize.protocol_fee);
+        assert_eq!(oversize.reserve_filled_bps, 10_000);
+
+        // Nothing to fill on the other side.
+        let empty = bin
+            .fill_quote(1_000, false, fee_rate, protocol_fee_rate)
+            .unwrap();
+        assert_eq!(
+            (
+                empty.amount_out,
+                empty.amount_unfilled,
+                empty.reserve_filled_bps
+            ),
+            (0, 1_000, 0)
+        );
+        assert!(bin.fill_quote(1_000, true, FEE_PRECISION, 0).is_err());
+    }
+
+    #[test]
+    fn u128_fill_matches_u64_and_handles_large_amounts() {
+        let bin = make_bin(1_000_000, 500_000, 3 << 63);
+        for (amount, a2b) in [
+            (123_456, true),
+            (10_000_000, true),
+            (777, false),
+            (u64::MAX, false),
+        ] {
+            let narrow = bin
+                .quote_exact_amount_in(amount, a2b, 3_000_000, 200_000_000)
+                .unwrap();
+            let wide = bin
+                .quote_exact_amount_in_u128(amount as u128, a2b, 3_000_000, 200_000_000)
+                .unwrap();
+            assert_eq!(
+                (
+                    narrow.0 as u128,
+                    narrow.1 as u128,
+                    narrow.2 as u128,
+                    narrow.3 as u128
+                ),
+                wide
+            );
+        }
+
+        // 10^20 base units of an 18-decimal token is 100 tokens.
+        let amount_in = 100_000_000_000_000_000_000u128;
+        let mut bin = make_bin(0, u64::MAX, 1 << 64);
+        let (used, out, fee, _) = bin
+            .swap_exact_amount_in_u128(amount_in, true, 2_500_000, 0)
+            .unwrap();
+        assert!(used < amount_in && out == u64::MAX as u128);
+        assert_eq!(bin.amount_a as u128, used - fee);
+        assert_eq!(bin.amount_b, 0);
+
+        // The input reserve would overflow u64: nothing changes.
+        let mut full = make_bin(u64::MAX - 10, u64::MAX, 1 << 64);
+        assert!(full
+            .swap_exact_amount_in_u128(amount_in, true, 0, 0)
+            .is_err());
+        assert_eq!(full, make_bin(u64::MAX - 10, u64::MAX, 1 << 64));
+    }
+
+    #[test]
+    fn inputs_buying_more_than_the_reserve_drain_it() {
+        use crate::math::dlmm_math::{calculate_amount_out_u128, calculate_fee_inclusive_u128};
+
+        // Reserves just below what each input buys, at prices and fee rates
+        // where the rounding of the fill is tightest.
+        let prices = [(1u128 << 64) - 1, 1 << 64, (1 << 64) + 1, 3 << 63, 1 << 32];
+        let fee_rates = [1, 3_000_000, 99_999_999, 100_000_000, 999_999_999];
+        for price in prices {
+            for fee_rate in fee_rates {
+                for a2b in [true, false] {
+                    for amount_in in 1u128..300 {
+                        let fee = calculate_fee_inclusive_u128(amount_in, fee_rate).unwrap();
+                        let net = amount_in - fee;
+                        let buys = calculate_amount_out_u128(net, price, a2b).unwrap();
+                        for reserve in buys.saturating_sub(3)..buys {
+                            let bin = make_bin(reserve as u64, reserve as u64, price);
+                            let (used, out, fee, _) = bin
+                                .quote_exact_amount_in_u128(amount_in, a2b, fee_rate, 0)
+                                .unwrap();
+                            assert_eq!(out, reserve);
+                            assert!(used <= amount_in && fee <= used);
+                            let bought = calculate_amount_out_u128(used - fee, price, a2b).unwrap();
+                            assert!(bought >= reserve);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_exact_amount_in_is_the_largest_input_taken_whole() {
+        let prices = [(1u128 << 64) - 1, 1 << 64, 3 << 63, 1 << 60, 1 << 70];
+        let fee_rates = [0, 1, 3_000_000, 100_000_000, 999_999_999];
+        for price in prices {
+            for fee_rate in fee_rates {
+                for a2b in [true, false] {
+                    for reserve in [1u64, 7, 1_000, 123_457] {
+                        let bin = make_bin(reserve, reserve, price);
+                        let max = bin.max_exact_amount_in_u128(a2b, fee_rate).unwrap();
+                        let taken = |amount_in| {
+                            bin.quote_exact_amount_in_u128(amount_in, a2b, fee_rate, 0)
+                                .unwrap()
+                                .0
+                        };
+                        assert_eq!(taken(max), max);
+                        assert!(taken(max + 1) <= max);
+                        let (cost, ..) = bin
+                            .quote_exact_amount_out_u128(reserve as u128, a2b, fee_rate, 0)
+                            .unwrap();
+                        assert!(max >= cost);
+                    }
+                }
+            }
+        }
+        assert!(make_bin(1, 1, 1 << 64)
+            .max_exact_amount_in_u128(true, 1_000_000_000)
+            .is_err());
+    }
+
+    #[test]
+    fn fee_rates_are_checked_up_front() {
+        let bin = make_bin(1_000_000, 1_000_000, 1 << 64);
+        let rejects = |fee_rate, protocol_fee_rate, expected: &str| {
+            let fills = [
+                bin.quote_exact_amount_in(1_000, true, fee_rate, protocol_fee_rate),
+                bin.quote_exact_amount_out(1_000, false, fee_rate, protocol_fee_rate),
+                bin.clone()
+                    .swap_exact_amount_in(1_000, false, fee_rate, protocol_fee_rate),
+                bin.clone()
+                    .swap_exact_amount_out(1_000, true, fee_rate, protocol_fee_rate),
+            ];
+            for fill in fills {
+                let err = fill.unwrap_err();
+                match err.downcast_ref() {
+                    Some(DlmmError::InvalidFeeRate { which, value }) => {
+                        assert_eq!(*which, expected);
+                        assert_eq!(
+                            *value,
+                            if expected == "fee_rate" {
+                                fee_rate
+                            } else {
+                                protocol_fee_rate
+                            }
+                        );
+                    }
+                    _ => panic!("{err:#}"),
+                }
+            }
+        };
+        rejects(FEE_PRECISION, 0, "fee_rate");
+        rejects(FEE_PRECISION + 1, 0, "fee_rate");
+        rejects(3_000_000, FEE_PRECISION + 1, "protocol_fee_rate");
+        // The fee rate is checked first.
+        rejects(FEE_PRECISION, FEE_PRECISION + 1, "fee_rate");
+
+        // The protocol may take the whole fee.
+        for fee_rate in [0, 3_000_000, FEE_PRECISION - 1] {
+            let (_, _, fee, protocol_fee) = bin
+                .quote_exact_amount_in(1_000, true, fee_rate, FEE_PRECISION)
+                .unwrap();
+            assert_eq!(protocol_fee, fee);
+            let (_, _, fee, protocol_fee) = bin
+                .quote_exact_amount_out(1_000, true, fee_rate, FEE_PRECISION)
+                .unwrap();
+            assert_eq!(protocol_fee, fee);
+        }
+        let err = bin
+            .max_exact_amount_in_u128(true, FEE_PRECISION)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(DlmmError::InvalidFeeRate {
+                which: "fee_rate",
+                ..
+            })
+        ));
+        assert_eq!(
+            DlmmError::InvalidFeeRate {
+                which: "fee_rate",
+                value: 7
+            }
+            .to_string(),
+            "Invalid fee_rate: 7"
+        );
+    }
 }
@@ -0,0 +1,32 @@
+//! Quotes a swap against a pool JSON snapshot; see [`cetus_swap_sdk::cli`].
+
+use std::process::ExitCode;
+
+use cetus_swap_sdk::cli::{self, QuoteArgs};
+
+fn main() -> ExitCode {
+    let args = match QuoteArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(reason) => {
+            eprintln!("{reason}\n{}", cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let pool_json = match std::fs::read_to_string(&args.pool_path) {
+        Ok(json) => json,
+        Err(err) => {
+            eprintln!("error: {}: {err}", args.pool_path);
+            return ExitCode::from(2);
+        }
+    };
+    match cli::run(&pool_json, &args) {
+        Ok(output) => {
+            println!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}: {err}", err.name());
+            ExitCode::FAILURE
+        }
+    }
+}
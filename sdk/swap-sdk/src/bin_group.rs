@@ -0,0 +1,291 @@
+//! Packed bin groups as stored under the bin manager's dynamic fields.
+//!
+//! On chain, bins are grouped [`BINS_PER_GROUP`] at a time. A bin's position
+//! is derived from its score `id + BIN_BOUND`: the group index is
+//! `score / BINS_PER_GROUP` and the slot within the group is the remainder.
+//! Unused slots are present but empty.
+
+use alloc::vec::Vec;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bin::Bin,
+    math::price_math::{get_price_from_id, BIN_BOUND},
+    pool::Pool,
+};
+
+/// Number of bin slots in a group (`constants::max_bin_per_group`).
+pub const BINS_PER_GROUP: usize = 16;
+
+/// A single slot of a [`BinGroup`]. The bin id is implied by the slot.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BinGroupEntry {
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub price: u128,
+    pub liquidity_supply: u128,
+    pub rewards_growth_global: Vec<u128>,
+    pub fee_amount_a_growth_global: u128,
+    pub fee_amount_b_growth_global: u128,
+}
+
+impl BinGroupEntry {
+    pub fn is_empty(&self) -> bool {
+        self.amount_a == 0 && self.amount_b == 0 && self.liquidity_supply == 0
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BinGroup {
+    pub idx: u32,
+    pub bin_step: u16,
+    /// Slots in order; at most [`BINS_PER_GROUP`] entries are read.
+    pub entries: Vec<BinGroupEntry>,
+}
+
+impl BinGroup {
+    /// Id of the bin stored in `offset` of group `idx`.
+    pub fn bin_id(idx: u32, offset: usize) -> i32 {
+        (idx as i64 * BINS_PER_GROUP as i64 + offset as i64 - BIN_BOUND as i64) as i32
+    }
+
+    /// Group index and slot of `bin_id`.
+    pub fn position_of(bin_id: i32) -> (u32, usize) {
+        let score = (bin_id as i64 + BIN_BOUND as i64) as u64;
+        (
+            (score / BINS_PER_GROUP as u64) as u32,
+            (score % BINS_PER_GROUP as u64) as usize,
+        )
+    }
+
+    /// Inclusive range of bin ids covered by this group.
+    pub fn id_range(&self) -> (i32, i32) {
+        (
+            Self::bin_id(self.idx, 0),
+            Self::bin_id(self.idx, BINS_PER_GROUP - 1),
+        )
+    }
+
+    /// Expands the non-empty slots into bins sorted by id. Entries without a
+    /// price get one derived from the id and `bin_step`, failing with the
+    /// error of [`get_price_from_id`] if there is none.
+    pub fn to_bins(&self) -> Result<Vec<Bin>, Error> {
+        self.entries
+            .iter()
+            .take(BINS_PER_GROUP)
+            .enumerate()
+            .filter(|(_, entry)| !entry.is_empty())
+            .map(|(offset, entry)| {
+                let id = Self::bin_id(self.idx, offset);
+                let price = if entry.price == 0 {
+                    get_price_from_id(id, self.bin_step)?
+                } else {
+                    entry.price
+                };
+                Ok(Bin {
+                    id,
+                    amount_a: entry.amount_a,
+                    amount_b: entry.amount_b,
+                    price: price.into(),
+                    liquidity_supply: entry.liquidity_supply,
+                    rewards_growth_global: entry.rewards_growth_global.clone(),
+                    fee_amount_a_growth_global: entry.fee_amount_a_growth_global,
+                    fee_amount_b_growth_global: entry.fee_amount_b_growth_global,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Pool {
+    /// Merges bin groups into `bins`, keeping them sorted by id.
+    ///
+    /// A group replaces everything previously loaded in its id range, so
+    /// re-fetching a group updates changed bins and drops bins that have
+    /// since been emptied. If the same group index appears more than once,
+    /// the last occurrence wins.
+    ///
+    /// Fails, leaving the pool unchanged, if a group has a bin whose price
+    /// cannot be derived, see [`BinGroup::to_bins`].
+    pub fn extend_from_bin_groups(
+        &mut self,
+        groups: impl IntoIterator<Item = BinGroup>,
+    ) -> Result<(), Error> {
+        let mut groups: Vec<BinGroup> = groups.into_iter().collect();
+        groups.reverse();
+        groups.sort_by_key(|group| group.idx);
+        groups.dedup_by_key(|group| group.idx);
+        if groups.is_empty() {
+            return Ok(());
+        }
+        let mut incoming = Vec::with_capacity(groups.len() * BINS_PER_GROUP);
+        for group in &groups {
+            incoming.extend(group.to_bins()?);
+        }
+
+        let ranges: Vec<(i32, i32)> = groups.iter().map(BinGroup::id_range).collect();
+        self.bins.retain(|bin| {
+            let i = ranges.partition_point(|(_, hi)| *hi < bin.id);
+            i == ranges.len() || ranges[i].0 > bin.id
+        });
+
+        let existing = core::mem::take(&mut self.bins);
+        let mut merged = Vec::with_capacity(existing.len() + groups.len() * BINS_PER_GROUP);
+        let mut existing = existing.into_iter().peekable();
+        for bin in incoming {
+            while let Some(prev) = existing.next_if(|prev| prev.id < bin.id) {
+                merged.push(prev);
+            }
+            merged.push(bin);
+        }
+        merged.extend(existing);
+        self.bins = merged;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BinStepConfig, VariableParameters};
+    use crate::error::DlmmError;
+
+    fn entry(amount_a: u64, amount_b: u64) -> BinGroupEntry {
+        BinGroupEntry {
+            amount_a,
+            amount_b,
+            price: 0,
+            liquidity_supply: (amount_a + amount_b) as u128,
+            ..Default::default()
+        }
+    }
+
+    fn group(idx: u32, slots: &[(usize, u64, u64)]) -> BinGroup {
+        let mut entries = vec![BinGroupEntry::default(); BINS_PER_GROUP];
+        for &(offset, amount_a, amount_b) in slots {
+            entries[offset] = entry(amount_a, amount_b);
+        }
+        BinGroup {
+            idx,
+            bin_step: 25,
+            entries,
+        }
+    }
+
+    fn empty_pool() -> Pool {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 30_000);
+        Pool::new(0, 30_000, VariableParameters::new(config, 0, 0), vec![])
+    }
+
+    fn ids(pool: &Pool) -> Vec<i32> {
+        pool.bins.iter().map(|bin| bin.id).collect()
+    }
+
+    #[test]
+    fn group_positions_round_trip() {
+        for id in [
+            -(BIN_BOUND as i32),
+            -17,
+            -16,
+            -1,
+            0,
+            1,
+            15,
+            16,
+            BIN_BOUND as i32,
+        ] {
+            let (idx, offset) = BinGroup::position_of(id);
+            assert_eq!(BinGroup::bin_id(idx, offset), id);
+        }
+        let (idx, offset) = BinGroup::position_of(0);
+        assert_eq!(offset, BIN_BOUND as usize % BINS_PER_GROUP);
+        assert_eq!(BinGroup::bin_id(idx, 0), -(offset as i32));
+    }
+
+    #[test]
+    fn to_bins_drops_empty_slots() {
+        let (idx, offset) = BinGroup::position_of(0);
+        let group = group(idx, &[(offset, 10, 20), (offset + 2, 0, 5)]);
+        let bins = group.to_bins().unwrap();
+        assert_eq!(bins.len(), 2);
+        assert_eq!(
+            (bins[0].id, bins[0].amount_a, bins[0].amount_b),
+            (0, 10, 20)
+        );
+        assert_eq!(bins[0].price.as_raw(), 1 << 64);
+        assert_eq!(bins[1].id, 2);
+        assert_eq!(bins[1].price.as_raw(), get_price_from_id(2, 25).unwrap());
+    }
+
+    #[test]
+    fn underivable_prices_are_errors() {
+        let (idx, offset) = BinGroup::position_of(crate::MAX_BIN_ID);
+        let past_the_bound = group(idx, &[(offset + 1, 1, 0)]);
+        let err = past_the_bound.to_bins().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DlmmError>(),
+            Some(DlmmError::InvalidBinId)
+        ));
+
+        let (idx, _) = BinGroup::position_of(0);
+        let mut pool = empty_pool();
+        pool.extend_from_bin_groups(vec![group(idx, &[(1, 1, 1)])])
+            .unwrap();
+        let before = pool.clone();
+        assert!(pool
+            .extend_from_bin_groups(vec![group(idx, &[(2, 2, 2)]), past_the_bound])
+            .is_err());
+        assert_eq!(pool, before);
+    }
+
+    #[test]
+    fn out_of_order_groups_are_sorted() {
+        let (idx, _) = BinGroup::position_of(0);
+        let mut pool = empty_pool();
+        pool.extend_from_bin_groups(vec![
+            group(idx + 1, &[(0, 1, 0), (15, 1, 0)]),
+            group(idx - 1, &[(3, 0, 1)]),
+            group(idx, &[(7, 1, 1)]),
+        ])
+        .unwrap();
+        let expected = vec![
+            BinGroup::bin_id(idx - 1, 3),
+            BinGroup::bin_id(idx, 7),
+            BinGroup::bin_id(idx + 1, 0),
+            BinGroup::bin_id(idx + 1, 15),
+        ];
+        assert_eq!(ids(&pool), expected);
+        assert!(pool.validate().is_ok());
+    }
+
+    #[test]
+    fn refetched_group_replaces_overlapping_bins() {
+        let (idx, _) = BinGroup::position_of(0);
+        let mut pool = empty_pool();
+        pool.extend_from_bin_groups(vec![
+            group(idx - 1, &[(15, 5, 5)]),
+            group(idx, &[(1, 1, 1), (2, 2, 2), (3, 3, 3)]),
+            group(idx + 1, &[(0, 7, 7)]),
+        ])
+        .unwrap();
+        assert_eq!(pool.bins.len(), 5);
+
+        pool.extend_from_bin_groups(vec![
+            group(idx, &[(1, 9, 9), (4, 4, 4)]),
+            group(idx, &[(2, 8, 8), (4, 4, 4)]),
+        ])
+        .unwrap();
+        let expected = vec![
+            BinGroup::bin_id(idx - 1, 15),
+            BinGroup::bin_id(idx, 2),
+            BinGroup::bin_id(idx, 4),
+            BinGroup::bin_id(idx + 1, 0),
+        ];
+        assert_eq!(ids(&pool), expected);
+        assert_eq!(pool.bins[1].amount_a, 8);
+        assert_eq!(pool.bins[0].amount_a, 5);
+        assert_eq!(pool.bins[3].amount_a, 7);
+    }
+}
@@ -0,0 +1,262 @@
+//! [`PoolBuilder`], a shorter way to set up a [`Pool`] for tests and tools.
+
+use alloc::{collections::BTreeMap, format, vec::Vec};
+
+use crate::{
+    bin::Bin,
+    config::{BinStepConfig, VariableParameters},
+    error::DlmmError,
+    fee_rate::FeeRate,
+    math::{price_math::get_price_from_id, q64x64_math::SCALE_OFFSET},
+    pool::Pool,
+};
+
+/// Builds a [`Pool`] from a bin step and per-bin reserves, deriving each
+/// bin's price from its id.
+///
+/// The default is bin step 10 with a static fee: no variable fee, base fee
+/// rate or protocol fee, active id 0 and timestamp 0.
+///
+/// ```
+/// use cetus_swap_sdk::{FeeRate, PoolBuilder};
+///
+/// let pool = PoolBuilder::new()
+///     .bin_step(25)
+///     .base_fee_rate(FeeRate::from_bps(25))
+///     .active_id(100)
+///     .uniform_liquidity(90, 110, 1_000_000, 1_000_000)
+///     .build()
+///     .unwrap();
+/// assert_eq!(pool.bins().len(), 21);
+/// assert_eq!(pool.base_fee_rate, 2_500_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PoolBuilder {
+    config: BinStepConfig,
+    base_fee_rate: u64,
+    active_id: i32,
+    timestamp: u64,
+    liquidity: Vec<Liquidity>,
+}
+
+#[derive(Debug, Clone)]
+enum Liquidity {
+    Bin {
+        id: i32,
+        amount_a: u64,
+        amount_b: u64,
+    },
+    Uniform {
+        lo: i32,
+        hi: i32,
+        amount_a: u64,
+        amount_b: u64,
+    },
+}
+
+impl Default for PoolBuilder {
+    fn default() -> Self {
+        Self {
+            config: BinStepConfig::new(10, 0, 0, 0, 0, 0, 0, 0),
+            base_fee_rate: 0,
+            active_id: 0,
+            timestamp: 0,
+            liquidity: Vec::new(),
+        }
+    }
+}
+
+impl PoolBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the bin step, keeping the other config parameters.
+    pub fn bin_step(mut self, bin_step: u16) -> Self {
+        self.config.bin_step = bin_step;
+        self
+    }
+
+    /// Replaces the whole config, bin step included.
+    pub fn config(mut self, config: BinStepConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Also accepts a raw `u64` in [`FEE_PRECISION`](crate::FEE_PRECISION)
+    /// units, see [`FeeRate`].
+    pub fn base_fee_rate(mut self, base_fee_rate: impl Into<FeeRate>) -> Self {
+        self.base_fee_rate = base_fee_rate.into().as_raw();
+        self
+    }
+
+    /// Sets the active id, which is also the volatility index reference.
+    pub fn active_id(mut self, active_id: i32) -> Self {
+        self.active_id = active_id;
+        self
+    }
+
+    /// Sets the last update timestamp, in seconds.
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Adds a bin holding exactly these reserves, replacing any earlier
+    /// reserves for `id`.
+    pub fn bin(mut self, id: i32, amount_a: u64, amount_b: u64) -> Self {
+        self.liquidity.push(Liquidity::Bin {
+            id,
+            amount_a,
+            amount_b,
+        });
+        self
+    }
+
+    /// Adds the bins `lo..=hi` as a pool would hold them: bins below the
+    /// active id get `amount_b` of token B, bins above it `amount_a` of
+    /// token A and the active bin both. The side is decided in
+    /// [`build`](Self::build), so the active id may be set later. Replaces
+    /// any earlier reserves for these ids.
+    pub fn uniform_liquidity(mut self, lo: i32, hi: i32, amount_a: u64, amount_b: u64) -> Self {
+        self.liquidity.push(Liquidity::Uniform {
+            lo,
+            hi,
+            amount_a,
+            amount_b,
+        });
+        self
+    }
+
+    /// Builds the pool and checks it with [`BinStepConfig::new_checked`] and
+    /// [`Pool::validate`]. Each bin's `liquidity_supply` is its value in
+    /// token B.
+    ///
+    /// Fails with [`DlmmError::InvalidInput`] for a range with `lo > hi`,
+    /// [`DlmmError::InvalidBinId`] for an id out of range,
+    /// [`DlmmError::InvalidConfig`] for an invalid config and
+    /// [`DlmmError::InvalidLayout`] for other validation failures.
+    pub fn build(self) -> Result<Pool, DlmmError> {
+        let config = &self.config;
+        let config = BinStepConfig::new_checked(
+            config.bin_step,
+            config.base_factor,
+            config.filter_period,
+            config.decay_period,
+            config.reduction_factor,
+            config.variable_fee_control,
+            config.max_volatility_accumulator,
+            config.protocol_fee_rate,
+        )?;
+
+        let mut reserves = BTreeMap::new();
+        for liquidity in self.liquidity {
+            match liquidity {
+                Liquidity::Bin {
+                    id,
+                    amount_a,
+                    amount_b,
+                } => {
+                    reserves.insert(id, (amount_a, amount_b));
+                }
+                Liquidity::Uniform {
+                    lo,
+                    hi,
+                    amount_a,
+                    amount_b,
+                } => {
+                    if lo > hi {
+                        return Err(DlmmError::InvalidInput);
+                    }
+                    for id in lo..=hi {
+                        let amount_a = if id < self.active_id { 0 } else { amount_a };
+                        let amount_b = if id > self.active_id { 0 } else { amount_b };
+                        reserves.insert(id, (amount_a, amount_b));
+                    }
+                }
+            }
+        }
+        let bins = reserves
+            .into_iter()
+            .map(|(id, (amount_a, amount_b))| {
+                let price = get_price_from_id(id, config.bin_step)?;
+                Ok(Bin {
+                    id,
+                    amount_a,
+                    amount_b,
+                    price: price.into(),
+                    liquidity_supply: ((amount_a as u128 * price) >> SCALE_OFFSET)
+                        + amount_b as u128,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, DlmmError>>()?;
+
+        let v_parameters = VariableParameters::new(config, self.active_id, self.timestamp);
+        let pool = Pool::new(self.active_id, self.base_fee_rate, v_parameters, bins);
+        pool.validate().map_err(|err| {
+            err.downcast::<DlmmError>()
+                .unwrap_or_else(|err| DlmmError::InvalidLayout(format!("{err:#}")))
+        })?;
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{price::PriceQ64, MAX_FEE_RATE};
+
+    #[test]
+    fn bins_are_priced_and_filled_by_side() {
+        let pool = PoolBuilder::new()
+            .bin_step(25)
+            .active_id(2)
+            .timestamp(1_700_000_000)
+            .uniform_liquidity(0, 4, 100, 200)
+            .bin(3, 7, 0)
+            .bin(-1, 0, 5)
+            .build()
+            .unwrap();
+
+        let reserves: Vec<_> = pool
+            .bins
+            .iter()
+            .map(|bin| (bin.id, bin.amount_a, bin.amount_b))
+            .collect();
+        assert_eq!(
+            reserves,
+            [
+                (-1, 0, 5),
+                (0, 0, 200),
+                (1, 0, 200),
+                (2, 100, 200),
+                (3, 7, 0),
+                (4, 100, 0)
+            ]
+        );
+        for bin in &pool.bins {
+            assert_eq!(bin.price.as_raw(), get_price_from_id(bin.id, 25).unwrap());
+        }
+        assert_eq!(pool.get_bin(0).unwrap().price, PriceQ64::ONE);
+        assert_eq!(pool.get_bin(0).unwrap().liquidity_supply, 200);
+        assert_eq!(pool.v_parameters.index_reference, 2);
+        assert_eq!(pool.v_parameters.last_update_timestamp, 1_700_000_000);
+        assert_eq!(pool.v_parameters.bin_step_config.bin_step, 25);
+    }
+
+    #[test]
+    fn invalid_pools_are_rejected() {
+        let err = PoolBuilder::new().uniform_liquidity(3, 2, 1, 1).build();
+        assert!(matches!(err, Err(DlmmError::InvalidInput)));
+        let err = PoolBuilder::new().bin(crate::MAX_BIN_ID + 1, 1, 1).build();
+        assert!(matches!(err, Err(DlmmError::InvalidBinId)));
+        let mut config = BinStepConfig::new(10, 0, 0, 0, 0, 0, 0, 0);
+        config.filter_period = 1;
+        let err = PoolBuilder::new().config(config).build();
+        assert!(matches!(err, Err(DlmmError::InvalidConfig(_))));
+        let err = PoolBuilder::new().base_fee_rate(MAX_FEE_RATE + 1).build();
+        assert!(matches!(err, Err(DlmmError::InvalidLayout(_))));
+        assert!(PoolBuilder::new().build().unwrap().bins.is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! The `dlmm-quote` command (`cli` feature): quotes a swap against a pool
+//! JSON snapshot, for debugging quote discrepancies without writing a
+//! program.
+//!
+//! ```text
+//! dlmm-quote <pool.json> --amount <n> [--a2b] [--exact-out] [--timestamp <secs>] [--json]
+//! ```
+//!
+//! Without `--timestamp` the quote is taken at the pool's
+//! `last_update_timestamp`, i.e. without volatility decay.
+
+use crate::{error::DlmmError, pool::Pool, pool::SwapResult, swappable::Swappable, Timestamp};
+
+pub const USAGE: &str =
+    "usage: dlmm-quote <pool.json> --amount <n> [--a2b] [--exact-out] [--timestamp <secs>] [--json]";
+
+/// Parsed command line of `dlmm-quote`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteArgs {
+    pub pool_path: String,
+    pub amount: u64,
+    pub a2b: bool,
+    pub exact_out: bool,
+    /// In seconds; `None` quotes at the pool's last update.
+    pub timestamp: Option<u64>,
+    /// Print the full [`SwapResult`] as JSON instead of the summary.
+    pub json: bool,
+}
+
+impl QuoteArgs {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut pool_path = None;
+        let mut amount = None;
+        let mut a2b = false;
+        let mut exact_out = false;
+        let mut timestamp = None;
+        let mut json = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--amount" => amount = Some(number(&arg, args.next())?),
+                "--timestamp" => timestamp = Some(number(&arg, args.next())?),
+                "--a2b" => a2b = true,
+                "--exact-out" => exact_out = true,
+                "--json" => json = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag {flag}")),
+                _ if pool_path.is_some() => return Err(format!("unexpected argument {arg}")),
+                _ => pool_path = Some(arg),
+            }
+        }
+        Ok(Self {
+            pool_path: pool_path.ok_or("missing pool file")?,
+            amount: amount.ok_or("missing --amount")?,
+            a2b,
+            exact_out,
+            timestamp,
+            json,
+        })
+    }
+}
+
+fn number(flag: &str, value: Option<String>) -> Result<u64, String> {
+    let value = value.ok_or_else(|| format!("{flag} needs a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("{flag}: {value} is not a non-negative integer"))
+}
+
+/// Loads and validates a pool from its JSON representation, upgrading
+/// snapshots written by older versions (see [`Pool::migrate`]).
+pub fn load_pool(json: &str) -> Result<Pool, DlmmError> {
+    let pool = Pool::migrate_str(json).map_err(|e| DlmmError::InvalidLayout(format!("{e:#}")))?;
+    pool.validate().map_err(|err| {
+        err.downcast::<DlmmError>()
+            .unwrap_or_else(|err| DlmmError::InvalidLayout(format!("{err:#}")))
+    })?;
+    Ok(pool)
+}
+
+/// Runs the quote described by `args` on `pool`.
+pub fn quote(pool: &Pool, args: &QuoteArgs) -> Result<SwapResult, DlmmError> {
+    let timestamp = Timestamp::from_seconds(
+        args.timestamp
+            .unwrap_or(pool.v_parameters.last_update_timestamp),
+    );
+    if args.exact_out {
+        pool.quote_exact_out(args.amount, args.a2b, timestamp)
+    } else {
+        pool.quote_exact_in(args.amount, args.a2b, timestamp)
+    }
+}
+
+/// Formats `result` as the summary line followed by one line per step, or
+/// as pretty-printed JSON.
+pub fn render(result: &SwapResult, json: bool) -> String {
+    if json {
+        return serde_json::to_string_pretty(result).expect("SwapResult serializes to JSON");
+    }
+    let mut out = result.to_string();
+    for step in &result.steps {
+        out.push_str("\n  ");
+        out.push_str(&step.to_string());
+    }
+    out
+}
+
+/// Quotes against the pool in `pool_json` and renders the result; the body
+/// of `dlmm-quote` once the file is read.
+pub fn run(pool_json: &str, args: &QuoteArgs) -> Result<String, DlmmError> {
+    let pool = load_pool(pool_json)?;
+    Ok(render(&quote(&pool, args)?, args.json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<QuoteArgs, String> {
+        QuoteArgs::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_flags_in_any_order() {
+        assert_eq!(
+            parse("--json --amount 1000 pool.json --exact-out --a2b --timestamp 17").unwrap(),
+            QuoteArgs {
+                pool_path: "pool.json".into(),
+                amount: 1_000,
+                a2b: true,
+                exact_out: true,
+                timestamp: Some(17),
+                json: true,
+            }
+        );
+        assert_eq!(
+            parse("pool.json --amount 5").unwrap(),
+            QuoteArgs {
+                pool_path: "pool.json".into(),
+                amount: 5,
+                a2b: false,
+                exact_out: false,
+                timestamp: None,
+                json: false,
+            }
+        );
+        assert_eq!(parse("--amount 5").unwrap_err(), "missing pool file");
+        assert_eq!(parse("pool.json").unwrap_err(), "missing --amount");
+        assert!(parse("pool.json --amount").is_err());
+        assert!(parse("pool.json --amount -5").is_err());
+        assert!(parse("pool.json --amount 5 --fast").is_err());
+        assert!(parse("a.json b.json --amount 5").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! A rough on-chain execution cost estimate for a quoted swap, for deciding
+//! whether a route is worth sending.
+//!
+//! The cost of a DLMM swap grows with the bins it crosses and with the bin
+//! groups it loads, since each group is a separate dynamic field (see
+//! [`bin_group`](crate::bin_group)). [`estimate_compute`] charges
+//! a fixed overhead, a cost per step and a cost per group boundary crossed
+//! between consecutive steps. The coefficients live in [`ComputeProfile`],
+//! which integrators can fill from their own transactions without a new
+//! release.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{bin_group::BinGroup, pool::SwapResult};
+
+/// Cost coefficients, in compute units.
+///
+/// The defaults are unmeasured placeholders: they were not fitted to any
+/// gas report and only encode that steps and group boundaries add cost.
+/// Fit the coefficients to the gas reports of your own swaps before relying
+/// on the estimates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComputeProfile {
+    /// Charged once per swap: the pool, config and volatility updates.
+    pub fixed_overhead: u64,
+    /// Charged per step, i.e. per bin filled.
+    pub per_step: u64,
+    /// Charged each time consecutive steps fall in different bin groups.
+    pub per_group_boundary: u64,
+}
+
+impl Default for ComputeProfile {
+    fn default() -> Self {
+        Self {
+            fixed_overhead: 20_000,
+            per_step: 2_500,
+            per_group_boundary: 6_000,
+        }
+    }
+}
+
+/// Number of bin group boundaries crossed between consecutive steps of
+/// `result`.
+pub fn group_boundaries(result: &SwapResult) -> usize {
+    result
+        .steps
+        .windows(2)
+        .filter(|pair| {
+            BinGroup::position_of(pair[0].bin_id).0 != BinGroup::position_of(pair[1].bin_id).0
+        })
+        .count()
+}
+
+/// Estimated compute units to execute `result` on chain; see the
+/// [module docs](self). Saturates rather than overflowing.
+pub fn estimate_compute(result: &SwapResult, profile: &ComputeProfile) -> u64 {
+    profile
+        .fixed_overhead
+        .saturating_add(profile.per_step.saturating_mul(result.steps.len() as u64))
+        .saturating_add(
+            profile
+                .per_group_boundary
+                .saturating_mul(group_boundaries(result) as u64),
+        )
+}
+
+impl SwapResult {
+    /// [`estimate_compute`] with the default [`ComputeProfile`], whose
+    /// coefficients are unmeasured placeholders.
+    pub fn estimated_compute_units(&self) -> u64 {
+        estimate_compute(self, &ComputeProfile::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bin_group::BINS_PER_GROUP, pool::BinSwap};
+
+    fn result(bin_ids: impl IntoIterator<Item = i32>) -> SwapResult {
+        SwapResult {
+            steps: bin_ids
+                .into_iter()
+                .map(|bin_id| BinSwap {
+                    bin_id,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn estimate_grows_with_steps() {
+        let profile = ComputeProfile::default();
+        let mut previous = estimate_compute(&result([]), &profile);
+        assert_eq!(previous, profile.fixed_overhead);
+        for len in 1..=40 {
+            let estimate = estimate_compute(&result(0..len), &profile);
+            assert!(estimate > previous, "{len} steps");
+            previous = estimate;
+        }
+    }
+
+    #[test]
+    fn group_boundaries_are_counted_where_steps_straddle_an_edge() {
+        // First id of a group, derived from the on-chain layout.
+        let (idx, _) = BinGroup::position_of(0);
+        let edge = BinGroup::bin_id(idx + 1, 0);
+        let group = BINS_PER_GROUP as i32;
+
+        assert_eq!(group_boundaries(&result([edge - 1])), 0);
+        assert_eq!(group_boundaries(&result(edge - group..edge)), 0);
+        assert_eq!(group_boundaries(&result([edge - 1, edge])), 1);
+        // Swapping down crosses the same edge.
+        assert_eq!(group_boundaries(&result([edge + 1, edge, edge - 1])), 1);
+        assert_eq!(group_boundaries(&result(edge - 1..edge + 2 * group)), 2);
+
+        let profile = ComputeProfile {
+            fixed_overhead: 100,
+            per_step: 10,
+            per_group_boundary: 1_000,
+        };
+        assert_eq!(
+            estimate_compute(&result([edge - 2, edge - 1]), &profile),
+            120
+        );
+        assert_eq!(estimate_compute(&result([edge - 1, edge]), &profile), 1_120);
+        assert_eq!(
+            result([edge - 1, edge]).estimated_compute_units(),
+            estimate_compute(&result([edge - 1, edge]), &ComputeProfile::default())
+        );
+    }
+}
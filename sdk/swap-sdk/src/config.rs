@@ -1,6 +1,16 @@
+use alloc::format;
+
+use anyhow::{Context, Error};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::{error::DlmmError, math::BASIS_POINT_MAX, FEE_PRECISION, MAX_FEE_RATE};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct BinStepConfig {
     pub bin_step: u16,
     pub base_factor: u16,
@@ -9,10 +19,19 @@ pub struct BinStepConfig {
     pub reduction_factor: u16,
     pub variable_fee_control: u32,
     pub max_volatility_accumulator: u32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub protocol_fee_rate: u64,
 }
 
 impl BinStepConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bin_step: u16,
         base_factor: u16,
@@ -34,13 +53,115 @@ impl BinStepConfig {
             protocol_fee_rate,
         }
     }
+
+    /// [`BinStepConfig::new`], rejecting parameters the swap math cannot
+    /// work with:
+    ///
+    /// - `reduction_factor` above `BASIS_POINT_MAX`, which would grow the
+    ///   volatility reference instead of decaying it
+    /// - `filter_period` above `decay_period`
+    /// - `protocol_fee_rate` above [`FEE_PRECISION`]
+    /// - a variable fee rate that does not fit in a `u64` at
+    ///   `max_volatility_accumulator`, the largest accumulator a swap can
+    ///   reach; swap steps report the rate as a `u64`
+    ///
+    /// Fails with [`DlmmError::InvalidConfig`] naming the parameter.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_checked(
+        bin_step: u16,
+        base_factor: u16,
+        filter_period: u16,
+        decay_period: u16,
+        reduction_factor: u16,
+        variable_fee_control: u32,
+        max_volatility_accumulator: u32,
+        protocol_fee_rate: u64,
+    ) -> Result<Self, DlmmError> {
+        let config = Self::new(
+            bin_step,
+            base_factor,
+            filter_period,
+            decay_period,
+            reduction_factor,
+            variable_fee_control,
+            max_volatility_accumulator,
+            protocol_fee_rate,
+        );
+        if reduction_factor as u64 > BASIS_POINT_MAX as u64 {
+            return Err(DlmmError::InvalidConfig(format!(
+                "reduction_factor {reduction_factor} exceeds {BASIS_POINT_MAX}"
+            )));
+        }
+        if filter_period > decay_period {
+            return Err(DlmmError::InvalidConfig(format!(
+                "filter_period {filter_period} exceeds decay_period {decay_period}"
+            )));
+        }
+        if protocol_fee_rate > FEE_PRECISION {
+            return Err(DlmmError::InvalidConfig(format!(
+                "protocol_fee_rate {protocol_fee_rate} exceeds FEE_PRECISION"
+            )));
+        }
+        let max_variable_fee = config
+            .variable_fee(max_volatility_accumulator)
+            .ok()
+            .and_then(|fee| u64::try_from(fee).ok());
+        if max_variable_fee.is_none() {
+            return Err(DlmmError::InvalidConfig(format!(
+                "variable fee overflows at max_volatility_accumulator {max_volatility_accumulator}"
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Variable fee rate for `volatility_accumulator`:
+    /// `variable_fee_control * (volatility_accumulator * bin_step)^2`,
+    /// scaled down by `10^11` and rounded up.
+    fn variable_fee(&self, volatility_accumulator: u32) -> Result<u128, Error> {
+        if self.variable_fee_control == 0 {
+            return Ok(0);
+        }
+        let va = volatility_accumulator as u128;
+        let bin_step = self.bin_step as u128;
+        let variable_fee_control = self.variable_fee_control as u128;
+
+        let combined = va.checked_mul(bin_step).context("variable fee overflow")?;
+        let square = combined
+            .checked_mul(combined)
+            .context("variable fee overflow")?;
+
+        let v_fee = variable_fee_control
+            .checked_mul(square)
+            .context("variable fee overflow")?;
+
+        v_fee
+            .checked_add(99_999_999_999)
+            .context("variable fee overflow")?
+            .checked_div(100_000_000_000)
+            .context("variable fee overflow")
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct VariableParameters {
+    #[serde(default)]
     pub volatility_accumulator: u32,
+    #[serde(default)]
     pub volatility_reference: u32,
     pub index_reference: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub last_update_timestamp: u64,
     pub bin_step_config: BinStepConfig,
 }
@@ -55,4 +176,271 @@ impl VariableParameters {
             bin_step_config,
         }
     }
+
+    /// Refreshes the volatility references at the start of a swap, based on
+    /// the time elapsed since the last update.
+    pub fn update_references(
+        &mut self,
+        active_id: i32,
+        current_timestamp: i64,
+    ) -> Result<(), Error> {
+        let s_params = &self.bin_step_config;
+        let last = self.last_update_timestamp as i64;
+
+        if current_timestamp <= last {
+            return Ok(());
+        }
+
+        let elapsed = current_timestamp - last;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            active_id,
+            elapsed,
+            volatility_accumulator = self.volatility_accumulator,
+            "update references"
+        );
+
+        if elapsed >= s_params.filter_period as i64 {
+            self.index_reference = active_id;
+
+            if elapsed < s_params.decay_period as i64 {
+                let scaled = u64::from(self.volatility_accumulator)
+                    .checked_mul(s_params.reduction_factor as u64)
+                    .context("volatility reference overflow")?
+                    .checked_div(BASIS_POINT_MAX as u64)
+                    .context("volatility reference overflow")?;
+                self.volatility_reference = scaled as u32;
+            } else {
+                self.volatility_reference = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies [`update_references`](Self::update_references) for a touch
+    /// at `current_timestamp` and records it as the last update, as every
+    /// swap does before crossing its first bin.
+    pub fn refresh(&mut self, active_id: i32, current_timestamp: u64) -> Result<(), Error> {
+        self.update_references(active_id, current_timestamp as i64)?;
+        self.last_update_timestamp = current_timestamp;
+        Ok(())
+    }
+
+    /// Updates the accumulator for `active_id`, called as each bin is crossed.
+    pub fn update_volatility_accumulator(&mut self, active_id: i32) -> Result<(), Error> {
+        let max_accumulator = self.bin_step_config.max_volatility_accumulator;
+        let delta_id = (self.index_reference as i64 - active_id as i64).unsigned_abs();
+
+        let accumulator = u64::from(self.volatility_reference)
+            .checked_add(
+                delta_id
+                    .checked_mul(BASIS_POINT_MAX as u64)
+                    .context("volatility accumulator overflow")?,
+            )
+            .context("volatility accumulator overflow")?;
+
+        let capped = accumulator.min(max_accumulator as u64);
+        self.volatility_accumulator = capped as u32;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            active_id,
+            delta_id,
+            volatility_accumulator = self.volatility_accumulator,
+            "update volatility accumulator"
+        );
+        Ok(())
+    }
+
+    /// The parameters the first bin of a swap at `future_timestamp` would be
+    /// priced with: [`update_references`](Self::update_references) followed
+    /// by [`update_volatility_accumulator`](Self::update_volatility_accumulator)
+    /// on a copy. `last_update_timestamp` is left as is, as it is only
+    /// advanced once the swap completes.
+    pub fn preview_at(&self, active_id: i32, future_timestamp: u64) -> VariableParameters {
+        let mut preview = self.clone();
+        // Neither step can overflow: the reference is a u32 scaled by a
+        // u16 factor and the accumulator a u32 plus an id distance times
+        // BASIS_POINT_MAX, all well within u64.
+        preview
+            .update_references(active_id, future_timestamp as i64)
+            .expect("volatility reference fits in u64");
+        preview
+            .update_volatility_accumulator(active_id)
+            .expect("volatility accumulator fits in u64");
+        preview
+    }
+
+    pub fn get_variable_fee(&self) -> Result<u128, Error> {
+        self.bin_step_config
+            .variable_fee(self.volatility_accumulator)
+    }
+
+    /// Returns `(total_fee_rate, variable_fee_rate)`; the total is capped at
+    /// [`MAX_FEE_RATE`].
+    pub fn get_total_fee(&self, base_fee_rate: u64) -> Result<(u64, u64), Error> {
+        let variable_fee = self.get_variable_fee()?;
+        let total_fee_rate = (base_fee_rate as u128)
+            .checked_add(variable_fee)
+            .context("total fee overflow")?;
+        let capped = total_fee_rate.min(MAX_FEE_RATE.into());
+        Ok((capped as u64, variable_fee as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> VariableParameters {
+        let config = BinStepConfig::new(25, 1, 30, 600, 5_000, 40_000, 350_000, 0);
+        VariableParameters {
+            volatility_accumulator: 120_000,
+            volatility_reference: 40_000,
+            index_reference: 3,
+            last_update_timestamp: 1_000,
+            bin_step_config: config,
+        }
+    }
+
+    fn mutated(params: &VariableParameters, active_id: i32, timestamp: u64) -> VariableParameters {
+        let mut params = params.clone();
+        params
+            .update_references(active_id, timestamp as i64)
+            .unwrap();
+        params.update_volatility_accumulator(active_id).unwrap();
+        params
+    }
+
+    #[test]
+    fn preview_matches_the_mutating_path() {
+        let params = params();
+        let active_id = 10;
+
+        // elapsed < filter_period: the references are kept.
+        let preview = params.preview_at(active_id, 1_020);
+        assert_eq!(preview.volatility_reference, 40_000);
+        assert_eq!(preview.index_reference, 3);
+        assert_eq!(preview.volatility_accumulator, 40_000 + 7 * 10_000);
+
+        // filter_period <= elapsed < decay_period: the reference decays by
+        // the reduction factor and the index moves to the active id.
+        let preview = params.preview_at(active_id, 1_030);
+        assert_eq!(preview.volatility_reference, 60_000);
+        assert_eq!(preview.index_reference, active_id);
+        assert_eq!(preview.volatility_accumulator, 60_000);
+
+        // elapsed >= decay_period: the reference resets.
+        let preview = params.preview_at(active_id, 1_600);
+        assert_eq!(preview.volatility_reference, 0);
+        assert_eq!(preview.volatility_accumulator, 0);
+
+        for timestamp in [0, 1_000, 1_029, 1_030, 1_599, 1_600, 1 << 40] {
+            for active_id in [-500, 3, 10, 100] {
+                let preview = params.preview_at(active_id, timestamp);
+                assert_eq!(
+                    preview,
+                    mutated(&params, active_id, timestamp),
+                    "{active_id} at {timestamp}"
+                );
+                assert_eq!(preview.last_update_timestamp, params.last_update_timestamp);
+            }
+        }
+        // The cap applies to the previewed accumulator too.
+        assert_eq!(
+            params.preview_at(-500, 1_020).volatility_accumulator,
+            350_000
+        );
+    }
+
+    fn checked(
+        reduction_factor: u16,
+        filter_period: u16,
+        decay_period: u16,
+        variable_fee_control: u32,
+        max_volatility_accumulator: u32,
+        protocol_fee_rate: u64,
+    ) -> Result<BinStepConfig, DlmmError> {
+        BinStepConfig::new_checked(
+            25,
+            10_000,
+            filter_period,
+            decay_period,
+            reduction_factor,
+            variable_fee_control,
+            max_volatility_accumulator,
+            protocol_fee_rate,
+        )
+    }
+
+    fn assert_rejects(result: Result<BinStepConfig, DlmmError>, field: &str) {
+        match result {
+            Err(DlmmError::InvalidConfig(reason)) => assert!(reason.contains(field), "{reason}"),
+            other => panic!("expected InvalidConfig for {field}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn new_checked_accepts_the_configs_in_use() {
+        let config = checked(5_000, 30, 600, 40_000, 350_000, 200_000_000).unwrap();
+        assert_eq!(
+            config,
+            BinStepConfig::new(25, 10_000, 30, 600, 5_000, 40_000, 350_000, 200_000_000)
+        );
+        // The bounds themselves are allowed.
+        assert!(checked(BASIS_POINT_MAX as u16, 600, 600, 0, u32::MAX, FEE_PRECISION).is_ok());
+    }
+
+    #[test]
+    fn new_checked_rejects_growing_decay() {
+        assert_rejects(
+            checked(BASIS_POINT_MAX as u16 + 1, 30, 600, 40_000, 350_000, 0),
+            "reduction_factor",
+        );
+    }
+
+    #[test]
+    fn new_checked_rejects_filter_after_decay() {
+        assert_rejects(
+            checked(5_000, 601, 600, 40_000, 350_000, 0),
+            "filter_period",
+        );
+    }
+
+    #[test]
+    fn new_checked_rejects_protocol_fee_above_precision() {
+        assert_rejects(
+            checked(5_000, 30, 600, 40_000, 350_000, FEE_PRECISION + 1),
+            "protocol_fee_rate",
+        );
+    }
+
+    #[test]
+    fn new_checked_rejects_overflowing_variable_fee() {
+        assert_rejects(
+            BinStepConfig::new_checked(u16::MAX, 1, 30, 600, 5_000, u32::MAX, u32::MAX, 0),
+            "max_volatility_accumulator",
+        );
+        // The same control passes with an accumulator cap swaps can live with.
+        assert!(
+            BinStepConfig::new_checked(u16::MAX, 1, 30, 600, 5_000, u32::MAX, 1_000, 0).is_ok()
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn generated_configs_pass_new_checked(config in proptest::prelude::any::<BinStepConfig>()) {
+            let checked = BinStepConfig::new_checked(
+                config.bin_step,
+                config.base_factor,
+                config.filter_period,
+                config.decay_period,
+                config.reduction_factor,
+                config.variable_fee_control,
+                config.max_volatility_accumulator,
+                config.protocol_fee_rate,
+            );
+            proptest::prop_assert_eq!(checked.unwrap(), config);
+        }
+    }
 }
@@ -0,0 +1,241 @@
+//! Checking SDK quotes against recorded swaps.
+//!
+//! A [`ConformanceCase`] holds a pool, a swap on it and the outputs recorded
+//! for that swap, e.g. by a transaction executed on chain. [`run_case`]
+//! quotes the same swap and compares field by field, so a divergence in
+//! rounding or in the volatility decay shows up as the exact field that
+//! differs. The bundled cases were recorded from the SDK itself, as their
+//! `source` says: they catch changes in its behavior, not divergence from
+//! the chain.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pool::{Pool, SwapResult},
+    scenario::SwapInstruction,
+};
+
+/// One recorded swap, loadable from JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConformanceCase {
+    pub name: String,
+    /// Where the expected outputs come from, e.g. a transaction digest, or
+    /// a note that the SDK generated them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The pool state the swap runs on, for an on-chain swap the state
+    /// right before its transaction.
+    pub pool: Pool,
+    pub swap: SwapInstruction,
+    pub expected: SwapOutputs,
+}
+
+/// The outputs a case compares.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapOutputs {
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_in: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_out: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub fee: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub protocol_fee: u64,
+    pub end_active_id: i32,
+}
+
+impl From<&SwapResult> for SwapOutputs {
+    fn from(result: &SwapResult) -> Self {
+        Self {
+            amount_in: result.amount_in,
+            amount_out: result.amount_out,
+            fee: result.fee,
+            protocol_fee: result.protocol_fee,
+            end_active_id: result.end_active_id,
+        }
+    }
+}
+
+impl SwapOutputs {
+    /// The fields where `actual` differs from `self`, in declaration order.
+    pub fn diff(&self, actual: &SwapOutputs) -> Vec<FieldDiff> {
+        [
+            ("amount_in", self.amount_in.into(), actual.amount_in.into()),
+            (
+                "amount_out",
+                self.amount_out.into(),
+                actual.amount_out.into(),
+            ),
+            ("fee", self.fee.into(), actual.fee.into()),
+            (
+                "protocol_fee",
+                self.protocol_fee.into(),
+                actual.protocol_fee.into(),
+            ),
+            (
+                "end_active_id",
+                self.end_active_id.into(),
+                actual.end_active_id.into(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, expected, actual)| expected != actual)
+        .map(|(field, expected, actual)| FieldDiff {
+            field,
+            expected,
+            actual,
+        })
+        .collect()
+    }
+}
+
+/// A field whose SDK value differs from the expected one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub expected: i128,
+    pub actual: i128,
+}
+
+/// `fee: expected 3, got 4 (+1)`.
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {} ({:+})",
+            self.field,
+            self.expected,
+            self.actual,
+            self.actual - self.expected
+        )
+    }
+}
+
+/// The result of [`run_case`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CaseOutcome {
+    pub name: String,
+    /// What the SDK quoted, or `None` if the quote failed.
+    pub actual: Option<SwapOutputs>,
+    /// Empty if every field matches.
+    pub diffs: Vec<FieldDiff>,
+    /// Why the quote failed.
+    pub error: Option<String>,
+}
+
+impl CaseOutcome {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.diffs.is_empty()
+    }
+}
+
+/// `name: ok`, or the name followed by one line per differing field.
+impl fmt::Display for CaseOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(error) = &self.error {
+            return write!(f, "{}: quote failed: {error}", self.name);
+        }
+        if self.diffs.is_empty() {
+            return write!(f, "{}: ok", self.name);
+        }
+        write!(f, "{}: {} field(s) differ", self.name, self.diffs.len())?;
+        for diff in &self.diffs {
+            write!(f, "\n  {diff}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes the case's swap against its pool and compares the outputs with
+/// the expected ones. The pool is not modified.
+pub fn run_case(case: &ConformanceCase) -> CaseOutcome {
+    let swap = &case.swap;
+    let quote = if swap.by_amount_in {
+        case.pool
+            .quote_exact_amount_in(swap.amount, swap.a2b, swap.timestamp)
+    } else {
+        case.pool
+            .quote_exact_amount_out(swap.amount, swap.a2b, swap.timestamp)
+    };
+    match quote {
+        Ok(result) => {
+            let actual = SwapOutputs::from(&result);
+            CaseOutcome {
+                name: case.name.clone(),
+                actual: Some(actual),
+                diffs: case.expected.diff(&actual),
+                error: None,
+            }
+        }
+        Err(err) => CaseOutcome {
+            name: case.name.clone(),
+            actual: None,
+            diffs: Vec::new(),
+            error: Some(format!("{err:#}")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::PriceQ64;
+
+    const CASES: &str = include_str!("../tests/fixtures/conformance_cases.json");
+
+    fn cases() -> Vec<ConformanceCase> {
+        serde_json::from_str(CASES).unwrap()
+    }
+
+    #[test]
+    fn fixtures_match_the_baseline() {
+        let cases = cases();
+        assert!(!cases.is_empty());
+        for case in &cases {
+            let outcome = run_case(case);
+            assert!(outcome.passed(), "{outcome}");
+            assert_eq!(outcome.to_string(), format!("{}: ok", case.name));
+        }
+    }
+
+    #[test]
+    fn divergences_are_reported_per_field() {
+        let mut case = cases().remove(0);
+        case.expected.fee += 1;
+        case.expected.end_active_id -= 2;
+        let outcome = run_case(&case);
+        assert!(!outcome.passed());
+        assert_eq!(outcome.actual.unwrap().fee + 1, case.expected.fee);
+        let fields: Vec<_> = outcome.diffs.iter().map(|diff| diff.field).collect();
+        assert_eq!(fields, ["fee", "end_active_id"]);
+        assert_eq!(outcome.diffs[0].actual - outcome.diffs[0].expected, -1);
+        let report = outcome.to_string();
+        assert!(report.contains("2 field(s) differ"), "{report}");
+        assert!(report.contains("end_active_id: expected"), "{report}");
+        assert!(report.contains("(+2)"), "{report}");
+
+        // A bin without a price cannot be quoted.
+        for bin in &mut case.pool.bins {
+            bin.price = PriceQ64::from_raw(0);
+        }
+        let failed = run_case(&case);
+        assert!(!failed.passed());
+        assert_eq!((failed.actual, failed.diffs.len()), (None, 0));
+        assert!(failed.to_string().contains("quote failed"), "{failed}");
+    }
+}
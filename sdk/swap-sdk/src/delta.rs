@@ -0,0 +1,320 @@
+//! Incremental pool updates, see [`Pool::delta_since`].
+//!
+//! A service that keeps a pool in sync can send a [`PoolDelta`] after each
+//! update instead of the whole pool: the scalars that changed, the bins
+//! that were added or changed, and the ids of the bins that were removed.
+//! For any two pools, `baseline.apply_delta(&current.delta_since(&baseline))`
+//! leaves `baseline` equal to `current`, except for `stats`,
+//! `schema_version` and `metadata`, which a delta does not carry.
+
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bin::Bin,
+    config::VariableParameters,
+    error::DlmmError,
+    pool::{check_bins, Pool},
+};
+
+/// The changes that turn one pool into another, from [`Pool::delta_since`].
+///
+/// A `None` scalar is unchanged. `changed_bins` holds the new and changed
+/// bins in full and `removed_bin_ids` the ids of the bins to drop, both
+/// ascending by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_id: Option<i32>,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::option_u64_string")
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_fee_rate: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v_parameters: Option<VariableParameters>,
+    #[serde(default)]
+    pub changed_bins: Vec<Bin>,
+    #[serde(default)]
+    pub removed_bin_ids: Vec<i32>,
+    /// The [`Pool::state_hash`] of the pool the delta was taken against.
+    /// When set, [`Pool::apply_delta`] refuses pools that hash differently.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline_hash: Option<[u8; 32]>,
+}
+
+impl PoolDelta {
+    /// Whether applying the delta changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.active_id.is_none()
+            && self.base_fee_rate.is_none()
+            && self.v_parameters.is_none()
+            && self.changed_bins.is_empty()
+            && self.removed_bin_ids.is_empty()
+    }
+}
+
+impl Pool {
+    /// The changes from `baseline` to this pool, with `baseline`'s
+    /// [`state_hash`](Pool::state_hash) as the `baseline_hash`.
+    ///
+    /// Bins are compared in full, growth fields included. Both pools'
+    /// bins are assumed sorted by id, as [`Pool::validate`] checks.
+    pub fn delta_since(&self, baseline: &Pool) -> PoolDelta {
+        let mut delta = PoolDelta {
+            active_id: changed(self.active_id, baseline.active_id),
+            base_fee_rate: changed(self.base_fee_rate, baseline.base_fee_rate),
+            v_parameters: changed(&self.v_parameters, &baseline.v_parameters).cloned(),
+            baseline_hash: Some(baseline.state_hash()),
+            ..Default::default()
+        };
+
+        let mut base = baseline.bins.iter().peekable();
+        for bin in &self.bins {
+            while let Some(old) = base.next_if(|old| old.id < bin.id) {
+                delta.removed_bin_ids.push(old.id);
+            }
+            match base.next_if(|old| old.id == bin.id) {
+                Some(old) if old == bin => {}
+                _ => delta.changed_bins.push(bin.clone()),
+            }
+        }
+        delta.removed_bin_ids.extend(base.map(|old| old.id));
+        delta
+    }
+
+    /// Applies `delta`, e.g. one from [`Pool::delta_since`].
+    ///
+    /// Fails, leaving the pool unchanged, with:
+    /// - [`DlmmError::BaselineMismatch`] if `baseline_hash` is set and is
+    ///   not this pool's [`state_hash`](Pool::state_hash)
+    /// - [`DlmmError::InvalidBinId`] if a removed id is not in the pool, or
+    ///   a changed bin's id is outside the bin id range
+    /// - [`DlmmError::InvalidInput`] if `changed_bins` or `removed_bin_ids`
+    ///   is not strictly ascending, an id is in both, or a changed bin has
+    ///   a zero price
+    ///
+    /// The hash leaves out growth fields, so a pool that differs from the
+    /// baseline only there passes the check and keeps its own growth in
+    /// the bins the delta does not touch.
+    pub fn apply_delta(&mut self, delta: &PoolDelta) -> Result<(), DlmmError> {
+        if let Some(expected) = delta.baseline_hash {
+            let actual = self.state_hash();
+            if expected != actual {
+                return Err(DlmmError::BaselineMismatch { expected, actual });
+            }
+        }
+        if delta
+            .removed_bin_ids
+            .windows(2)
+            .any(|pair| pair[0] >= pair[1])
+        {
+            return Err(DlmmError::InvalidInput);
+        }
+        let removed = |id: i32| delta.removed_bin_ids.binary_search(&id).is_ok();
+        if delta.changed_bins.iter().any(|bin| removed(bin.id)) {
+            return Err(DlmmError::InvalidInput);
+        }
+        if delta
+            .removed_bin_ids
+            .iter()
+            .any(|id| self.bins.binary_search_by_key(id, |bin| bin.id).is_err())
+        {
+            return Err(DlmmError::InvalidBinId);
+        }
+
+        let mut bins = Vec::with_capacity(self.bins.len() + delta.changed_bins.len());
+        let mut existing = self.bins.iter().filter(|bin| !removed(bin.id)).peekable();
+        for bin in &delta.changed_bins {
+            bins.extend(core::iter::from_fn(|| existing.next_if(|old| old.id < bin.id)).cloned());
+            existing.next_if(|old| old.id == bin.id);
+            bins.push(bin.clone());
+        }
+        bins.extend(existing.cloned());
+        // Unsorted changed bins come out of the merge unsorted.
+        check_bins(&bins).map_err(|err| match err.downcast_ref() {
+            Some(DlmmError::InvalidBinId) => DlmmError::InvalidBinId,
+            _ => DlmmError::InvalidInput,
+        })?;
+
+        let baseline = self.debug_invariants_before();
+        if let Some(active_id) = delta.active_id {
+            self.active_id = active_id;
+        }
+        if let Some(base_fee_rate) = delta.base_fee_rate {
+            self.base_fee_rate = base_fee_rate;
+        }
+        if let Some(v_parameters) = &delta.v_parameters {
+            self.v_parameters.clone_from(v_parameters);
+        }
+        self.bins = bins;
+        self.debug_invariants_after(&baseline, "apply_delta");
+        Ok(())
+    }
+}
+
+/// `this` if it differs from `base`.
+fn changed<T: PartialEq>(this: T, base: T) -> Option<T> {
+    (this != base).then_some(this)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{arbitrary, builder::PoolBuilder, config::BinStepConfig, price::PriceQ64};
+
+    fn pool() -> Pool {
+        PoolBuilder::new()
+            .config(BinStepConfig::new(
+                10, 10_000, 30, 600, 5_000, 40_000, 350_000, 0,
+            ))
+            .base_fee_rate(1_000_000)
+            .uniform_liquidity(-5, 5, 1_000_000, 1_000_000)
+            .build()
+            .unwrap()
+    }
+
+    fn round_trips(baseline: &Pool, current: &Pool) -> Result<(), TestCaseError> {
+        let delta = current.delta_since(baseline);
+        let mut applied = baseline.clone();
+        applied.apply_delta(&delta).unwrap();
+        prop_assert_eq!(&applied, current);
+        prop_assert_eq!(delta.is_empty(), baseline == current);
+        Ok(())
+    }
+
+    #[test]
+    fn removed_and_added_bins_round_trip() {
+        let baseline = pool();
+        let mut current = baseline.clone();
+        current
+            .bins_mut_with(|bins| {
+                bins.retain(|bin| bin.id != -5 && bin.id != 2);
+            })
+            .unwrap();
+        let price = PriceQ64::from_id(9, 10).unwrap();
+        current
+            .insert_bin(Bin {
+                id: 9,
+                amount_a: 7,
+                price,
+                liquidity_supply: 7,
+                ..Default::default()
+            })
+            .unwrap();
+        current.swap_exact_amount_in(3_000_000, true, 0).unwrap();
+        current.reset_stats();
+
+        let delta = current.delta_since(&baseline);
+        assert_eq!(delta.removed_bin_ids, [-5, 2]);
+        assert_eq!(delta.changed_bins.last().unwrap().id, 9);
+        assert_eq!(delta.active_id, Some(current.active_id));
+
+        let mut applied = baseline.clone();
+        applied.apply_delta(&delta).unwrap();
+        assert_eq!(applied, current);
+        assert!(current.delta_since(&current).is_empty());
+
+        let json = serde_json::to_string(&delta).unwrap();
+        assert_eq!(serde_json::from_str::<PoolDelta>(&json).unwrap(), delta);
+        assert_eq!(
+            serde_json::from_str::<PoolDelta>("{}").unwrap(),
+            PoolDelta::default()
+        );
+    }
+
+    #[test]
+    fn bad_deltas_leave_the_pool_unchanged() {
+        let baseline = pool();
+        let mut current = baseline.clone();
+        current.swap_exact_amount_in(1_000, true, 0).unwrap();
+        let delta = current.delta_since(&baseline);
+
+        // Applying twice: the pool is no longer the baseline.
+        let mut applied = baseline.clone();
+        applied.apply_delta(&delta).unwrap();
+        let before = applied.clone();
+        let err = applied.apply_delta(&delta).unwrap_err();
+        assert!(matches!(
+            err,
+            DlmmError::BaselineMismatch { expected, actual }
+                if expected == baseline.state_hash() && actual == before.state_hash()
+        ));
+        assert_eq!(applied, before);
+
+        let unchecked = |delta: PoolDelta| PoolDelta {
+            baseline_hash: None,
+            ..delta
+        };
+        let mut target = baseline.clone();
+        let missing = unchecked(PoolDelta {
+            removed_bin_ids: vec![6],
+            ..Default::default()
+        });
+        assert!(matches!(
+            target.apply_delta(&missing),
+            Err(DlmmError::InvalidBinId)
+        ));
+        let unsorted = unchecked(PoolDelta {
+            removed_bin_ids: vec![1, 0],
+            ..Default::default()
+        });
+        assert!(matches!(
+            target.apply_delta(&unsorted),
+            Err(DlmmError::InvalidInput)
+        ));
+        let both = unchecked(PoolDelta {
+            changed_bins: vec![baseline.bins()[5].clone()],
+            removed_bin_ids: vec![0],
+            ..Default::default()
+        });
+        assert!(matches!(
+            target.apply_delta(&both),
+            Err(DlmmError::InvalidInput)
+        ));
+        let reversed = unchecked(PoolDelta {
+            changed_bins: vec![baseline.bins()[1].clone(), baseline.bins()[0].clone()],
+            ..Default::default()
+        });
+        assert!(matches!(
+            target.apply_delta(&reversed),
+            Err(DlmmError::InvalidInput)
+        ));
+        let out_of_range = unchecked(PoolDelta {
+            changed_bins: vec![Bin {
+                id: i32::MAX,
+                price: PriceQ64::ONE,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        assert!(matches!(
+            target.apply_delta(&out_of_range),
+            Err(DlmmError::InvalidBinId)
+        ));
+        assert_eq!(target, baseline);
+    }
+
+    proptest! {
+        #[test]
+        fn deltas_round_trip(baseline in arbitrary::small_pool(), current in arbitrary::small_pool()) {
+            round_trips(&baseline, &current)?;
+        }
+
+        #[test]
+        fn deltas_round_trip_after_swaps(
+            baseline in arbitrary::small_pool(),
+            amount in 1u64..1_000_000,
+            a2b: bool,
+        ) {
+            let mut current = baseline.clone();
+            let _ = current.swap_exact_amount_in(amount, a2b, 0);
+            current.reset_stats();
+            round_trips(&baseline, &current)?;
+        }
+    }
+}
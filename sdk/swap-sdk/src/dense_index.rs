@@ -0,0 +1,223 @@
+//! Constant-time bin lookup for pools quoted in a hot loop, see
+//! [`DenseBinIndex`].
+
+use alloc::{format, vec::Vec};
+
+use anyhow::{Context, Error};
+
+use crate::{bin::Bin, error::DlmmError, pool::Pool};
+
+/// Widest id range, from the lowest to the highest bin, that
+/// [`DenseBinIndex::new`] accepts. A slot takes 16 bytes, so an index is
+/// at most 1 MiB whatever the bin count.
+pub const MAX_DENSE_SPAN: u32 = 1 << 16;
+
+/// The position in [`Pool::bins`] of every bin, in a slot per id from the
+/// lowest bin to the highest, so finding a bin takes one array access
+/// instead of a binary search.
+///
+/// A swap walks from one bin to the next by position, so the only search it
+/// does is for its first bin; [`Pool::swap_with_index`] and
+/// [`Pool::quote_with_index`] find that bin here when the active id holds
+/// one, and search as before when it falls in a gap. That pays off for
+/// small swaps on pools with many bins, quoted many times between changes.
+///
+/// The index does not borrow the pool. A lookup checks the id of the bin it
+/// lands on and falls back to a binary search on a mismatch or an empty
+/// slot, so an index left behind by added or removed bins returns the same
+/// results, only more slowly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DenseBinIndex {
+    min_id: i32,
+    slots: Vec<Option<usize>>,
+    /// The bin count when built, to notice added or removed bins.
+    len: usize,
+}
+
+impl DenseBinIndex {
+    /// Indexes the bins of `pool`. Fails with [`DlmmError::InvalidInput`] if
+    /// they span more than [`MAX_DENSE_SPAN`] ids.
+    pub fn new(pool: &Pool) -> Result<DenseBinIndex, Error> {
+        let mut index = DenseBinIndex::default();
+        index.rebuild(pool)?;
+        Ok(index)
+    }
+
+    /// Indexes the bins of `pool` again, reusing the allocation. Fails as
+    /// [`DenseBinIndex::new`] does, leaving the index empty.
+    pub fn rebuild(&mut self, pool: &Pool) -> Result<(), Error> {
+        self.slots.clear();
+        self.len = 0;
+        let bins = pool.bins();
+        let (Some(first), Some(last)) = (bins.first(), bins.last()) else {
+            return Ok(());
+        };
+        let span = last.id as i64 - first.id as i64 + 1;
+        if span > MAX_DENSE_SPAN as i64 {
+            return Err(DlmmError::InvalidInput).with_context(|| {
+                format!(
+                    "bins {}..={} span {span} ids, more than {MAX_DENSE_SPAN}",
+                    first.id, last.id
+                )
+            });
+        }
+        self.min_id = first.id;
+        self.slots.resize(span as usize, None);
+        for (position, bin) in bins.iter().enumerate() {
+            self.slots[(bin.id - first.id) as usize] = Some(position);
+        }
+        self.len = bins.len();
+        Ok(())
+    }
+
+    /// The bin of `pool` with `id`, as [`Pool::get_bin`] returns it.
+    pub fn get<'a>(&self, pool: &'a Pool, id: i32) -> Option<&'a Bin> {
+        let bins = pool.bins();
+        match self.position(bins, id) {
+            Some(position) => Some(&bins[position]),
+            None => pool.get_bin(id),
+        }
+    }
+
+    /// Whether the index was built from bins with the count and id range
+    /// `pool` has now. Bins replaced without changing either are not
+    /// noticed, but lookups still check the ids they find.
+    pub fn is_current(&self, pool: &Pool) -> bool {
+        let bins = pool.bins();
+        bins.len() == self.len
+            && bins.first().map(|bin| bin.id) == self.slots.first().map(|_| self.min_id)
+            && bins.last().map(|bin| bin.id)
+                == self
+                    .slots
+                    .len()
+                    .checked_sub(1)
+                    .map(|last| self.min_id + last as i32)
+    }
+
+    /// The position of the bin with `id` in `bins`, if the index has it
+    /// there. `None` does not mean `bins` lacks the id.
+    pub(crate) fn position(&self, bins: &[Bin], id: i32) -> Option<usize> {
+        let slot = usize::try_from(id.checked_sub(self.min_id)?).ok()?;
+        let position = (*self.slots.get(slot)?)?;
+        (bins.get(position)?.id == id).then_some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        arbitrary::{small_pool, MAX_BIN_RESERVE},
+        pool::SwapOptions,
+        Bin, BinStepConfig, PriceQ64, VariableParameters,
+    };
+
+    /// An empty bin at bin step 1.
+    fn bin(id: i32) -> Bin {
+        Bin {
+            id,
+            price: PriceQ64::from_id(id, 1).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn pool_with(ids: impl IntoIterator<Item = i32>) -> Pool {
+        let config = BinStepConfig::new(1, 1, 60, 600, 9000, 0, 350_000, 30_000);
+        Pool::new(
+            0,
+            0,
+            VariableParameters::new(config, 0, 0),
+            ids.into_iter().map(bin).collect(),
+        )
+    }
+
+    #[test]
+    fn lookups_match_the_binary_search() {
+        let pool = pool_with([-7, -3, -2, 0, 5, 6]);
+        let index = DenseBinIndex::new(&pool).unwrap();
+        assert!(index.is_current(&pool));
+        for id in -10..10 {
+            assert_eq!(index.get(&pool, id), pool.get_bin(id), "{id}");
+        }
+        assert_eq!(index.get(&pool, i32::MIN), None);
+        assert_eq!(index.get(&pool, i32::MAX), None);
+
+        let empty = pool_with([]);
+        let index = DenseBinIndex::new(&empty).unwrap();
+        assert!(index.is_current(&empty));
+        assert_eq!(index.get(&empty, 0), None);
+    }
+
+    #[test]
+    fn stale_indexes_still_find_every_bin() {
+        let mut pool = pool_with([-3, 0, 3]);
+        let index = DenseBinIndex::new(&pool).unwrap();
+        pool.insert_bin(bin(1)).unwrap();
+        pool.insert_bin(bin(9)).unwrap();
+        pool.remove_bin(-3);
+        assert!(!index.is_current(&pool));
+        for id in -5..12 {
+            assert_eq!(index.get(&pool, id), pool.get_bin(id), "{id}");
+        }
+    }
+
+    #[test]
+    fn spans_are_bounded() {
+        let span = MAX_DENSE_SPAN as i32;
+        assert!(DenseBinIndex::new(&pool_with([0, span - 1])).is_ok());
+        let mut index = DenseBinIndex::new(&pool_with([0])).unwrap();
+        let wide = pool_with([0, span]);
+        let err = index.rebuild(&wide).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidInput)));
+        assert_eq!(index, DenseBinIndex::default());
+        let err = DenseBinIndex::new(&pool_with([i32::MIN, i32::MAX])).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidInput)));
+    }
+
+    proptest! {
+        #[test]
+        fn indexed_swaps_match(
+            pool in small_pool(),
+            amount in 1..=MAX_BIN_RESERVE,
+            a2b in any::<bool>(),
+            by_amount_in in any::<bool>(),
+            extra_id in -40i32..=40,
+        ) {
+            let now = pool.v_parameters.last_update_timestamp;
+            let options = SwapOptions::default();
+            let swap = |pool: &mut Pool| if by_amount_in {
+                pool.swap_exact_amount_in(amount, a2b, now)
+            } else {
+                pool.swap_exact_amount_out(amount, a2b, now)
+            };
+            let mut index = DenseBinIndex::new(&pool).unwrap();
+            for id in pool.bins().first().unwrap().id - 2..=pool.bins().last().unwrap().id + 2 {
+                prop_assert_eq!(index.get(&pool, id), pool.get_bin(id));
+            }
+
+            let quote = pool.quote_with_index(&index, amount, a2b, by_amount_in, now, options);
+            let mut indexed = pool.clone();
+            let mut plain = pool.clone();
+            let executed = indexed.swap_with_index(&mut index, amount, a2b, by_amount_in, now, options);
+            let expected = swap(&mut plain);
+            prop_assert_eq!(quote.as_ref().ok(), expected.as_ref().ok());
+            prop_assert_eq!(executed.as_ref().ok(), expected.as_ref().ok());
+            prop_assert_eq!(&indexed, &plain);
+
+            // A bin added after the index was built, near the active id.
+            let stale = index.clone();
+            let mut grown = pool.clone();
+            let id = pool.active_id + extra_id;
+            let bin_step = pool.v_parameters.bin_step_config.bin_step;
+            let _ = grown.insert_bin(Bin { id, price: PriceQ64::from_id(id, bin_step).unwrap(), ..Default::default() });
+            let quote = grown.quote_with_index(&stale, amount, a2b, by_amount_in, now, options);
+            let expected = swap(&mut grown.clone());
+            prop_assert_eq!(quote.as_ref().ok(), expected.as_ref().ok());
+            let executed = grown.clone().swap_with_index(&mut index, amount, a2b, by_amount_in, now, options);
+            prop_assert_eq!(executed.as_ref().ok(), expected.as_ref().ok());
+            prop_assert!(index.is_current(&grown));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! An order book view of a pool, see [`Pool::depth_levels`].
+//!
+//! Each bin with inventory is a price level. Asks sell token A at or above
+//! the active bin and bids sell token B at or below it, so the active bin,
+//! which can hold both tokens, appears on both sides.
+
+use alloc::{string::String, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{bin::Bin, pool::Pool, price::q64_to_decimal_string};
+
+/// One price level of a [`DepthSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub bin_id: i32,
+    /// The bin's Q64.64 price.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub price: u128,
+    /// `price` in whole tokens, once set by
+    /// [`DepthSnapshot::with_decimal_prices`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_decimal: Option<String>,
+    /// Token A for asks, token B for bids, in base units.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub size: u64,
+    /// `size` summed over this level and the ones before it.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub cumulative_size: u128,
+}
+
+/// The levels nearest the active bin on each side, see
+/// [`Pool::depth_levels`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub active_id: i32,
+    /// Bins at or above the active id holding token A, by ascending price.
+    pub asks: Vec<DepthLevel>,
+    /// Bins at or below the active id holding token B, by descending price.
+    pub bids: Vec<DepthLevel>,
+}
+
+impl DepthSnapshot {
+    /// Sets [`DepthLevel::price_decimal`] on every level, formatted by
+    /// [`q64_to_decimal_string`] with `precision` fractional digits.
+    pub fn with_decimal_prices(mut self, decimals_a: u8, decimals_b: u8, precision: usize) -> Self {
+        for level in self.asks.iter_mut().chain(&mut self.bids) {
+            level.price_decimal = Some(q64_to_decimal_string(
+                level.price,
+                decimals_a,
+                decimals_b,
+                precision,
+            ));
+        }
+        self
+    }
+}
+
+impl Pool {
+    /// Up to `max_levels_per_side` levels on each side of the active id.
+    /// Bins without inventory on a side are left out of it.
+    pub fn depth_levels(&self, max_levels_per_side: usize) -> DepthSnapshot {
+        let split = self.bins.partition_point(|bin| bin.id < self.active_id);
+        let asks = self.bins[split..].iter().map(|bin| (bin, bin.amount_a));
+        // The active bin, if present, is the first of `asks`.
+        let bids_end = match self.bins.get(split) {
+            Some(bin) if bin.id == self.active_id => split + 1,
+            _ => split,
+        };
+        let bids = self.bins[..bids_end]
+            .iter()
+            .rev()
+            .map(|bin| (bin, bin.amount_b));
+        DepthSnapshot {
+            active_id: self.active_id,
+            asks: levels(asks, max_levels_per_side),
+            bids: levels(bids, max_levels_per_side),
+        }
+    }
+}
+
+fn levels<'a>(bins: impl Iterator<Item = (&'a Bin, u64)>, max_levels: usize) -> Vec<DepthLevel> {
+    let mut cumulative_size = 0u128;
+    bins.filter(|&(_, size)| size > 0)
+        .take(max_levels)
+        .map(|(bin, size)| {
+            cumulative_size += size as u128;
+            DepthLevel {
+                bin_id: bin.id,
+                price: bin.price.as_raw(),
+                price_decimal: None,
+                size,
+                cumulative_size,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BinStepConfig, VariableParameters},
+        math::q64x64_math::ONE,
+    };
+
+    fn level(bin_id: i32, price: u128, size: u64, cumulative_size: u128) -> DepthLevel {
+        DepthLevel {
+            bin_id,
+            price,
+            price_decimal: None,
+            size,
+            cumulative_size,
+        }
+    }
+
+    #[test]
+    fn levels_cover_both_sides_of_the_active_bin() {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 0);
+        // 1 + id / 4.
+        let price = |id: i32| (ONE as i128 + id as i128 * (ONE / 4) as i128) as u128;
+        let bin = |id, amount_a, amount_b| Bin {
+            id,
+            amount_a,
+            amount_b,
+            price: price(id).into(),
+            ..Default::default()
+        };
+        let pool = Pool::new(
+            0,
+            0,
+            VariableParameters::new(config, 0, 0),
+            vec![
+                bin(-3, 0, 0),
+                bin(-2, 0, 100),
+                bin(-1, 0, 200),
+                bin(0, 50, 60),
+                bin(1, 300, 0),
+                bin(2, 0, 0),
+                bin(3, 400, 0),
+            ],
+        );
+
+        let depth = pool.depth_levels(10);
+        assert_eq!(depth.active_id, 0);
+        assert_eq!(
+            depth.asks,
+            [
+                level(0, price(0), 50, 50),
+                level(1, price(1), 300, 350),
+                level(3, price(3), 400, 750),
+            ]
+        );
+        assert_eq!(
+            depth.bids,
+            [
+                level(0, price(0), 60, 60),
+                level(-1, price(-1), 200, 260),
+                level(-2, price(-2), 100, 360),
+            ]
+        );
+
+        let top = pool.depth_levels(2);
+        assert_eq!(top.asks, depth.asks[..2]);
+        assert_eq!(top.bids, depth.bids[..2]);
+        assert_eq!(pool.depth_levels(0), DepthSnapshot::default());
+
+        let decimal = top.with_decimal_prices(6, 6, 2);
+        let prices: Vec<_> = decimal
+            .asks
+            .iter()
+            .chain(&decimal.bids)
+            .map(|level| level.price_decimal.as_deref().unwrap())
+            .collect();
+        assert_eq!(prices, ["1.00", "1.25", "1.00", "0.75"]);
+    }
+
+    #[test]
+    fn a_missing_active_bin_splits_the_sides() {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 0);
+        let bin = |id, amount_a, amount_b| Bin {
+            id,
+            amount_a,
+            amount_b,
+            price: ONE.into(),
+            ..Default::default()
+        };
+        // Bin 1 holds both tokens but lies above the active id 0.
+        let pool = Pool::new(
+            0,
+            0,
+            VariableParameters::new(config, 0, 0),
+            vec![bin(-1, 5, 10), bin(1, 20, 40)],
+        );
+        let depth = pool.depth_levels(5);
+        assert_eq!(depth.asks, [level(1, ONE, 20, 20)]);
+        assert_eq!(depth.bids, [level(-1, ONE, 10, 10)]);
+    }
+}
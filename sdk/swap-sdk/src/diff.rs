@@ -0,0 +1,510 @@
+//! Field-by-field comparison of two pool snapshots, see [`Pool::diff`].
+//!
+//! Meant for tracking down why a locally maintained pool drifted from the
+//! chain: rather than comparing two JSON dumps, the diff lists each scalar
+//! that differs, the bins only one side has, and the changed fields of the
+//! bins both have, with the values on both sides.
+
+use alloc::vec::Vec;
+use core::{cmp::Ordering, fmt};
+
+use crate::{bin::Bin, config::VariableParameters, pool::Pool};
+
+/// Entries [`Pool::diff`] lists before summarizing the rest as a count.
+pub const DEFAULT_MAX_DIFF_ENTRIES: usize = 100;
+
+/// What differs between two pools, from [`Pool::diff`].
+///
+/// "This" is the pool `diff` was called on, "other" its argument, and
+/// deltas are `other - this`. An entry is a scalar field, a bin on one side
+/// only or a bin whose fields differ. Entries past the limit are counted in
+/// `omitted` instead of listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolDiff {
+    /// `active_id`, `base_fee_rate` and the `v_parameters` fields that
+    /// differ, in declaration order.
+    pub fields: Vec<FieldChange>,
+    /// Ids of the bins only this pool has, ascending.
+    pub only_in_this: Vec<i32>,
+    /// Ids of the bins only the other pool has, ascending.
+    pub only_in_other: Vec<i32>,
+    /// The bins both pools have whose fields differ, ascending by id.
+    pub bins: Vec<BinChange>,
+    /// Differing entries left out by the limit.
+    pub omitted: usize,
+}
+
+/// A pool scalar with a different value on each side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldChange {
+    /// The field's path, e.g. `v_parameters.volatility_accumulator`.
+    pub field: &'static str,
+    pub this: i128,
+    pub other: i128,
+}
+
+impl FieldChange {
+    /// `other - this`.
+    pub fn delta(&self) -> i128 {
+        self.other - self.this
+    }
+}
+
+/// A bin both pools have, with the fields that differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinChange {
+    pub bin_id: i32,
+    /// In declaration order, never empty.
+    pub fields: Vec<BinFieldChange>,
+}
+
+/// A bin field with a different value on each side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinFieldChange {
+    pub field: &'static str,
+    /// The entry of `rewards_growth_global`, for that field. A side with
+    /// fewer entries counts the missing ones as zero.
+    pub reward_index: Option<usize>,
+    pub this: u128,
+    pub other: u128,
+}
+
+impl BinFieldChange {
+    /// `other - this`, or `None` if it does not fit in an `i128`.
+    pub fn delta(&self) -> Option<i128> {
+        if self.other >= self.this {
+            i128::try_from(self.other - self.this).ok()
+        } else {
+            i128::try_from(self.this - self.other)
+                .ok()
+                .map(|delta| -delta)
+        }
+    }
+}
+
+impl PoolDiff {
+    /// Whether the pools are the same in every compared field.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+            && self.only_in_this.is_empty()
+            && self.only_in_other.is_empty()
+            && self.bins.is_empty()
+            && self.omitted == 0
+    }
+
+    /// Entries listed, not counting `omitted`.
+    fn listed(&self) -> usize {
+        self.fields.len() + self.only_in_this.len() + self.only_in_other.len() + self.bins.len()
+    }
+}
+
+impl Pool {
+    /// Compares this pool with `other`, listing at most
+    /// [`DEFAULT_MAX_DIFF_ENTRIES`] entries; see [`Pool::diff_with_limit`].
+    pub fn diff(&self, other: &Pool) -> PoolDiff {
+        self.diff_with_limit(other, DEFAULT_MAX_DIFF_ENTRIES)
+    }
+
+    /// Compares this pool with `other`, listing at most `max_entries`
+    /// entries and counting the rest in [`PoolDiff::omitted`].
+    ///
+    /// Compares `active_id`, `base_fee_rate`, every `v_parameters` field,
+    /// and for bins with the same id their reserves, price, liquidity
+    /// supply and growth globals. `stats`, `schema_version` and
+    /// `metadata` are not compared. Both pools' bins are expected sorted by
+    /// id, as [`Pool::validate`] requires.
+    pub fn diff_with_limit(&self, other: &Pool, max_entries: usize) -> PoolDiff {
+        let mut diff = PoolDiff::default();
+        let (this_v, other_v) = (&self.v_parameters, &other.v_parameters);
+        let scalars = [
+            ("active_id", self.active_id.into(), other.active_id.into()),
+            (
+                "base_fee_rate",
+                self.base_fee_rate.into(),
+                other.base_fee_rate.into(),
+            ),
+        ]
+        .into_iter()
+        .chain(v_parameter_fields(this_v, other_v));
+        for (field, this, other) in scalars.filter(|(_, this, other)| this != other) {
+            if diff.listed() < max_entries {
+                diff.fields.push(FieldChange { field, this, other });
+            } else {
+                diff.omitted += 1;
+            }
+        }
+
+        let (mut this_bins, mut other_bins) =
+            (self.bins.iter().peekable(), other.bins.iter().peekable());
+        loop {
+            let order = match (this_bins.peek(), other_bins.peek()) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(this), Some(other)) => this.id.cmp(&other.id),
+            };
+            let listed = diff.listed() < max_entries;
+            match order {
+                Ordering::Less => {
+                    let id = this_bins.next().unwrap().id;
+                    if listed {
+                        diff.only_in_this.push(id);
+                    } else {
+                        diff.omitted += 1;
+                    }
+                }
+                Ordering::Greater => {
+                    let id = other_bins.next().unwrap().id;
+                    if listed {
+                        diff.only_in_other.push(id);
+                    } else {
+                        diff.omitted += 1;
+                    }
+                }
+                Ordering::Equal => {
+                    let (this, other) = (this_bins.next().unwrap(), other_bins.next().unwrap());
+                    let fields = bin_fields(this, other);
+                    if fields.is_empty() {
+                        continue;
+                    }
+                    if listed {
+                        diff.bins.push(BinChange {
+                            bin_id: this.id,
+                            fields,
+                        });
+                    } else {
+                        diff.omitted += 1;
+                    }
+                }
+            }
+        }
+        diff
+    }
+}
+
+fn v_parameter_fields(
+    this: &VariableParameters,
+    other: &VariableParameters,
+) -> [(&'static str, i128, i128); 12] {
+    let (this_config, other_config) = (&this.bin_step_config, &other.bin_step_config);
+    [
+        (
+            "v_parameters.volatility_accumulator",
+            this.volatility_accumulator.into(),
+            other.volatility_accumulator.into(),
+        ),
+        (
+            "v_parameters.volatility_reference",
+            this.volatility_reference.into(),
+            other.volatility_reference.into(),
+        ),
+        (
+            "v_parameters.index_reference",
+            this.index_reference.into(),
+            other.index_reference.into(),
+        ),
+        (
+            "v_parameters.last_update_timestamp",
+            this.last_update_timestamp.into(),
+            other.last_update_timestamp.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.bin_step",
+            this_config.bin_step.into(),
+            other_config.bin_step.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.base_factor",
+            this_config.base_factor.into(),
+            other_config.base_factor.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.filter_period",
+            this_config.filter_period.into(),
+            other_config.filter_period.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.decay_period",
+            this_config.decay_period.into(),
+            other_config.decay_period.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.reduction_factor",
+            this_config.reduction_factor.into(),
+            other_config.reduction_factor.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.variable_fee_control",
+            this_config.variable_fee_control.into(),
+            other_config.variable_fee_control.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.max_volatility_accumulator",
+            this_config.max_volatility_accumulator.into(),
+            other_config.max_volatility_accumulator.into(),
+        ),
+        (
+            "v_parameters.bin_step_config.protocol_fee_rate",
+            this_config.protocol_fee_rate.into(),
+            other_config.protocol_fee_rate.into(),
+        ),
+    ]
+}
+
+/// The fields of two bins with the same id that differ.
+fn bin_fields(this: &Bin, other: &Bin) -> Vec<BinFieldChange> {
+    let change = |field, this, other| BinFieldChange {
+        field,
+        reward_index: None,
+        this,
+        other,
+    };
+    let mut fields: Vec<_> = [
+        change("amount_a", this.amount_a.into(), other.amount_a.into()),
+        change("amount_b", this.amount_b.into(), other.amount_b.into()),
+        change("price", this.price.into(), other.price.into()),
+        change(
+            "liquidity_supply",
+            this.liquidity_supply,
+            other.liquidity_supply,
+        ),
+    ]
+    .into_iter()
+    .filter(|change| change.this != change.other)
+    .collect();
+    let rewards = this
+        .rewards_growth_global
+        .len()
+        .max(other.rewards_growth_global.len());
+    for index in 0..rewards {
+        let at = |bin: &Bin| bin.rewards_growth_global.get(index).copied().unwrap_or(0);
+        if at(this) != at(other) {
+            fields.push(BinFieldChange {
+                reward_index: Some(index),
+                ..change("rewards_growth_global", at(this), at(other))
+            });
+        }
+    }
+    fields.extend(
+        [
+            change(
+                "fee_amount_a_growth_global",
+                this.fee_amount_a_growth_global,
+                other.fee_amount_a_growth_global,
+            ),
+            change(
+                "fee_amount_b_growth_global",
+                this.fee_amount_b_growth_global,
+                other.fee_amount_b_growth_global,
+            ),
+        ]
+        .into_iter()
+        .filter(|change| change.this != change.other),
+    );
+    fields
+}
+
+/// `amount_a 100 -> 95 (-5)`, with the index for reward entries, e.g.
+/// `rewards_growth_global[1] 0 -> 7 (+7)`.
+impl fmt::Display for BinFieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.field)?;
+        if let Some(index) = self.reward_index {
+            write!(f, "[{index}]")?;
+        }
+        // The exact difference, which may not fit in an `i128`.
+        let (sign, delta) = if self.other >= self.this {
+            ('+', self.other - self.this)
+        } else {
+            ('-', self.this - self.other)
+        };
+        write!(f, " {} -> {} ({sign}{delta})", self.this, self.other)
+    }
+}
+
+/// One line per entry, e.g.
+///
+/// ```text
+/// active_id: 5 -> 6 (+1)
+/// bin 3: amount_a 100 -> 95 (-5), liquidity_supply 200 -> 190 (-10)
+/// bin 8 only in this pool
+/// bin 9 only in the other pool
+/// ... 4 more
+/// ```
+///
+/// or `no differences`.
+impl fmt::Display for PoolDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+        let mut lines = 0;
+        let mut line = |f: &mut fmt::Formatter<'_>| {
+            lines += 1;
+            if lines > 1 {
+                writeln!(f)
+            } else {
+                Ok(())
+            }
+        };
+        for change in &self.fields {
+            line(f)?;
+            write!(
+                f,
+                "{}: {} -> {} ({:+})",
+                change.field,
+                change.this,
+                change.other,
+                change.delta()
+            )?;
+        }
+        for bin in &self.bins {
+            line(f)?;
+            write!(f, "bin {}: ", bin.bin_id)?;
+            for (i, field) in bin.fields.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{field}")?;
+            }
+        }
+        for id in &self.only_in_this {
+            line(f)?;
+            write!(f, "bin {id} only in this pool")?;
+        }
+        for id in &self.only_in_other {
+            line(f)?;
+            write!(f, "bin {id} only in the other pool")?;
+        }
+        if self.omitted > 0 {
+            line(f)?;
+            write!(f, "... {} more", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::BinStepConfig, math::price_math::get_price_from_id};
+
+    fn pool(ids: impl IntoIterator<Item = i32>) -> Pool {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 30_000);
+        let bins = ids
+            .into_iter()
+            .map(|id| Bin {
+                id,
+                amount_a: if id >= 0 { 1_000 } else { 0 },
+                amount_b: if id <= 0 { 1_000 } else { 0 },
+                price: get_price_from_id(id, 25).unwrap().into(),
+                liquidity_supply: 2_000,
+                ..Default::default()
+            })
+            .collect();
+        Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(config, 0, 1_000),
+            bins,
+        )
+    }
+
+    #[test]
+    fn identical_pools_do_not_differ() {
+        let pool = pool(-5..=5);
+        let diff = pool.diff(&pool.clone());
+        assert!(diff.is_empty(), "{diff}");
+        assert_eq!(diff, PoolDiff::default());
+        assert_eq!(diff.to_string(), "no differences");
+    }
+
+    #[test]
+    fn reserve_changes_are_reported_with_their_delta() {
+        let local = pool(-5..=5);
+        let mut chain = local.clone();
+        chain.bins[7].amount_a -= 37;
+
+        let diff = local.diff(&chain);
+        assert!(!diff.is_empty());
+        assert!(diff.fields.is_empty() && diff.only_in_this.is_empty());
+        assert_eq!(diff.bins.len(), 1);
+        assert_eq!(diff.bins[0].bin_id, 2);
+        let [change] = diff.bins[0].fields[..] else {
+            panic!("{diff}");
+        };
+        assert_eq!(
+            (change.field, change.this, change.other),
+            ("amount_a", 1_000, 963)
+        );
+        assert_eq!(change.delta(), Some(-37));
+        assert_eq!(diff.to_string(), "bin 2: amount_a 1000 -> 963 (-37)");
+        // The other way round, the delta flips.
+        assert_eq!(chain.diff(&local).bins[0].fields[0].delta(), Some(37));
+    }
+
+    #[test]
+    fn scalars_rewards_and_missing_bins_are_listed() {
+        let local = pool(-5..=5);
+        let mut chain = pool(-3..=7);
+        chain.active_id = 1;
+        chain.v_parameters.volatility_accumulator = 10_000;
+        chain.v_parameters.bin_step_config.protocol_fee_rate = 0;
+        chain.bins[0].rewards_growth_global = alloc::vec![0, u128::MAX];
+        chain.bins[0].fee_amount_b_growth_global = 5;
+
+        let diff = local.diff(&chain);
+        let fields: Vec<_> = diff.fields.iter().map(|change| change.field).collect();
+        assert_eq!(
+            fields,
+            [
+                "active_id",
+                "v_parameters.volatility_accumulator",
+                "v_parameters.bin_step_config.protocol_fee_rate"
+            ]
+        );
+        assert_eq!(diff.fields[2].delta(), -30_000);
+        assert_eq!(diff.only_in_this, [-5, -4]);
+        assert_eq!(diff.only_in_other, [6, 7]);
+        let rewards = diff.bins[0].fields[0];
+        assert_eq!((rewards.reward_index, rewards.delta()), (Some(1), None));
+        assert_eq!(
+            diff.to_string(),
+            format!(
+                "active_id: 0 -> 1 (+1)\n\
+                 v_parameters.volatility_accumulator: 0 -> 10000 (+10000)\n\
+                 v_parameters.bin_step_config.protocol_fee_rate: 30000 -> 0 (-30000)\n\
+                 bin -3: rewards_growth_global[1] 0 -> {max} (+{max}), \
+                 fee_amount_b_growth_global 0 -> 5 (+5)\n\
+                 bin -5 only in this pool\n\
+                 bin -4 only in this pool\n\
+                 bin 6 only in the other pool\n\
+                 bin 7 only in the other pool",
+                max = u128::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn entries_past_the_limit_are_counted() {
+        let local = pool(-50..=50);
+        let mut chain = pool(-60..=40);
+        chain.active_id = 3;
+        for bin in &mut chain.bins {
+            bin.liquidity_supply += 1;
+        }
+
+        // One scalar, ten bins on each side and 91 changed bins.
+        let full = local.diff_with_limit(&chain, usize::MAX);
+        assert_eq!(full.omitted, 0);
+        assert_eq!(full.listed(), 112);
+        assert_eq!(local.diff(&chain).omitted, 12);
+
+        let short = local.diff_with_limit(&chain, 3);
+        assert_eq!(short.listed(), 3);
+        assert_eq!(short.omitted, 109);
+        assert!(!short.is_empty());
+        assert!(short.to_string().ends_with("... 109 more"), "{short}");
+        assert!(!local.diff_with_limit(&chain, 0).is_empty());
+    }
+}
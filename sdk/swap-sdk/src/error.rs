@@ -1,4 +1,11 @@
-use std::fmt;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+};
+use core::fmt;
+
+use crate::{abort::AbortReason, math::q64x64_math::PowError};
 
 #[derive(Debug)]
 pub enum DlmmError {
@@ -6,6 +13,90 @@ pub enum DlmmError {
     InvalidBinId,
     InvalidInput,
     MathOverflow,
+    ZeroLiquidity,
+    InvalidLayout(String),
+    SwapFailed(String),
+    InvalidConfig(String),
+    /// The pool was last updated `age` seconds before the swap timestamp,
+    /// more than [`SwapOptions::max_staleness_seconds`] allows.
+    ///
+    /// [`SwapOptions::max_staleness_seconds`]: crate::pool::SwapOptions::max_staleness_seconds
+    StaleState {
+        age: u64,
+    },
+    /// The swap timestamp is `behind` seconds before the pool's last update,
+    /// more than [`SwapOptions::max_clock_skew_seconds`] allows, or at all
+    /// under [`TimePolicy::Reject`].
+    ///
+    /// [`SwapOptions::max_clock_skew_seconds`]: crate::pool::SwapOptions::max_clock_skew_seconds
+    /// [`TimePolicy::Reject`]: crate::pool::TimePolicy::Reject
+    TimestampInPast {
+        behind: u64,
+    },
+    /// A fee rate passed to a [`Bin`](crate::Bin) fill is out of range:
+    /// `fee_rate` must be below [`FEE_PRECISION`](crate::FEE_PRECISION) and
+    /// `protocol_fee_rate` at most it. `which` names the parameter.
+    InvalidFeeRate {
+        which: &'static str,
+        value: u64,
+    },
+    /// The price of `bin_id` at `bin_step` is not a Q64.64 number; `error`
+    /// says which step of the power failed.
+    PriceOutOfRange {
+        bin_id: i32,
+        bin_step: u16,
+        error: PowError,
+    },
+    /// Step `step_index` of a swap, counting from 0 in the order the bins
+    /// were filled, failed in bin `bin_id` with `remaining_amount` of the
+    /// swap left. `source` is the step's own error, a
+    /// [`DlmmError::SwapFailed`] with its message if it was untyped.
+    StepFailed {
+        bin_id: i32,
+        step_index: usize,
+        remaining_amount: u128,
+        a2b: bool,
+        source: Box<DlmmError>,
+    },
+    /// A transaction aborted on-chain with one of the DLMM contract's errors.
+    Aborted(AbortReason),
+    /// A transaction aborted on-chain with an error that could not be
+    /// resolved to an [`AbortReason`], see [`DlmmError::from_move_abort`].
+    OnChainAbort {
+        module: String,
+        code: u64,
+    },
+    /// A [`PoolDelta`](crate::PoolDelta) was taken against a pool whose
+    /// [`state_hash`](crate::Pool::state_hash) is `expected`, but applied to
+    /// one whose hash is `actual`.
+    BaselineMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+}
+
+impl DlmmError {
+    /// The variant name, e.g. `"InvalidBinId"`, for reporting errors by kind.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DlmmError::InvalidStartBinIndex => "InvalidStartBinIndex",
+            DlmmError::InvalidBinId => "InvalidBinId",
+            DlmmError::InvalidInput => "InvalidInput",
+            DlmmError::MathOverflow => "MathOverflow",
+            DlmmError::ZeroLiquidity => "ZeroLiquidity",
+            DlmmError::InvalidLayout(_) => "InvalidLayout",
+            DlmmError::SwapFailed(_) => "SwapFailed",
+            DlmmError::InvalidConfig(_) => "InvalidConfig",
+            DlmmError::StaleState { .. } => "StaleState",
+            DlmmError::TimestampInPast { .. } => "TimestampInPast",
+            DlmmError::InvalidFeeRate { .. } => "InvalidFeeRate",
+            DlmmError::PriceOutOfRange { .. } => "PriceOutOfRange",
+            DlmmError::StepFailed { .. } => "StepFailed",
+            DlmmError::Aborted(_) => "Aborted",
+            DlmmError::OnChainAbort { .. } => "OnChainAbort",
+            DlmmError::BaselineMismatch { .. } => "BaselineMismatch",
+        }
+    }
 }
 
 impl fmt::Display for DlmmError {
@@ -15,8 +106,241 @@ impl fmt::Display for DlmmError {
             DlmmError::InvalidBinId => write!(f, "Invalid bin id"),
             DlmmError::InvalidInput => write!(f, "Invalid input data"),
             DlmmError::MathOverflow => write!(f, "Math overflow"),
+            DlmmError::ZeroLiquidity => write!(f, "Zero liquidity"),
+            DlmmError::InvalidLayout(reason) => write!(f, "Invalid object layout: {}", reason),
+            DlmmError::SwapFailed(reason) => write!(f, "Swap failed: {}", reason),
+            DlmmError::InvalidConfig(reason) => write!(f, "Invalid bin step config: {}", reason),
+            DlmmError::StaleState { age } => write!(f, "Pool state is {}s old", age),
+            DlmmError::TimestampInPast { behind } => {
+                write!(f, "Timestamp is {}s before the pool's last update", behind)
+            }
+            DlmmError::InvalidFeeRate { which, value } => write!(f, "Invalid {}: {}", which, value),
+            DlmmError::PriceOutOfRange {
+                bin_id,
+                bin_step,
+                error,
+            } => {
+                write!(
+                    f,
+                    "Price of bin {} at bin step {}: {}",
+                    bin_id, bin_step, error
+                )
+            }
+            DlmmError::StepFailed {
+                bin_id,
+                step_index,
+                remaining_amount,
+                a2b,
+                source,
+            } => write!(
+                f,
+                "Swap step {} failed in bin {} ({}, {} remaining): {}",
+                step_index,
+                bin_id,
+                if *a2b { "A to B" } else { "B to A" },
+                remaining_amount,
+                source
+            ),
+            DlmmError::Aborted(reason) => write!(f, "Aborted on-chain: {}", reason),
+            DlmmError::OnChainAbort { module, code } => {
+                write!(f, "Aborted on-chain in {} with code {}", module, code)
+            }
+            DlmmError::BaselineMismatch { expected, actual } => {
+                write!(f, "Delta baseline is ")?;
+                write_hex(f, expected)?;
+                write!(f, ", pool state is ")?;
+                write_hex(f, actual)
+            }
         }
     }
 }
 
-impl std::error::Error for DlmmError {}
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+}
+
+impl core::error::Error for DlmmError {}
+
+impl DlmmError {
+    /// The [`DlmmError`] in `err`, or `err` and its context wrapped in
+    /// [`DlmmError::SwapFailed`] if it is untyped.
+    pub(crate) fn from_anyhow(err: anyhow::Error) -> DlmmError {
+        err.downcast::<DlmmError>()
+            .unwrap_or_else(|err| DlmmError::SwapFailed(format!("{err:#}")))
+    }
+}
+
+/// The modules whose errors [`AbortReason`] covers.
+const DLMM_MODULES: [&str; 3] = ["pool", "bin", "config"];
+
+impl DlmmError {
+    /// The error for an abort with `code` in `module`, as returned by
+    /// [`parse_move_abort`]. The contract's codes are clever error codes
+    /// (see [`CleverAbort`]), which cannot be resolved to an
+    /// [`AbortReason`] without the deployed bytecode, so this is always
+    /// [`DlmmError::OnChainAbort`]; use [`DlmmError::from_abort_message`]
+    /// when the message names the error constant.
+    ///
+    /// [`CleverAbort`]: crate::abort::CleverAbort
+    pub fn from_move_abort(module: &str, code: u64) -> DlmmError {
+        DlmmError::OnChainAbort {
+            module: module.to_string(),
+            code,
+        }
+    }
+
+    /// The error for an abort message from the RPC or the Sui CLI, or
+    /// `None` if it does not describe a Move abort.
+    ///
+    /// An abort in the `pool`, `bin` or `config` module whose message names
+    /// the error constant, as the CLI does for clever errors, becomes
+    /// [`DlmmError::Aborted`]. Only the module name is compared, not the
+    /// package address.
+    pub fn from_abort_message(message: &str) -> Option<DlmmError> {
+        let constant = message
+            .split_once("Aborted with '")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map(|(constant, _)| constant);
+        let module = aborting_function(message).and_then(|function| function.rsplit_once("::"));
+        if let (Some(constant), Some((module, _))) = (constant, module) {
+            let name = module_name(module);
+            if DLMM_MODULES.contains(&name) {
+                if let Some(reason) = AbortReason::from_constant(constant) {
+                    if reason.module() == name {
+                        return Some(DlmmError::Aborted(reason));
+                    }
+                }
+            }
+        }
+        let (module, code) = parse_move_abort(message)?;
+        Some(DlmmError::from_move_abort(&module, code))
+    }
+}
+
+/// Extracts the aborting module and the abort code from an error string,
+/// either the RPC's `MoveAbort(MoveLocation { module: .., .. }, code)` or
+/// the CLI's `aborted within function '<module>::<function>' .. with code
+/// <code>`. The module is returned as printed, e.g. `0x2::pool`; a
+/// `ModuleId { address, name }` is joined the same way.
+pub fn parse_move_abort(s: &str) -> Option<(String, u64)> {
+    if let Some((_, location)) = s.split_once("MoveAbort(MoveLocation {") {
+        // The location ends at the brace closing `MoveLocation {`.
+        let mut depth = 1;
+        let end = location.find(|c| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })?;
+        let module = location[..end].split_once("module:")?.1.trim_start();
+        let module = match module.strip_prefix("ModuleId {") {
+            Some(id) => {
+                let address = field(id, "address:")?;
+                let name = id.split_once("name: Identifier(\"")?.1.split_once('"')?.0;
+                alloc::format!("{}::{}", address, name)
+            }
+            None => field(module, "")?.to_string(),
+        };
+        let code = location[end + 1..].trim_start().strip_prefix(',')?;
+        return Some((module, leading_number(code)?));
+    }
+    let function = aborting_function(s)?;
+    let (module, _) = function.rsplit_once("::")?;
+    let code = s.split_once(" with code ")?.1;
+    Some((module.to_string(), leading_number(code)?))
+}
+
+/// The `<module>::<function>` of a CLI abort message.
+fn aborting_function(s: &str) -> Option<&str> {
+    let rest = s.split_once("aborted within function '")?.1;
+    Some(rest.split_once('\'')?.0)
+}
+
+/// The module name of `0x2::pool` or `pool`.
+fn module_name(module: &str) -> &str {
+    module.rsplit("::").next().unwrap_or(module)
+}
+
+/// The value after `label` in a debug-printed struct, up to the next
+/// separator.
+fn field<'a>(s: &'a str, label: &str) -> Option<&'a str> {
+    let value = s.split_once(label)?.1.trim_start();
+    let end = value
+        .find(|c: char| c == ',' || c == '}' || c.is_whitespace())
+        .unwrap_or(value.len());
+    Some(&value[..end]).filter(|value| !value.is_empty())
+}
+
+fn leading_number(s: &str) -> Option<u64> {
+    let s = s.trim_start();
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abort_strings_parse() {
+        let compact =
+            "MoveAbort(MoveLocation { module: 0x2::pool, function: 12, instruction: 41 }, 5)";
+        assert_eq!(parse_move_abort(compact), Some(("0x2::pool".into(), 5)));
+
+        let module_id = "Transaction failed: MoveAbort(MoveLocation { module: ModuleId { \
+            address: 5664f9d3fd82c84023870cfbda8ea84e14c8dd56ce557ad2116e0668581a682b, \
+            name: Identifier(\"bin\") }, function: 3, instruction: 17, \
+            function_name: Some(\"swap\") }, 9223372638020091913) in command 2";
+        assert_eq!(
+            parse_move_abort(module_id),
+            Some((
+                "5664f9d3fd82c84023870cfbda8ea84e14c8dd56ce557ad2116e0668581a682b::bin".into(),
+                9223372638020091913
+            ))
+        );
+
+        let cli = "Error executing transaction 'Dx3e': 1st command aborted within function \
+            '0x2::coin::split' at instruction 8 with code 2";
+        assert_eq!(parse_move_abort(cli), Some(("0x2::coin".into(), 2)));
+
+        for invalid in [
+            "",
+            "InsufficientGas",
+            "MoveAbort(MoveLocation { module: 0x2::pool, function: 12 }",
+            "MoveAbort(MoveLocation { module: 0x2::pool }, code)",
+            "MoveAbort(MoveLocation { function: 12 }, 5)",
+        ] {
+            assert_eq!(parse_move_abort(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn abort_messages_name_dlmm_errors() {
+        let clever = "Error executing transaction 'Dx3e': 1st command aborted within function \
+            '0xabc::pool::swap' at line 902. Aborted with 'EPoolIsBlocked' -- 'Pool is blocked'";
+        assert!(matches!(
+            DlmmError::from_abort_message(clever),
+            Some(DlmmError::Aborted(AbortReason::PoolIsBlocked))
+        ));
+
+        // The constant has to belong to the aborting module.
+        let other_module = clever.replace("::pool::swap", "::bin::swap");
+        assert!(DlmmError::from_abort_message(&other_module).is_none());
+        let other_package = clever.replace("::pool::swap", "::router::swap");
+        assert!(DlmmError::from_abort_message(&other_package).is_none());
+
+        let code =
+            "MoveAbort(MoveLocation { module: 0xabc::config, function: 1, instruction: 4 }, 13)";
+        let err = DlmmError::from_abort_message(code).unwrap();
+        assert!(
+            matches!(err, DlmmError::OnChainAbort { ref module, code: 13 } if module == "0xabc::config")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Aborted on-chain in 0xabc::config with code 13"
+        );
+        assert!(DlmmError::from_abort_message("InsufficientGas").is_none());
+    }
+}
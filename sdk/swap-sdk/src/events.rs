@@ -0,0 +1,722 @@
+//! Pool events and replaying them onto a local [`Pool`].
+//!
+//! The data types mirror `SwapEvent`, `AddLiquidityEvent` and
+//! `RemoveLiquidityEvent` from `cetusdlmm::pool`, with the swap direction,
+//! resulting active id and transaction timestamp attached by the indexer (the
+//! on-chain swap event does not carry them). [`Pool::apply_event`] mutates the
+//! bins, `active_id`, fee growth and `v_parameters` the same way the contract
+//! did, so a snapshot can be kept in sync without re-fetching every bin.
+//!
+//! [`SwapEvent`], [`AddLiquidityEvent`], [`RemoveLiquidityEvent`] and
+//! [`CollectFeeEvent`] parse the events as Sui RPC renders them and convert
+//! into the data types above.
+
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    string::String,
+    vec::Vec,
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    bin::Bin,
+    error::DlmmError,
+    math::dlmm_math::{calculate_fee_inclusive, calculate_growth_by_amount},
+    pool::{BinSwap, Pool},
+    price::PriceQ64,
+    raw::{move_i32, move_struct},
+    serde_utils::{u128_string, u64_string},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapEventData {
+    pub a2b: bool,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_in: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_out: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub fee: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub protocol_fee: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub ref_fee: u64,
+    pub bin_swaps: Vec<BinSwap>,
+    /// Active bin id after the swap.
+    pub active_id: i32,
+    /// Transaction timestamp in seconds.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub timestamp: u64,
+}
+
+/// `cetusdlmm::pool::BinLiquidityDelta`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinLiquidityDelta {
+    pub bin_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub liquidity_share: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_a: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_b: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddLiquidityEventData {
+    pub active_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub total_amount_a: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub total_amount_b: u64,
+    /// Composition fees charged on the active bin.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub fee_a: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub fee_b: u64,
+    pub liquidity_deltas: Vec<BinLiquidityDelta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveLiquidityEventData {
+    pub active_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub total_amount_a: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub total_amount_b: u64,
+    pub liquidity_deltas: Vec<BinLiquidityDelta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolEvent {
+    Swap(SwapEventData),
+    AddLiquidity(AddLiquidityEventData),
+    RemoveLiquidity(RemoveLiquidityEventData),
+}
+
+// On-chain events as returned by Sui RPC (`parsedJson` of
+// `suix_queryEvents`): numbers are strings, `I32` is `{ "bits": <u32> }` and
+// `TypeName` is `{ "name": "<address>::<module>::<name>" }`.
+
+#[derive(Deserialize)]
+struct TypeName {
+    name: String,
+}
+
+fn move_type_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    move_struct::<D, TypeName>(deserializer).map(|type_name| type_name.name)
+}
+
+/// Whether two coin types name the same type, ignoring the `0x` prefix and
+/// leading zeros of the address (`TypeName` spells out all 64 hex digits).
+fn same_type(lhs: &str, rhs: &str) -> bool {
+    fn normalize(ty: &str) -> (&str, &str) {
+        let ty = ty.strip_prefix("0x").unwrap_or(ty);
+        let (address, rest) = ty.split_once("::").unwrap_or(("", ty));
+        (address.trim_start_matches('0'), rest)
+    }
+    normalize(lhs) == normalize(rhs)
+}
+
+/// `cetusdlmm::pool::BinSwap` as rendered by RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RawBinSwap {
+    #[serde(deserialize_with = "move_i32")]
+    pub bin_id: i32,
+    #[serde(with = "u64_string")]
+    pub amount_in: u64,
+    #[serde(with = "u64_string")]
+    pub amount_out: u64,
+    #[serde(with = "u64_string")]
+    pub fee: u64,
+    #[serde(with = "u64_string")]
+    pub var_fee_rate: u64,
+}
+
+impl RawBinSwap {
+    /// Converts into a [`BinSwap`] of a swap in the `a2b` direction, which
+    /// says whether `fee` was charged in token A or in token B.
+    ///
+    /// The event does not split the protocol fee per bin nor carry the bin
+    /// price, so `protocol_fee` and its per-token parts, `price` and
+    /// `execution_price` are left at zero.
+    pub fn to_bin_swap(&self, a2b: bool) -> BinSwap {
+        let (fee_a, fee_b) = if a2b { (self.fee, 0) } else { (0, self.fee) };
+        BinSwap {
+            bin_id: self.bin_id,
+            amount_in: self.amount_in,
+            amount_out: self.amount_out,
+            fee: self.fee,
+            var_fee_rate: self.var_fee_rate,
+            protocol_fee: 0,
+            price: 0,
+            execution_price: 0,
+            fee_a,
+            fee_b,
+            protocol_fee_a: 0,
+            protocol_fee_b: 0,
+        }
+    }
+}
+
+/// `cetusdlmm::pool::SwapEvent` as rendered by RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SwapEvent {
+    pub pool: String,
+    /// Coin type of the input token.
+    #[serde(deserialize_with = "move_type_name")]
+    pub from: String,
+    /// Coin type of the output token.
+    #[serde(deserialize_with = "move_type_name")]
+    pub target: String,
+    pub partner: String,
+    #[serde(with = "u64_string")]
+    pub amount_in: u64,
+    #[serde(with = "u64_string")]
+    pub amount_out: u64,
+    #[serde(with = "u64_string")]
+    pub fee: u64,
+    #[serde(with = "u64_string")]
+    pub protocol_fee: u64,
+    #[serde(with = "u64_string")]
+    pub ref_fee: u64,
+    /// Pool balances before the swap.
+    #[serde(with = "u64_string")]
+    pub vault_a: u64,
+    #[serde(with = "u64_string")]
+    pub vault_b: u64,
+    pub bin_swaps: Vec<RawBinSwap>,
+}
+
+impl SwapEvent {
+    /// The per-bin steps of a swap in the `a2b` direction, see
+    /// [`RawBinSwap::to_bin_swap`].
+    pub fn to_bin_swaps(&self, a2b: bool) -> Vec<BinSwap> {
+        self.bin_swaps
+            .iter()
+            .map(|step| step.to_bin_swap(a2b))
+            .collect()
+    }
+
+    /// Whether the swap sold `coin_type_a`, the pool's first coin type.
+    pub fn is_a2b(&self, coin_type_a: &str) -> bool {
+        same_type(&self.from, coin_type_a)
+    }
+
+    /// Converts into the replayable [`SwapEventData`], given the direction
+    /// (see [`is_a2b`](Self::is_a2b)) and the transaction timestamp in
+    /// seconds.
+    ///
+    /// The swap ends in the bin of its last step, which becomes the active
+    /// bin; an event without steps is rejected.
+    pub fn to_event_data(&self, a2b: bool, timestamp: u64) -> Result<SwapEventData, DlmmError> {
+        let active_id = self.bin_swaps.last().ok_or(DlmmError::InvalidInput)?.bin_id;
+        Ok(SwapEventData {
+            a2b,
+            amount_in: self.amount_in,
+            amount_out: self.amount_out,
+            fee: self.fee,
+            protocol_fee: self.protocol_fee,
+            ref_fee: self.ref_fee,
+            bin_swaps: self.to_bin_swaps(a2b),
+            active_id,
+            timestamp,
+        })
+    }
+}
+
+/// `cetusdlmm::pool::BinLiquidityDelta` as rendered by RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RawBinLiquidityDelta {
+    #[serde(deserialize_with = "move_i32")]
+    pub bin_id: i32,
+    #[serde(with = "u128_string")]
+    pub liquidity_share: u128,
+    #[serde(with = "u64_string")]
+    pub amount_a: u64,
+    #[serde(with = "u64_string")]
+    pub amount_b: u64,
+}
+
+impl From<RawBinLiquidityDelta> for BinLiquidityDelta {
+    fn from(raw: RawBinLiquidityDelta) -> Self {
+        BinLiquidityDelta {
+            bin_id: raw.bin_id,
+            liquidity_share: raw.liquidity_share,
+            amount_a: raw.amount_a,
+            amount_b: raw.amount_b,
+        }
+    }
+}
+
+/// `cetusdlmm::pool::AddLiquidityEvent` as rendered by RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AddLiquidityEvent {
+    pub pool: String,
+    pub position_id: String,
+    #[serde(deserialize_with = "move_i32")]
+    pub active_id: i32,
+    #[serde(with = "u64_string")]
+    pub total_amount_a: u64,
+    #[serde(with = "u64_string")]
+    pub total_amount_b: u64,
+    #[serde(with = "u64_string")]
+    pub fee_a: u64,
+    #[serde(with = "u64_string")]
+    pub fee_b: u64,
+    pub liquidity_deltas: Vec<RawBinLiquidityDelta>,
+}
+
+impl From<AddLiquidityEvent> for AddLiquidityEventData {
+    fn from(event: AddLiquidityEvent) -> Self {
+        AddLiquidityEventData {
+            active_id: event.active_id,
+            total_amount_a: event.total_amount_a,
+            total_amount_b: event.total_amount_b,
+            fee_a: event.fee_a,
+            fee_b: event.fee_b,
+            liquidity_deltas: event.liquidity_deltas.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// `cetusdlmm::pool::RemoveLiquidityEvent` as rendered by RPC.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RemoveLiquidityEvent {
+    pub pool: String,
+    pub position_id: String,
+    #[serde(deserialize_with = "move_i32")]
+    pub active_id: i32,
+    #[serde(with = "u64_string")]
+    pub total_amount_a: u64,
+    #[serde(with = "u64_string")]
+    pub total_amount_b: u64,
+    pub liquidity_deltas: Vec<RawBinLiquidityDelta>,
+}
+
+impl From<RemoveLiquidityEvent> for RemoveLiquidityEventData {
+    fn from(event: RemoveLiquidityEvent) -> Self {
+        RemoveLiquidityEventData {
+            active_id: event.active_id,
+            total_amount_a: event.total_amount_a,
+            total_amount_b: event.total_amount_b,
+            liquidity_deltas: event.liquidity_deltas.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// `cetusdlmm::pool::CollectFeeEvent` as rendered by RPC. Collecting fees
+/// does not change bin state, so it has no counterpart in [`PoolEvent`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CollectFeeEvent {
+    pub pool: String,
+    pub position: String,
+    #[serde(with = "u64_string")]
+    pub fee_a: u64,
+    #[serde(with = "u64_string")]
+    pub fee_b: u64,
+}
+
+/// Bins touched by an event, applied to the pool only once the whole event
+/// has been validated.
+type StagedBins = BTreeMap<i32, Bin>;
+
+impl Pool {
+    /// Applies an observed event to the local state.
+    ///
+    /// The pool is left untouched if the event does not fit it, e.g. it
+    /// references a bin that is not loaded or would drain more than a bin
+    /// holds.
+    pub fn apply_event(&mut self, event: &PoolEvent) -> Result<(), DlmmError> {
+        let baseline = self.debug_invariants_before();
+        match event {
+            PoolEvent::Swap(data) => self.apply_swap_event(data),
+            PoolEvent::AddLiquidity(data) => self.apply_add_liquidity_event(data),
+            PoolEvent::RemoveLiquidity(data) => self.apply_remove_liquidity_event(data),
+        }?;
+        self.debug_invariants_after(&baseline, "apply_event");
+        Ok(())
+    }
+
+    fn apply_swap_event(&mut self, data: &SwapEventData) -> Result<(), DlmmError> {
+        let (mut amount_in, mut amount_out) = (0u64, 0u64);
+        for step in &data.bin_swaps {
+            amount_in = amount_in
+                .checked_add(step.amount_in)
+                .ok_or(DlmmError::MathOverflow)?;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or(DlmmError::MathOverflow)?;
+        }
+        if amount_in != data.amount_in || amount_out != data.amount_out {
+            return Err(DlmmError::InvalidInput);
+        }
+
+        let protocol_fee_rate = self.v_parameters.bin_step_config.protocol_fee_rate;
+        let mut staged = StagedBins::new();
+        for step in &data.bin_swaps {
+            let bin = self.stage_bin(&mut staged, step.bin_id, false)?;
+            let lp_fee = step
+                .fee
+                .checked_sub(
+                    calculate_fee_inclusive(step.fee, protocol_fee_rate)
+                        .map_err(|_| DlmmError::MathOverflow)?,
+                )
+                .ok_or(DlmmError::MathOverflow)?;
+            let amount_in = step
+                .amount_in
+                .checked_sub(step.fee)
+                .ok_or(DlmmError::InvalidInput)?;
+            let (reserve_in, reserve_out, fee_growth) = if data.a2b {
+                (
+                    &mut bin.amount_a,
+                    &mut bin.amount_b,
+                    &mut bin.fee_amount_a_growth_global,
+                )
+            } else {
+                (
+                    &mut bin.amount_b,
+                    &mut bin.amount_a,
+                    &mut bin.fee_amount_b_growth_global,
+                )
+            };
+            *reserve_in = reserve_in
+                .checked_add(amount_in)
+                .ok_or(DlmmError::MathOverflow)?;
+            *reserve_out = reserve_out
+                .checked_sub(step.amount_out)
+                .ok_or(DlmmError::MathOverflow)?;
+            accrue_fee_growth(fee_growth, lp_fee, bin.liquidity_supply)?;
+        }
+
+        // Replays the volatility updates of the swap loop: references are
+        // refreshed once, then the accumulator is updated as each bin becomes
+        // active.
+        let mut v_parameters = self.v_parameters.clone();
+        let mut active_id = self.active_id;
+        v_parameters
+            .refresh(active_id, data.timestamp)
+            .map_err(|_| DlmmError::MathOverflow)?;
+        for (i, step) in data.bin_swaps.iter().enumerate() {
+            if i > 0 {
+                active_id = step.bin_id;
+            }
+            v_parameters
+                .update_volatility_accumulator(active_id)
+                .map_err(|_| DlmmError::MathOverflow)?;
+        }
+        self.v_parameters = v_parameters;
+        self.active_id = data.active_id;
+        self.commit_bins(staged);
+        Ok(())
+    }
+
+    fn apply_add_liquidity_event(&mut self, data: &AddLiquidityEventData) -> Result<(), DlmmError> {
+        let mut staged = StagedBins::new();
+        if data.fee_a > 0 || data.fee_b > 0 {
+            let protocol_fee_rate = self.v_parameters.bin_step_config.protocol_fee_rate;
+            let bin = self.stage_bin(&mut staged, data.active_id, false)?;
+            for (fee, fee_growth) in [
+                (data.fee_a, &mut bin.fee_amount_a_growth_global),
+                (data.fee_b, &mut bin.fee_amount_b_growth_global),
+            ] {
+                let protocol_fee = calculate_fee_inclusive(fee, protocol_fee_rate)
+                    .map_err(|_| DlmmError::MathOverflow)?;
+                accrue_fee_growth(fee_growth, fee - protocol_fee, bin.liquidity_supply)?;
+            }
+        }
+        for delta in &data.liquidity_deltas {
+            let bin = self.stage_bin(&mut staged, delta.bin_id, true)?;
+            bin.amount_a = bin
+                .amount_a
+                .checked_add(delta.amount_a)
+                .ok_or(DlmmError::MathOverflow)?;
+            bin.amount_b = bin
+                .amount_b
+                .checked_add(delta.amount_b)
+                .ok_or(DlmmError::MathOverflow)?;
+            bin.liquidity_supply = bin
+                .liquidity_supply
+                .checked_add(delta.liquidity_share)
+                .ok_or(DlmmError::MathOverflow)?;
+        }
+        self.active_id = data.active_id;
+        self.commit_bins(staged);
+        Ok(())
+    }
+
+    fn apply_remove_liquidity_event(
+        &mut self,
+        data: &RemoveLiquidityEventData,
+    ) -> Result<(), DlmmError> {
+        let mut staged = StagedBins::new();
+        for delta in &data.liquidity_deltas {
+            let bin = self.stage_bin(&mut staged, delta.bin_id, false)?;
+            bin.amount_a = bin
+                .amount_a
+                .checked_sub(delta.amount_a)
+                .ok_or(DlmmError::MathOverflow)?;
+            bin.amount_b = bin
+                .amount_b
+                .checked_sub(delta.amount_b)
+                .ok_or(DlmmError::MathOverflow)?;
+            bin.liquidity_supply = bin
+                .liquidity_supply
+                .checked_sub(delta.liquidity_share)
+                .ok_or(DlmmError::MathOverflow)?;
+        }
+        self.active_id = data.active_id;
+        self.commit_bins(staged);
+        Ok(())
+    }
+
+    /// Returns the staged copy of `bin_id`, cloning it from `bins` on first
+    /// use. Missing bins are an error unless `create` is set, in which case an
+    /// empty bin priced from its id is staged.
+    fn stage_bin<'a>(
+        &self,
+        staged: &'a mut StagedBins,
+        bin_id: i32,
+        create: bool,
+    ) -> Result<&'a mut Bin, DlmmError> {
+        match staged.entry(bin_id) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let bin = match self.bins.binary_search_by_key(&bin_id, |bin| bin.id) {
+                    Ok(idx) => self.bins[idx].clone(),
+                    Err(_) if create => Bin {
+                        id: bin_id,
+                        price: PriceQ64::from_id(
+                            bin_id,
+                            self.v_parameters.bin_step_config.bin_step,
+                        )?,
+                        ..Default::default()
+                    },
+                    Err(_) => return Err(DlmmError::InvalidBinId),
+                };
+                Ok(entry.insert(bin))
+            }
+        }
+    }
+
+    /// Writes staged bins back, keeping `bins` sorted. Bins left without
+    /// liquidity or reserves are removed, as the contract does.
+    fn commit_bins(&mut self, staged: StagedBins) {
+        for (bin_id, bin) in staged {
+            let is_empty = bin.liquidity_supply == 0 && bin.amount_a == 0 && bin.amount_b == 0;
+            match (
+                self.bins.binary_search_by_key(&bin_id, |bin| bin.id),
+                is_empty,
+            ) {
+                (Ok(idx), true) => {
+                    self.bins.remove(idx);
+                }
+                (Ok(idx), false) => self.bins[idx] = bin,
+                (Err(idx), false) => self.bins.insert(idx, bin),
+                (Err(_), true) => {}
+            }
+        }
+    }
+}
+
+fn accrue_fee_growth(fee_growth: &mut u128, fee: u64, liquidity: u128) -> Result<(), DlmmError> {
+    if fee == 0 || liquidity == 0 {
+        return Ok(());
+    }
+    let delta = calculate_growth_by_amount(fee, liquidity).map_err(|_| DlmmError::MathOverflow)?;
+    *fee_growth = fee_growth.wrapping_add(delta);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = include_str!("../tests/fixtures/synthetic_events_start_pool.json");
+    const EVENTS: &str = include_str!("../tests/fixtures/synthetic_events_replay.json");
+    const END: &str = include_str!("../tests/fixtures/synthetic_events_end_pool.json");
+
+    #[test]
+    fn replay_matches_end_snapshot() {
+        let mut pool: Pool = serde_json::from_str(START).unwrap();
+        let events: Vec<PoolEvent> = serde_json::from_str(EVENTS).unwrap();
+        let end: Pool = serde_json::from_str(END).unwrap();
+
+        for event in &events {
+            pool.apply_event(event).unwrap();
+        }
+        assert_eq!(pool, end);
+        assert!(pool.validate().is_ok());
+    }
+
+    #[test]
+    fn rejected_event_leaves_pool_untouched() {
+        let pool: Pool = serde_json::from_str(START).unwrap();
+        let events: Vec<PoolEvent> = serde_json::from_str(EVENTS).unwrap();
+        let PoolEvent::Swap(mut swap) = events[0].clone() else {
+            panic!("first fixture event is a swap");
+        };
+
+        let mut missing = swap.clone();
+        missing.bin_swaps.last_mut().unwrap().bin_id = 1_000;
+        let mut copy = pool.clone();
+        assert!(matches!(
+            copy.apply_event(&PoolEvent::Swap(missing)),
+            Err(DlmmError::InvalidBinId)
+        ));
+        assert_eq!(copy, pool);
+
+        swap.bin_swaps.last_mut().unwrap().amount_out = u64::MAX / 2;
+        swap.amount_out = swap.bin_swaps.iter().map(|step| step.amount_out).sum();
+        let mut copy = pool.clone();
+        assert!(matches!(
+            copy.apply_event(&PoolEvent::Swap(swap)),
+            Err(DlmmError::MathOverflow)
+        ));
+        assert_eq!(copy, pool);
+    }
+
+    #[test]
+    fn rpc_events_convert_into_the_replayed_events() {
+        const SUI: &str = "0x2::sui::SUI";
+        let response: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/rpc_events.json")).unwrap();
+        let mut converted = Vec::new();
+        let mut collected = Vec::new();
+        for event in response["data"].as_array().unwrap() {
+            let kind = event["type"].as_str().unwrap().rsplit("::").next().unwrap();
+            let timestamp = event["timestampMs"]
+                .as_str()
+                .unwrap()
+                .parse::<u64>()
+                .unwrap()
+                / 1000;
+            let json = event["parsedJson"].clone();
+            match kind {
+                "SwapEvent" => {
+                    let swap: SwapEvent = serde_json::from_value(json).unwrap();
+                    let a2b = swap.is_a2b(SUI);
+                    let steps = swap.to_bin_swaps(a2b);
+                    assert_eq!(
+                        steps.iter().map(|step| step.amount_in).sum::<u64>(),
+                        swap.amount_in
+                    );
+                    // The fees are all in the input token.
+                    let fees = steps
+                        .iter()
+                        .fold((0, 0), |(a, b), step| (a + step.fee_a, b + step.fee_b));
+                    assert_eq!(fees, if a2b { (swap.fee, 0) } else { (0, swap.fee) });
+                    let data = swap.to_event_data(a2b, timestamp).unwrap();
+                    converted.push(PoolEvent::Swap(data));
+                }
+                "AddLiquidityEvent" => {
+                    let add: AddLiquidityEvent = serde_json::from_value(json).unwrap();
+                    converted.push(PoolEvent::AddLiquidity(add.into()));
+                }
+                "RemoveLiquidityEvent" => {
+                    let remove: RemoveLiquidityEvent = serde_json::from_value(json).unwrap();
+                    converted.push(PoolEvent::RemoveLiquidity(remove.into()));
+                }
+                "CollectFeeEvent" => {
+                    collected.push(serde_json::from_value::<CollectFeeEvent>(json).unwrap());
+                }
+                other => panic!("unexpected event {other}"),
+            }
+        }
+
+        let expected: Vec<PoolEvent> = serde_json::from_str(EVENTS).unwrap();
+        assert_eq!(converted, expected);
+        let PoolEvent::Swap(first) = &converted[0] else {
+            panic!("first fixture event is a swap");
+        };
+        assert_eq!(
+            first
+                .bin_swaps
+                .iter()
+                .map(|step| step.bin_id)
+                .collect::<Vec<_>>(),
+            [0, -1, -2]
+        );
+        assert_eq!((collected[0].fee_a, collected[0].fee_b), (1_187, 2_410));
+
+        let mut pool: Pool = serde_json::from_str(START).unwrap();
+        for event in &converted {
+            pool.apply_event(event).unwrap();
+        }
+        assert_eq!(pool, serde_json::from_str::<Pool>(END).unwrap());
+    }
+
+    #[test]
+    fn swap_event_direction_and_empty_steps() {
+        let mut swap: SwapEvent = serde_json::from_value(serde_json::json!({
+            "pool": "0x1",
+            "from": { "name": "dba34672e30cb065b1f93e3ab55318768fd6fef66c15942c9f7cb846e2f900e7::usdc::USDC" },
+            "target": { "name": "0000000000000000000000000000000000000000000000000000000000000002::sui::SUI" },
+            "partner": "0x0",
+            "amount_in": "0", "amount_out": "0", "fee": "0", "protocol_fee": "0", "ref_fee": "0",
+            "vault_a": "0", "vault_b": "0",
+            "bin_swaps": []
+        }))
+        .unwrap();
+        assert!(!swap.is_a2b("0x2::sui::SUI"));
+        assert!(swap.is_a2b(
+            "0xdba34672e30cb065b1f93e3ab55318768fd6fef66c15942c9f7cb846e2f900e7::usdc::USDC"
+        ));
+        assert!(!swap.is_a2b(
+            "0xdba34672e30cb065b1f93e3ab55318768fd6fef66c15942c9f7cb846e2f900e7::usdc::USDT"
+        ));
+        assert!(matches!(
+            swap.to_event_data(true, 0),
+            Err(DlmmError::InvalidInput)
+        ));
+        swap.from = "0x2::sui::SUI".into();
+        assert!(swap
+            .is_a2b("0000000000000000000000000000000000000000000000000000000000000002::sui::SUI"));
+    }
+}
@@ -0,0 +1,219 @@
+//! Unit-safe fee rates.
+//!
+//! Swap fee rates are in [`FEE_PRECISION`] units, where `1e9` is 100%,
+//! while volatility and reduction factors are in basis points of
+//! [`BASIS_POINT_MAX`](crate::math::BASIS_POINT_MAX). Passing one where the
+//! other is expected is off by a factor of `1e5` and still looks like a
+//! plausible number. [`FeeRate`] is always stored in [`FEE_PRECISION`]
+//! units, and its constructors say which unit the caller has.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::FEE_PRECISION;
+
+/// [`FEE_PRECISION`] units in one basis point.
+const RAW_PER_BPS: u64 = FEE_PRECISION / 10_000;
+
+/// [`FEE_PRECISION`] units in one percent.
+const RAW_PER_PERCENT: u64 = FEE_PRECISION / 100;
+
+/// Fractional digits of a percent that [`FEE_PRECISION`] can represent.
+const PERCENT_DECIMALS: usize = 7;
+
+/// A fee rate in [`FEE_PRECISION`] units, so `FeeRate::from_raw(2_500_000)`
+/// is 0.25%.
+///
+/// Methods taking a fee rate take `impl Into<FeeRate>`. A plain `u64` is
+/// still accepted and read as [`FEE_PRECISION`] units, which is deprecated:
+/// a basis point value passes silently and charges a fee `1e5` times too
+/// small. Use [`FeeRate::from_bps`] or [`FeeRate::from_percent_str`]
+/// instead, or [`FeeRate::from_raw`] for values read from chain.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct FeeRate(u64);
+
+/// Why a [`FeeRate`] conversion has no exact result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeRateError {
+    /// The string is not a percentage such as `0.25%`.
+    Malformed,
+    /// The value is finer than the target unit, e.g. `0.00000001%` or
+    /// half a basis point.
+    Inexact,
+    /// The value does not fit in the target type.
+    OutOfRange,
+}
+
+impl fmt::Display for FeeRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeRateError::Malformed => write!(f, "Malformed fee rate percentage"),
+            FeeRateError::Inexact => write!(f, "Fee rate is not exactly representable"),
+            FeeRateError::OutOfRange => write!(f, "Fee rate out of range"),
+        }
+    }
+}
+
+impl core::error::Error for FeeRateError {}
+
+impl FeeRate {
+    pub const ZERO: Self = Self(0);
+
+    /// A rate in [`FEE_PRECISION`] units, as stored on chain.
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    /// A rate in basis points; one is `100_000` raw units.
+    pub const fn from_bps(bps: u32) -> Self {
+        Self(bps as u64 * RAW_PER_BPS)
+    }
+
+    /// Parses a percentage such as `"0.25%"` or `"1%"`. The `%` is
+    /// required, and at most seven fractional digits may be non-zero, the
+    /// resolution of [`FEE_PRECISION`].
+    pub fn from_percent_str(percent: &str) -> Result<Self, FeeRateError> {
+        let number = percent.strip_suffix('%').ok_or(FeeRateError::Malformed)?;
+        let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty()
+            || !is_digits(int)
+            || !is_digits(frac)
+            || (number.contains('.') && frac.is_empty())
+        {
+            return Err(FeeRateError::Malformed);
+        }
+        let (kept, dropped) = frac.split_at(frac.len().min(PERCENT_DECIMALS));
+        if dropped.bytes().any(|b| b != b'0') {
+            return Err(FeeRateError::Inexact);
+        }
+
+        let digits = |s: &str| {
+            s.bytes().try_fold(0u64, |acc, b| {
+                acc.checked_mul(10)?.checked_add((b - b'0').into())
+            })
+        };
+        let frac_raw =
+            digits(kept).unwrap_or_default() * 10u64.pow((PERCENT_DECIMALS - kept.len()) as u32);
+        digits(int)
+            .and_then(|int| int.checked_mul(RAW_PER_PERCENT))
+            .and_then(|raw| raw.checked_add(frac_raw))
+            .map(Self)
+            .ok_or(FeeRateError::OutOfRange)
+    }
+
+    pub const fn as_raw(self) -> u64 {
+        self.0
+    }
+
+    /// The rate in basis points. Fails with [`FeeRateError::Inexact`]
+    /// instead of truncating a rate that is not a whole number of them.
+    pub const fn as_bps(self) -> Result<u32, FeeRateError> {
+        if !self.0.is_multiple_of(RAW_PER_BPS) {
+            return Err(FeeRateError::Inexact);
+        }
+        let bps = self.0 / RAW_PER_BPS;
+        if bps > u32::MAX as u64 {
+            return Err(FeeRateError::OutOfRange);
+        }
+        Ok(bps as u32)
+    }
+}
+
+/// Reads `raw` as [`FEE_PRECISION`] units, matching the old `u64`
+/// parameters.
+impl From<u64> for FeeRate {
+    fn from(raw: u64) -> Self {
+        Self::from_raw(raw)
+    }
+}
+
+/// The exact percentage, e.g. `0.25%`, which
+/// [`from_percent_str`](FeeRate::from_percent_str) reads back.
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let int = self.0 / RAW_PER_PERCENT;
+        let frac = self.0 % RAW_PER_PERCENT;
+        if frac == 0 {
+            return write!(f, "{int}%");
+        }
+        let mut digits = alloc::format!("{frac:0width$}", width = PERCENT_DECIMALS);
+        digits.truncate(digits.trim_end_matches('0').len());
+        write!(f, "{int}.{digits}%")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_convert_exactly() {
+        let quarter = FeeRate::from_raw(2_500_000);
+        assert_eq!(FeeRate::from_bps(25), quarter);
+        assert_eq!(FeeRate::from_percent_str("0.25%"), Ok(quarter));
+        assert_eq!(FeeRate::from(2_500_000), quarter);
+        assert_eq!(quarter.as_bps(), Ok(25));
+        assert_eq!(quarter.to_string(), "0.25%");
+
+        assert_eq!(
+            FeeRate::from_percent_str("100%").unwrap().as_raw(),
+            FEE_PRECISION
+        );
+        assert_eq!(FeeRate::from_percent_str("1.0%").unwrap().to_string(), "1%");
+        assert_eq!(FeeRate::from_percent_str("0%"), Ok(FeeRate::ZERO));
+        // The smallest raw unit, with zeros past it.
+        let one = FeeRate::from_percent_str("0.000000100%").unwrap();
+        assert_eq!(one.as_raw(), 1);
+        assert_eq!(one.to_string(), "0.0000001%");
+        assert_eq!(FeeRate::from_bps(u32::MAX).as_bps(), Ok(u32::MAX));
+        for raw in [0, 1, 30_000, 2_500_000, 123_456_789, u64::MAX] {
+            let rate = FeeRate::from_raw(raw);
+            assert_eq!(FeeRate::from_percent_str(&rate.to_string()), Ok(rate));
+        }
+    }
+
+    #[test]
+    fn lossy_conversions_are_rejected() {
+        // 0.0003 bps, what passing 3 bps as raw units quotes.
+        assert_eq!(FeeRate::from(30).as_bps(), Err(FeeRateError::Inexact));
+        assert_eq!(
+            FeeRate::from_raw(150_000).as_bps(),
+            Err(FeeRateError::Inexact)
+        );
+        assert_eq!(
+            FeeRate::from_raw(u64::MAX - u64::MAX % RAW_PER_BPS).as_bps(),
+            Err(FeeRateError::OutOfRange)
+        );
+
+        assert_eq!(
+            FeeRate::from_percent_str("0.00000001%"),
+            Err(FeeRateError::Inexact)
+        );
+        assert_eq!(
+            FeeRate::from_percent_str("0.250000005%"),
+            Err(FeeRateError::Inexact)
+        );
+        assert_eq!(
+            FeeRate::from_percent_str("1844674407371%"),
+            Err(FeeRateError::OutOfRange)
+        );
+        assert_eq!(
+            FeeRate::from_percent_str("99999999999999999999%"),
+            Err(FeeRateError::OutOfRange)
+        );
+        for malformed in [
+            "0.25", "", "%", ".5%", "1.%", "-1%", "+1%", "1e2%", "0.2 5%", "1%%",
+        ] {
+            assert_eq!(
+                FeeRate::from_percent_str(malformed),
+                Err(FeeRateError::Malformed),
+                "{malformed}"
+            );
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! A full consistency audit of a pool, see [`Pool::check_invariants`].
+//!
+//! [`Pool::validate`] checks what the swap loop needs to run at all. The
+//! audit goes further and checks that the pool could exist on chain: prices
+//! match the bin step, the volatility accumulator is within its cap and
+//! bins hold reserves exactly when they have liquidity. It is meant for
+//! pools that were deserialized, merged or patched by hand.
+//!
+//! With the `debug-invariants` feature, debug builds audit the pool around
+//! each swap, [`Pool::apply_swap_result`], [`Pool::apply_event`],
+//! [`Pool::insert_bin`], [`Pool::bins_mut_with`] and [`Pool::merge_from`],
+//! and panic if the call added a violation. Violations the pool already had
+//! are not reported again, so pools built for tests with made-up prices can
+//! still be swapped against. Removing bins cannot break a consistent pool
+//! and is not audited. Release builds skip the audit.
+
+use alloc::{string::ToString, vec::Vec};
+use core::fmt;
+
+use crate::{
+    math::price_math::get_price_from_id, pool::Pool, price::PriceQ64, FEE_PRECISION, MAX_BIN_ID,
+    MAX_FEE_RATE, MIN_BIN_ID,
+};
+
+/// A bin's price may differ from the price of its id by up to
+/// `expected >> PRICE_TOLERANCE_SHIFT`, about two parts in `10^10`. Prices
+/// read from chain match exactly.
+pub const PRICE_TOLERANCE_SHIFT: u32 = 32;
+
+/// An inconsistency found by [`Pool::check_invariants`], with the observed
+/// value and the bound or value it was expected to have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// `active_id` is outside [`MIN_BIN_ID`]`..=`[`MAX_BIN_ID`].
+    ActiveIdOutOfRange { active_id: i32 },
+    /// A bin id is outside [`MIN_BIN_ID`]`..=`[`MAX_BIN_ID`].
+    BinIdOutOfRange { bin_id: i32 },
+    /// The bin at `index` has a lower id than the bin before it.
+    BinsOutOfOrder {
+        index: usize,
+        previous_id: i32,
+        bin_id: i32,
+    },
+    /// The bin at `index` has the same id as the bin before it.
+    DuplicateBin { index: usize, bin_id: i32 },
+    /// A bin's price is not above the price of the bin before it.
+    PriceNotIncreasing {
+        bin_id: i32,
+        previous_price: PriceQ64,
+        price: PriceQ64,
+    },
+    /// A bin's price is not within [`PRICE_TOLERANCE_SHIFT`] of the price
+    /// of its id at the pool's bin step. `expected` is `None` when that
+    /// price is beyond Q64.64, so no bin can have the id.
+    PriceMismatch {
+        bin_id: i32,
+        bin_step: u16,
+        observed: PriceQ64,
+        expected: Option<PriceQ64>,
+    },
+    /// A fee rate is above its bound; `field` is the path of the field.
+    FeeRateAboveMax {
+        field: &'static str,
+        observed: u64,
+        max: u64,
+    },
+    /// `volatility_accumulator` is above the config's
+    /// `max_volatility_accumulator`, which swaps never exceed.
+    VolatilityAboveMax { observed: u32, max: u32 },
+    /// A bin holds reserves but has no liquidity supply to own them.
+    ReservesWithoutLiquidity {
+        bin_id: i32,
+        amount_a: u64,
+        amount_b: u64,
+    },
+    /// A bin has liquidity supply but no reserves. The contract removes
+    /// the supply with the last reserves, and a swap always leaves input
+    /// in the bin it takes output from.
+    LiquidityWithoutReserves { bin_id: i32, liquidity_supply: u128 },
+}
+
+impl InvariantViolation {
+    /// The bin the violation is about, if it is about one.
+    pub fn bin_id(&self) -> Option<i32> {
+        match *self {
+            Self::BinIdOutOfRange { bin_id }
+            | Self::BinsOutOfOrder { bin_id, .. }
+            | Self::DuplicateBin { bin_id, .. }
+            | Self::PriceNotIncreasing { bin_id, .. }
+            | Self::PriceMismatch { bin_id, .. }
+            | Self::ReservesWithoutLiquidity { bin_id, .. }
+            | Self::LiquidityWithoutReserves { bin_id, .. } => Some(bin_id),
+            Self::ActiveIdOutOfRange { .. }
+            | Self::FeeRateAboveMax { .. }
+            | Self::VolatilityAboveMax { .. } => None,
+        }
+    }
+
+    /// Identifies the violation across mutations, for `debug-invariants`:
+    /// its class and bin, but not the values, which a swap may change.
+    fn key(&self) -> (u8, Option<i32>) {
+        let class = match self {
+            Self::ActiveIdOutOfRange { .. } => 0,
+            Self::BinIdOutOfRange { .. } => 1,
+            Self::BinsOutOfOrder { .. } => 2,
+            Self::DuplicateBin { .. } => 3,
+            Self::PriceNotIncreasing { .. } => 4,
+            Self::PriceMismatch { .. } => 5,
+            Self::FeeRateAboveMax { .. } => 6,
+            Self::VolatilityAboveMax { .. } => 7,
+            Self::ReservesWithoutLiquidity { .. } => 8,
+            Self::LiquidityWithoutReserves { .. } => 9,
+        };
+        (class, self.bin_id())
+    }
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ActiveIdOutOfRange { active_id } => write!(
+                f,
+                "active_id {active_id} is outside [{MIN_BIN_ID}, {MAX_BIN_ID}]"
+            ),
+            Self::BinIdOutOfRange { bin_id } => write!(
+                f,
+                "bin {bin_id}: id is outside [{MIN_BIN_ID}, {MAX_BIN_ID}]"
+            ),
+            Self::BinsOutOfOrder {
+                index,
+                previous_id,
+                bin_id,
+            } => write!(
+                f,
+                "bin {bin_id}: at index {index} after bin {previous_id}, expected a higher id"
+            ),
+            Self::DuplicateBin { index, bin_id } => {
+                write!(f, "bin {bin_id}: listed again at index {index}")
+            }
+            Self::PriceNotIncreasing {
+                bin_id,
+                previous_price,
+                price,
+            } => write!(
+                f,
+                "bin {bin_id}: price {price}, expected above the previous bin's {previous_price}"
+            ),
+            Self::PriceMismatch {
+                bin_id,
+                bin_step,
+                observed,
+                expected: Some(expected),
+            } => write!(
+                f,
+                "bin {bin_id}: price {observed}, expected {expected} at bin_step {bin_step}"
+            ),
+            Self::PriceMismatch {
+                bin_id,
+                bin_step,
+                observed,
+                expected: None,
+            } => write!(
+                f,
+                "bin {bin_id}: price {observed}, but the id has no Q64.64 price at bin_step {bin_step}"
+            ),
+            Self::FeeRateAboveMax {
+                field,
+                observed,
+                max,
+            } => write!(f, "{field} {observed}, expected at most {max}"),
+            Self::VolatilityAboveMax { observed, max } => write!(
+                f,
+                "v_parameters.volatility_accumulator {observed}, expected at most {max}"
+            ),
+            Self::ReservesWithoutLiquidity {
+                bin_id,
+                amount_a,
+                amount_b,
+            } => write!(
+                f,
+                "bin {bin_id}: reserves {amount_a} A and {amount_b} B, expected none without liquidity_supply"
+            ),
+            Self::LiquidityWithoutReserves {
+                bin_id,
+                liquidity_supply,
+            } => write!(
+                f,
+                "bin {bin_id}: liquidity_supply {liquidity_supply}, expected 0 without reserves"
+            ),
+        }
+    }
+}
+
+impl Pool {
+    /// Audits the pool's internal consistency, returning every violation
+    /// found: pool-level ones first, then the bins' in bin order. See the
+    /// [module docs](crate::invariants) for what is checked beyond
+    /// [`Pool::validate`].
+    ///
+    /// Runs in one pass over the bins, computing each bin's price from its
+    /// id, so it is cheap enough to call after every mutation.
+    pub fn check_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let violations = self.invariant_violations();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn invariant_violations(&self) -> Vec<InvariantViolation> {
+        let mut violations = Vec::new();
+        if !(MIN_BIN_ID..=MAX_BIN_ID).contains(&self.active_id) {
+            violations.push(InvariantViolation::ActiveIdOutOfRange {
+                active_id: self.active_id,
+            });
+        }
+        let config = &self.v_parameters.bin_step_config;
+        for (field, observed, max) in [
+            ("base_fee_rate", self.base_fee_rate, MAX_FEE_RATE),
+            (
+                "v_parameters.bin_step_config.protocol_fee_rate",
+                config.protocol_fee_rate,
+                FEE_PRECISION,
+            ),
+        ] {
+            if observed > max {
+                violations.push(InvariantViolation::FeeRateAboveMax {
+                    field,
+                    observed,
+                    max,
+                });
+            }
+        }
+        let volatility_accumulator = self.v_parameters.volatility_accumulator;
+        if volatility_accumulator > config.max_volatility_accumulator {
+            violations.push(InvariantViolation::VolatilityAboveMax {
+                observed: volatility_accumulator,
+                max: config.max_volatility_accumulator,
+            });
+        }
+
+        for (index, bin) in self.bins.iter().enumerate() {
+            if let Some(previous) = index.checked_sub(1).map(|i| &self.bins[i]) {
+                if bin.id < previous.id {
+                    violations.push(InvariantViolation::BinsOutOfOrder {
+                        index,
+                        previous_id: previous.id,
+                        bin_id: bin.id,
+                    });
+                } else if bin.id == previous.id {
+                    violations.push(InvariantViolation::DuplicateBin {
+                        index,
+                        bin_id: bin.id,
+                    });
+                } else if bin.price <= previous.price {
+                    violations.push(InvariantViolation::PriceNotIncreasing {
+                        bin_id: bin.id,
+                        previous_price: previous.price,
+                        price: bin.price,
+                    });
+                }
+            }
+
+            if !(MIN_BIN_ID..=MAX_BIN_ID).contains(&bin.id) {
+                violations.push(InvariantViolation::BinIdOutOfRange { bin_id: bin.id });
+            } else {
+                let expected = get_price_from_id(bin.id, config.bin_step).ok();
+                let matches = expected.is_some_and(|expected| {
+                    bin.price.as_raw().abs_diff(expected) <= expected >> PRICE_TOLERANCE_SHIFT
+                });
+                if !matches {
+                    violations.push(InvariantViolation::PriceMismatch {
+                        bin_id: bin.id,
+                        bin_step: config.bin_step,
+                        observed: bin.price,
+                        expected: expected.map(PriceQ64::from_raw),
+                    });
+                }
+            }
+
+            let has_reserves = bin.amount_a != 0 || bin.amount_b != 0;
+            if has_reserves && bin.liquidity_supply == 0 {
+                violations.push(InvariantViolation::ReservesWithoutLiquidity {
+                    bin_id: bin.id,
+                    amount_a: bin.amount_a,
+                    amount_b: bin.amount_b,
+                });
+            } else if !has_reserves && bin.liquidity_supply != 0 {
+                violations.push(InvariantViolation::LiquidityWithoutReserves {
+                    bin_id: bin.id,
+                    liquidity_supply: bin.liquidity_supply,
+                });
+            }
+        }
+        violations
+    }
+
+    /// The violations to compare against after a mutation, sorted, or
+    /// nothing unless `debug-invariants` is on in a debug build.
+    pub(crate) fn debug_invariants_before(&self) -> Vec<(u8, Option<i32>)> {
+        if !cfg!(all(feature = "debug-invariants", debug_assertions)) {
+            return Vec::new();
+        }
+        let mut keys: Vec<_> = self
+            .invariant_violations()
+            .iter()
+            .map(InvariantViolation::key)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Panics if `operation` added a violation to those in `before`, from
+    /// [`Pool::debug_invariants_before`]. A no-op unless `debug-invariants`
+    /// is on in a debug build.
+    pub(crate) fn debug_invariants_after(&self, before: &[(u8, Option<i32>)], operation: &str) {
+        if !cfg!(all(feature = "debug-invariants", debug_assertions)) {
+            return;
+        }
+        let added: Vec<_> = self
+            .invariant_violations()
+            .into_iter()
+            .filter(|violation| before.binary_search(&violation.key()).is_err())
+            .map(|violation| violation.to_string())
+            .collect();
+        assert!(
+            added.is_empty(),
+            "{operation} broke pool invariants: {}",
+            added.join("; ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{arbitrary::small_pool, builder::PoolBuilder, config::BinStepConfig};
+
+    /// Bins -3 to 3 with reserves, priced for bin step 25.
+    fn pool() -> Pool {
+        PoolBuilder::new()
+            .config(BinStepConfig::new(
+                25, 1, 60, 600, 9000, 40_000, 350_000, 30_000,
+            ))
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-3, 3, 1_000_000, 1_000_000)
+            .build()
+            .unwrap()
+    }
+
+    /// The one violation `f` introduces into [`pool`].
+    fn violation(f: impl FnOnce(&mut Pool)) -> InvariantViolation {
+        let mut pool = pool();
+        f(&mut pool);
+        let mut violations = pool.check_invariants().unwrap_err();
+        assert_eq!(violations.len(), 1, "{violations:?}");
+        violations.remove(0)
+    }
+
+    #[test]
+    fn each_violation_is_reported_on_its_own() {
+        assert_eq!(pool().check_invariants(), Ok(()));
+
+        assert_eq!(
+            violation(|pool| pool.active_id = MAX_BIN_ID + 1),
+            InvariantViolation::ActiveIdOutOfRange {
+                active_id: MAX_BIN_ID + 1
+            }
+        );
+        // Prices are only checked for ids in range.
+        assert_eq!(
+            violation(|pool| pool.bins[6].id = MAX_BIN_ID + 1),
+            InvariantViolation::BinIdOutOfRange {
+                bin_id: MAX_BIN_ID + 1
+            }
+        );
+        assert_eq!(
+            violation(|pool| pool.bins.swap(2, 3)),
+            InvariantViolation::BinsOutOfOrder {
+                index: 3,
+                previous_id: 0,
+                bin_id: -1
+            }
+        );
+        assert_eq!(
+            violation(|pool| pool.bins.insert(3, pool.bins[3].clone())),
+            InvariantViolation::DuplicateBin {
+                index: 4,
+                bin_id: 0
+            }
+        );
+        // At a bin step of 0 every id is priced 1, so only the order of the
+        // prices is wrong.
+        assert_eq!(
+            violation(|pool| {
+                pool.v_parameters.bin_step_config.bin_step = 0;
+                pool.bins.truncate(2);
+                for bin in &mut pool.bins {
+                    bin.price = PriceQ64::ONE;
+                }
+            }),
+            InvariantViolation::PriceNotIncreasing {
+                bin_id: -2,
+                previous_price: PriceQ64::ONE,
+                price: PriceQ64::ONE
+            }
+        );
+        let off = PriceQ64::from_raw(PriceQ64::ONE.as_raw() + (1 << 40));
+        assert_eq!(
+            violation(|pool| pool.bins[3].price = off),
+            InvariantViolation::PriceMismatch {
+                bin_id: 0,
+                bin_step: 25,
+                observed: off,
+                expected: Some(PriceQ64::ONE)
+            }
+        );
+        let price = pool().bins[6].price;
+        assert_eq!(
+            violation(|pool| pool.bins[6].id = MAX_BIN_ID),
+            InvariantViolation::PriceMismatch {
+                bin_id: MAX_BIN_ID,
+                bin_step: 25,
+                observed: price,
+                expected: None
+            }
+        );
+        assert_eq!(
+            violation(|pool| pool.base_fee_rate = MAX_FEE_RATE + 1),
+            InvariantViolation::FeeRateAboveMax {
+                field: "base_fee_rate",
+                observed: MAX_FEE_RATE + 1,
+                max: MAX_FEE_RATE
+            }
+        );
+        assert_eq!(
+            violation(
+                |pool| pool.v_parameters.bin_step_config.protocol_fee_rate = FEE_PRECISION + 1
+            ),
+            InvariantViolation::FeeRateAboveMax {
+                field: "v_parameters.bin_step_config.protocol_fee_rate",
+                observed: FEE_PRECISION + 1,
+                max: FEE_PRECISION
+            }
+        );
+        assert_eq!(
+            violation(|pool| pool.v_parameters.volatility_accumulator = 350_001),
+            InvariantViolation::VolatilityAboveMax {
+                observed: 350_001,
+                max: 350_000
+            }
+        );
+        assert_eq!(
+            violation(|pool| pool.bins[0].liquidity_supply = 0),
+            InvariantViolation::ReservesWithoutLiquidity {
+                bin_id: -3,
+                amount_a: 0,
+                amount_b: 1_000_000
+            }
+        );
+        assert_eq!(
+            violation(|pool| pool.bins[0].amount_b = 0),
+            InvariantViolation::LiquidityWithoutReserves {
+                bin_id: -3,
+                liquidity_supply: 1_000_000
+            }
+        );
+    }
+
+    #[test]
+    fn prices_within_the_tolerance_pass() {
+        let mut pool = pool();
+        let price = pool.bins[3].price.as_raw();
+        pool.bins[3].price = PriceQ64::from_raw(price + (price >> PRICE_TOLERANCE_SHIFT));
+        assert_eq!(pool.check_invariants(), Ok(()));
+        pool.bins[3].price = PriceQ64::from_raw(price - (price >> PRICE_TOLERANCE_SHIFT) - 1);
+        assert!(pool.check_invariants().is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "debug-invariants", debug_assertions))]
+    #[should_panic(expected = "insert_bin broke pool invariants: bin 4: price")]
+    fn debug_invariants_catch_a_new_violation() {
+        let mut pool = pool();
+        let bin = crate::Bin {
+            id: 4,
+            ..pool.bins[6].clone()
+        };
+        let _ = pool.insert_bin(bin);
+    }
+
+    #[test]
+    fn debug_invariants_allow_existing_violations() {
+        let mut pool = pool();
+        pool.bins[0].liquidity_supply = 0;
+        let result = pool.swap_exact_amount_in(5_000_000, true, 0).unwrap();
+        assert!(result.steps.iter().any(|step| step.bin_id == -3));
+        assert_eq!(pool.check_invariants().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn violations_name_their_values() {
+        let violation = InvariantViolation::PriceMismatch {
+            bin_id: 7,
+            bin_step: 25,
+            observed: PriceQ64::from_raw(5),
+            expected: Some(PriceQ64::from_raw(6)),
+        };
+        assert_eq!(violation.bin_id(), Some(7));
+        assert_eq!(
+            violation.to_string(),
+            "bin 7: price 5, expected 6 at bin_step 25"
+        );
+        let violation = InvariantViolation::VolatilityAboveMax {
+            observed: 2,
+            max: 1,
+        };
+        assert_eq!(violation.bin_id(), None);
+        assert_eq!(
+            violation.to_string(),
+            "v_parameters.volatility_accumulator 2, expected at most 1"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn swaps_keep_generated_pools_consistent(
+            mut pool in small_pool(),
+            amount in 1..=crate::arbitrary::MAX_BIN_RESERVE,
+            a2b: bool,
+        ) {
+            prop_assert_eq!(pool.check_invariants(), Ok(()));
+            if pool.swap_exact_amount_in(amount, a2b, 0).is_ok() {
+                prop_assert_eq!(pool.check_invariants(), Ok(()));
+            }
+        }
+    }
+}
@@ -1,12 +1,80 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod abort;
+pub mod analytics;
+#[cfg(any(test, feature = "arbitrary"))]
+pub mod arbitrary;
+#[cfg(feature = "rayon")]
+pub mod batch;
+#[cfg(feature = "bcs")]
+pub mod bcs;
 pub mod bin;
+pub mod bin_group;
+pub mod builder;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod compute;
 pub mod config;
+pub mod conformance;
+pub mod delta;
+pub mod dense_index;
+pub mod depth;
+pub mod diff;
 pub mod error;
+pub mod events;
+pub mod fee_rate;
+pub mod invariants;
+pub mod liquidity;
+#[cfg(feature = "manager")]
+pub mod manager;
 pub mod math;
+pub mod metadata;
+#[cfg(feature = "migrate")]
+pub mod migrate;
+pub mod packed;
 pub mod pool;
+pub mod position;
+pub mod price;
+pub mod raw;
+pub mod replay;
+pub mod router;
+pub mod scenario;
+#[cfg(feature = "schemars")]
+pub mod schema;
+pub mod serde_utils;
+pub mod state_hash;
+pub mod swappable;
+pub mod timestamp;
+pub mod verify;
+pub mod wire;
 
 pub const MAX_FEE_RATE: u64 = 100_000_000;
 pub const FEE_PRECISION: u64 = 1_000_000_000;
 
-pub use crate::bin::Bin;
+/// Smallest bin id the on-chain program accepts.
+pub const MIN_BIN_ID: i32 = -(math::price_math::BIN_BOUND as i32);
+/// Largest bin id the on-chain program accepts.
+pub const MAX_BIN_ID: i32 = math::price_math::BIN_BOUND as i32;
+
+pub use crate::bin::{Bin, FillQuote};
+pub use crate::builder::PoolBuilder;
 pub use crate::config::{BinStepConfig, VariableParameters};
-pub use crate::pool::{BinSwap, Pool, SwapResult};
+pub use crate::delta::PoolDelta;
+pub use crate::dense_index::DenseBinIndex;
+pub use crate::diff::PoolDiff;
+pub use crate::fee_rate::FeeRate;
+pub use crate::invariants::InvariantViolation;
+pub use crate::metadata::PoolMetadata;
+pub use crate::pool::{
+    BinSwap, BinSwapU128, BinsToward, DrainQuote, LadderPoint, MergePreference, Pool,
+    PoolCheckpoint, PoolStats, PoolSummary, SwapMode, SwapOptions, SwapResult, SwapResultU128,
+    TimePolicy,
+};
+pub use crate::position::{Position, PositionBin, RewarderInfo};
+pub use crate::price::{PriceQ64, PriceTable};
+pub use crate::swappable::Swappable;
+#[cfg(feature = "std")]
+pub use crate::timestamp::SystemClock;
+pub use crate::timestamp::{Clock, ManualClock, Timestamp};
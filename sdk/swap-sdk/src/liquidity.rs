@@ -0,0 +1,290 @@
+//! Planning changes to a liquidity position.
+//!
+//! Positions are described bin by bin as `(bin_id, amount_a, amount_b)`.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+/// One change to a bin of the position, see [`plan_rebalance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebalanceAction {
+    /// Withdraw these amounts from the bin.
+    Remove {
+        id: i32,
+        amount_a: u64,
+        amount_b: u64,
+    },
+    /// Deposit these amounts into the bin.
+    Add {
+        id: i32,
+        amount_a: u64,
+        amount_b: u64,
+    },
+    /// Change a bin where one token goes up and the other down. Positive
+    /// deltas are deposits and negative ones withdrawals.
+    Adjust {
+        id: i32,
+        delta_a: i128,
+        delta_b: i128,
+    },
+}
+
+impl RebalanceAction {
+    pub fn id(&self) -> i32 {
+        match *self {
+            RebalanceAction::Remove { id, .. }
+            | RebalanceAction::Add { id, .. }
+            | RebalanceAction::Adjust { id, .. } => id,
+        }
+    }
+
+    /// Signed change of `(amount_a, amount_b)` in the bin.
+    pub fn deltas(&self) -> (i128, i128) {
+        match *self {
+            RebalanceAction::Remove {
+                amount_a, amount_b, ..
+            } => (-(amount_a as i128), -(amount_b as i128)),
+            RebalanceAction::Add {
+                amount_a, amount_b, ..
+            } => (amount_a as i128, amount_b as i128),
+            RebalanceAction::Adjust {
+                delta_a, delta_b, ..
+            } => (delta_a, delta_b),
+        }
+    }
+}
+
+/// The actions turning one position into another, see [`plan_rebalance`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RebalancePlan {
+    /// At most one action per bin whose amounts change. Removals come
+    /// first, then adjustments, then additions, each by ascending bin id, so
+    /// freed tokens are available before they are needed.
+    pub actions: Vec<RebalanceAction>,
+    /// Total withdrawn across all actions.
+    pub removed_a: u128,
+    pub removed_b: u128,
+    /// Total deposited across all actions.
+    pub added_a: u128,
+    pub added_b: u128,
+}
+
+impl RebalancePlan {
+    /// `added - removed` per token: positive amounts have to be provided on
+    /// top of what the plan frees, negative ones are left over.
+    pub fn net(&self) -> (i128, i128) {
+        (
+            self.added_a as i128 - self.removed_a as i128,
+            self.added_b as i128 - self.removed_b as i128,
+        )
+    }
+
+    /// The amounts to provide on top of what the plan frees.
+    pub fn top_up(&self) -> (u128, u128) {
+        (
+            self.added_a.saturating_sub(self.removed_a),
+            self.added_b.saturating_sub(self.removed_b),
+        )
+    }
+}
+
+/// Plans moving a position from the `current` amounts per bin to the
+/// `target` amounts, with a single action for each bin that changes.
+///
+/// Bins missing from one side hold nothing there. Entries repeating a bin
+/// id are added together, saturating at `u64::MAX`.
+pub fn plan_rebalance(current: &[(i32, u64, u64)], target: &[(i32, u64, u64)]) -> RebalancePlan {
+    let mut bins: BTreeMap<i32, [(u64, u64); 2]> = BTreeMap::new();
+    for (side, entries) in [current, target].into_iter().enumerate() {
+        for &(id, amount_a, amount_b) in entries {
+            let amounts = &mut bins.entry(id).or_default()[side];
+            amounts.0 = amounts.0.saturating_add(amount_a);
+            amounts.1 = amounts.1.saturating_add(amount_b);
+        }
+    }
+
+    let mut plan = RebalancePlan::default();
+    let (mut removes, mut adjusts, mut adds) = (Vec::new(), Vec::new(), Vec::new());
+    for (id, [(from_a, from_b), (to_a, to_b)]) in bins {
+        let removed = (from_a.saturating_sub(to_a), from_b.saturating_sub(to_b));
+        let added = (to_a.saturating_sub(from_a), to_b.saturating_sub(from_b));
+        plan.removed_a += removed.0 as u128;
+        plan.removed_b += removed.1 as u128;
+        plan.added_a += added.0 as u128;
+        plan.added_b += added.1 as u128;
+        match (removed != (0, 0), added != (0, 0)) {
+            (false, false) => {}
+            (true, false) => removes.push(RebalanceAction::Remove {
+                id,
+                amount_a: removed.0,
+                amount_b: removed.1,
+            }),
+            (false, true) => adds.push(RebalanceAction::Add {
+                id,
+                amount_a: added.0,
+                amount_b: added.1,
+            }),
+            (true, true) => adjusts.push(RebalanceAction::Adjust {
+                id,
+                delta_a: to_a as i128 - from_a as i128,
+                delta_b: to_b as i128 - from_b as i128,
+            }),
+        }
+    }
+    plan.actions = removes;
+    plan.actions.append(&mut adjusts);
+    plan.actions.append(&mut adds);
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies `plan` to `current`, returning the non-empty bins.
+    fn apply(current: &[(i32, u64, u64)], plan: &RebalancePlan) -> Vec<(i32, u64, u64)> {
+        let mut bins: BTreeMap<i32, (i128, i128)> = current
+            .iter()
+            .map(|&(id, a, b)| (id, (a as i128, b as i128)))
+            .collect();
+        for action in &plan.actions {
+            let (delta_a, delta_b) = action.deltas();
+            let bin = bins.entry(action.id()).or_default();
+            bin.0 += delta_a;
+            bin.1 += delta_b;
+            assert!(bin.0 >= 0 && bin.1 >= 0, "{action:?} overdraws");
+        }
+        bins.into_iter()
+            .filter(|(_, amounts)| *amounts != (0, 0))
+            .map(|(id, (a, b))| (id, a as u64, b as u64))
+            .collect()
+    }
+
+    fn assert_conserves(plan: &RebalancePlan) {
+        let (top_up_a, top_up_b) = plan.top_up();
+        let (net_a, net_b) = plan.net();
+        assert_eq!(
+            plan.removed_a + top_up_a,
+            plan.added_a + (-net_a).max(0) as u128
+        );
+        assert_eq!(
+            plan.removed_b + top_up_b,
+            plan.added_b + (-net_b).max(0) as u128
+        );
+        let (mut added, mut removed) = ((0, 0), (0, 0));
+        for action in &plan.actions {
+            let (a, b) = action.deltas();
+            added.0 += a.max(0);
+            added.1 += b.max(0);
+            removed.0 -= a.min(0);
+            removed.1 -= b.min(0);
+        }
+        assert_eq!(added, (plan.added_a as i128, plan.added_b as i128));
+        assert_eq!(removed, (plan.removed_a as i128, plan.removed_b as i128));
+    }
+
+    #[test]
+    fn shifting_a_position_up_conserves_tokens() {
+        // A position around active bin 0 moved to one around bin 2.
+        let current = [
+            (-2, 0, 100),
+            (-1, 0, 100),
+            (0, 50, 50),
+            (1, 100, 0),
+            (2, 100, 0),
+        ];
+        let target = [
+            (0, 0, 100),
+            (1, 0, 100),
+            (2, 50, 50),
+            (3, 100, 0),
+            (4, 100, 0),
+        ];
+        let plan = plan_rebalance(&current, &target);
+        assert_eq!(apply(&current, &plan), target);
+        assert_conserves(&plan);
+        assert_eq!(
+            plan.actions,
+            [
+                RebalanceAction::Remove {
+                    id: -2,
+                    amount_a: 0,
+                    amount_b: 100
+                },
+                RebalanceAction::Remove {
+                    id: -1,
+                    amount_a: 0,
+                    amount_b: 100
+                },
+                RebalanceAction::Adjust {
+                    id: 0,
+                    delta_a: -50,
+                    delta_b: 50
+                },
+                RebalanceAction::Adjust {
+                    id: 1,
+                    delta_a: -100,
+                    delta_b: 100
+                },
+                RebalanceAction::Adjust {
+                    id: 2,
+                    delta_a: -50,
+                    delta_b: 50
+                },
+                RebalanceAction::Add {
+                    id: 3,
+                    amount_a: 100,
+                    amount_b: 0
+                },
+                RebalanceAction::Add {
+                    id: 4,
+                    amount_a: 100,
+                    amount_b: 0
+                },
+            ]
+        );
+        // The same shape shifted: 200 of each token out and 200 back in.
+        assert_eq!((plan.removed_a, plan.removed_b), (200, 200));
+        assert_eq!(plan.net(), (0, 0));
+        assert_eq!(plan.top_up(), (0, 0));
+    }
+
+    #[test]
+    fn changed_amounts_are_one_action_per_bin() {
+        let current = [(0, 100, 100), (1, 100, 0), (2, 5, 5)];
+        let target = [(0, 150, 120), (1, 40, 0), (2, 5, 5)];
+        let plan = plan_rebalance(&current, &target);
+        assert_eq!(
+            plan.actions,
+            [
+                RebalanceAction::Remove {
+                    id: 1,
+                    amount_a: 60,
+                    amount_b: 0
+                },
+                RebalanceAction::Add {
+                    id: 0,
+                    amount_a: 50,
+                    amount_b: 20
+                },
+            ]
+        );
+        assert_eq!(apply(&current, &plan), target);
+        assert_conserves(&plan);
+        assert!(plan_rebalance(&current, &current).actions.is_empty());
+    }
+
+    #[test]
+    fn repeated_ids_are_summed() {
+        let plan = plan_rebalance(&[(0, 10, 0), (0, 5, 0)], &[(0, 15, 0), (1, u64::MAX, 0)]);
+        assert_eq!(
+            plan.actions,
+            [RebalanceAction::Add {
+                id: 1,
+                amount_a: u64::MAX,
+                amount_b: 0
+            }]
+        );
+        assert_eq!(plan.top_up(), (u64::MAX as u128, 0));
+        assert_conserves(&plan);
+    }
+}
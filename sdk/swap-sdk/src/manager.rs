@@ -0,0 +1,400 @@
+//! A thread-safe registry of pools keyed by address (`manager` feature).
+//!
+//! [`PoolManager`] locks at two levels. The map from keys to pools sits
+//! behind one lock, held only long enough to find or replace a pool; each
+//! pool then has its own lock. Quotes take the pool's read lock, and events
+//! its write lock, so an event on one pool never blocks quotes on another,
+//! and quotes on the same pool run concurrently.
+//!
+//! The locks are `std::sync::RwLock`, or `parking_lot::RwLock` with the
+//! `parking_lot` feature. A `std` lock poisoned by a panicking thread is
+//! used as is: pool updates validate before they write, so a panic does not
+//! leave a half-applied change behind.
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    ops::{Deref, DerefMut},
+    string::String,
+    sync::Arc,
+};
+
+use crate::{
+    error::DlmmError,
+    events::PoolEvent,
+    pool::{Pool, SwapMode, SwapResult},
+    timestamp::Timestamp,
+};
+
+#[cfg(feature = "parking_lot")]
+use parking_lot::RwLock;
+#[cfg(not(feature = "parking_lot"))]
+use std::sync::RwLock;
+
+/// Errors from [`PoolManager`].
+#[derive(Debug)]
+pub enum ManagerError {
+    /// No pool is registered under the key.
+    UnknownPool,
+    /// The pool rejected the quote or event.
+    Pool(DlmmError),
+}
+
+impl fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManagerError::UnknownPool => write!(f, "Unknown pool"),
+            ManagerError::Pool(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ManagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ManagerError::UnknownPool => None,
+            ManagerError::Pool(err) => Some(err),
+        }
+    }
+}
+
+impl From<DlmmError> for ManagerError {
+    fn from(err: DlmmError) -> Self {
+        ManagerError::Pool(err)
+    }
+}
+
+/// Pools shared between threads, keyed by pool address or any other key.
+///
+/// Every method takes `&self`; share the manager with an `Arc`. See the
+/// [module docs](self) for the locking.
+pub struct PoolManager<K = String> {
+    pools: RwLock<HashMap<K, Arc<RwLock<Pool>>>>,
+}
+
+impl<K> Default for PoolManager<K> {
+    fn default() -> Self {
+        Self {
+            pools: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> PoolManager<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `pool` under `key`, returning the pool it replaces.
+    ///
+    /// A replaced pool is swapped under its own write lock, so quotes
+    /// already running on it finish on the old state. The map lock is held
+    /// meanwhile, so a concurrent [`remove`](Self::remove) cannot take the
+    /// pool out of the map in between.
+    pub fn upsert(&self, key: K, pool: Pool) -> Option<Pool> {
+        {
+            let pools = read(&self.pools);
+            if let Some(existing) = pools.get(&key) {
+                return Some(core::mem::replace(&mut *write(existing), pool));
+            }
+        }
+        let mut pools = write(&self.pools);
+        match pools.get(&key) {
+            // Inserted by another thread since the lookup.
+            Some(existing) => Some(core::mem::replace(&mut *write(existing), pool)),
+            None => {
+                pools.insert(key, Arc::new(RwLock::new(pool)));
+                None
+            }
+        }
+    }
+
+    /// Quotes a swap on the pool under `key` without changing it, as
+    /// [`Pool::quote_exact_amount_in`] or [`Pool::quote_exact_amount_out`]
+    /// depending on `mode`.
+    pub fn quote<Q>(
+        &self,
+        key: &Q,
+        amount: u64,
+        a2b: bool,
+        mode: SwapMode,
+        timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResult, ManagerError>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let pool = self.pool(key)?;
+        let pool = read(&pool);
+        let quote = match mode {
+            SwapMode::ExactIn => pool.quote_exact_amount_in(amount, a2b, timestamp),
+            SwapMode::ExactOut => pool.quote_exact_amount_out(amount, a2b, timestamp),
+        };
+        quote.map_err(|err| DlmmError::from_anyhow(err).into())
+    }
+
+    /// Applies `event` to the pool under `key` with [`Pool::apply_event`].
+    /// A rejected event leaves the pool unchanged.
+    pub fn apply_event<Q>(&self, key: &Q, event: &PoolEvent) -> Result<(), ManagerError>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let pool = self.pool(key)?;
+        let result = write(&pool).apply_event(event);
+        Ok(result?)
+    }
+
+    /// The [`Pool::state_hash`] of the pool under `key`.
+    pub fn state_hash<Q>(&self, key: &Q) -> Result<[u8; 32], ManagerError>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let pool = self.pool(key)?;
+        let hash = read(&pool).state_hash();
+        Ok(hash)
+    }
+
+    /// A copy of the pool under `key`, e.g. to diff against a fresh fetch.
+    pub fn get<Q>(&self, key: &Q) -> Option<Pool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let pool = self.pool(key).ok()?;
+        let pool = read(&pool).clone();
+        Some(pool)
+    }
+
+    /// Unregisters the pool under `key` and returns it. Quotes already
+    /// running on it finish.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Pool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let pool = write(&self.pools).remove(key)?;
+        let pool = Arc::try_unwrap(pool)
+            .map(into_inner)
+            .unwrap_or_else(|pool| read(&pool).clone());
+        Some(pool)
+    }
+
+    /// The number of registered pools.
+    pub fn len(&self) -> usize {
+        read(&self.pools).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The pool under `key`, with the map lock released.
+    fn pool<Q>(&self, key: &Q) -> Result<Arc<RwLock<Pool>>, ManagerError>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        read(&self.pools)
+            .get(key)
+            .cloned()
+            .ok_or(ManagerError::UnknownPool)
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+fn read<T>(lock: &RwLock<T>) -> impl Deref<Target = T> + '_ {
+    lock.read()
+}
+
+#[cfg(feature = "parking_lot")]
+fn write<T>(lock: &RwLock<T>) -> impl DerefMut<Target = T> + '_ {
+    lock.write()
+}
+
+#[cfg(feature = "parking_lot")]
+fn into_inner<T>(lock: RwLock<T>) -> T {
+    lock.into_inner()
+}
+
+#[cfg(not(feature = "parking_lot"))]
+fn read<T>(lock: &RwLock<T>) -> impl Deref<Target = T> + '_ {
+    lock.read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(not(feature = "parking_lot"))]
+fn write<T>(lock: &RwLock<T>) -> impl DerefMut<Target = T> + '_ {
+    lock.write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(not(feature = "parking_lot"))]
+fn into_inner<T>(lock: RwLock<T>) -> T {
+    lock.into_inner()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        vec::Vec,
+    };
+
+    use super::*;
+
+    const START: &str = include_str!("../tests/fixtures/synthetic_events_start_pool.json");
+    const EVENTS: &str = include_str!("../tests/fixtures/synthetic_events_replay.json");
+    const END: &str = include_str!("../tests/fixtures/synthetic_events_end_pool.json");
+
+    fn start_pool() -> Pool {
+        serde_json::from_str(START).unwrap()
+    }
+
+    #[test]
+    fn pools_are_registered_by_key() {
+        let manager = PoolManager::<String>::new();
+        assert!(manager.is_empty());
+        assert!(manager.upsert("0xa".into(), start_pool()).is_none());
+
+        let quote = manager
+            .quote("0xa", 1_000, true, SwapMode::ExactIn, 1_000)
+            .unwrap();
+        assert_eq!(
+            quote,
+            start_pool()
+                .quote_exact_amount_in(1_000, true, 1_000)
+                .unwrap()
+        );
+        let quote = manager
+            .quote("0xa", 1_000, false, SwapMode::ExactOut, 1_000)
+            .unwrap();
+        assert_eq!(
+            quote,
+            start_pool()
+                .quote_exact_amount_out(1_000, false, 1_000)
+                .unwrap()
+        );
+        assert_eq!(
+            manager.state_hash("0xa").unwrap(),
+            start_pool().state_hash()
+        );
+
+        let end: Pool = serde_json::from_str(END).unwrap();
+        assert_eq!(
+            manager.upsert("0xa".into(), end.clone()),
+            Some(start_pool())
+        );
+        assert_eq!(manager.get("0xa"), Some(end.clone()));
+        assert_eq!(manager.len(), 1);
+
+        assert!(matches!(
+            manager.quote("0xb", 1_000, true, SwapMode::ExactIn, 1_000),
+            Err(ManagerError::UnknownPool)
+        ));
+        assert!(matches!(
+            manager.state_hash("0xb"),
+            Err(ManagerError::UnknownPool)
+        ));
+        assert_eq!(manager.remove("0xa"), Some(end));
+        assert_eq!(manager.remove("0xa"), None);
+        assert!(manager.is_empty());
+    }
+
+    #[test]
+    fn pool_errors_are_passed_through() {
+        let manager = PoolManager::<u32>::new();
+        let mut empty = start_pool();
+        empty.bins.clear();
+        manager.upsert(7u32, empty);
+        let events: Vec<PoolEvent> = serde_json::from_str(EVENTS).unwrap();
+        let err = manager.apply_event(&7, &events[0]).unwrap_err();
+        assert!(matches!(err, ManagerError::Pool(_)), "{err}");
+        assert!(matches!(
+            manager.apply_event(&8, &events[0]),
+            Err(ManagerError::UnknownPool)
+        ));
+    }
+
+    #[test]
+    fn concurrent_upserts_and_removes_lose_no_pool() {
+        const POOLS: u64 = 5_000;
+        let manager = PoolManager::<String>::new();
+        let template = start_pool();
+        let done = AtomicBool::new(false);
+        let (mut seen, removed) = thread::scope(|scope| {
+            let remover = scope.spawn(|| {
+                let mut removed = Vec::new();
+                while !done.load(Ordering::Relaxed) {
+                    removed.extend(manager.remove("0xa"));
+                }
+                removed
+            });
+            let mut replaced = Vec::new();
+            for fee in 1..=POOLS {
+                let pool = Pool {
+                    base_fee_rate: fee,
+                    ..template.clone()
+                };
+                replaced.extend(manager.upsert("0xa".into(), pool));
+            }
+            done.store(true, Ordering::Relaxed);
+            (replaced, remover.join().unwrap())
+        });
+        seen.extend(removed);
+        seen.extend(manager.remove("0xa"));
+
+        // Every pool comes back exactly once: replaced, removed or last.
+        let mut fees: Vec<u64> = seen.iter().map(|pool| pool.base_fee_rate).collect();
+        fees.sort_unstable();
+        fees.dedup();
+        assert_eq!((fees.len(), seen.len()), (POOLS as usize, POOLS as usize));
+    }
+
+    #[test]
+    fn quotes_on_one_pool_run_while_another_is_updated() {
+        let manager = PoolManager::<String>::new();
+        manager.upsert("hot".into(), start_pool());
+        manager.upsert("cold".into(), start_pool());
+        let events: Vec<PoolEvent> = serde_json::from_str(EVENTS).unwrap();
+        let expected = start_pool()
+            .quote_exact_amount_in(50_000, true, 2_000)
+            .unwrap();
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for i in 0..500u64 {
+                        let a2b = i % 2 == 0;
+                        let quote = manager
+                            .quote("hot", 50_000, a2b, SwapMode::ExactIn, 2_000)
+                            .unwrap();
+                        if a2b {
+                            assert_eq!(quote, expected);
+                        }
+                        // Quotes against the pool being updated see a
+                        // consistent state, before or after each event.
+                        let _ = manager.quote("cold", 50_000, a2b, SwapMode::ExactIn, 2_000);
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for _ in 0..50 {
+                    manager.upsert("cold".into(), start_pool());
+                    for event in &events {
+                        manager.apply_event("cold", event).unwrap();
+                    }
+                }
+            });
+        });
+
+        let end: Pool = serde_json::from_str(END).unwrap();
+        assert_eq!(manager.state_hash("cold").unwrap(), end.state_hash());
+        assert_eq!(manager.get("hot"), Some(start_pool()));
+    }
+}
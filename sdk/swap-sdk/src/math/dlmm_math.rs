@@ -3,51 +3,105 @@ use ruint::aliases::U256;
 
 use crate::{
     FEE_PRECISION,
+    error::DlmmError,
     math::{Rounding, full_math::mul_div, q64x64_math::ONE},
+    price::PriceQ64,
 };
 
 /// U256::from_limbs([0, 0, 1, 0]) = 1 << 128
+///
+/// Fails with [`DlmmError::ZeroLiquidity`] when `liquidity` is zero and with
+/// [`DlmmError::MathOverflow`] when the growth does not fit in a `u128`.
 pub fn calculate_growth_by_amount(amount: u64, liquidity: u128) -> Result<u128, Error> {
+    if liquidity == 0 {
+        return Err(DlmmError::ZeroLiquidity).context("calculate_growth_by_amount");
+    }
     let amount = U256::from(amount);
     let liquidity = U256::from(liquidity);
     let result = amount
         .checked_mul(U256::from_limbs([0, 0, 1, 0]))
         .context("calculate_growth_by_amount: overflow")?
-        .checked_div(liquidity)
-        .context("calculate_growth_by_amount: overflow")?;
-    Ok(result.try_into().unwrap())
+        / liquidity;
+    result
+        .try_into()
+        .map_err(|_| DlmmError::MathOverflow)
+        .context("calculate_growth_by_amount: overflow")
 }
 
 /// U256::from_limbs([0, 0, 1, 0]) = 1 << 128
+///
+/// Zero liquidity earns nothing, so the amount is `0`. Fails with
+/// [`DlmmError::MathOverflow`] when the amount does not fit in a `u64`.
 pub fn calculate_amount_by_growth(growth_delta: u128, liquidity: u128) -> Result<u64, Error> {
     let growth_delta = U256::from(growth_delta);
     let liquidity = U256::from(liquidity);
     let result = growth_delta
         .checked_mul(liquidity)
         .context("calculate_amount_by_growth: overflow")?
-        .checked_div(U256::from_limbs([0, 0, 1, 0]))
-        .context("calculate_amount_by_growth: overflow")?;
-    Ok(result.try_into().unwrap())
+        >> 128usize;
+    result
+        .try_into()
+        .map_err(|_| DlmmError::MathOverflow)
+        .context("calculate_amount_by_growth: overflow")
 }
 
 pub fn calculate_fee_inclusive(amount: u64, fee_rate: u64) -> Result<u64, Error> {
+    // The fee is at most `amount`, so it always fits.
+    Ok(calculate_fee_inclusive_u128(amount as u128, fee_rate)? as u64)
+}
+
+pub fn calculate_fee_exclusive(amount: u64, fee_rate: u64) -> Result<u64, Error> {
+    narrow(
+        calculate_fee_exclusive_u128(amount as u128, fee_rate)?,
+        "fee_amount overflow",
+    )
+}
+
+pub fn calculate_amount_in(
+    amount_out: u64,
+    price: impl Into<PriceQ64>,
+    a2b: bool,
+) -> Result<u64, Error> {
+    narrow(
+        calculate_amount_in_u128(amount_out as u128, price, a2b)?,
+        "amount_in overflow",
+    )
+}
+
+pub fn calculate_amount_out(
+    amount_in: u64,
+    price: impl Into<PriceQ64>,
+    a2b: bool,
+) -> Result<u64, Error> {
+    narrow(
+        calculate_amount_out_u128(amount_in as u128, price, a2b)?,
+        "amount_out overflow",
+    )
+}
+
+fn narrow(value: u128, overflow: &'static str) -> Result<u64, Error> {
+    u64::try_from(value).map_err(|_| anyhow!(overflow))
+}
+
+/// [`calculate_fee_inclusive`] for `u128` amounts.
+pub fn calculate_fee_inclusive_u128(amount: u128, fee_rate: u64) -> Result<u128, Error> {
     if amount == 0 || fee_rate == 0 {
         return Ok(0);
     }
     if fee_rate > FEE_PRECISION {
         return Err(anyhow!("fee_rate is greater than FEE_PRECISION"));
     }
-    let r = mul_div(
-        amount as u128,
+    mul_div(
+        amount,
         fee_rate as u128,
         FEE_PRECISION as u128,
         Rounding::Up,
     )
-    .ok_or(anyhow!("fee_amount overflow"))?;
-    Ok(r as u64)
+    .ok_or_else(|| anyhow!("fee_amount overflow"))
 }
 
-pub fn calculate_fee_exclusive(amount: u64, fee_rate: u64) -> Result<u64, Error> {
+/// [`calculate_fee_exclusive`] for `u128` amounts.
+pub fn calculate_fee_exclusive_u128(amount: u128, fee_rate: u64) -> Result<u128, Error> {
     if amount == 0 || fee_rate == 0 {
         return Ok(0);
     }
@@ -55,54 +109,134 @@ pub fn calculate_fee_exclusive(amount: u64, fee_rate: u64) -> Result<u64, Error>
         return Err(anyhow!("fee_rate is greater than FEE_PRECISION"));
     }
     let denominator = FEE_PRECISION as u128 - fee_rate as u128;
-    let r = mul_div(amount as u128, fee_rate as u128, denominator, Rounding::Up)
-        .ok_or(anyhow!("fee_amount overflow"))?;
-    Ok(r as u64)
+    mul_div(amount, fee_rate as u128, denominator, Rounding::Up)
+        .ok_or_else(|| anyhow!("fee_amount overflow"))
 }
 
-pub fn calculate_amount_in(amount_out: u64, price: u128, a2b: bool) -> Result<u64, Error> {
+/// [`calculate_amount_in`] for `u128` amounts.
+pub fn calculate_amount_in_u128(
+    amount_out: u128,
+    price: impl Into<PriceQ64>,
+    a2b: bool,
+) -> Result<u128, Error> {
+    let price = price.into().as_raw();
     if price == 0 {
         return Err(anyhow!("price is zero"));
     }
     if amount_out == 0 {
         return Ok(0);
     }
-    let r = if a2b {
-        mul_div(amount_out as u128, ONE, price, Rounding::Up)
-            .ok_or(anyhow!("amount_in overflow"))?
+    if a2b {
+        mul_div(amount_out, ONE, price, Rounding::Up)
     } else {
-        mul_div(amount_out as u128, price, ONE, Rounding::Up)
-            .ok_or(anyhow!("amount_in overflow"))?
-    };
-    if r > u64::MAX as u128 {
-        return Err(anyhow!("amount_in overflow"));
+        mul_div(amount_out, price, ONE, Rounding::Up)
     }
-    Ok(r as u64)
+    .ok_or_else(|| anyhow!("amount_in overflow"))
 }
 
-pub fn calculate_amount_out(amount_in: u64, price: u128, a2b: bool) -> Result<u64, Error> {
+/// [`calculate_amount_out`] for `u128` amounts.
+pub fn calculate_amount_out_u128(
+    amount_in: u128,
+    price: impl Into<PriceQ64>,
+    a2b: bool,
+) -> Result<u128, Error> {
+    let price = price.into().as_raw();
     if price == 0 {
         return Err(anyhow!("price is zero"));
     }
     if amount_in == 0 {
         return Ok(0);
     }
-    let r = if a2b {
-        mul_div(amount_in as u128, price, ONE, Rounding::Down)
-            .ok_or(anyhow!("amount_out overflow"))?
+    if a2b {
+        mul_div(amount_in, price, ONE, Rounding::Down)
     } else {
-        mul_div(amount_in as u128, ONE, price, Rounding::Down)
-            .ok_or(anyhow!("amount_out overflow"))?
-    };
-    if r > u64::MAX as u128 {
-        return Err(anyhow!("amount_out overflow"));
+        mul_div(amount_in, ONE, price, Rounding::Down)
+    }
+    .ok_or_else(|| anyhow!("amount_out overflow"))
+}
+
+/// The Q64.64 price a fill of `amount_in`, `fee` included, executes at in
+/// the bin at `price`, quoted like `price` as token B per token A.
+///
+/// Selling token A (`a2b`) the fee lowers it to
+/// `price * (amount_in - fee) / amount_in`, rounded down; buying token A it
+/// raises it to `price * amount_in / (amount_in - fee)`, rounded up. The
+/// rounding of the output amount is not included. A fill without input is
+/// at `price`, and one whose input is all fee saturates at `0` or
+/// `u128::MAX`.
+pub fn calculate_execution_price(price: u128, amount_in: u128, fee: u128, a2b: bool) -> u128 {
+    if amount_in == 0 {
+        return price;
+    }
+    let net = amount_in.saturating_sub(fee);
+    if a2b {
+        // At most `price`, so it fits.
+        mul_div(price, net, amount_in, Rounding::Down).unwrap_or(0)
+    } else if net == 0 {
+        u128::MAX
+    } else {
+        mul_div(price, amount_in, net, Rounding::Up).unwrap_or(u128::MAX)
     }
-    Ok(r as u64)
 }
 
 #[cfg(test)]
 mod test {
-    use super::{calculate_amount_in, calculate_amount_out};
+    use super::{
+        calculate_amount_by_growth, calculate_amount_in, calculate_amount_in_u128,
+        calculate_amount_out, calculate_amount_out_u128, calculate_execution_price,
+        calculate_fee_exclusive_u128, calculate_fee_inclusive_u128, calculate_growth_by_amount,
+    };
+    use crate::{error::DlmmError, math::q64x64_math::ONE};
+
+    fn dlmm_error(err: &anyhow::Error) -> Option<&DlmmError> {
+        err.downcast_ref::<DlmmError>()
+    }
+
+    #[test]
+    fn execution_price_edges() {
+        // A 1% fee.
+        assert_eq!(calculate_execution_price(ONE, 100, 1, true), ONE * 99 / 100);
+        assert_eq!(
+            calculate_execution_price(ONE, 100, 1, false),
+            (ONE * 100).div_ceil(99)
+        );
+        for a2b in [true, false] {
+            assert_eq!(calculate_execution_price(ONE, 0, 0, a2b), ONE);
+            assert_eq!(calculate_execution_price(ONE, 7, 0, a2b), ONE);
+        }
+        assert_eq!(calculate_execution_price(ONE, 7, 7, true), 0);
+        assert_eq!(calculate_execution_price(ONE, 7, 7, false), u128::MAX);
+        assert_eq!(calculate_execution_price(u128::MAX, 2, 1, false), u128::MAX);
+    }
+
+    #[test]
+    fn growth_conversions_round_trip() {
+        let growth = calculate_growth_by_amount(1_000, 4_000).unwrap();
+        assert_eq!(growth, 1 << 126);
+        assert_eq!(calculate_amount_by_growth(growth, 4_000).unwrap(), 1_000);
+        assert_eq!(calculate_amount_by_growth(u128::MAX, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn growth_conversions_report_overflow() {
+        let err = calculate_growth_by_amount(u64::MAX, 1).unwrap_err();
+        assert!(
+            matches!(dlmm_error(&err), Some(DlmmError::MathOverflow)),
+            "{err:#}"
+        );
+
+        let err = calculate_amount_by_growth(u128::MAX, u128::MAX / 3).unwrap_err();
+        assert!(
+            matches!(dlmm_error(&err), Some(DlmmError::MathOverflow)),
+            "{err:#}"
+        );
+
+        let err = calculate_growth_by_amount(1, 0).unwrap_err();
+        assert!(
+            matches!(dlmm_error(&err), Some(DlmmError::ZeroLiquidity)),
+            "{err:#}"
+        );
+    }
 
     #[test]
     fn test_calculate_amount_in() {
@@ -190,4 +324,33 @@ mod test {
                 == 7_500,
         );
     }
+
+    #[test]
+    fn u128_amounts_beyond_u64() {
+        let amount = 100_000_000_000_000_000_000u128;
+        assert!(amount > u64::MAX as u128);
+        assert!(calculate_amount_in(u64::MAX, 1 << 63, true).is_err());
+        assert_eq!(
+            calculate_amount_in_u128(amount, 1 << 63, true).unwrap(),
+            2 * amount
+        );
+        assert_eq!(
+            calculate_amount_out_u128(amount, 1 << 63, true).unwrap(),
+            amount / 2
+        );
+        assert_eq!(
+            calculate_amount_out_u128(amount, 1 << 63, false).unwrap(),
+            2 * amount
+        );
+        assert_eq!(
+            calculate_fee_inclusive_u128(amount, 2_500_000).unwrap(),
+            250_000_000_000_000_000
+        );
+        // 10^20 * 0.0025 / 0.9975, rounded up.
+        assert_eq!(
+            calculate_fee_exclusive_u128(amount, 2_500_000).unwrap(),
+            250_626_566_416_040_101
+        );
+        assert!(calculate_amount_in_u128(u128::MAX, 1, true).is_err());
+    }
 }
@@ -0,0 +1,141 @@
+use crate::{
+    error::DlmmError,
+    math::{
+        q64x64_math::{log2, pow_checked, ONE, SCALE_OFFSET},
+        BASIS_POINT_MAX,
+    },
+    price::PriceQ64,
+    MAX_BIN_ID, MIN_BIN_ID,
+};
+
+/// Bin ids are bounded by `±BIN_BOUND`, the largest exponent for which the
+/// smallest bin step still yields a representable Q64.64 price.
+pub const BIN_BOUND: u32 = 443_636;
+
+/// Computes the Q64.64 price of `bin_id`, i.e. `(1 + bin_step / 10_000) ^ bin_id`.
+///
+/// Fails with [`DlmmError::InvalidBinId`] outside
+/// [`MIN_BIN_ID`]`..=`[`MAX_BIN_ID`], and with [`DlmmError::PriceOutOfRange`]
+/// if the price is not a Q64.64 number, as for the far ids of large bin
+/// steps.
+pub fn get_price_from_id(bin_id: i32, bin_step: u16) -> Result<u128, DlmmError> {
+    if !(MIN_BIN_ID..=MAX_BIN_ID).contains(&bin_id) {
+        return Err(DlmmError::InvalidBinId);
+    }
+    pow_checked(bin_base(bin_step), bin_id).map_err(|error| DlmmError::PriceOutOfRange {
+        bin_id,
+        bin_step,
+        error,
+    })
+}
+
+/// The Q64.64 price ratio of adjacent bins, `1 + bin_step / 10_000`.
+pub(crate) fn bin_base(bin_step: u16) -> u128 {
+    ONE + ((bin_step as u128) << SCALE_OFFSET) / BASIS_POINT_MAX as u128
+}
+
+/// Returns the largest bin id whose price does not exceed `price`.
+///
+/// The id is estimated from fixed-point logarithms and then corrected
+/// against [`get_price_from_id`], so the result agrees exactly with the
+/// on-chain price of the returned bin.
+pub fn get_id_from_price(price: impl Into<PriceQ64>, bin_step: u16) -> Result<i32, DlmmError> {
+    let price = price.into().as_raw();
+    if price == 0 || bin_step == 0 {
+        return Err(DlmmError::InvalidInput);
+    }
+    let bound = BIN_BOUND as i32;
+    let log_step = log2(get_price_from_id(1, bin_step)?).ok_or(DlmmError::InvalidInput)?;
+    let log_price = log2(price).ok_or(DlmmError::InvalidInput)?;
+    let mut id = log_price
+        .div_euclid(log_step)
+        .clamp(-(bound as i128), bound as i128) as i32;
+
+    let price_at = |id: i32| get_price_from_id(id, bin_step);
+    while id > -bound && !price_at(id).is_ok_and(|p| p <= price) {
+        id -= 1;
+    }
+    while id < bound && price_at(id + 1).is_ok_and(|p| p <= price) {
+        id += 1;
+    }
+    if price_at(id)? > price {
+        return Err(DlmmError::InvalidInput);
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_from_id() {
+        assert_eq!(get_price_from_id(0, 25).unwrap(), ONE);
+        let up = get_price_from_id(1, 25).unwrap();
+        let down = get_price_from_id(-1, 25).unwrap();
+        assert!(up > ONE && down < ONE);
+        assert_eq!(up, ONE + (25u128 << 64) / 10_000);
+        assert!(get_price_from_id(-(BIN_BOUND as i32), 1).is_ok());
+    }
+
+    #[test]
+    fn price_from_id_enforces_bin_id_bounds() {
+        assert!(get_price_from_id(MIN_BIN_ID, 1).is_ok());
+        assert!(get_price_from_id(MAX_BIN_ID, 1).is_ok());
+        for bin_step in [1u16, 25, 100] {
+            assert!(matches!(
+                get_price_from_id(MIN_BIN_ID - 1, bin_step),
+                Err(DlmmError::InvalidBinId)
+            ));
+            assert!(matches!(
+                get_price_from_id(MAX_BIN_ID + 1, bin_step),
+                Err(DlmmError::InvalidBinId)
+            ));
+        }
+        assert!(matches!(
+            get_price_from_id(2_000_000, 1),
+            Err(DlmmError::InvalidBinId)
+        ));
+    }
+
+    #[test]
+    fn price_out_of_range_names_the_bin() {
+        let err = get_price_from_id(MAX_BIN_ID, 100).unwrap_err();
+        let DlmmError::PriceOutOfRange {
+            bin_id,
+            bin_step,
+            error,
+        } = err
+        else {
+            panic!("{err}");
+        };
+        assert_eq!((bin_id, bin_step), (MAX_BIN_ID, 100));
+        assert_eq!(error.base, ONE + (100u128 << SCALE_OFFSET) / 10_000);
+        assert_eq!(error.exp, MAX_BIN_ID);
+        assert_eq!(
+            error.kind,
+            crate::math::q64x64_math::PowErrorKind::InversionOverflow
+        );
+        assert!(
+            err.to_string()
+                .starts_with("Price of bin 443636 at bin step 100: Inverted power overflows"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn id_from_price_inverts_price_from_id() {
+        for bin_step in [1u16, 25, 100] {
+            for id in [-1_000, -101, -1, 0, 1, 77, 1_000] {
+                let price = get_price_from_id(id, bin_step).unwrap();
+                assert_eq!(get_id_from_price(price, bin_step).unwrap(), id);
+                assert_eq!(get_id_from_price(price + 1, bin_step).unwrap(), id);
+                assert_eq!(get_id_from_price(price - 1, bin_step).unwrap(), id - 1);
+            }
+        }
+        assert!(matches!(
+            get_id_from_price(0, 25),
+            Err(DlmmError::InvalidInput)
+        ));
+    }
+}
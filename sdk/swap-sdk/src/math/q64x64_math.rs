@@ -1,3 +1,10 @@
+use core::fmt;
+
+use ruint::{
+    aliases::{U256, U512},
+    UintTryFrom,
+};
+
 pub const PRECISION: u128 = 1_000_000_000_000;
 
 pub const SCALE_OFFSET: u8 = 64;
@@ -6,128 +13,661 @@ const MAX_EXPONENTIAL: u32 = 0x80000;
 
 pub const ONE: u128 = 1u128 << SCALE_OFFSET;
 
-pub fn pow(base: u128, exp: i32) -> Option<u128> {
+/// Which step of [`pow_checked`] or [`pow_precise`] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowErrorKind {
+    /// The exponent's magnitude is `0x80000` or more, beyond any bin id.
+    ExponentOutOfRange,
+    /// The power, or a value computed on the way to it, is too large for a
+    /// Q64.64 number. [`pow_checked`] keeps every intermediate value below
+    /// [`ONE`], so only [`pow_precise`] reports this.
+    Overflow,
+    /// The power is below the smallest positive Q64.64 number.
+    Underflow,
+    /// The power of a negative exponent, or of a base of at least [`ONE`],
+    /// is computed as the inverse of a power that rounded down to zero, or
+    /// whose inverse is too large for a Q64.64 number.
+    InversionOverflow,
+}
+
+impl fmt::Display for PowErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowErrorKind::ExponentOutOfRange => write!(f, "Exponent out of range"),
+            PowErrorKind::Overflow => write!(f, "Power overflows Q64.64"),
+            PowErrorKind::Underflow => write!(f, "Power underflows Q64.64"),
+            PowErrorKind::InversionOverflow => write!(f, "Inverted power overflows Q64.64"),
+        }
+    }
+}
+
+/// Why [`pow_checked`] or [`pow_precise`] has no result, with the inputs
+/// that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowError {
+    /// The Q64.64 base.
+    pub base: u128,
+    pub exp: i32,
+    pub kind: PowErrorKind,
+}
+
+impl fmt::Display for PowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} for {:#x} ^ {}", self.kind, self.base, self.exp)
+    }
+}
+
+impl core::error::Error for PowError {}
+
+/// `base ^ exp` for a Q64.64 `base`, computed exactly like the contract's
+/// `price_math::pow` so that bin prices match the on-chain ones bit for bit.
+///
+/// The base is inverted up front when it is at least [`ONE`], so every
+/// intermediate value stays below `ONE`, and a positive power is inverted
+/// again at the end. Each of these steps rounds, which leaves powers with
+/// large exponents many units in the last place off the exact value, e.g.
+/// 2,565 for `1.0001 ^ -5000`; [`pow_precise`] is accurate to one.
+pub fn pow_checked(base: u128, exp: i32) -> Result<u128, PowError> {
+    contract_pow(base, exp).map_err(|kind| PowError { base, exp, kind })
+}
+
+/// The same as [`pow_checked`].
+pub fn pow(base: u128, exp: i32) -> Result<u128, PowError> {
+    pow_checked(base, exp)
+}
+
+fn contract_pow(base: u128, exp: i32) -> Result<u128, PowErrorKind> {
     let mut invert = exp.is_negative();
 
     if exp == 0 {
-        return Some(ONE);
+        return Ok(ONE);
+    }
+    if base == ONE {
+        return Ok(ONE);
     }
 
-    let exp: u32 = if invert { exp.abs() as u32 } else { exp as u32 };
+    let exp: u32 = if invert {
+        exp.unsigned_abs()
+    } else {
+        exp as u32
+    };
 
     if exp >= MAX_EXPONENTIAL {
-        return None;
+        return Err(PowErrorKind::ExponentOutOfRange);
     }
 
     let mut squared_base = base;
     let mut result = ONE;
 
     if squared_base >= result {
-        squared_base = u128::MAX.checked_div(squared_base)?;
+        squared_base = u128::MAX / squared_base;
         invert = !invert;
     }
 
     if exp & 0x1 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x2 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x4 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x8 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x10 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x20 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x40 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x80 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x100 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x200 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x400 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x800 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x1000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x2000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x4000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x8000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x10000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x20000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
-    squared_base = (squared_base.checked_mul(squared_base)?) >> SCALE_OFFSET;
+    squared_base = mul_q64(squared_base, squared_base)?;
     if exp & 0x40000 > 0 {
-        result = (result.checked_mul(squared_base)?) >> SCALE_OFFSET
+        result = mul_q64(result, squared_base)?;
     }
 
+    // The contract aborts here either way; a zero that is about to be
+    // inverted stands for a power too large to represent.
     if result == 0 {
-        return None;
+        return Err(if invert {
+            PowErrorKind::InversionOverflow
+        } else {
+            PowErrorKind::Underflow
+        });
     }
 
     if invert {
-        result = u128::MAX.checked_div(result)?;
+        result = u128::MAX / result;
+    }
+
+    Ok(result)
+}
+
+fn mul_q64(x: u128, y: u128) -> Result<u128, PowErrorKind> {
+    x.checked_mul(y)
+        .map(|product| product >> SCALE_OFFSET)
+        .ok_or(PowErrorKind::Overflow)
+}
+
+/// The squarings of [`pow_checked`] for one base, which do not depend on the
+/// exponent. [`PowLadder::pow`] multiplies the ones an exponent selects, in
+/// the same order and with the same rounding, so it returns exactly what
+/// [`pow_checked`] does while skipping the 18 squarings.
+pub(crate) struct PowLadder {
+    base: u128,
+    /// Whether the base was inverted up front, being at least [`ONE`].
+    inverted: bool,
+    /// The base, or its inverse, squared `i` times at index `i`.
+    squares: [u128; 19],
+}
+
+impl PowLadder {
+    pub(crate) fn new(base: u128) -> Self {
+        let inverted = base >= ONE;
+        let mut squared_base = if inverted { u128::MAX / base } else { base };
+        let mut squares = [0; 19];
+        for square in &mut squares {
+            *square = squared_base;
+            // Below 2^64, so the product cannot overflow.
+            squared_base = squared_base.wrapping_mul(squared_base) >> SCALE_OFFSET;
+        }
+        Self {
+            base,
+            inverted,
+            squares,
+        }
+    }
+
+    pub(crate) fn pow(&self, exp: i32) -> Result<u128, PowErrorKind> {
+        if exp == 0 || self.base == ONE {
+            return Ok(ONE);
+        }
+        let exp_abs = exp.unsigned_abs();
+        if exp_abs >= MAX_EXPONENTIAL {
+            return Err(PowErrorKind::ExponentOutOfRange);
+        }
+        let invert = exp.is_negative() != self.inverted;
+        let mut result = ONE;
+        let mut bits = exp_abs;
+        while bits != 0 {
+            result = mul_q64(result, self.squares[bits.trailing_zeros() as usize])?;
+            bits &= bits - 1;
+        }
+        if result == 0 {
+            return Err(if invert {
+                PowErrorKind::InversionOverflow
+            } else {
+                PowErrorKind::Underflow
+            });
+        }
+        Ok(if invert { u128::MAX / result } else { result })
     }
+}
+
+/// Fractional bits of the intermediate values in [`pow_precise`].
+const PRECISE_OFFSET: usize = 128;
+
+/// `base ^ exp` for a Q64.64 `base`, rounded down to within one unit in the
+/// last place of the exact value.
+///
+/// The power of `|exp|` is computed with 128 fractional bits in [`U256`]
+/// and a negative exponent inverts it once at the end, so unlike [`pow`]
+/// the error does not grow with the exponent. This is not what the
+/// contract computes: bin prices come from [`pow`], and a pool's prices
+/// should never be derived from this.
+pub fn pow_precise(base: u128, exp: i32) -> Result<u128, PowError> {
+    precise_pow(base, exp).map_err(|kind| PowError { base, exp, kind })
+}
+
+fn precise_pow(base: u128, exp: i32) -> Result<u128, PowErrorKind> {
+    if exp == 0 {
+        return Ok(ONE);
+    }
+    let magnitude = exp.unsigned_abs();
+    if magnitude >= MAX_EXPONENTIAL {
+        return Err(PowErrorKind::ExponentOutOfRange);
+    }
+
+    let power = power_q128(base, magnitude);
+    let result = if exp > 0 {
+        power.ok_or(PowErrorKind::Overflow)? >> (PRECISE_OFFSET - SCALE_OFFSET as usize)
+    } else {
+        // A power of 2^128 or more inverts to less than 2^-128.
+        let power = power.ok_or(PowErrorKind::Underflow)?;
+        if power.is_zero() {
+            return Err(PowErrorKind::InversionOverflow);
+        }
+        (U256::from(1) << (PRECISE_OFFSET + SCALE_OFFSET as usize)) / power
+    };
+
+    match u128::try_from(result) {
+        Ok(0) => Err(PowErrorKind::Underflow),
+        Ok(result) => Ok(result),
+        Err(_) if exp > 0 => Err(PowErrorKind::Overflow),
+        Err(_) => Err(PowErrorKind::InversionOverflow),
+    }
+}
 
-    Some(result)
+/// `base ^ exp` as a Q128.128 number, rounded down at each step, or `None`
+/// once it reaches 2^128.
+fn power_q128(base: u128, mut exp: u32) -> Option<U256> {
+    let mut squared_base = U256::from(base) << (PRECISE_OFFSET - SCALE_OFFSET as usize);
+    let mut result = U256::from(1) << PRECISE_OFFSET;
+    loop {
+        if exp & 0x1 > 0 {
+            result = mul_q128(result, squared_base)?;
+        }
+        exp >>= 1;
+        if exp == 0 {
+            return Some(result);
+        }
+        squared_base = mul_q128(squared_base, squared_base)?;
+    }
+}
+
+fn mul_q128(x: U256, y: U256) -> Option<U256> {
+    let product: U512 = x.widening_mul(y);
+    U256::uint_try_from(product >> PRECISE_OFFSET).ok()
+}
+
+/// Square root of a Q64.64 number, rounded down to the nearest Q64.64 value.
+///
+/// Every input has a representable root, so this never returns `None`;
+/// `sqrt(0)` is `0`, and inputs below [`ONE`] have roots larger than
+/// themselves.
+pub fn sqrt(x: u128) -> Option<u128> {
+    if x == 0 {
+        return Some(0);
+    }
+
+    // sqrt(x / 2^64) * 2^64 == sqrt(x * 2^64), computed on the widened value
+    // with Newton's method from a starting guess above the root.
+    let value = U256::from(x) << SCALE_OFFSET as usize;
+    let mut root = U256::from(1) << value.bit_len().div_ceil(2);
+    loop {
+        let next = (root + value / root) >> 1;
+        if next >= root {
+            break;
+        }
+        root = next;
+    }
+
+    root.try_into().ok()
+}
+
+/// Base-2 logarithm of a Q64.64 number as a signed Q64.64 value, rounded
+/// down.
+///
+/// Returns `None` for `x == 0`. Inputs below [`ONE`] have negative
+/// logarithms, down to `-64` for the smallest input `1`.
+pub fn log2(x: u128) -> Option<i128> {
+    if x == 0 {
+        return None;
+    }
+
+    let msb = 127 - x.leading_zeros() as i128;
+    let integer = (msb - SCALE_OFFSET as i128) << SCALE_OFFSET;
+
+    // Normalize to y = x / 2^msb in [1, 2) as Q1.127, which keeps every bit
+    // of x, then square repeatedly: each time y reaches 2, the next fractional
+    // bit is set and y is halved.
+    let mut y = U256::from(x << (127 - msb as u32));
+    let mut fraction: i128 = 0;
+    for bit in (0..SCALE_OFFSET).rev() {
+        y = (y * y) >> 127;
+        if y >= U256::from(1) << 128 {
+            fraction |= 1 << bit;
+            y >>= 1;
+        }
+    }
+
+    Some(integer + fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    // Expected values were computed in Python: `math.isqrt(x << 64)` for
+    // sqrt, and `floor(log2(x / 2^64) * 2^64)` with mpmath at 600 bits of
+    // precision for log2.
+
+    #[test]
+    fn sqrt_matches_reference_values() {
+        let vectors: [(u128, u128); 26] = [
+            (0x1, 0x100000000),
+            (0x2, 0x16a09e667),
+            (0x3, 0x1bb67ae85),
+            (0xe8d4a51000, 0xf424000000000),
+            (0xffe6f6d9c8d2d17, 0x3ffcdec7a1d6c6df),
+            (0x8000000000000000, 0xb504f333f9de6484),
+            (0x8000000000000001, 0xb504f333f9de6485),
+            (0xffffffffffffffff, 0xffffffffffffffff),
+            (0x10000000000000000, 0x10000000000000000),
+            (0x10000000000000001, 0x10000000000000000),
+            (0x100190a851da66e1c, 0x1000c84f430da8f28),
+            (0x20000000000000000, 0x16a09e667f3bcc908),
+            (0x30000000000000000, 0x1bb67ae8584caa73b),
+            (0x77e6e166ce0e2db26c, 0xaf33155d19bae50c4),
+            (0x3e80000000000000000, 0x1f9f6e4990f2273685),
+            (0x39fc5cb3419543bfd604a2, 0x79d6628e1b8ff25ad16),
+            (0x27e41b3246bec9b16e398115, 0x650e124750a71c24c5e1),
+            (0xc9f2c9cd04674edea40000000, 0x38d7ea4c6800000000000),
+            (0xe0e3e49118cdf4b9d1dd3cb8786a72, 0xeff101f7a42a4e5150ff531),
+            (
+                0x95d182adb3d3b51d98388a4380cd4b7,
+                0x30f5cb2121b8573cd213d6e5,
+            ),
+            (
+                0x319b924d17121856b877d947462a7b14,
+                0x70b13f84cb10c2aa907fb6aa,
+            ),
+            (
+                0x7fffffffffffffffffffffffffffffff,
+                0xb504f333f9de6484597d89b3,
+            ),
+            (
+                0x80000000000000000000000000000000,
+                0xb504f333f9de6484597d89b3,
+            ),
+            (
+                0x80000000000000000000000000000001,
+                0xb504f333f9de6484597d89b3,
+            ),
+            (
+                0xfffffffffffffffffffffffffffffffe,
+                0xffffffffffffffffffffffff,
+            ),
+            (
+                0xffffffffffffffffffffffffffffffff,
+                0xffffffffffffffffffffffff,
+            ),
+        ];
+        for (x, expected) in vectors {
+            assert_eq!(sqrt(x), Some(expected), "sqrt({x:#x})");
+        }
+        assert_eq!(sqrt(0), Some(0));
+    }
+
+    #[test]
+    fn log2_is_within_one_ulp_of_reference_values() {
+        let vectors: [(u128, i128); 26] = [
+            (0x1, -1180591620717411303424),
+            (0x2, -1162144876643701751808),
+            (0x3, -1151354223100181444705),
+            (0xe8d4a51000, -445246531945578754131),
+            (0xffe6f6d9c8d2d17, -73797144814187292197),
+            (0x8000000000000000, -18446744073709551616),
+            (0x8000000000000001, -18446744073709551614),
+            (0xffffffffffffffff, -2),
+            (0x10000000000000000, 0),
+            (0x10000000000000001, 1),
+            (0x100190a851da66e1c, 10166809026131695),
+            (0x20000000000000000, 18446744073709551616),
+            (0x30000000000000000, 29237397617229858719),
+            (0x77e6e166ce0e2db26c, 127387872978565606308),
+            (0x3e80000000000000000, 183836272192958137323),
+            (0x39fc5cb3419543bfd604a2, 403202060162205667667),
+            (0x27e41b3246bec9b16e398115, 540821510503733398068),
+            (0xc9f2c9cd04674edea40000000, 657771101212170069810),
+            (0xe0e3e49118cdf4b9d1dd3cb8786a72, 1029569547851364462222),
+            (0x95d182adb3d3b51d98388a4380cd4b7, 1092546621192420065771),
+            (0x319b924d17121856b877d947462a7b14, 1136918810195793112625),
+            (0x7fffffffffffffffffffffffffffffff, 1162144876643701751807),
+            (0x80000000000000000000000000000000, 1162144876643701751808),
+            (0x80000000000000000000000000000001, 1162144876643701751808),
+            (0xfffffffffffffffffffffffffffffffe, 1180591620717411303423),
+            (0xffffffffffffffffffffffffffffffff, 1180591620717411303423),
+        ];
+        for (x, expected) in vectors {
+            let actual = log2(x).unwrap();
+            assert!(
+                actual <= expected && expected - actual <= 1,
+                "log2({x:#x}) = {actual}, expected {expected}"
+            );
+        }
+        assert_eq!(log2(0), None);
+    }
+
+    /// `(bin_step, exp, exact, contract)`: `exact` is
+    /// `floor((base / 2^64) ^ exp * 2^64)` with mpmath at 2000 bits of
+    /// precision, and `contract` is the contract's `price_math::pow`
+    /// algorithm run in Python, for `base = ONE + (bin_step << 64) / 10_000`.
+    const POW_VECTORS: [(u16, i32, u128, u128); 16] = [
+        (1, -5000, 0x9b469645b17e3df0, 0x9b469645b17e33eb),
+        (1, -100, 0xfd73f03756694178, 0xfd73f03756694126),
+        (1, -1, 0xfff97272373d4132, 0xfff97272373d4132),
+        (1, 1, 0x100068db8bac710cb, 0x100068db8bac710cb),
+        (1, 100, 0x102929d5a065664f7, 0x102929d5a0656654b),
+        (1, 5000, 0x1a60fe569cab6b5fe, 0x1a60fe569cab6d13a),
+        (25, -5000, 0x3f817c869a5a, 0x3f817c869a5a),
+        (25, -100, 0xc76f69f60521ca54, 0xc76f69f60521c9db),
+        (25, -1, 0xff5c918e5d34fcce, 0xff5c918e5d34fcce),
+        (25, 1, 0x100a3d70a3d70a3d7, 0x100a3d70a3d70a3d7),
+        (25, 100, 0x1489ba405769e9d62, 0x1489ba405769e9e2a),
+        (25, 5000, 0x407f7f82591b8d9cace39, 0x407f7f82591bcf825cb0a),
+        (100, -100, 0x5ea564ddeb073d52, 0x5ea564ddeb073d38),
+        (100, -1, 0xfd7720f353a4c0a2, 0xfd7720f353a4c0a2),
+        (100, 1, 0x1028f5c28f5c28f5c, 0x1028f5c28f5c28f5c),
+        (100, 100, 0x2b46eaddb224c53bc, 0x2b46eaddb224c547e),
+    ];
+
+    fn bin_base(bin_step: u16) -> u128 {
+        ONE + ((bin_step as u128) << SCALE_OFFSET) / 10_000
+    }
+
+    #[test]
+    fn pow_matches_the_contract() {
+        for (bin_step, exp, _, contract) in POW_VECTORS {
+            assert_eq!(
+                pow(bin_base(bin_step), exp),
+                Ok(contract),
+                "{bin_step}: {exp}"
+            );
+        }
+        assert_eq!(pow(ONE, 7), Ok(ONE));
+    }
+
+    #[test]
+    fn pow_precise_is_within_one_ulp_of_reference_values() {
+        for (bin_step, exp, exact, _) in POW_VECTORS {
+            let actual = pow_precise(bin_base(bin_step), exp).unwrap();
+            assert!(
+                actual <= exact && exact - actual <= 1,
+                "{bin_step}: {exp}: {actual:#x}, expected {exact:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn pow_errors_name_the_failure() {
+        let two = 2 * ONE;
+        let kind = |result: Result<u128, PowError>| result.unwrap_err().kind;
+        for pow in [pow_checked, pow_precise] {
+            assert_eq!(
+                kind(pow(bin_base(1), 0x80000)),
+                PowErrorKind::ExponentOutOfRange
+            );
+            assert_eq!(
+                kind(pow(bin_base(1), -0x80000)),
+                PowErrorKind::ExponentOutOfRange
+            );
+            assert_eq!(
+                kind(pow(bin_base(1), i32::MIN)),
+                PowErrorKind::ExponentOutOfRange
+            );
+            assert_eq!(kind(pow(two, -65)), PowErrorKind::Underflow);
+            assert_eq!(kind(pow(bin_base(100), -5000)), PowErrorKind::Underflow);
+            assert_eq!(kind(pow(0, -1)), PowErrorKind::InversionOverflow);
+            assert_eq!(pow(two, 0), Ok(ONE));
+        }
+        // The contract inverts 2 up front, and 0.5 ^ 64 rounds to zero
+        // before being inverted back.
+        assert_eq!(kind(pow_checked(two, 64)), PowErrorKind::InversionOverflow);
+        // The precise power is exactly 2^64, one past the largest Q64.64.
+        assert_eq!(kind(pow_precise(two, 64)), PowErrorKind::Overflow);
+        // 2^100 already overflows the Q128.128 intermediate.
+        assert_eq!(kind(pow_precise(two, 100)), PowErrorKind::Overflow);
+        // 0.5 ^ 129 is below 2^-128, so its inverse does not fit.
+        assert_eq!(
+            kind(pow_precise(ONE / 2, -129)),
+            PowErrorKind::InversionOverflow
+        );
+        assert_eq!(pow_precise(two, 63), Ok(1 << 127));
+        assert_eq!(pow_precise(two, -64), Ok(1));
+        assert_eq!(pow(two, 64), pow_checked(two, 64));
+
+        let err = pow_checked(bin_base(100), -5000).unwrap_err();
+        assert_eq!(
+            err,
+            PowError {
+                base: bin_base(100),
+                exp: -5000,
+                kind: PowErrorKind::Underflow,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Power underflows Q64.64 for 0x1028f5c28f5c28f5c ^ -5000"
+        );
+    }
+
+    #[test]
+    fn log2_inverts_pow() {
+        let base = ONE + (ONE / 10_000) * 25;
+        let log_base = log2(base).unwrap();
+        for exp in [-5_000, -100, -1, 1, 7, 100, 5_000] {
+            let price = pow(base, exp).unwrap();
+            let estimate = log2(price).unwrap() as f64 / log_base as f64;
+            assert!((estimate - exp as f64).abs() < 1e-6, "{exp}: {estimate}");
+        }
+    }
+
+    #[test]
+    fn ladder_matches_the_contract_at_the_edges() {
+        for base in [
+            0,
+            1,
+            ONE - 1,
+            ONE,
+            ONE + 1,
+            2 * ONE,
+            bin_base(1),
+            bin_base(100),
+            u128::MAX,
+        ] {
+            let ladder = PowLadder::new(base);
+            for exp in [
+                i32::MIN,
+                -0x80000,
+                -0x7ffff,
+                -129,
+                -64,
+                -1,
+                0,
+                1,
+                63,
+                64,
+                0x7ffff,
+                0x80000,
+                i32::MAX,
+            ] {
+                assert_eq!(
+                    ladder.pow(exp),
+                    contract_pow(base, exp),
+                    "{base:#x} ^ {exp}"
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn ladder_matches_the_contract(
+            base in prop_oneof![
+                (1u16..=10_000).prop_map(bin_base),
+                any::<u128>(),
+            ],
+            exps in proptest::collection::vec(-0x80001i32..=0x80001, 1..16),
+        ) {
+            let ladder = PowLadder::new(base);
+            for exp in exps {
+                prop_assert_eq!(ladder.pow(exp), contract_pow(base, exp), "{:#x} ^ {}", base, exp);
+            }
+        }
+    }
 }
@@ -0,0 +1,306 @@
+//! What a pool is, besides its state, see [`PoolMetadata`].
+//!
+//! The swap math never reads the metadata. It is carried on the [`Pool`] so
+//! that the pool's identity and token decimals travel with its state, and
+//! the display helpers below read the decimals from it. Without metadata, or
+//! with unknown decimals, they show base units.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    depth::DepthSnapshot,
+    pool::{Pool, SwapResult},
+    price::PriceQ64,
+};
+
+/// The identity of a pool and its tokens, see [`Pool::metadata`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PoolMetadata {
+    /// The pool's object id, e.g. `0x4fe3…1c2e`.
+    pub pool_id: String,
+    /// The Move type of token A, e.g. `0x2::sui::SUI`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coin_type_a: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coin_type_b: Option<String>,
+    /// Decimals of token A, from its coin metadata. The pool object does
+    /// not hold them, so the loaders leave them unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals_a: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals_b: Option<u8>,
+    /// The bin step of the pool's config. [`Pool::validate`] checks that the
+    /// two agree.
+    pub bin_step: u16,
+}
+
+impl PoolMetadata {
+    /// `(decimals_a, decimals_b)`, if both are known.
+    pub fn decimals(&self) -> Option<(u8, u8)> {
+        self.decimals_a.zip(self.decimals_b)
+    }
+
+    /// The last segment of token A's type, e.g. `SUI`, or `A` if the type
+    /// is unknown.
+    pub fn symbol_a(&self) -> &str {
+        self.coin_type_a.as_deref().map_or("A", symbol)
+    }
+
+    /// The last segment of token B's type, or `B` if the type is unknown.
+    pub fn symbol_b(&self) -> &str {
+        self.coin_type_b.as_deref().map_or("B", symbol)
+    }
+}
+
+/// The name of a Move type without its address, module or type arguments.
+fn symbol(coin_type: &str) -> &str {
+    let name = coin_type.split('<').next().unwrap_or(coin_type);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// The type arguments of a pool's Move type,
+/// `<package>::pool::Pool<A, B>`, or `None` if it does not have two.
+pub fn coin_types_from_pool_type(pool_type: &str) -> Option<(String, String)> {
+    let (_, arguments) = pool_type.split_once('<')?;
+    let arguments = arguments.strip_suffix('>')?;
+    // Split at the comma outside of any nested type arguments.
+    let mut depth = 0usize;
+    let comma = arguments.char_indices().find_map(|(index, c)| {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => return Some(index),
+            _ => {}
+        }
+        None
+    })?;
+    let (a, b) = (arguments[..comma].trim(), arguments[comma + 1..].trim());
+    (!a.is_empty() && !b.is_empty()).then(|| (a.to_string(), b.to_string()))
+}
+
+/// `amount` base units of a token with `decimals` as a decimal string,
+/// without trailing zeros, e.g. `1.5` for `1500000` with 6 decimals.
+pub fn format_amount(amount: u128, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if amount == 0 || decimals == 0 {
+        return digits;
+    }
+    let digits = if digits.len() <= decimals {
+        format!("{}{digits}", "0".repeat(decimals + 1 - digits.len()))
+    } else {
+        digits
+    };
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    match fraction.trim_end_matches('0') {
+        "" => integer.to_string(),
+        fraction => format!("{integer}.{fraction}"),
+    }
+}
+
+impl Pool {
+    /// The pool's metadata, if it was loaded with it or given one.
+    pub fn metadata(&self) -> Option<&PoolMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// The pool with `metadata` attached.
+    pub fn with_metadata(mut self, metadata: PoolMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// `(decimals_a, decimals_b)` from the metadata, or `(0, 0)`, i.e. base
+    /// units, if either is unknown.
+    pub fn decimals(&self) -> (u8, u8) {
+        self.metadata
+            .as_ref()
+            .and_then(PoolMetadata::decimals)
+            .unwrap_or((0, 0))
+    }
+
+    /// The price of the active bin in whole tokens of B per token A, with
+    /// `precision` fractional digits; see [`PriceQ64::to_decimal_string`].
+    /// Fails if the active id has no price at the bin step.
+    pub fn active_price_decimal(&self, precision: usize) -> Result<String, Error> {
+        let (decimals_a, decimals_b) = self.decimals();
+        let price = PriceQ64::from_id(self.active_id, self.v_parameters.bin_step_config.bin_step)?;
+        Ok(price.to_decimal_string(decimals_a, decimals_b, precision))
+    }
+
+    /// [`Pool::depth_levels`] with [`DepthSnapshot::with_decimal_prices`]
+    /// applied using [`Pool::decimals`].
+    pub fn depth_levels_decimal(
+        &self,
+        max_levels_per_side: usize,
+        precision: usize,
+    ) -> DepthSnapshot {
+        let (decimals_a, decimals_b) = self.decimals();
+        self.depth_levels(max_levels_per_side)
+            .with_decimal_prices(decimals_a, decimals_b, precision)
+    }
+
+    /// A swap on this pool in whole tokens, e.g.
+    /// `1.5 SUI -> 3.2 USDC (fee 0.0045 SUI)`. Tokens are named by their
+    /// type and amounts scaled by their decimals when the metadata has
+    /// them, and shown as `A`/`B` and base units otherwise.
+    pub fn describe_swap(&self, result: &SwapResult, a2b: bool) -> String {
+        let (decimals_a, decimals_b) = self.decimals();
+        let (symbol_a, symbol_b) = self.metadata.as_ref().map_or(("A", "B"), |metadata| {
+            (metadata.symbol_a(), metadata.symbol_b())
+        });
+        let ((symbol_in, decimals_in), (symbol_out, decimals_out)) = if a2b {
+            ((symbol_a, decimals_a), (symbol_b, decimals_b))
+        } else {
+            ((symbol_b, decimals_b), (symbol_a, decimals_a))
+        };
+        format!(
+            "{} {symbol_in} -> {} {symbol_out} (fee {} {symbol_in})",
+            format_amount(result.amount_in.into(), decimals_in),
+            format_amount(result.amount_out.into(), decimals_out),
+            format_amount(result.fee.into(), decimals_in),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_POOL: &str = include_str!("../tests/fixtures/synthetic_events_start_pool.json");
+
+    fn metadata() -> PoolMetadata {
+        PoolMetadata {
+            pool_id: "0x4fe3".into(),
+            coin_type_a: Some("0x2::sui::SUI".into()),
+            coin_type_b: Some("0xdba3::usdc::USDC".into()),
+            decimals_a: Some(9),
+            decimals_b: Some(6),
+            bin_step: 25,
+        }
+    }
+
+    fn pool() -> Pool {
+        serde_json::from_str(START_POOL).unwrap()
+    }
+
+    #[test]
+    fn metadata_round_trips_through_json() {
+        let pool = pool().with_metadata(metadata());
+        pool.validate().unwrap();
+        let json = serde_json::to_string(&pool).unwrap();
+        let back: Pool = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, pool);
+        assert_eq!(back.metadata(), Some(&metadata()));
+
+        // Snapshots without metadata still load, and write none.
+        assert_eq!(self::pool().metadata(), None);
+        assert!(!serde_json::to_string(&self::pool())
+            .unwrap()
+            .contains("metadata"));
+        let partial: PoolMetadata =
+            serde_json::from_str(r#"{"pool_id":"0x1","bin_step":25}"#).unwrap();
+        assert_eq!(partial.decimals(), None);
+        assert_eq!((partial.symbol_a(), partial.symbol_b()), ("A", "B"));
+
+        // Metadata is not quote state.
+        assert_eq!(pool.state_hash(), self::pool().state_hash());
+        let mismatched = self::pool().with_metadata(PoolMetadata {
+            bin_step: 10,
+            ..metadata()
+        });
+        assert!(mismatched.validate().is_err());
+    }
+
+    #[test]
+    fn display_helpers_use_the_decimals() {
+        let plain = pool();
+        let pool = pool().with_metadata(metadata());
+        assert_eq!(pool.decimals(), (9, 6));
+        assert_eq!(plain.decimals(), (0, 0));
+        let no_decimals = plain.clone().with_metadata(PoolMetadata {
+            decimals_b: None,
+            ..metadata()
+        });
+        assert_eq!(no_decimals.decimals(), (0, 0));
+
+        // Bin 0 trades base units one for one: 10^9 base units of SUI for
+        // as many of USDC, 1000 whole USDC.
+        assert_eq!(pool.active_price_decimal(4).unwrap(), "1000.0000");
+        assert_eq!(plain.active_price_decimal(4).unwrap(), "1.0000");
+        let depth = pool.depth_levels_decimal(1, 3);
+        assert_eq!(depth.asks[0].price_decimal.as_deref(), Some("1000.000"));
+        assert_eq!(
+            plain.depth_levels_decimal(1, 3).asks[0]
+                .price_decimal
+                .as_deref(),
+            Some("1.000")
+        );
+
+        let result = plain.quote_exact_amount_in(1_500_000, true, 1_000).unwrap();
+        assert_eq!(
+            pool.describe_swap(&result, true),
+            format!(
+                "{} SUI -> {} USDC (fee {} SUI)",
+                format_amount(result.amount_in.into(), 9),
+                format_amount(result.amount_out.into(), 6),
+                format_amount(result.fee.into(), 9)
+            )
+        );
+        assert_eq!(
+            plain.describe_swap(&result, true),
+            format!(
+                "1500000 A -> {} B (fee {} A)",
+                result.amount_out, result.fee
+            )
+        );
+        assert!(pool
+            .describe_swap(&result, false)
+            .starts_with("1.5 USDC -> "));
+    }
+
+    #[test]
+    fn amounts_and_types_are_formatted() {
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(2_000_000, 6), "2");
+        assert_eq!(format_amount(3_000, 6), "0.003");
+        assert_eq!(format_amount(0, 6), "0");
+        assert_eq!(format_amount(42, 0), "42");
+        assert_eq!(
+            format_amount(u128::MAX, 38),
+            "3.40282366920938463463374607431768211455"
+        );
+        assert_eq!(
+            format_amount(5, 40),
+            "0.0000000000000000000000000000000000000005"
+        );
+        assert_eq!(format_amount(0, 40), "0");
+
+        assert_eq!(
+            coin_types_from_pool_type("0x56::pool::Pool<0x2::sui::SUI, 0xdb::usdc::USDC>"),
+            Some(("0x2::sui::SUI".into(), "0xdb::usdc::USDC".into()))
+        );
+        assert_eq!(
+            coin_types_from_pool_type(
+                "0x56::pool::Pool<0x1::lp::LP<0x2::sui::SUI, 0x3::x::X>, 0xdb::usdc::USDC>"
+            ),
+            Some((
+                "0x1::lp::LP<0x2::sui::SUI, 0x3::x::X>".into(),
+                "0xdb::usdc::USDC".into()
+            ))
+        );
+        assert_eq!(coin_types_from_pool_type("0x56::pool::Pool"), None);
+        assert_eq!(
+            coin_types_from_pool_type("0x56::pool::Pool<0x2::sui::SUI>"),
+            None
+        );
+        assert_eq!(symbol("0x1::lp::LP<0x2::sui::SUI, 0x3::x::X>"), "LP");
+    }
+}
@@ -0,0 +1,168 @@
+//! Loading `Pool` JSON snapshots written by older versions of the crate
+//! (`migrate` feature).
+//!
+//! Each layout change bumps [`POOL_SCHEMA_VERSION`] and adds a step to
+//! `UPGRADES` that rewrites a snapshot of the previous version into the
+//! next one, so a snapshot of any known version loads. Fields that only
+//! gain a serde default (the bin growth globals, the volatility accumulator
+//! and reference, `stats`) need nothing beyond the version bump.
+//!
+//! The steps work on the top-level fields with their values kept as raw
+//! JSON text, so `u128` values above `u64::MAX` pass through exactly.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Error};
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+use crate::pool::{Pool, POOL_SCHEMA_VERSION};
+
+/// A snapshot's top-level fields, values left as JSON text.
+type Snapshot = BTreeMap<String, Box<RawValue>>;
+
+type Upgrade = fn(&mut Snapshot) -> Result<(), Error>;
+
+/// `UPGRADES[v]` turns a version `v` snapshot into a version `v + 1` one.
+const UPGRADES: [Upgrade; POOL_SCHEMA_VERSION as usize] = [upgrade_v0];
+
+/// Version 0 lacks `schema_version` and `stats`; the latter defaults to
+/// empty.
+fn upgrade_v0(snapshot: &mut Snapshot) -> Result<(), Error> {
+    stamp(snapshot, 1)
+}
+
+fn stamp(snapshot: &mut Snapshot, version: u32) -> Result<(), Error> {
+    let raw = RawValue::from_string(version.to_string())?;
+    snapshot.insert("schema_version".into(), raw);
+    Ok(())
+}
+
+impl Pool {
+    /// Loads a JSON snapshot of any known [`schema_version`], upgrading
+    /// older layouts to the current one. Snapshots without the field are
+    /// version 0.
+    ///
+    /// Fails for snapshots from a newer version of the crate and for JSON
+    /// that does not describe a pool. The pool is not validated, see
+    /// [`Pool::validate`].
+    ///
+    /// A [`Value`] holds integers above `u64::MAX` as `f64` unless
+    /// serde_json's `arbitrary_precision` feature is on, so bin prices of
+    /// 1.0 and above only load if they were written as strings
+    /// (`serde-string-numbers`). Pass JSON text to [`Pool::migrate_str`]
+    /// to load them from plain numbers.
+    ///
+    /// [`schema_version`]: Pool::schema_version
+    pub fn migrate(value: Value) -> Result<Pool, Error> {
+        if !value.is_object() {
+            bail!("pool snapshot is not a JSON object");
+        }
+        Self::migrate_str(&value.to_string())
+    }
+
+    /// [`Pool::migrate`] for JSON text, reading every number exactly.
+    pub fn migrate_str(json: &str) -> Result<Pool, Error> {
+        let mut snapshot: Snapshot = serde_json::from_str(json).context("pool snapshot")?;
+        let version = match snapshot.get("schema_version") {
+            Some(raw) => serde_json::from_str::<u32>(raw.get()).context("schema_version")?,
+            None => 0,
+        };
+        if version > POOL_SCHEMA_VERSION {
+            bail!("schema_version {version} is newer than the supported {POOL_SCHEMA_VERSION}");
+        }
+        for upgrade in &UPGRADES[version as usize..] {
+            upgrade(&mut snapshot)?;
+        }
+        let json = serde_json::to_string(&snapshot)?;
+        serde_json::from_str(&json).context("pool snapshot")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pool as the crate serialized it before `schema_version` existed.
+    const V0: &str = include_str!("../tests/fixtures/pool_v0.json");
+    const V1: &str = include_str!("../tests/fixtures/pool_v1.json");
+
+    fn json(snapshot: &str) -> Value {
+        serde_json::from_str(snapshot).unwrap()
+    }
+
+    /// `snapshot` with prices that fit a u64, since a [`Value`] cannot
+    /// hold the fixtures'.
+    fn small_prices(snapshot: &str) -> Value {
+        let mut value = json(snapshot);
+        for (i, bin) in value["bins"].as_array_mut().unwrap().iter_mut().enumerate() {
+            bin["price"] = (1u64 << (61 + i)).into();
+        }
+        value
+    }
+
+    #[test]
+    fn old_and_current_snapshots_load_the_same_pool() {
+        let current = Pool::migrate_str(V1).unwrap();
+        let upgraded = Pool::migrate_str(V0).unwrap();
+        assert_eq!(upgraded, current);
+        assert_eq!(current.schema_version, POOL_SCHEMA_VERSION);
+        assert_eq!(current.bins.len(), 3);
+        assert_eq!(current.bins[2].price.as_raw(), 18_492_860_933_893_825_495);
+        assert!(current.validate().is_ok());
+
+        let written = serde_json::to_string(&current).unwrap();
+        assert_eq!(json(&written)["schema_version"], POOL_SCHEMA_VERSION);
+        assert_eq!(Pool::migrate_str(&written).unwrap(), current);
+    }
+
+    #[test]
+    fn values_are_upgraded_like_text() {
+        let current = Pool::migrate(small_prices(V1)).unwrap();
+        assert_eq!(Pool::migrate(small_prices(V0)).unwrap(), current);
+        assert_eq!(current.schema_version, POOL_SCHEMA_VERSION);
+        assert_eq!(current.bins[1].price.as_raw(), 1 << 62);
+        assert_eq!(
+            Pool::migrate_str(&small_prices(V0).to_string()).unwrap(),
+            current
+        );
+
+        // The plain numbers of the fixtures arrive as f64 and are refused.
+        assert!(Pool::migrate(json(V0)).is_err());
+        assert!(Pool::migrate(Value::Array(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn fields_with_defaults_may_be_missing() {
+        let mut sparse = small_prices(V0);
+        let v_parameters = sparse["v_parameters"].as_object_mut().unwrap();
+        v_parameters.remove("volatility_accumulator");
+        v_parameters.remove("volatility_reference");
+        for bin in sparse["bins"].as_array_mut().unwrap() {
+            let bin = bin.as_object_mut().unwrap();
+            bin.remove("rewards_growth_global");
+            bin.remove("fee_amount_a_growth_global");
+            bin.remove("fee_amount_b_growth_global");
+        }
+        assert_eq!(
+            Pool::migrate(sparse).unwrap(),
+            Pool::migrate(small_prices(V1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let mut newer = small_prices(V1);
+        newer["schema_version"] = (POOL_SCHEMA_VERSION + 1).into();
+        let err = Pool::migrate_str(&newer.to_string()).unwrap_err();
+        assert!(err.to_string().contains("newer"), "{err}");
+
+        let mut invalid = small_prices(V1);
+        invalid["schema_version"] = "1".into();
+        assert!(Pool::migrate_str(&invalid.to_string()).is_err());
+        assert!(Pool::migrate_str("[]").is_err());
+
+        let missing = V0.replace("\"bins\"", "\"bins_\"");
+        assert!(Pool::migrate_str(&missing).is_err());
+    }
+}
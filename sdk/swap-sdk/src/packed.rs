@@ -0,0 +1,266 @@
+//! A fixed-width binary layout of bin arrays, see [`Pool::bins_from_packed`].
+//!
+//! A bin is a 52-byte record of little-endian integers:
+//!
+//! | bytes | field |
+//! |---|---|
+//! | 0..4 | `id: i32` |
+//! | 4..12 | `amount_a: u64` |
+//! | 12..20 | `amount_b: u64` |
+//! | 20..36 | `price: u128` |
+//! | 36..52 | `liquidity_supply: u128` |
+//!
+//! Reward and fee growth are not part of the record and decode as zero.
+//! An array of bins is a 9-byte header followed by its records:
+//!
+//! 1. the magic bytes `DLMB`
+//! 2. the version byte `1`
+//! 3. the record count as a little-endian `u32`
+//!
+//! Decoding rejects a wrong magic, unknown versions and input whose length
+//! is not exactly that of its records. A layout change means a new version
+//! byte.
+
+use alloc::{format, vec::Vec};
+
+use anyhow::Error;
+
+use crate::{bin::Bin, error::DlmmError, pool::Pool};
+
+/// Bytes of one packed [`Bin`].
+pub const PACKED_BIN_LEN: usize = 52;
+
+/// Leading bytes of a packed bin array.
+pub const MAGIC: [u8; 4] = *b"DLMB";
+
+const VERSION: u8 = 1;
+
+/// Bytes of the header before the records.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+impl Bin {
+    /// Decodes one record from the start of `bytes`, returning the bin and
+    /// the bytes read, [`PACKED_BIN_LEN`]. Bytes past the record are left to
+    /// the caller. Fails with [`DlmmError::InvalidLayout`] if `bytes` is
+    /// shorter than a record.
+    pub fn read_packed(bytes: &[u8]) -> Result<(Bin, usize), Error> {
+        let record: &[u8; PACKED_BIN_LEN] = bytes
+            .get(..PACKED_BIN_LEN)
+            .and_then(|record| record.try_into().ok())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "packed bin needs {PACKED_BIN_LEN} bytes, got {}",
+                    bytes.len()
+                ))
+            })?;
+        let bin = Bin {
+            id: i32::from_le_bytes(field(record, 0)),
+            amount_a: u64::from_le_bytes(field(record, 4)),
+            amount_b: u64::from_le_bytes(field(record, 12)),
+            price: u128::from_le_bytes(field(record, 20)).into(),
+            liquidity_supply: u128::from_le_bytes(field(record, 36)),
+            ..Default::default()
+        };
+        Ok((bin, PACKED_BIN_LEN))
+    }
+
+    /// Appends the bin's record to `out`, dropping its reward and fee
+    /// growth.
+    pub fn write_packed(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.id.to_le_bytes());
+        out.extend_from_slice(&self.amount_a.to_le_bytes());
+        out.extend_from_slice(&self.amount_b.to_le_bytes());
+        out.extend_from_slice(&self.price.as_raw().to_le_bytes());
+        out.extend_from_slice(&self.liquidity_supply.to_le_bytes());
+    }
+}
+
+impl Pool {
+    /// Decodes a bin array in the layout of the [module docs](self),
+    /// allocating only the returned vector. The bins are returned in
+    /// record order, unchecked; [`Pool::new_checked`] validates them.
+    /// Malformed input fails with [`DlmmError::InvalidLayout`].
+    pub fn bins_from_packed(bytes: &[u8]) -> Result<Vec<Bin>, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(invalid(format!(
+                "packed bins need a {HEADER_LEN}-byte header, got {} bytes",
+                bytes.len()
+            )));
+        }
+        let (header, mut records) = bytes.split_at(HEADER_LEN);
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(invalid("packed bins do not start with DLMB".into()));
+        }
+        let version = header[MAGIC.len()];
+        if version != VERSION {
+            return Err(invalid(format!("unknown packed bins version {version}")));
+        }
+        let count = u32::from_le_bytes(field(header, MAGIC.len() + 1)) as usize;
+        // Checked before allocating, so a corrupt count cannot reserve more
+        // than the input holds.
+        if records.len() / PACKED_BIN_LEN != count || records.len() % PACKED_BIN_LEN != 0 {
+            return Err(invalid(format!(
+                "{count} packed bins need {} bytes, got {}",
+                count as u64 * PACKED_BIN_LEN as u64,
+                records.len()
+            )));
+        }
+        let mut bins = Vec::with_capacity(count);
+        while !records.is_empty() {
+            let (bin, read) = Bin::read_packed(records)?;
+            bins.push(bin);
+            records = &records[read..];
+        }
+        Ok(bins)
+    }
+
+    /// Encodes `bins` for [`Pool::bins_from_packed`]. Fails with
+    /// [`DlmmError::InvalidInput`] if there are more than `u32::MAX`.
+    pub fn bins_to_packed(bins: &[Bin]) -> Result<Vec<u8>, Error> {
+        let count = u32::try_from(bins.len()).map_err(|_| DlmmError::InvalidInput)?;
+        let mut out = Vec::with_capacity(HEADER_LEN + bins.len() * PACKED_BIN_LEN);
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&count.to_le_bytes());
+        for bin in bins {
+            bin.write_packed(&mut out);
+        }
+        Ok(out)
+    }
+}
+
+/// The `N` bytes of `bytes` at `offset`, which the caller has bounds
+/// checked.
+fn field<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    let mut out = [0; N];
+    out.copy_from_slice(&bytes[offset..offset + N]);
+    out
+}
+
+fn invalid(message: alloc::string::String) -> Error {
+    DlmmError::InvalidLayout(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn bin(id: i32, amount_a: u64, amount_b: u64, price: u128, liquidity_supply: u128) -> Bin {
+        Bin {
+            id,
+            amount_a,
+            amount_b,
+            price: price.into(),
+            liquidity_supply,
+            ..Default::default()
+        }
+    }
+
+    fn rejects(bytes: &[u8]) {
+        let err = Pool::bins_from_packed(bytes).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(DlmmError::InvalidLayout(_))),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn layout_is_pinned() {
+        let bins = [
+            bin(-1, 0, 1_000, 1 << 64, 1_000),
+            bin(2, u64::MAX, 0, u128::MAX, 0),
+        ];
+        #[rustfmt::skip]
+        let golden: &[u8] = &[
+            b'D', b'L', b'M', b'B', 1, 2, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            0xe8, 0x03, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+            0xe8, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            2, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(Pool::bins_to_packed(&bins).unwrap(), golden);
+        assert_eq!(Pool::bins_from_packed(golden).unwrap(), bins);
+        assert_eq!(
+            Bin::read_packed(&golden[HEADER_LEN..]).unwrap(),
+            (bins[0].clone(), PACKED_BIN_LEN)
+        );
+
+        // Growth is not part of the record.
+        let grown = Bin {
+            rewards_growth_global: vec![1, 2],
+            fee_amount_a_growth_global: 3,
+            fee_amount_b_growth_global: 4,
+            ..bins[0].clone()
+        };
+        assert_eq!(
+            Pool::bins_to_packed(&[grown]).unwrap(),
+            Pool::bins_to_packed(&bins[..1]).unwrap()
+        );
+        assert_eq!(
+            Pool::bins_from_packed(&Pool::bins_to_packed(&[]).unwrap()).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn malformed_bytes_are_rejected() {
+        let bytes = Pool::bins_to_packed(&[bin(1, 2, 3, 4, 5), bin(6, 7, 8, 9, 10)]).unwrap();
+        for len in 0..bytes.len() {
+            rejects(&bytes[..len]);
+        }
+        rejects(&[bytes.as_slice(), &[0]].concat());
+        rejects(&[bytes.as_slice(), &[0; PACKED_BIN_LEN]].concat());
+
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        rejects(&magic);
+        let mut version = bytes.clone();
+        version[4] = 2;
+        rejects(&version);
+        // A count far past the input.
+        let mut count = bytes.clone();
+        count[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+        rejects(&count);
+
+        let err = Bin::read_packed(&bytes[HEADER_LEN..HEADER_LEN + 51]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(DlmmError::InvalidLayout(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn bins_round_trip(
+            fields in proptest::collection::vec(
+                (any::<i32>(), any::<u64>(), any::<u64>(), any::<u128>(), any::<u128>()),
+                0..64,
+            ),
+        ) {
+            let bins: Vec<_> = fields
+                .into_iter()
+                .map(|(id, a, b, price, supply)| bin(id, a, b, price, supply))
+                .collect();
+            let bytes = Pool::bins_to_packed(&bins).unwrap();
+            prop_assert_eq!(bytes.len(), HEADER_LEN + bins.len() * PACKED_BIN_LEN);
+            prop_assert_eq!(Pool::bins_from_packed(&bytes).unwrap(), bins);
+        }
+
+        #[test]
+        fn arbitrary_bytes_do_not_panic(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let _ = Pool::bins_from_packed(&bytes);
+            let _ = Bin::read_packed(&bytes);
+            // Past the magic and version, into the count and records.
+            let _ = Pool::bins_from_packed(&[&MAGIC[..], &[VERSION], &bytes].concat());
+        }
+    }
+}
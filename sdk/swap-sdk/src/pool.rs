@@ -1,38 +1,178 @@
+use alloc::{boxed::Box, collections::BTreeMap, format, vec, vec::Vec};
+use core::fmt;
+#[cfg(feature = "std")]
 use std::collections::HashMap;
 
-use anyhow::{Context, Error};
+use anyhow::{anyhow, Context, Error};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    bin::Bin,
-    config::{BinStepConfig, VariableParameters},
-    math::BASIS_POINT_MAX,
-    MAX_FEE_RATE,
+    bin::{Bin, FillQuote},
+    config::VariableParameters,
+    dense_index::DenseBinIndex,
+    error::DlmmError,
+    fee_rate::FeeRate,
+    math::{dlmm_math::calculate_execution_price, q64x64_math::ONE, BASIS_POINT_MAX},
+    metadata::PoolMetadata,
+    timestamp::{Clock, Timestamp},
+    FEE_PRECISION, MAX_BIN_ID, MAX_FEE_RATE, MIN_BIN_ID,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct SwapResult {
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub amount_in: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub amount_out: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub fee: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub ref_fee: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub protocol_fee: u64,
     pub steps: Vec<BinSwap>,
     pub is_exceed: bool,
-}
-
-impl Default for SwapResult {
-    fn default() -> Self {
-        Self {
-            amount_in: 0,
-            amount_out: 0,
-            fee: 0,
-            ref_fee: 0,
-            protocol_fee: 0,
-            steps: Vec::new(),
-            is_exceed: false,
-        }
-    }
+    /// `v_parameters.volatility_accumulator` after the swap, which the
+    /// variable fee of the next swap starts from.
+    #[serde(default)]
+    pub end_volatility_accumulator: u32,
+    /// `v_parameters.volatility_reference` after the swap.
+    #[serde(default)]
+    pub end_volatility_reference: u32,
+    /// `v_parameters.index_reference` after the swap.
+    #[serde(default)]
+    pub end_index_reference: i32,
+    /// The pool's `active_id` after the swap.
+    #[serde(default)]
+    pub end_active_id: i32,
+    /// `v_parameters.last_update_timestamp` after the swap, in seconds.
+    #[serde(default)]
+    pub end_last_update_timestamp: u64,
+    /// The part of the requested amount left unfilled: input for exact-in
+    /// swaps, output for exact-out ones. Non-zero only if `is_exceed`.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub amount_remaining: u64,
+    /// The part of `fee` charged in token A, i.e. by `a2b` steps.
+    /// `fee_a + fee_b` is `fee`, and a swap through one pool only charges
+    /// its input token, so the other side is zero.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_a: u64,
+    /// The part of `fee` charged in token B.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_b: u64,
+    /// The part of `protocol_fee` charged in token A. `protocol_fee_a +
+    /// protocol_fee_b` is `protocol_fee`.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_a: u64,
+    /// The part of `protocol_fee` charged in token B.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_b: u64,
+    /// An upper bound on how far `amount_out` falls below what the same
+    /// exact input would buy without rounding, e.g. in a closed-form model
+    /// used to size a trade. The SDK rounds like the contract, so against
+    /// the same state the chain pays exactly `amount_out`. Zero for
+    /// exact-out swaps, which pay their rounding in `amount_in` instead.
+    ///
+    /// Every step rounds against the swapper. A step that drains its bin
+    /// pays `ceil(reserve / price)` plus a fee rounded up on that: less
+    /// than `2 + f / (1 - f)` input units over the exact cost, so below 3
+    /// with fee rates capped at [`MAX_FEE_RATE`]. The last step rounds its
+    /// fee up, losing less than one input unit, and its output down,
+    /// losing less than one output unit. An input unit lost in a step would
+    /// have bought at most `ceil(r)` output in that bin or a later one,
+    /// where `r` is the step's price for A to B swaps and its inverse for B
+    /// to A, since prices only get worse along a swap. Each step adds
+    /// `1 + 3 * ceil(r)` to the bound, saturating.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub max_rounding_shortfall: u64,
 }
 
 impl SwapResult {
@@ -40,327 +180,5324 @@ impl SwapResult {
         self.amount_in += swap_step.amount_in;
         self.amount_out += swap_step.amount_out;
         self.fee += swap_step.fee;
+        self.protocol_fee += swap_step.protocol_fee;
+        self.fee_a += swap_step.fee_a;
+        self.fee_b += swap_step.fee_b;
+        self.protocol_fee_a += swap_step.protocol_fee_a;
+        self.protocol_fee_b += swap_step.protocol_fee_b;
         self.steps.push(swap_step);
     }
+
+    /// Aggregates this result with `other`, a swap made after it in the
+    /// same pool and direction, e.g. the next chunk of a split order.
+    ///
+    /// The amount and fee fields, `amount_remaining` included, are added;
+    /// `max_rounding_shortfall` saturates like the bound it is. `steps` are
+    /// concatenated, see [`SwapResult::merge_consecutive_steps`], and
+    /// `is_exceed` is set if it is on either side. The `end_*` fields are
+    /// `other`'s, the state after both swaps.
+    ///
+    /// Nothing is recomputed from a pool, so the sum matches one swap of
+    /// the full size only if `other` ran on the pool `self` left behind;
+    /// two quotes against the same state double count the best bins. Both
+    /// results should be in the same token: the hops of a route are
+    /// combined hop by hop across chunks, not with each other.
+    ///
+    /// Fails with [`DlmmError::MathOverflow`] if a sum does not fit in a
+    /// `u64`.
+    pub fn combine(&self, other: &SwapResult) -> Result<SwapResult, DlmmError> {
+        let add = |a: u64, b: u64| a.checked_add(b).ok_or(DlmmError::MathOverflow);
+        let mut steps = Vec::with_capacity(self.steps.len() + other.steps.len());
+        steps.extend_from_slice(&self.steps);
+        steps.extend_from_slice(&other.steps);
+        Ok(SwapResult {
+            amount_in: add(self.amount_in, other.amount_in)?,
+            amount_out: add(self.amount_out, other.amount_out)?,
+            fee: add(self.fee, other.fee)?,
+            ref_fee: add(self.ref_fee, other.ref_fee)?,
+            protocol_fee: add(self.protocol_fee, other.protocol_fee)?,
+            steps,
+            is_exceed: self.is_exceed || other.is_exceed,
+            end_volatility_accumulator: other.end_volatility_accumulator,
+            end_volatility_reference: other.end_volatility_reference,
+            end_index_reference: other.end_index_reference,
+            end_active_id: other.end_active_id,
+            end_last_update_timestamp: other.end_last_update_timestamp,
+            amount_remaining: add(self.amount_remaining, other.amount_remaining)?,
+            fee_a: add(self.fee_a, other.fee_a)?,
+            fee_b: add(self.fee_b, other.fee_b)?,
+            protocol_fee_a: add(self.protocol_fee_a, other.protocol_fee_a)?,
+            protocol_fee_b: add(self.protocol_fee_b, other.protocol_fee_b)?,
+            max_rounding_shortfall: self
+                .max_rounding_shortfall
+                .saturating_add(other.max_rounding_shortfall),
+        })
+    }
+
+    /// [`SwapResult::combine`] over `results` in order, starting from an
+    /// empty result, which is also what no results give.
+    pub fn combine_all<'a>(
+        results: impl IntoIterator<Item = &'a SwapResult>,
+    ) -> Result<SwapResult, DlmmError> {
+        results
+            .into_iter()
+            .try_fold(SwapResult::default(), |total, result| total.combine(result))
+    }
+
+    /// Merges each run of consecutive steps in the same bin into one step,
+    /// as when one chunk ends in the bin the next starts from.
+    ///
+    /// A merged step adds the amounts and fees, keeps the bin's `price`
+    /// and the last step's `var_fee_rate`, and recomputes
+    /// `execution_price` from the totals. Fails with
+    /// [`DlmmError::MathOverflow`] if a sum does not fit in a `u64`, leaving
+    /// the steps unchanged.
+    pub fn merge_consecutive_steps(&mut self) -> Result<(), DlmmError> {
+        let add = |a: u64, b: u64| a.checked_add(b).ok_or(DlmmError::MathOverflow);
+        let mut merged: Vec<BinSwap> = Vec::with_capacity(self.steps.len());
+        for step in &self.steps {
+            match merged.last_mut() {
+                Some(last) if last.bin_id == step.bin_id => {
+                    last.amount_in = add(last.amount_in, step.amount_in)?;
+                    last.amount_out = add(last.amount_out, step.amount_out)?;
+                    last.fee = add(last.fee, step.fee)?;
+                    last.protocol_fee = add(last.protocol_fee, step.protocol_fee)?;
+                    last.fee_a = add(last.fee_a, step.fee_a)?;
+                    last.fee_b = add(last.fee_b, step.fee_b)?;
+                    last.protocol_fee_a = add(last.protocol_fee_a, step.protocol_fee_a)?;
+                    last.protocol_fee_b = add(last.protocol_fee_b, step.protocol_fee_b)?;
+                    last.var_fee_rate = step.var_fee_rate;
+                    // A step without a fee executes at `price` either way.
+                    let a2b = last.fee_a > 0;
+                    last.execution_price = calculate_execution_price(
+                        last.price,
+                        last.amount_in as u128,
+                        last.fee as u128,
+                        a2b,
+                    );
+                }
+                _ => merged.push(step.clone()),
+            }
+        }
+        self.steps = merged;
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct BinSwap {
     pub bin_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub amount_in: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub amount_out: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub fee: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
     pub var_fee_rate: u64,
+    /// The protocol's share of `fee`. Summed over the steps, this is
+    /// [`SwapResult::protocol_fee`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee: u64,
+    /// The Q64.64 price of the bin, token B per token A as in [`Bin::price`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub price: u128,
+    /// The Q64.64 price of the step with its fee, also token B per token
+    /// A: `price * (amount_in - fee) / amount_in` for `a2b` steps and
+    /// `price * amount_in / (amount_in - fee)` otherwise, so it is worse
+    /// than `price` by the fee fraction. See
+    /// [`calculate_execution_price`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub execution_price: u128,
+    /// `fee` if the step sold token A, otherwise zero.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_a: u64,
+    /// `fee` if the step sold token B, otherwise zero.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_b: u64,
+    /// `protocol_fee` if the step sold token A, otherwise zero.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_a: u64,
+    /// `protocol_fee` if the step sold token B, otherwise zero.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_b: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Pool {
-    pub active_id: i32,
-    pub base_fee_rate: u64,
-    pub v_parameters: VariableParameters,
-    pub bins: Vec<Bin>,
+/// One summary line, e.g.
+/// `in=1000 out=997 fee=3 protocol_fee=0 steps=2 exceed=false`.
+impl fmt::Display for SwapResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "in={} out={} fee={} protocol_fee={} steps={} exceed={}",
+            self.amount_in,
+            self.amount_out,
+            self.fee,
+            self.protocol_fee,
+            self.steps.len(),
+            self.is_exceed
+        )
+    }
 }
 
-impl Pool {
-    pub fn new(
-        active_id: i32,
-        base_fee_rate: u64,
-        v_parameters: VariableParameters,
-        bins: Vec<Bin>,
-    ) -> Self {
-        Self {
-            active_id,
-            base_fee_rate,
-            v_parameters,
-            bins,
-        }
+/// e.g. `bin 5: 1000 -> 997 fee=3 var_fee=0.25bps`.
+impl fmt::Display for BinSwap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bin {}: {} -> {} fee={} var_fee={}",
+            self.bin_id,
+            self.amount_in,
+            self.amount_out,
+            self.fee,
+            Bps(self.var_fee_rate)
+        )
     }
+}
 
-    pub fn bins_map(&self) -> HashMap<i32, Bin> {
-        self.bins.iter().cloned().map(|bin| (bin.id, bin)).collect()
-    }
+/// Running totals of the swaps made on a [`Pool`], see [`Pool::stats`].
+///
+/// Volumes are the input amounts including fees. Fees are charged in the
+/// input token, so they are tracked per token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct PoolStats {
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub cumulative_volume_a_in: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub cumulative_volume_b_in: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub cumulative_fee_a: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub cumulative_fee_b: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub cumulative_protocol_fee_a: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub cumulative_protocol_fee_b: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub swap_count: u64,
+}
 
-    pub fn swap_exact_amount_in(
-        &mut self,
-        amount_in: u64,
-        a2b: bool,
-        current_timestamp: u64,
-    ) -> Result<SwapResult, Error> {
-        self.swap_in_pool(amount_in, a2b, true, current_timestamp)
+impl PoolStats {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
     }
 
-    pub fn swap_exact_amount_out(
-        &mut self,
-        amount_out: u64,
-        a2b: bool,
-        current_timestamp: u64,
-    ) -> Result<SwapResult, Error> {
-        self.swap_in_pool(amount_out, a2b, false, current_timestamp)
+    fn record(&mut self, a2b: bool, amount_in: u128, fee: u128, protocol_fee: u128) {
+        let (volume, fees, protocol_fees) = if a2b {
+            (
+                &mut self.cumulative_volume_a_in,
+                &mut self.cumulative_fee_a,
+                &mut self.cumulative_protocol_fee_a,
+            )
+        } else {
+            (
+                &mut self.cumulative_volume_b_in,
+                &mut self.cumulative_fee_b,
+                &mut self.cumulative_protocol_fee_b,
+            )
+        };
+        *volume = volume.saturating_add(amount_in);
+        *fees = fees.saturating_add(fee);
+        *protocol_fees = protocol_fees.saturating_add(protocol_fee);
+        self.swap_count = self.swap_count.saturating_add(1);
     }
+}
 
-    fn swap_in_pool(
-        &mut self,
-        amount: u64,
-        a2b: bool,
-        by_amount_in: bool,
-        current_timestamp: u64,
-    ) -> Result<SwapResult, Error> {
-        if self.bins.is_empty() {
-            return Ok(SwapResult {
-                is_exceed: true,
-                ..Default::default()
-            });
+/// Overview of a pool's state, see [`Pool::summary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSummary {
+    pub active_id: i32,
+    pub bin_count: usize,
+    /// Lowest and highest bin id, or `None` for a pool without bins.
+    pub id_range: Option<(i32, i32)>,
+    pub total_amount_a: u128,
+    pub total_amount_b: u128,
+    /// Base plus variable fee rate at the current volatility, in
+    /// `FEE_PRECISION` units.
+    pub fee_rate: u64,
+}
+
+/// e.g. `active_id=12 bins=40 ids=[-3, 36] reserves=(a=5000, b=7000) fee=25.00bps`.
+impl fmt::Display for PoolSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "active_id={} bins={} ids=",
+            self.active_id, self.bin_count
+        )?;
+        match self.id_range {
+            Some((lo, hi)) => write!(f, "[{lo}, {hi}]")?,
+            None => f.write_str("none")?,
         }
+        write!(
+            f,
+            " reserves=(a={}, b={}) fee={}",
+            self.total_amount_a,
+            self.total_amount_b,
+            Bps(self.fee_rate)
+        )
+    }
+}
 
-        self.update_references(current_timestamp as i64)?;
-        let (mut op_next_bin_idx, _) = self.find_first_swap_bin_index(self.active_id, a2b);
-        let mut remaining_amount = amount;
-        let mut swap_result = SwapResult::default();
-        let protocol_fee_rate = self.v_parameters.bin_step_config.protocol_fee_rate;
-        let mut protocol_fee_acc = 0u64;
+/// A `FEE_PRECISION` rate shown in basis points, truncated to two decimals.
+struct Bps(u64);
 
-        while remaining_amount > 0 {
-            if op_next_bin_idx.is_none() {
-                swap_result.is_exceed = true;
-                break;
-            }
+impl fmt::Display for Bps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // One basis point is 10^5 in FEE_PRECISION units.
+        write!(f, "{}.{:02}bps", self.0 / 100_000, self.0 % 100_000 / 1_000)
+    }
+}
 
-            let current_bin_idx = op_next_bin_idx.unwrap();
-            let next_bin_idx = if a2b {
-                if current_bin_idx > 0 {
-                    Some(current_bin_idx - 1)
-                } else {
-                    None
-                }
-            } else if current_bin_idx < self.bins.len() - 1 {
-                Some(current_bin_idx + 1)
-            } else {
-                None
-            };
+/// What one exact input step at `price` adds to
+/// [`SwapResult::max_rounding_shortfall`]: `1 + 3 * ceil(r)`, where `r` is
+/// the output one input unit buys at `price`.
+fn step_rounding_shortfall(price: u128, a2b: bool) -> u128 {
+    // The step succeeded, so `price` is not zero.
+    let rate = if a2b {
+        price.div_ceil(ONE)
+    } else {
+        ONE.div_ceil(price)
+    };
+    rate.saturating_mul(3).saturating_add(1)
+}
 
-            op_next_bin_idx = next_bin_idx;
-            self.update_volatility_accumulator()?;
-            let (fee_rate, dy_fee_rate) = self.get_total_fee()?;
-            let cur_bin = &mut self.bins[current_bin_idx];
-            let (amount_in, amount_out, fee, bin_protocol_fee) = if by_amount_in {
-                cur_bin.swap_exact_amount_in(remaining_amount, a2b, fee_rate, protocol_fee_rate)?
-            } else {
-                cur_bin.swap_exact_amount_out(remaining_amount, a2b, fee_rate, protocol_fee_rate)?
-            };
+/// Bins a typical swap crosses; the step and fill vectors start with room
+/// for this many so small swaps allocate once.
+const INITIAL_STEPS_CAPACITY: usize = 8;
 
-            let step_result = BinSwap {
-                bin_id: cur_bin.id,
-                amount_in,
-                amount_out,
-                fee,
-                var_fee_rate: dy_fee_rate,
-            };
+/// How a swap treats a timestamp before the pool's
+/// `last_update_timestamp`, e.g. a replayed trade that happened a few
+/// seconds before the snapshot was taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimePolicy {
+    /// Fail with [`DlmmError::TimestampInPast`] however small the gap.
+    Reject,
+    /// Swap at `last_update_timestamp` instead, with no time elapsed.
+    #[default]
+    ClampToLast,
+    /// Swap at the earlier timestamp. `last_update_timestamp` is not moved
+    /// back. The contract only decays the volatility reference over time
+    /// after `last_update_timestamp`, so this quotes the same as
+    /// [`ClampToLast`](TimePolicy::ClampToLast); it states that the
+    /// timestamp is expected to be early rather than a clock error.
+    Allow,
+}
 
-            if by_amount_in {
-                remaining_amount = remaining_amount.saturating_sub(amount_in);
-            } else {
-                remaining_amount = remaining_amount.saturating_sub(amount_out);
-            }
-            protocol_fee_acc = protocol_fee_acc.saturating_add(bin_protocol_fee);
-            swap_result.update_swap_result(step_result);
+/// Whether a swap amount is the input or the output of the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapMode {
+    ExactIn,
+    ExactOut,
+}
 
-            if remaining_amount > 0 {
-                if let Some(next_idx) = op_next_bin_idx {
-                    let next_bin = &self.bins[next_idx];
-                    self.active_id = next_bin.id;
+/// Controls what a swap records besides its totals, and which pool states
+/// it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapOptions {
+    /// Record a [`BinSwap`] per crossed bin in [`SwapResult::steps`]. When
+    /// disabled, `steps` is left empty and the other fields are unchanged.
+    pub collect_steps: bool,
+    /// Fail with [`DlmmError::StaleState`] if the swap timestamp is more
+    /// than this many seconds after `last_update_timestamp`, i.e. the
+    /// reserves may have moved since the snapshot. `None` accepts any age.
+    pub max_staleness_seconds: Option<u64>,
+    /// Fail with [`DlmmError::TimestampInPast`] if the swap timestamp is
+    /// more than this many seconds before `last_update_timestamp`. Such a
+    /// swap skips the volatility decay, so the fee can be off. `None`
+    /// accepts any skew.
+    pub max_clock_skew_seconds: Option<u64>,
+    /// What to do with a swap timestamp before `last_update_timestamp`
+    /// that `max_clock_skew_seconds` lets through.
+    pub time_policy: TimePolicy,
+    /// Charge this protocol fee rate instead of
+    /// `bin_step_config.protocol_fee_rate`. Only the split of the fee
+    /// changes, not the total. Rates above 100% fail with
+    /// [`DlmmError::InvalidConfig`].
+    pub protocol_fee_rate_override: Option<FeeRate>,
+    /// Discount on the fee rate of every step, in basis points, applied
+    /// after the [`MAX_FEE_RATE`] cap. The protocol fee stays its share of
+    /// the discounted fee. Discounts above [`BASIS_POINT_MAX`] fail with
+    /// [`DlmmError::InvalidConfig`].
+    pub fee_discount_bps: Option<u32>,
+}
+
+impl Default for SwapOptions {
+    fn default() -> Self {
+        Self {
+            collect_steps: true,
+            max_staleness_seconds: None,
+            max_clock_skew_seconds: None,
+            time_policy: TimePolicy::ClampToLast,
+            protocol_fee_rate_override: None,
+            fee_discount_bps: None,
+        }
+    }
+}
+
+impl SwapOptions {
+    /// Checks a swap at `current_timestamp` against a pool last updated at
+    /// `last_update_timestamp`, both in seconds. Differences equal to the
+    /// limits pass, and [`TimePolicy::Reject`] fails on any timestamp
+    /// before the last update.
+    pub fn check_timestamp(
+        &self,
+        last_update_timestamp: u64,
+        current_timestamp: u64,
+    ) -> Result<(), DlmmError> {
+        match current_timestamp.checked_sub(last_update_timestamp) {
+            Some(age) if self.max_staleness_seconds.is_some_and(|max| age > max) => {
+                Err(DlmmError::StaleState { age })
+            }
+            None => {
+                let behind = last_update_timestamp - current_timestamp;
+                if self.max_clock_skew_seconds.is_some_and(|max| behind > max)
+                    || self.time_policy == TimePolicy::Reject
+                {
+                    return Err(DlmmError::TimestampInPast { behind });
                 }
+                Ok(())
             }
+            Some(_) => Ok(()),
         }
+    }
 
-        swap_result.protocol_fee = protocol_fee_acc;
-        self.v_parameters.last_update_timestamp = current_timestamp;
+    /// The protocol fee rate a swap charges: the override if set, otherwise
+    /// `config_rate`.
+    pub fn protocol_fee_rate(&self, config_rate: u64) -> Result<u64, DlmmError> {
+        match self.protocol_fee_rate_override.map(FeeRate::as_raw) {
+            Some(rate) if rate > FEE_PRECISION => Err(DlmmError::InvalidConfig(format!(
+                "protocol_fee_rate_override {rate} exceeds FEE_PRECISION"
+            ))),
+            Some(rate) => Ok(rate),
+            None => Ok(config_rate),
+        }
+    }
 
-        Ok(swap_result)
+    /// The share of each step's fee rate a swap charges, in basis points:
+    /// [`BASIS_POINT_MAX`] minus the discount.
+    pub fn fee_rate_multiplier_bps(&self) -> Result<u32, DlmmError> {
+        let discount = self.fee_discount_bps.unwrap_or(0);
+        BASIS_POINT_MAX.checked_sub(discount).ok_or_else(|| {
+            DlmmError::InvalidConfig(format!(
+                "fee_discount_bps {discount} exceeds BASIS_POINT_MAX"
+            ))
+        })
     }
+}
 
-    fn find_first_swap_bin_index(
-        &self,
-        current_bin_index: i32,
-        a2b: bool,
-    ) -> (Option<usize>, Option<usize>) {
-        if self.bins.is_empty() {
-            return (None, None);
-        }
+/// One sample of the output curve, see [`Pool::quote_ladder`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LadderPoint {
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_in: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_out: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub fee: u64,
+    /// `amount_out / amount_in`, or 0 if nothing was swapped.
+    pub effective_price: f64,
+    pub is_exceed: bool,
+}
 
-        if a2b {
-            let mut left = 0;
-            let mut right = self.bins.len() - 1;
-            while left <= right {
-                let mid = left + ((right - left) >> 1);
-                if self.bins[mid].id <= current_bin_index {
-                    if mid == self.bins.len() - 1 || self.bins[mid + 1].id > current_bin_index {
-                        let next_idx = if mid > 0 { Some(mid - 1) } else { None };
-                        return (Some(mid), next_idx);
-                    } else {
-                        left = mid + 1;
-                    }
-                } else if mid == 0 {
-                    return (None, None);
-                } else {
-                    right = mid - 1;
-                }
-            }
-            (None, None)
+/// The whole output side of a pool in one direction, see
+/// [`Pool::drain_quote`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrainQuote {
+    /// Every output token reachable within the bin id bounds.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub max_amount_out: u128,
+    /// The input, fees included, that takes all of `max_amount_out`.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub required_amount_in: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub total_fee: u128,
+    /// Bins with output liquidity, each of which is emptied.
+    pub bins_crossed: usize,
+}
+
+impl LadderPoint {
+    fn new(amount_in: u64, amount_out: u64, fee: u64, is_exceed: bool) -> Self {
+        let effective_price = if amount_in > 0 {
+            amount_out as f64 / amount_in as f64
         } else {
-            let mut left = 0;
-            let mut right = self.bins.len() - 1;
-            while left <= right {
-                let mid = left + ((right - left) >> 1);
-                if self.bins[mid].id > current_bin_index {
-                    if mid == 0 || self.bins[mid - 1].id <= current_bin_index {
-                        let next_idx = if mid >= self.bins.len() - 1 {
-                            None
-                        } else {
-                            Some(mid + 1)
-                        };
-                        return (Some(mid), next_idx);
-                    } else {
-                        right = mid - 1;
-                    }
-                } else {
-                    left = mid + 1;
-                }
-            }
-            (None, None)
+            0.0
+        };
+        Self {
+            amount_in,
+            amount_out,
+            fee,
+            effective_price,
+            is_exceed,
         }
     }
+}
 
-    fn update_references(&mut self, current_timestamp: i64) -> Result<(), Error> {
-        let v_params = &mut self.v_parameters;
-        let s_params: &BinStepConfig = &v_params.bin_step_config;
-        let last = v_params.last_update_timestamp as i64;
+/// [`SwapResult`] with `u128` amounts, returned by the `*_u128` swap and
+/// quote methods.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SwapResultU128 {
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub amount_in: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub amount_out: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub fee: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub ref_fee: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub protocol_fee: u128,
+    pub steps: Vec<BinSwapU128>,
+    pub is_exceed: bool,
+    #[serde(default)]
+    pub end_volatility_accumulator: u32,
+    #[serde(default)]
+    pub end_volatility_reference: u32,
+    #[serde(default)]
+    pub end_index_reference: i32,
+    #[serde(default)]
+    pub end_active_id: i32,
+    #[serde(default)]
+    pub end_last_update_timestamp: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub amount_remaining: u128,
+    /// See [`SwapResult::fee_a`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_a: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_b: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_a: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_b: u128,
+    /// See [`SwapResult::max_rounding_shortfall`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub max_rounding_shortfall: u128,
+}
 
-        if current_timestamp <= last {
-            return Ok(());
-        }
+/// [`BinSwap`] with `u128` amounts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct BinSwapU128 {
+    pub bin_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub amount_in: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub amount_out: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub fee: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub var_fee_rate: u64,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee: u128,
+    /// See [`BinSwap::price`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub price: u128,
+    /// See [`BinSwap::execution_price`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub execution_price: u128,
+    /// See [`BinSwap::fee_a`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_a: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub fee_b: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_a: u128,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    #[serde(default)]
+    pub protocol_fee_b: u128,
+}
 
-        let elapsed = current_timestamp - last;
+/// Collects the per-bin fills of the swap loop, which works in `u128`, into
+/// a result type.
+trait SwapTotals: Default {
+    fn reserve_steps(&mut self, capacity: usize);
 
-        if elapsed >= s_params.filter_period as i64 {
-            v_params.index_reference = self.active_id;
+    fn add_step(&mut self, step: BinSwapU128, collect_steps: bool) -> Result<(), Error>;
 
-            if elapsed < s_params.decay_period as i64 {
-                let scaled = u64::from(v_params.volatility_accumulator)
-                    .checked_mul(s_params.reduction_factor as u64)
-                    .context("volatility reference overflow")?
-                    .checked_div(BASIS_POINT_MAX as u64)
-                    .context("volatility reference overflow")?;
-                v_params.volatility_reference = scaled as u32;
-            } else {
-                v_params.volatility_reference = 0;
-            }
-        }
+    fn finish(
+        &mut self,
+        protocol_fee: u128,
+        is_exceed: bool,
+        amount_remaining: u128,
+        max_rounding_shortfall: u128,
+        active_id: i32,
+        end: &VariableParameters,
+    );
+
+    /// `(amount_in, fee, protocol_fee)`.
+    fn totals(&self) -> (u128, u128, u128);
+}
 
+impl SwapTotals for SwapResultU128 {
+    fn reserve_steps(&mut self, capacity: usize) {
+        self.steps.reserve(capacity);
+    }
+
+    fn add_step(&mut self, step: BinSwapU128, collect_steps: bool) -> Result<(), Error> {
+        self.amount_in = self
+            .amount_in
+            .checked_add(step.amount_in)
+            .ok_or_else(|| anyhow!("amount_in overflow"))?;
+        self.amount_out = self
+            .amount_out
+            .checked_add(step.amount_out)
+            .ok_or_else(|| anyhow!("amount_out overflow"))?;
+        self.fee = self
+            .fee
+            .checked_add(step.fee)
+            .ok_or_else(|| anyhow!("fee overflow"))?;
+        // Each part is at most its total, which did not overflow.
+        self.fee_a += step.fee_a;
+        self.fee_b += step.fee_b;
+        self.protocol_fee_a += step.protocol_fee_a;
+        self.protocol_fee_b += step.protocol_fee_b;
+        if collect_steps {
+            self.steps.push(step);
+        }
         Ok(())
     }
 
-    fn update_volatility_accumulator(&mut self) -> Result<(), Error> {
-        let max_accumulator = self.v_parameters.bin_step_config.max_volatility_accumulator;
-        let v_params = &mut self.v_parameters;
+    fn finish(
+        &mut self,
+        protocol_fee: u128,
+        is_exceed: bool,
+        amount_remaining: u128,
+        max_rounding_shortfall: u128,
+        active_id: i32,
+        end: &VariableParameters,
+    ) {
+        self.protocol_fee = protocol_fee;
+        self.is_exceed = is_exceed;
+        self.amount_remaining = amount_remaining;
+        self.max_rounding_shortfall = max_rounding_shortfall;
+        self.end_volatility_accumulator = end.volatility_accumulator;
+        self.end_volatility_reference = end.volatility_reference;
+        self.end_index_reference = end.index_reference;
+        self.end_active_id = active_id;
+        self.end_last_update_timestamp = end.last_update_timestamp;
+    }
 
-        let delta_id = (v_params.index_reference as i64 - self.active_id as i64).abs() as u64;
+    fn totals(&self) -> (u128, u128, u128) {
+        (self.amount_in, self.fee, self.protocol_fee)
+    }
+}
 
-        let accumulator = u64::from(v_params.volatility_reference)
-            .checked_add(
-                delta_id
-                    .checked_mul(BASIS_POINT_MAX as u64)
-                    .context("volatility accumulator overflow")?,
-            )
-            .context("volatility accumulator overflow")?;
+impl SwapTotals for SwapResult {
+    fn reserve_steps(&mut self, capacity: usize) {
+        self.steps.reserve(capacity);
+    }
 
-        let capped = accumulator.min(max_accumulator as u64);
-        v_params.volatility_accumulator = capped as u32;
+    fn add_step(&mut self, step: BinSwapU128, collect_steps: bool) -> Result<(), Error> {
+        let narrow = |value: u128, total: u64, what: &str| {
+            u64::try_from(value)
+                .ok()
+                .filter(|value| total.checked_add(*value).is_some())
+                .ok_or_else(|| anyhow!("{what} overflow"))
+        };
+        let step = BinSwap {
+            bin_id: step.bin_id,
+            amount_in: narrow(step.amount_in, self.amount_in, "amount_in")?,
+            amount_out: narrow(step.amount_out, self.amount_out, "amount_out")?,
+            fee: narrow(step.fee, self.fee, "fee")?,
+            var_fee_rate: step.var_fee_rate,
+            // At most `fee`, which fits.
+            protocol_fee: step.protocol_fee as u64,
+            price: step.price,
+            execution_price: step.execution_price,
+            // Each part is at most its total, which fits.
+            fee_a: step.fee_a as u64,
+            fee_b: step.fee_b as u64,
+            protocol_fee_a: step.protocol_fee_a as u64,
+            protocol_fee_b: step.protocol_fee_b as u64,
+        };
+        if collect_steps {
+            self.update_swap_result(step);
+        } else {
+            self.amount_in += step.amount_in;
+            self.amount_out += step.amount_out;
+            self.fee += step.fee;
+            self.protocol_fee += step.protocol_fee;
+            self.fee_a += step.fee_a;
+            self.fee_b += step.fee_b;
+            self.protocol_fee_a += step.protocol_fee_a;
+            self.protocol_fee_b += step.protocol_fee_b;
+        }
         Ok(())
     }
 
-    fn get_variable_fee(&self) -> Result<u128, Error> {
-        self.compute_variable_fee(self.v_parameters.volatility_accumulator)
+    fn finish(
+        &mut self,
+        protocol_fee: u128,
+        is_exceed: bool,
+        amount_remaining: u128,
+        max_rounding_shortfall: u128,
+        active_id: i32,
+        end: &VariableParameters,
+    ) {
+        self.protocol_fee = protocol_fee.min(u64::MAX as u128) as u64;
+        self.is_exceed = is_exceed;
+        // Never more than the `u64` amount requested.
+        self.amount_remaining = amount_remaining as u64;
+        self.max_rounding_shortfall = max_rounding_shortfall.min(u64::MAX as u128) as u64;
+        self.end_volatility_accumulator = end.volatility_accumulator;
+        self.end_volatility_reference = end.volatility_reference;
+        self.end_index_reference = end.index_reference;
+        self.end_active_id = active_id;
+        self.end_last_update_timestamp = end.last_update_timestamp;
     }
 
-    fn compute_variable_fee(&self, volatility_accumulator: u32) -> Result<u128, Error> {
-        let s_params = &self.v_parameters.bin_step_config;
-        if s_params.variable_fee_control > 0 {
-            let va = volatility_accumulator as u128;
-            let bin_step = s_params.bin_step as u128;
-            let variable_fee_control = s_params.variable_fee_control as u128;
-
-            let combined = va
-                .checked_mul(bin_step)
-                .context("variable fee overflow")?;
-            let square = combined
-                .checked_mul(combined)
-                .context("variable fee overflow")?;
+    fn totals(&self) -> (u128, u128, u128) {
+        (
+            self.amount_in as u128,
+            self.fee as u128,
+            self.protocol_fee as u128,
+        )
+    }
+}
 
-            let v_fee = variable_fee_control
-                .checked_mul(square)
-                .context("variable fee overflow")?;
+/// Bins in the order a swap crosses them, see [`Pool::bins_toward`].
+/// Iterating from the back goes the other way, ending at the start bin.
+#[derive(Debug, Clone)]
+pub struct BinsToward<'a> {
+    bins: core::slice::Iter<'a, Bin>,
+    a2b: bool,
+}
 
-            let scaled_v_fee = v_fee
-                .checked_add(99_999_999_999)
-                .context("variable fee overflow")?
-                .checked_div(100_000_000_000)
-                .context("variable fee overflow")?;
+impl<'a> Iterator for BinsToward<'a> {
+    type Item = &'a Bin;
 
-            return Ok(scaled_v_fee);
+    fn next(&mut self) -> Option<&'a Bin> {
+        if self.a2b {
+            self.bins.next_back()
+        } else {
+            self.bins.next()
         }
-
-        Ok(0)
     }
 
-    fn get_total_fee(&self) -> Result<(u64, u64), Error> {
-        let variable_fee = self.get_variable_fee()?;
-        let total_fee_rate = (self.base_fee_rate as u128)
-            .checked_add(variable_fee)
-            .context("total fee overflow")?;
-        let capped = total_fee_rate.min(MAX_FEE_RATE.into());
-        Ok((capped as u64, variable_fee as u64))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.bins.size_hint()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::bin::Bin;
-
-    fn make_bin(id: i32, amount_a: u64, amount_b: u64, price: u128) -> Bin {
-        Bin {
-            id,
-            amount_a,
-            amount_b,
-            price,
-            liquidity_supply: 0,
-            rewards_growth_global: vec![],
-            fee_amount_a_growth_global: 0,
-            fee_amount_b_growth_global: 0,
+impl DoubleEndedIterator for BinsToward<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.a2b {
+            self.bins.next()
+        } else {
+            self.bins.next_back()
         }
     }
+}
 
-    fn default_bin_step() -> BinStepConfig {
-        BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 30_000)
+impl ExactSizeIterator for BinsToward<'_> {}
+
+/// Reserves of one bin after a simulated swap.
+struct BinFill {
+    bin_index: usize,
+    amount_a: u64,
+    amount_b: u64,
+}
+
+/// State a swap would leave behind, computed without touching the pool.
+struct SwapSimulation<R> {
+    result: R,
+    active_id: i32,
+    v_parameters: VariableParameters,
+}
+
+/// Pool state captured by [`Pool::checkpoint`], to be undone with
+/// [`Pool::restore`].
+///
+/// Only `active_id` and `v_parameters` are copied up front. Bins are saved
+/// lazily by [`Pool::swap_tracked`], the first time it changes each one, so
+/// a checkpoint costs the bins a swap crossed rather than the whole pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolCheckpoint {
+    active_id: i32,
+    v_parameters: VariableParameters,
+    stats: PoolStats,
+    /// `(amount_a, amount_b)` of each changed bin before its first change,
+    /// by index into `bins`.
+    reserves: BTreeMap<usize, (u64, u64)>,
+}
+
+impl PoolCheckpoint {
+    /// Number of bins saved so far.
+    pub fn saved_bins(&self) -> usize {
+        self.reserves.len()
     }
+}
 
-    #[test]
-    fn swap_exact_in_across_bins() {
-        let mut pool = Pool::new(
-            0,
-            30_000,
-            VariableParameters::new(default_bin_step(), 0, 0),
-            vec![
-                make_bin(0, 1_000_000, 500_000, 1 << 64),
-                make_bin(1, 1_000_000, 2_000_000, (1 << 64) + 1000),
-            ],
-        );
+/// Which pool [`Pool::merge_from`] takes a bin id present in both from,
+/// along with `active_id` and `v_parameters`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePreference {
+    /// Always the other pool.
+    Other,
+    /// The pool with the later `last_update_timestamp`, keeping this one
+    /// on a tie.
+    Newer,
+}
 
-        let result = pool
-            .swap_exact_amount_in(200_000, true, 10)
-            .expect("swap succeeds");
+/// Version of the `Pool` JSON layout written by this crate, see
+/// [`Pool::schema_version`].
+///
+/// - `0`: snapshots written before the field existed, which lack
+///   `schema_version` and `stats`
+/// - `1`: the current layout
+pub const POOL_SCHEMA_VERSION: u32 = 1;
+
+fn current_schema_version() -> u32 {
+    POOL_SCHEMA_VERSION
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Pool {
+    /// Layout version of the snapshot, [`POOL_SCHEMA_VERSION`] for pools
+    /// built or loaded by this crate. Older JSON snapshots are upgraded by
+    /// `Pool::migrate` (`migrate` feature). Not part of the Borsh encoding,
+    /// which always decodes as the current version.
+    #[serde(default = "current_schema_version")]
+    #[cfg_attr(feature = "borsh", borsh(skip))]
+    pub schema_version: u32,
+    pub active_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    pub base_fee_rate: u64,
+    pub v_parameters: VariableParameters,
+    /// Sorted by id with no duplicates or zero prices, see
+    /// [`Pool::validate`]. Read it with [`Pool::bins`] and change it with
+    /// [`Pool::insert_bin`], [`Pool::remove_bin`] or [`Pool::bins_mut_with`],
+    /// which keep those invariants. Writing the field directly is
+    /// deprecated: it is private with the `strict-invariants` feature and
+    /// will be in the next minor release.
+    #[cfg(not(feature = "strict-invariants"))]
+    pub bins: Vec<Bin>,
+    #[cfg(feature = "strict-invariants")]
+    pub(crate) bins: Vec<Bin>,
+    /// Totals of the swaps made on this instance. Omitted from JSON while
+    /// empty and not part of the Borsh encoding.
+    #[serde(default, skip_serializing_if = "PoolStats::is_empty")]
+    #[cfg_attr(feature = "borsh", borsh(skip))]
+    pub stats: PoolStats,
+    /// Which pool this is and its tokens' decimals, see
+    /// [`Pool::metadata`]. Omitted from JSON when unset and not part of
+    /// the Borsh encoding or [`Pool::state_hash`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "borsh", borsh(skip))]
+    pub metadata: Option<PoolMetadata>,
+}
+
+/// Decodes the Borsh fields in their documented order; `schema_version`,
+/// `stats` and `metadata` are not encoded and come back as the current
+/// version, empty and unset.
+#[cfg(feature = "borsh")]
+impl borsh::BorshDeserialize for Pool {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(Pool::new(
+            i32::deserialize_reader(reader)?,
+            u64::deserialize_reader(reader)?,
+            VariableParameters::deserialize_reader(reader)?,
+            Vec::<Bin>::deserialize_reader(reader)?,
+        ))
+    }
+}
+
+impl Pool {
+    /// `base_fee_rate` also accepts a raw `u64` in [`FEE_PRECISION`] units,
+    /// see [`FeeRate`].
+    pub fn new(
+        active_id: i32,
+        base_fee_rate: impl Into<FeeRate>,
+        v_parameters: VariableParameters,
+        bins: Vec<Bin>,
+    ) -> Self {
+        Self {
+            schema_version: POOL_SCHEMA_VERSION,
+            active_id,
+            base_fee_rate: base_fee_rate.into().as_raw(),
+            v_parameters,
+            bins,
+            stats: PoolStats::default(),
+            metadata: None,
+        }
+    }
+
+    /// [`Pool::new`] followed by [`Pool::validate`].
+    pub fn new_checked(
+        active_id: i32,
+        base_fee_rate: impl Into<FeeRate>,
+        v_parameters: VariableParameters,
+        bins: Vec<Bin>,
+    ) -> Result<Self, Error> {
+        let pool = Self::new(active_id, base_fee_rate, v_parameters, bins);
+        pool.validate()?;
+        Ok(pool)
+    }
+
+    /// The `base_fee_rate` field as a [`FeeRate`]. The field stays a raw
+    /// `u64` since it mirrors the on-chain pool and the serialized layouts.
+    pub fn base_fee_rate(&self) -> FeeRate {
+        FeeRate::from_raw(self.base_fee_rate)
+    }
+
+    /// Checks that bins are strictly sorted by id with non-zero prices, that
+    /// the active and bin ids are within [`MIN_BIN_ID`]`..=`[`MAX_BIN_ID`]
+    /// and that the fee rates are within the protocol bounds. The bin step
+    /// of the [`metadata`](Pool::metadata), if any, must match the config's.
+    pub fn validate(&self) -> Result<(), Error> {
+        check_bin_id(self.active_id).context("active_id")?;
+        if self.base_fee_rate > MAX_FEE_RATE {
+            return Err(anyhow!(
+                "base_fee_rate {} exceeds MAX_FEE_RATE",
+                self.base_fee_rate
+            ));
+        }
+        let protocol_fee_rate = self.v_parameters.bin_step_config.protocol_fee_rate;
+        if protocol_fee_rate > FEE_PRECISION {
+            return Err(anyhow!(
+                "protocol_fee_rate {} exceeds FEE_PRECISION",
+                protocol_fee_rate
+            ));
+        }
+        if let Some(metadata) = &self.metadata {
+            let bin_step = self.v_parameters.bin_step_config.bin_step;
+            if metadata.bin_step != bin_step {
+                return Err(anyhow!(
+                    "metadata bin_step {} does not match the config's {}",
+                    metadata.bin_step,
+                    bin_step
+                ));
+            }
+        }
+        check_bins(&self.bins)
+    }
+
+    /// The bins, sorted by id.
+    pub fn bins(&self) -> &[Bin] {
+        &self.bins
+    }
+
+    /// Runs `f` on the bins, then checks that they are still strictly
+    /// sorted with non-zero prices and ids within
+    /// [`MIN_BIN_ID`]`..=`[`MAX_BIN_ID`]. If they are not, the bins are put
+    /// back as they were before `f` and the check's error is returned.
+    ///
+    /// The rollback keeps a copy of the bins for the duration of the call;
+    /// [`Pool::insert_bin`] and [`Pool::remove_bin`] change one bin without
+    /// it.
+    pub fn bins_mut_with(&mut self, f: impl FnOnce(&mut Vec<Bin>)) -> Result<(), Error> {
+        let before = self.bins.clone();
+        let baseline = self.debug_invariants_before();
+        f(&mut self.bins);
+        check_bins(&self.bins).inspect_err(|_| self.bins = before)?;
+        self.debug_invariants_after(&baseline, "bins_mut_with");
+        Ok(())
+    }
+
+    /// Takes the pool apart into the arguments of [`Pool::new`]:
+    /// `(active_id, base_fee_rate, v_parameters, bins)`. `stats` and
+    /// `metadata` are dropped.
+    pub fn into_parts(self) -> (i32, u64, VariableParameters, Vec<Bin>) {
+        (
+            self.active_id,
+            self.base_fee_rate,
+            self.v_parameters,
+            self.bins,
+        )
+    }
+
+    /// Compares two pools field by field, except for
+    /// `v_parameters.last_update_timestamp`, which every swap rewrites even
+    /// when nothing else changes, the local [`stats`](Pool::stats),
+    /// `schema_version` and `metadata`.
+    pub fn state_eq_ignoring_time(&self, other: &Pool) -> bool {
+        let VariableParameters {
+            volatility_accumulator,
+            volatility_reference,
+            index_reference,
+            last_update_timestamp: _,
+            bin_step_config,
+        } = &self.v_parameters;
+        let theirs = &other.v_parameters;
+        self.active_id == other.active_id
+            && self.base_fee_rate == other.base_fee_rate
+            && *volatility_accumulator == theirs.volatility_accumulator
+            && *volatility_reference == theirs.volatility_reference
+            && *index_reference == theirs.index_reference
+            && *bin_step_config == theirs.bin_step_config
+            && self.bins == other.bins
+    }
+
+    /// Inserts `bin` at its sorted position, returning the bin it replaces
+    /// if one with the same id was present.
+    ///
+    /// Fails with [`DlmmError::InvalidBinId`] if the id is outside
+    /// [`MIN_BIN_ID`]`..=`[`MAX_BIN_ID`]. Also fails if the price is zero,
+    /// as [`Pool::validate`] does. The pool is unchanged on failure.
+    pub fn insert_bin(&mut self, bin: Bin) -> Result<Option<Bin>, Error> {
+        check_bin_id(bin.id)?;
+        if bin.price.as_raw() == 0 {
+            return Err(anyhow!("bin {} has zero price", bin.id));
+        }
+        let baseline = self.debug_invariants_before();
+        let replaced = match self.bins.binary_search_by_key(&bin.id, |b| b.id) {
+            Ok(index) => Some(core::mem::replace(&mut self.bins[index], bin)),
+            Err(index) => {
+                self.bins.insert(index, bin);
+                None
+            }
+        };
+        self.debug_invariants_after(&baseline, "insert_bin");
+        Ok(replaced)
+    }
+
+    /// Merges `other`'s bins into this pool, keeping them sorted by id.
+    ///
+    /// Bins in only one pool are kept. For an id in both, and for
+    /// `active_id` and `v_parameters`, `prefer` picks the pool to take it
+    /// from; it picks the same pool for all of them. The base fee rate,
+    /// `stats`, `schema_version` and `metadata` stay this pool's. Both
+    /// pools are assumed to be fetches of the same on-chain pool.
+    pub fn merge_from(&mut self, other: &Pool, prefer: MergePreference) {
+        let baseline = self.debug_invariants_before();
+        let take_other = match prefer {
+            MergePreference::Other => true,
+            MergePreference::Newer => {
+                other.v_parameters.last_update_timestamp > self.v_parameters.last_update_timestamp
+            }
+        };
+        if take_other {
+            self.active_id = other.active_id;
+            self.v_parameters.clone_from(&other.v_parameters);
+        }
+
+        let existing = core::mem::take(&mut self.bins);
+        let mut merged = Vec::with_capacity(existing.len() + other.bins.len());
+        let mut existing = existing.into_iter().peekable();
+        for bin in &other.bins {
+            while let Some(prev) = existing.next_if(|prev| prev.id < bin.id) {
+                merged.push(prev);
+            }
+            match existing.next_if(|prev| prev.id == bin.id) {
+                Some(prev) if !take_other => merged.push(prev),
+                _ => merged.push(bin.clone()),
+            }
+        }
+        merged.extend(existing);
+        self.bins = merged;
+        self.debug_invariants_after(&baseline, "merge_from");
+    }
+
+    /// Removes and returns the bin with `id`, if the pool holds it.
+    pub fn remove_bin(&mut self, id: i32) -> Option<Bin> {
+        self.bin_index(id).map(|index| self.bins.remove(index))
+    }
+
+    #[cfg(feature = "std")]
+    #[deprecated(note = "clones every bin; use `Pool::get_bin` or `Pool::bins_by_id`")]
+    pub fn bins_map(&self) -> HashMap<i32, Bin> {
+        self.bins.iter().cloned().map(|bin| (bin.id, bin)).collect()
+    }
+
+    /// Position in `bins` of the bin with `id`.
+    ///
+    /// Bins are kept sorted by id (see [`Pool::validate`]), so this is a
+    /// binary search over the vector itself and nothing needs to be rebuilt
+    /// when bins are modified, added or removed.
+    pub fn bin_index(&self, id: i32) -> Option<usize> {
+        self.bins.binary_search_by_key(&id, |bin| bin.id).ok()
+    }
+
+    /// The bin with `id`, if the pool holds it.
+    pub fn get_bin(&self, id: i32) -> Option<&Bin> {
+        self.bin_index(id).map(|index| &self.bins[index])
+    }
+
+    /// Borrowing lookup by bin id, for call sites that want a function
+    /// rather than a map.
+    pub fn bins_by_id<'a>(&'a self) -> impl Fn(i32) -> Option<&'a Bin> + 'a {
+        move |id| self.get_bin(id)
+    }
+
+    /// The bin a swap starts from, with its index in `bins`.
+    ///
+    /// From A to B that is the bin with the highest id at or below the
+    /// active id, and from B to A the bin with the lowest id above it, so
+    /// an active id in a gap between bins starts from the bin on the side
+    /// the swap moves to. `None` if there is no bin on that side, e.g. from
+    /// A to B with the active id below every bin. Bins without output
+    /// liquidity are not skipped here; the swap passes over them.
+    pub fn start_bin_for_swap(&self, a2b: bool) -> Option<(usize, &Bin)> {
+        let (index, _) = self.find_first_swap_bin_index(self.active_id, a2b);
+        index.map(|index| (index, &self.bins[index]))
+    }
+
+    /// The next bin after id `from_id` in the swap direction: the bin with
+    /// the highest id below it from A to B, and with the lowest id above it
+    /// from B to A. `from_id` need not be a bin.
+    pub fn neighbor_bin(&self, from_id: i32, a2b: bool) -> Option<&Bin> {
+        let from_id = if a2b {
+            from_id.checked_sub(1)?
+        } else {
+            from_id
+        };
+        let (index, _) = self.find_first_swap_bin_index(from_id, a2b);
+        index.map(|index| &self.bins[index])
+    }
+
+    /// The bins a swap in the `a2b` direction would cross, in order from
+    /// [`Pool::start_bin_for_swap`] to the end of `bins`. Bins without
+    /// output liquidity are included.
+    pub fn bins_toward(&self, a2b: bool) -> BinsToward<'_> {
+        self.bins_from(self.active_id, a2b)
+    }
+
+    /// [`Pool::bins_toward`] for a swap starting with active id `id`.
+    pub fn bins_from(&self, id: i32, a2b: bool) -> BinsToward<'_> {
+        let bins = match self.find_first_swap_bin_index(id, a2b) {
+            (Some(start), _) if a2b => &self.bins[..=start],
+            (Some(start), _) => &self.bins[start..],
+            (None, _) => &[],
+        };
+        BinsToward {
+            bins: bins.iter(),
+            a2b,
+        }
+    }
+
+    /// Active id, bin range, reserves and current fee rate, for logs.
+    ///
+    /// The fee rate uses the stored volatility accumulator, without the
+    /// decay a swap at a later timestamp would apply.
+    pub fn summary(&self) -> PoolSummary {
+        let fee_rate = self
+            .v_parameters
+            .get_total_fee(self.base_fee_rate)
+            // Only a variable fee far above the cap overflows.
+            .map_or(MAX_FEE_RATE, |(rate, _)| rate);
+        PoolSummary {
+            active_id: self.active_id,
+            bin_count: self.bins.len(),
+            id_range: self
+                .bins
+                .first()
+                .zip(self.bins.last())
+                .map(|(lo, hi)| (lo.id, hi.id)),
+            total_amount_a: self.total_amount_a(),
+            total_amount_b: self.total_amount_b(),
+            fee_rate,
+        }
+    }
+
+    /// Applies the time-based decay of the volatility references up to
+    /// `current_timestamp`, exactly as a swap at that time would before
+    /// crossing its first bin, and records the timestamp.
+    ///
+    /// Swaps call the same logic, so refreshing first and then swapping at
+    /// the same timestamp gives the same result as swapping directly.
+    pub fn refresh(&mut self, current_timestamp: impl Into<Timestamp>) -> Result<(), Error> {
+        self.v_parameters
+            .refresh(self.active_id, current_timestamp.into().as_seconds())
+    }
+
+    /// Removes bins whose `amount_a`, `amount_b` and `liquidity_supply` are
+    /// all zero, keeping the rest in order, and returns how many were
+    /// removed. The bin at `active_id` is always kept.
+    ///
+    /// Swaps pass over such bins, so quotes are unchanged, except that a
+    /// swap running out of bins may stop at a different `end_active_id`.
+    /// Pruning drops the bins' fee and reward growth, which matters when they
+    /// are refilled later; with `keep_growth`, bins with any non-zero growth
+    /// are kept.
+    pub fn prune_empty_bins(&mut self, keep_growth: bool) -> usize {
+        let before = self.bins.len();
+        let active_id = self.active_id;
+        self.bins.retain(|bin| {
+            let is_empty = bin.amount_a == 0 && bin.amount_b == 0 && bin.liquidity_supply == 0;
+            let has_growth = bin.fee_amount_a_growth_global != 0
+                || bin.fee_amount_b_growth_global != 0
+                || bin.rewards_growth_global.iter().any(|growth| *growth != 0);
+            !is_empty || bin.id == active_id || (keep_growth && has_growth)
+        });
+        before - self.bins.len()
+    }
+
+    /// A copy of the pool with only the bins whose ids are within
+    /// `half_width` of the active id, for quoting sizes that stay near it.
+    ///
+    /// Quotes are unchanged as long as the swap stays inside the window. One
+    /// that would leave it stops at the last bin with `is_exceed` set, as if
+    /// the pool had no more bins.
+    pub fn trim_to_window(&self, half_width: u32) -> Pool {
+        Pool {
+            schema_version: self.schema_version,
+            active_id: self.active_id,
+            base_fee_rate: self.base_fee_rate,
+            v_parameters: self.v_parameters.clone(),
+            bins: self.bins[self.window(half_width)].to_vec(),
+            stats: self.stats,
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// [`Pool::trim_to_window`] without copying the pool.
+    pub fn trim_in_place(&mut self, half_width: u32) {
+        let window = self.window(half_width);
+        self.bins.truncate(window.end);
+        self.bins.drain(..window.start);
+    }
+
+    /// The indices of the bins within `half_width` of the active id.
+    fn window(&self, half_width: u32) -> core::ops::Range<usize> {
+        let lo = self.active_id as i64 - half_width as i64;
+        let hi = self.active_id as i64 + half_width as i64;
+        let start = self.bins.partition_point(|bin| (bin.id as i64) < lo);
+        let end = self.bins.partition_point(|bin| bin.id as i64 <= hi);
+        start..end
+    }
+
+    /// Captures `active_id` and `v_parameters`; see [`PoolCheckpoint`].
+    pub fn checkpoint(&self) -> PoolCheckpoint {
+        PoolCheckpoint {
+            active_id: self.active_id,
+            v_parameters: self.v_parameters.clone(),
+            stats: self.stats,
+            reserves: BTreeMap::new(),
+        }
+    }
+
+    /// [`Pool::swap_exact_amount_in`] if `by_amount_in`, otherwise
+    /// [`Pool::swap_exact_amount_out`], saving the bins it changes into
+    /// `checkpoint` so that [`Pool::restore`] can undo it.
+    pub fn swap_tracked(
+        &mut self,
+        amount: u64,
+        a2b: bool,
+        by_amount_in: bool,
+        current_timestamp: impl Into<Timestamp>,
+        checkpoint: &mut PoolCheckpoint,
+    ) -> Result<SwapResult, Error> {
+        self.swap_in_pool(
+            amount as u128,
+            a2b,
+            by_amount_in,
+            current_timestamp.into(),
+            SwapOptions::default(),
+            Some(checkpoint),
+            None,
+        )
+    }
+
+    /// Returns the pool to its state at [`Pool::checkpoint`], undoing every
+    /// [`Pool::swap_tracked`] since. Changes made any other way, such as
+    /// untracked swaps or inserted bins, are not undone.
+    pub fn restore(&mut self, checkpoint: &PoolCheckpoint) {
+        for (&index, &(amount_a, amount_b)) in &checkpoint.reserves {
+            if let Some(bin) = self.bins.get_mut(index) {
+                bin.amount_a = amount_a;
+                bin.amount_b = amount_b;
+            }
+        }
+        self.active_id = checkpoint.active_id;
+        self.v_parameters = checkpoint.v_parameters.clone();
+        self.stats = checkpoint.stats;
+    }
+
+    /// Makes `target` equal to `self.clone()`, reusing its bins vector and
+    /// each bin's rewards vector instead of allocating new ones. Copying a
+    /// pool into the same scratch pool over and over, e.g. to try candidate
+    /// swaps, allocates only when the bins or rewarders outgrow it.
+    pub fn clone_into(&self, target: &mut Pool) {
+        let Pool {
+            schema_version,
+            active_id,
+            base_fee_rate,
+            v_parameters,
+            bins,
+            stats,
+            metadata,
+        } = self;
+        target.schema_version = *schema_version;
+        target.active_id = *active_id;
+        target.base_fee_rate = *base_fee_rate;
+        target.v_parameters.clone_from(v_parameters);
+        target.bins.truncate(bins.len());
+        let reused = target.bins.len();
+        for (bin, target_bin) in bins.iter().zip(&mut target.bins) {
+            bin.clone_into(target_bin);
+        }
+        target.bins.extend_from_slice(&bins[reused..]);
+        target.stats = *stats;
+        target.metadata.clone_from(metadata);
+    }
+
+    /// Makes this pool equal to `source.clone()`; see [`Pool::clone_into`].
+    pub fn reset_from(&mut self, source: &Pool) {
+        source.clone_into(self);
+    }
+
+    /// Volume, fees and number of the swaps made on this instance, including
+    /// those applied with [`Pool::apply_swap_result`]. Quotes are not
+    /// counted.
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Clears [`Pool::stats`].
+    pub fn reset_stats(&mut self) {
+        self.stats = PoolStats::default();
+    }
+
+    /// Applies a swap computed earlier, e.g. by a quote on a replica, without
+    /// re-running the swap loop: each step moves its input, less the fee,
+    /// into its bin and its output out of it, exactly as the swap did, and
+    /// `active_id` and `v_parameters` are set to the state the swap ended in.
+    ///
+    /// `result` must carry its steps, so results computed with
+    /// `collect_steps: false` are rejected. Fails with
+    /// [`DlmmError::InvalidInput`] if the steps do not add up to the totals,
+    /// [`DlmmError::InvalidBinId`] if a step's bin is missing and
+    /// [`DlmmError::MathOverflow`] if a reserve would go negative or overflow;
+    /// the pool is unchanged on failure.
+    pub fn apply_swap_result(&mut self, result: &SwapResult, a2b: bool) -> Result<(), DlmmError> {
+        let baseline = self.debug_invariants_before();
+        let (mut amount_in, mut amount_out) = (0u64, 0u64);
+        for step in &result.steps {
+            amount_in = amount_in
+                .checked_add(step.amount_in)
+                .ok_or(DlmmError::MathOverflow)?;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or(DlmmError::MathOverflow)?;
+        }
+        if amount_in != result.amount_in || amount_out != result.amount_out {
+            return Err(DlmmError::InvalidInput);
+        }
+
+        let mut fills: Vec<BinFill> = Vec::with_capacity(result.steps.len());
+        for step in &result.steps {
+            let bin_index = self.bin_index(step.bin_id).ok_or(DlmmError::InvalidBinId)?;
+            let amount_in = step
+                .amount_in
+                .checked_sub(step.fee)
+                .ok_or(DlmmError::InvalidInput)?;
+            // A bin listed twice continues from its reserves after the
+            // earlier step.
+            let bin = &self.bins[bin_index];
+            let (reserve_a, reserve_b) = fills
+                .iter()
+                .rev()
+                .find(|fill| fill.bin_index == bin_index)
+                .map_or((bin.amount_a, bin.amount_b), |fill| {
+                    (fill.amount_a, fill.amount_b)
+                });
+            let (reserve_in, reserve_out) = if a2b {
+                (reserve_a, reserve_b)
+            } else {
+                (reserve_b, reserve_a)
+            };
+            let reserve_in = reserve_in
+                .checked_add(amount_in)
+                .ok_or(DlmmError::MathOverflow)?;
+            let reserve_out = reserve_out
+                .checked_sub(step.amount_out)
+                .ok_or(DlmmError::MathOverflow)?;
+            let (amount_a, amount_b) = if a2b {
+                (reserve_in, reserve_out)
+            } else {
+                (reserve_out, reserve_in)
+            };
+            fills.push(BinFill {
+                bin_index,
+                amount_a,
+                amount_b,
+            });
+        }
+
+        for fill in &fills {
+            let bin = &mut self.bins[fill.bin_index];
+            bin.amount_a = fill.amount_a;
+            bin.amount_b = fill.amount_b;
+        }
+        self.active_id = result.end_active_id;
+        self.v_parameters.volatility_accumulator = result.end_volatility_accumulator;
+        self.v_parameters.volatility_reference = result.end_volatility_reference;
+        self.v_parameters.index_reference = result.end_index_reference;
+        self.v_parameters.last_update_timestamp = result.end_last_update_timestamp;
+        self.stats.record(
+            a2b,
+            result.amount_in as u128,
+            result.fee as u128,
+            result.protocol_fee as u128,
+        );
+        self.debug_invariants_after(&baseline, "apply_swap_result");
+        Ok(())
+    }
+
+    /// Total fee rate, in `FEE_PRECISION` units, that a swap starting at
+    /// `future_timestamp` would pay in the active bin, given the volatility
+    /// decay in between. See [`VariableParameters::preview_at`].
+    ///
+    /// This is the pool's live fee: it runs the decay on a copy of
+    /// `v_parameters`, so polling it leaves the pool untouched.
+    #[doc(alias = "current_fee_rate")]
+    pub fn fee_rate_at(&self, future_timestamp: impl Into<Timestamp>) -> Result<u64, Error> {
+        let (fee_rate, _) = self
+            .v_parameters
+            .preview_at(self.active_id, future_timestamp.into().as_seconds())
+            .get_total_fee(self.base_fee_rate)?;
+        Ok(fee_rate)
+    }
+
+    /// [`Bin::fill_quote`] for the bin with `bin_id`, at the fee rate
+    /// [`Pool::fee_rate_at`] gives for `current_timestamp` and the pool's
+    /// protocol fee rate.
+    ///
+    /// That is the fee of a swap starting now. A swap that reaches a bin
+    /// away from the active one has raised the volatility accumulator on
+    /// the way, so it may pay more by then. Fails with
+    /// [`DlmmError::InvalidBinId`] if the pool has no such bin.
+    pub fn fill_quote_at_bin(
+        &self,
+        bin_id: i32,
+        taker_amount_in: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<FillQuote, Error> {
+        let bin = self
+            .get_bin(bin_id)
+            .ok_or(DlmmError::InvalidBinId)
+            .with_context(|| format!("no bin {bin_id}"))?;
+        let fee_rate = self.fee_rate_at(current_timestamp)?;
+        bin.fill_quote(
+            taker_amount_in,
+            a2b,
+            fee_rate,
+            self.v_parameters.bin_step_config.protocol_fee_rate,
+        )
+    }
+
+    /// Seconds from `current_timestamp` until a swap would start with a
+    /// total fee rate of at most `target_total_fee_rate`, or `None` if it
+    /// never does because the fully decayed fee, the base fee, is above it.
+    ///
+    /// [`Pool::fee_rate_at`] only changes where the filter/decay logic
+    /// changes branch: at `filter_period` seconds after the last update the
+    /// reference decays by the reduction factor, and at `decay_period` it
+    /// resets. So the answer is the first of `current_timestamp` and those
+    /// two moments still ahead whose fee is at or below the target.
+    pub fn wait_time_for_fee_below(
+        &self,
+        target_total_fee_rate: impl Into<FeeRate>,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<Option<u64>, Error> {
+        let target = target_total_fee_rate.into().as_raw();
+        let now = current_timestamp.into().as_seconds();
+        let last = self.v_parameters.last_update_timestamp;
+        let config = &self.v_parameters.bin_step_config;
+        for at in [
+            now,
+            last.saturating_add(config.filter_period.into()).max(now),
+            last.saturating_add(config.decay_period.into()).max(now),
+        ] {
+            if self.fee_rate_at(at)? <= target {
+                return Ok(Some(at - now));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sum of `amount_a` across all bins.
+    pub fn total_amount_a(&self) -> u128 {
+        self.bins.iter().map(|bin| bin.amount_a as u128).sum()
+    }
+
+    /// Sum of `amount_b` across all bins.
+    pub fn total_amount_b(&self) -> u128 {
+        self.bins.iter().map(|bin| bin.amount_b as u128).sum()
+    }
+
+    /// Sum of `liquidity_supply` across all bins, saturating at `u128::MAX`.
+    pub fn total_liquidity(&self) -> u128 {
+        self.bins
+            .iter()
+            .fold(0u128, |acc, bin| acc.saturating_add(bin.liquidity_supply))
+    }
+
+    /// Returns the `(amount_a, amount_b)` held by bins with `lo <= id <= hi`.
+    pub fn reserves_in_range(&self, lo: i32, hi: i32) -> (u128, u128) {
+        if lo > hi {
+            return (0, 0);
+        }
+        let start = self.bins.partition_point(|bin| bin.id < lo);
+        let end = self.bins.partition_point(|bin| bin.id <= hi);
+        self.bins[start..end]
+            .iter()
+            .fold((0u128, 0u128), |(a, b), bin| {
+                (a + bin.amount_a as u128, b + bin.amount_b as u128)
+            })
+    }
+
+    pub fn swap_exact_amount_in(
+        &mut self,
+        amount_in: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResult, Error> {
+        self.swap_in_pool(
+            amount_in as u128,
+            a2b,
+            true,
+            current_timestamp.into(),
+            SwapOptions::default(),
+            None,
+            None,
+        )
+    }
+
+    pub fn swap_exact_amount_out(
+        &mut self,
+        amount_out: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResult, Error> {
+        self.swap_in_pool(
+            amount_out as u128,
+            a2b,
+            false,
+            current_timestamp.into(),
+            SwapOptions::default(),
+            None,
+            None,
+        )
+    }
+
+    pub fn swap_exact_amount_in_with_options(
+        &mut self,
+        amount_in: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+        options: SwapOptions,
+    ) -> Result<SwapResult, Error> {
+        self.swap_in_pool(
+            amount_in as u128,
+            a2b,
+            true,
+            current_timestamp.into(),
+            options,
+            None,
+            None,
+        )
+    }
+
+    pub fn swap_exact_amount_out_with_options(
+        &mut self,
+        amount_out: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+        options: SwapOptions,
+    ) -> Result<SwapResult, Error> {
+        self.swap_in_pool(
+            amount_out as u128,
+            a2b,
+            false,
+            current_timestamp.into(),
+            options,
+            None,
+            None,
+        )
+    }
+
+    /// Quotes an exact-input swap without modifying the pool.
+    pub fn quote_exact_amount_in(
+        &self,
+        amount_in: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResult, Error> {
+        self.quote_exact_amount_in_with_options(
+            amount_in,
+            a2b,
+            current_timestamp,
+            SwapOptions::default(),
+        )
+    }
+
+    /// Quotes an exact-output swap without modifying the pool.
+    pub fn quote_exact_amount_out(
+        &self,
+        amount_out: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResult, Error> {
+        self.quote_exact_amount_out_with_options(
+            amount_out,
+            a2b,
+            current_timestamp,
+            SwapOptions::default(),
+        )
+    }
+
+    pub fn quote_exact_amount_in_with_options(
+        &self,
+        amount_in: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+        options: SwapOptions,
+    ) -> Result<SwapResult, Error> {
+        Ok(self
+            .simulate_swap(
+                amount_in as u128,
+                a2b,
+                true,
+                current_timestamp.into(),
+                options,
+                None,
+                None,
+            )?
+            .result)
+    }
+
+    pub fn quote_exact_amount_out_with_options(
+        &self,
+        amount_out: u64,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+        options: SwapOptions,
+    ) -> Result<SwapResult, Error> {
+        Ok(self
+            .simulate_swap(
+                amount_out as u128,
+                a2b,
+                false,
+                current_timestamp.into(),
+                options,
+                None,
+                None,
+            )?
+            .result)
+    }
+
+    /// [`Pool::swap_exact_amount_in_with_options`] if `by_amount_in`,
+    /// otherwise [`Pool::swap_exact_amount_out_with_options`], finding the
+    /// first bin through `index`. The index is rebuilt first if bins were
+    /// added or removed since it was built, which fails as
+    /// [`DenseBinIndex::new`] does. The result is the same as without it.
+    pub fn swap_with_index(
+        &mut self,
+        index: &mut DenseBinIndex,
+        amount: u64,
+        a2b: bool,
+        by_amount_in: bool,
+        current_timestamp: impl Into<Timestamp>,
+        options: SwapOptions,
+    ) -> Result<SwapResult, Error> {
+        if !index.is_current(self) {
+            index.rebuild(self)?;
+        }
+        self.swap_in_pool(
+            amount as u128,
+            a2b,
+            by_amount_in,
+            current_timestamp.into(),
+            options,
+            None,
+            Some(index),
+        )
+    }
+
+    /// [`Pool::swap_with_index`] without modifying the pool. A stale
+    /// `index` is not rebuilt; the lookups it cannot answer fall back to a
+    /// binary search, so the quote is still the same as without it.
+    pub fn quote_with_index(
+        &self,
+        index: &DenseBinIndex,
+        amount: u64,
+        a2b: bool,
+        by_amount_in: bool,
+        current_timestamp: impl Into<Timestamp>,
+        options: SwapOptions,
+    ) -> Result<SwapResult, Error> {
+        Ok(self
+            .simulate_swap(
+                amount as u128,
+                a2b,
+                by_amount_in,
+                current_timestamp.into(),
+                options,
+                None,
+                Some(index),
+            )?
+            .result)
+    }
+
+    /// [`Pool::swap_exact_amount_in`] at the time `clock` reads.
+    pub fn swap_exact_amount_in_with_clock(
+        &mut self,
+        amount_in: u64,
+        a2b: bool,
+        clock: &impl Clock,
+    ) -> Result<SwapResult, Error> {
+        self.swap_exact_amount_in(amount_in, a2b, clock.now())
+    }
+
+    /// [`Pool::swap_exact_amount_out`] at the time `clock` reads.
+    pub fn swap_exact_amount_out_with_clock(
+        &mut self,
+        amount_out: u64,
+        a2b: bool,
+        clock: &impl Clock,
+    ) -> Result<SwapResult, Error> {
+        self.swap_exact_amount_out(amount_out, a2b, clock.now())
+    }
+
+    /// [`Pool::quote_exact_amount_in`] at the time `clock` reads.
+    pub fn quote_exact_amount_in_with_clock(
+        &self,
+        amount_in: u64,
+        a2b: bool,
+        clock: &impl Clock,
+    ) -> Result<SwapResult, Error> {
+        self.quote_exact_amount_in(amount_in, a2b, clock.now())
+    }
+
+    /// [`Pool::quote_exact_amount_out`] at the time `clock` reads.
+    pub fn quote_exact_amount_out_with_clock(
+        &self,
+        amount_out: u64,
+        a2b: bool,
+        clock: &impl Clock,
+    ) -> Result<SwapResult, Error> {
+        self.quote_exact_amount_out(amount_out, a2b, clock.now())
+    }
+
+    /// [`Pool::swap_exact_amount_in`] with `u128` amounts, for tokens whose
+    /// trade sizes exceed `u64::MAX` base units. Bin reserves stay `u64`; a
+    /// swap that would overflow one fails without changing the pool.
+    pub fn swap_exact_amount_in_u128(
+        &mut self,
+        amount_in: u128,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResultU128, Error> {
+        self.swap_in_pool(
+            amount_in,
+            a2b,
+            true,
+            current_timestamp.into(),
+            SwapOptions::default(),
+            None,
+            None,
+        )
+    }
+
+    /// [`Pool::swap_exact_amount_out`] with `u128` amounts.
+    pub fn swap_exact_amount_out_u128(
+        &mut self,
+        amount_out: u128,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResultU128, Error> {
+        self.swap_in_pool(
+            amount_out,
+            a2b,
+            false,
+            current_timestamp.into(),
+            SwapOptions::default(),
+            None,
+            None,
+        )
+    }
+
+    /// [`Pool::quote_exact_amount_in`] with `u128` amounts.
+    pub fn quote_exact_amount_in_u128(
+        &self,
+        amount_in: u128,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResultU128, Error> {
+        Ok(self
+            .simulate_swap(
+                amount_in,
+                a2b,
+                true,
+                current_timestamp.into(),
+                SwapOptions::default(),
+                None,
+                None,
+            )?
+            .result)
+    }
+
+    /// [`Pool::quote_exact_amount_out`] with `u128` amounts.
+    pub fn quote_exact_amount_out_u128(
+        &self,
+        amount_out: u128,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<SwapResultU128, Error> {
+        Ok(self
+            .simulate_swap(
+                amount_out,
+                a2b,
+                false,
+                current_timestamp.into(),
+                SwapOptions::default(),
+                None,
+                None,
+            )?
+            .result)
+    }
+
+    /// Quotes exact-input swaps of each of `amounts_in`, which must be sorted
+    /// ascending, in a single walk over the bins. Each point is identical to
+    /// [`Pool::quote_exact_amount_in`] for that amount.
+    ///
+    /// Bins that a smaller amount drains completely are drained by every
+    /// larger amount too, so their totals are carried over from one point to
+    /// the next and only the last, partially filled bin is quoted per point.
+    pub fn quote_ladder(
+        &self,
+        amounts_in: &[u64],
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<Vec<LadderPoint>, Error> {
+        if amounts_in.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(anyhow!("amounts_in must be sorted ascending"));
+        }
+        if self.bins.is_empty() {
+            return Ok(vec![LadderPoint::new(0, 0, 0, true); amounts_in.len()]);
+        }
+
+        let mut v_parameters = self.v_parameters.clone();
+        v_parameters.refresh(self.active_id, current_timestamp.into().as_seconds())?;
+        let protocol_fee_rate = v_parameters.bin_step_config.protocol_fee_rate;
+        let (mut bin_idx, _) = self.find_first_swap_bin_index(self.active_id, a2b);
+        let mut active_id = self.active_id;
+        // Fee rate of the bin at `bin_idx`, computed when first reached.
+        let mut fee_rate = None;
+        let (mut drained_in, mut drained_out, mut drained_fee) = (0u64, 0u64, 0u64);
+
+        let mut points = Vec::with_capacity(amounts_in.len());
+        for &amount in amounts_in {
+            let point = loop {
+                let remaining = amount - drained_in;
+                if remaining == 0 {
+                    break LadderPoint::new(drained_in, drained_out, drained_fee, false);
+                }
+                let in_bounds =
+                    |idx: &usize| (MIN_BIN_ID..=MAX_BIN_ID).contains(&self.bins[*idx].id);
+                let Some(idx) = bin_idx.filter(in_bounds) else {
+                    break LadderPoint::new(drained_in, drained_out, drained_fee, true);
+                };
+                let bin = &self.bins[idx];
+                let next_idx = if a2b {
+                    idx.checked_sub(1)
+                } else {
+                    Some(idx + 1).filter(|next| *next < self.bins.len())
+                };
+                // Passed over as in the swap: no fee update, and the active
+                // id only moves on if the walk had reached this bin.
+                let reserve_out = if a2b { bin.amount_b } else { bin.amount_a };
+                if reserve_out == 0 {
+                    if let Some(next) = next_idx.filter(|_| active_id == bin.id) {
+                        active_id = self.bins[next].id;
+                    }
+                    bin_idx = next_idx;
+                    continue;
+                }
+                let rate = match fee_rate {
+                    Some(rate) => rate,
+                    None => {
+                        v_parameters.update_volatility_accumulator(active_id)?;
+                        let (rate, _) = v_parameters.get_total_fee(self.base_fee_rate)?;
+                        fee_rate = Some(rate);
+                        rate
+                    }
+                };
+                let (amount_in, amount_out, fee, _) =
+                    bin.quote_exact_amount_in(remaining, a2b, rate, protocol_fee_rate)?;
+                // Fail where the swap itself would.
+                bin.reserves_after_swap(a2b, (amount_in - fee) as u128, amount_out as u128)?;
+                let total_out = drained_out
+                    .checked_add(amount_out)
+                    .ok_or_else(|| anyhow!("amount_out overflow"))?;
+                if amount_in == remaining {
+                    break LadderPoint::new(
+                        drained_in + amount_in,
+                        total_out,
+                        drained_fee + fee,
+                        false,
+                    );
+                }
+
+                // The bin ran dry before `remaining` was used up.
+                drained_in += amount_in;
+                drained_out = total_out;
+                drained_fee += fee;
+                bin_idx = next_idx;
+                if let Some(next) = bin_idx {
+                    active_id = self.bins[next].id;
+                }
+                fee_rate = None;
+            };
+            points.push(point);
+        }
+        Ok(points)
+    }
+
+    /// Quotes taking every output token in the `a2b` direction, with the
+    /// same per-bin fee and volatility updates as a swap. The result matches
+    /// [`Pool::quote_exact_amount_out_u128`] for `max_amount_out`. A pool
+    /// without output liquidity in that direction quotes all zeros.
+    pub fn drain_quote(
+        &self,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<DrainQuote, Error> {
+        let result = self.drain(a2b, current_timestamp.into())?;
+        Ok(DrainQuote {
+            max_amount_out: result.amount_out,
+            required_amount_in: result.amount_in,
+            total_fee: result.fee,
+            bins_crossed: result.steps.len(),
+        })
+    }
+
+    /// Every output token a swap in the `a2b` direction can reach: the
+    /// reserves of [`Pool::bins_toward`] within the bin id bounds, summed
+    /// and saturating at `u64::MAX`. Cheaper than [`Pool::drain_quote`],
+    /// as no fees are computed.
+    pub fn max_output(&self, a2b: bool) -> u64 {
+        self.bins_toward(a2b)
+            .take_while(|bin| (MIN_BIN_ID..=MAX_BIN_ID).contains(&bin.id))
+            .map(|bin| if a2b { bin.amount_b } else { bin.amount_a })
+            .fold(0u64, u64::saturating_add)
+    }
+
+    /// The largest exact input the pool fills without `is_exceed`,
+    /// saturating at `u64::MAX`; one unit more exceeds it. Quoted with the
+    /// same per-bin fees as [`Pool::drain_quote`].
+    ///
+    /// This is at least the `required_amount_in` of the drain: the last bin
+    /// crossed also takes the input that buys less than one more unit of
+    /// output, see [`Bin::max_exact_amount_in_u128`]. It can also be an
+    /// earlier bin that takes all of the input, in which case the input
+    /// does not reach the bins after it and the output is below
+    /// [`Pool::max_output`].
+    pub fn max_input(
+        &self,
+        a2b: bool,
+        current_timestamp: impl Into<Timestamp>,
+    ) -> Result<u64, Error> {
+        let drain = self.drain(a2b, current_timestamp.into())?;
+        // An input ending in a bin is taken whole up to the cost of the bins
+        // before it plus that bin's maximum.
+        let (mut max_input, mut crossed_cost) = (0u128, 0u128);
+        for step in &drain.steps {
+            let bin = self.get_bin(step.bin_id).context("drained bin")?;
+            let fee_rate =
+                (self.base_fee_rate as u128 + step.var_fee_rate as u128).min(MAX_FEE_RATE as u128);
+            let bin_max = bin.max_exact_amount_in_u128(a2b, fee_rate as u64)?;
+            max_input = max_input.max(crossed_cost + bin_max);
+            crossed_cost += step.amount_in;
+        }
+        Ok(u64::try_from(max_input).unwrap_or(u64::MAX))
+    }
+
+    /// Runs a swap taking every output token in the `a2b` direction.
+    fn drain(&self, a2b: bool, current_timestamp: Timestamp) -> Result<SwapResultU128, Error> {
+        Ok(self
+            .simulate_swap(
+                u128::MAX,
+                a2b,
+                false,
+                current_timestamp,
+                SwapOptions::default(),
+                None,
+                None,
+            )?
+            .result)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn swap_in_pool<R: SwapTotals>(
+        &mut self,
+        amount: u128,
+        a2b: bool,
+        by_amount_in: bool,
+        current_timestamp: Timestamp,
+        options: SwapOptions,
+        checkpoint: Option<&mut PoolCheckpoint>,
+        index: Option<&DenseBinIndex>,
+    ) -> Result<R, Error> {
+        let baseline = self.debug_invariants_before();
+        let mut fills = Vec::with_capacity(INITIAL_STEPS_CAPACITY);
+        let simulation = self.simulate_swap::<R>(
+            amount,
+            a2b,
+            by_amount_in,
+            current_timestamp,
+            options,
+            Some(&mut fills),
+            index,
+        )?;
+        if let Some(checkpoint) = checkpoint {
+            for fill in &fills {
+                let bin = &self.bins[fill.bin_index];
+                checkpoint
+                    .reserves
+                    .entry(fill.bin_index)
+                    .or_insert((bin.amount_a, bin.amount_b));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            bins = fills.len(),
+            active_id = simulation.active_id,
+            "applying swap"
+        );
+        for fill in &fills {
+            let bin = &mut self.bins[fill.bin_index];
+            bin.amount_a = fill.amount_a;
+            bin.amount_b = fill.amount_b;
+        }
+        self.active_id = simulation.active_id;
+        self.v_parameters = simulation.v_parameters;
+        let (amount_in, fee, protocol_fee) = simulation.result.totals();
+        self.stats.record(a2b, amount_in, fee, protocol_fee);
+        self.debug_invariants_after(&baseline, "swap");
+        Ok(simulation.result)
+    }
+
+    /// Runs the swap loop against the current state without mutating it.
+    /// The new reserves of each crossed bin are pushed to `fills` when given,
+    /// and `index` finds the first bin when it holds the active id.
+    #[allow(clippy::too_many_arguments)]
+    fn simulate_swap<R: SwapTotals>(
+        &self,
+        amount: u128,
+        a2b: bool,
+        by_amount_in: bool,
+        current_timestamp: Timestamp,
+        options: SwapOptions,
+        mut fills: Option<&mut Vec<BinFill>>,
+        index: Option<&DenseBinIndex>,
+    ) -> Result<SwapSimulation<R>, Error> {
+        options.check_timestamp(
+            self.v_parameters.last_update_timestamp,
+            current_timestamp.as_seconds(),
+        )?;
+        let protocol_fee_rate =
+            options.protocol_fee_rate(self.v_parameters.bin_step_config.protocol_fee_rate)?;
+        let fee_rate_multiplier = options.fee_rate_multiplier_bps()?;
+        let mut active_id = self.active_id;
+        let mut v_parameters = self.v_parameters.clone();
+        let mut swap_result = R::default();
+        if self.bins.is_empty() {
+            swap_result.finish(0, true, amount, 0, active_id, &v_parameters);
+            return Ok(SwapSimulation {
+                result: swap_result,
+                active_id,
+                v_parameters,
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            amount,
+            a2b,
+            by_amount_in,
+            timestamp = current_timestamp.as_seconds(),
+            active_id,
+            "swap"
+        );
+        let last_update_timestamp = v_parameters.last_update_timestamp;
+        match options.time_policy {
+            TimePolicy::Allow => {
+                v_parameters.update_references(active_id, current_timestamp.as_seconds() as i64)?;
+                v_parameters.last_update_timestamp =
+                    last_update_timestamp.max(current_timestamp.as_seconds());
+            }
+            // `check_timestamp` has rejected earlier timestamps under `Reject`.
+            TimePolicy::Reject | TimePolicy::ClampToLast => {
+                v_parameters.refresh(
+                    active_id,
+                    last_update_timestamp.max(current_timestamp.as_seconds()),
+                )?;
+            }
+        }
+        // With the active bin in hand the swap starts on it from A to B and
+        // on the bin after it from B to A, as the search would find.
+        let mut op_next_bin_idx =
+            match index.and_then(|index| index.position(&self.bins, active_id)) {
+                Some(position) if a2b => Some(position),
+                Some(position) => Some(position + 1).filter(|&next| next < self.bins.len()),
+                None => self.find_first_swap_bin_index(active_id, a2b).0,
+            };
+        let mut remaining_amount = amount;
+        if options.collect_steps {
+            swap_result.reserve_steps(INITIAL_STEPS_CAPACITY);
+        }
+        let base_fee_rate = self.base_fee_rate;
+        let mut protocol_fee_acc = 0u128;
+        let mut is_exceed = false;
+        // The fee rates only depend on the accumulator, which stays put once
+        // it reaches `max_volatility_accumulator`; they are recomputed only
+        // when it moves.
+        let mut fee_rates: Option<(u32, (u64, u64))> = None;
+        let mut step_index = 0;
+        let mut rounding_shortfall = 0u128;
+
+        while remaining_amount > 0 {
+            if op_next_bin_idx.is_none() {
+                is_exceed = true;
+                break;
+            }
+
+            let current_bin_idx = op_next_bin_idx.unwrap();
+            let next_bin_idx = if a2b {
+                if current_bin_idx > 0 {
+                    Some(current_bin_idx - 1)
+                } else {
+                    None
+                }
+            } else if current_bin_idx < self.bins.len() - 1 {
+                Some(current_bin_idx + 1)
+            } else {
+                None
+            };
+
+            let cur_bin = &self.bins[current_bin_idx];
+            // The program cannot walk past the bin id bounds, so bins beyond
+            // them count as missing liquidity.
+            if !(MIN_BIN_ID..=MAX_BIN_ID).contains(&cur_bin.id) {
+                is_exceed = true;
+                break;
+            }
+
+            op_next_bin_idx = next_bin_idx;
+            // A bin without the output token has nothing to fill. It is
+            // passed over without a step or a volatility update, as the
+            // reference LB swap loop only touches bins that are not empty on
+            // the output side. The active id only moves on if the swap had
+            // reached this bin, so a pool quotes the same with or without
+            // such bins.
+            let reserve_out = if a2b {
+                cur_bin.amount_b
+            } else {
+                cur_bin.amount_a
+            };
+            if reserve_out == 0 {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(
+                    bin_index = current_bin_idx,
+                    bin_id = cur_bin.id,
+                    "skip bin without output liquidity"
+                );
+                if let Some(next_idx) = op_next_bin_idx.filter(|_| active_id == cur_bin.id) {
+                    active_id = self.bins[next_idx].id;
+                }
+                continue;
+            }
+            let bin_id = cur_bin.id;
+            let step_failed = move |err: Error| -> Error {
+                let source = Box::new(DlmmError::from_anyhow(err));
+                DlmmError::StepFailed {
+                    bin_id,
+                    step_index,
+                    remaining_amount,
+                    a2b,
+                    source,
+                }
+                .into()
+            };
+            v_parameters
+                .update_volatility_accumulator(active_id)
+                .map_err(step_failed)?;
+            let accumulator = v_parameters.volatility_accumulator;
+            let (fee_rate, dy_fee_rate) = match fee_rates {
+                Some((cached, rates)) if cached == accumulator => rates,
+                _ => {
+                    let (mut fee_rate, dy_fee_rate) = v_parameters
+                        .get_total_fee(base_fee_rate)
+                        .map_err(step_failed)?;
+                    if fee_rate_multiplier != BASIS_POINT_MAX {
+                        // At most the capped rate, so it fits in a u64.
+                        fee_rate = (fee_rate as u128 * fee_rate_multiplier as u128
+                            / BASIS_POINT_MAX as u128) as u64;
+                    }
+                    let rates = (fee_rate, dy_fee_rate);
+                    fee_rates = Some((accumulator, rates));
+                    rates
+                }
+            };
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                bin_index = current_bin_idx,
+                bin_id = cur_bin.id,
+                active_id,
+                fee_rate,
+                var_fee_rate = dy_fee_rate,
+                volatility_accumulator = v_parameters.volatility_accumulator,
+                remaining = remaining_amount,
+                "swap step"
+            );
+            let (amount_in, amount_out, fee, bin_protocol_fee) = if by_amount_in {
+                cur_bin.quote_exact_amount_in_u128(
+                    remaining_amount,
+                    a2b,
+                    fee_rate,
+                    protocol_fee_rate,
+                )
+            } else {
+                cur_bin.quote_exact_amount_out_u128(
+                    remaining_amount,
+                    a2b,
+                    fee_rate,
+                    protocol_fee_rate,
+                )
+            }
+            .map_err(step_failed)?;
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                bin_id = cur_bin.id,
+                amount_in,
+                amount_out,
+                fee,
+                protocol_fee = bin_protocol_fee,
+                "swap step filled"
+            );
+            let (amount_a, amount_b) = cur_bin
+                .reserves_after_swap(a2b, amount_in - fee, amount_out)
+                .map_err(step_failed)?;
+            if let Some(fills) = fills.as_deref_mut() {
+                fills.push(BinFill {
+                    bin_index: current_bin_idx,
+                    amount_a,
+                    amount_b,
+                });
+            }
+
+            if by_amount_in {
+                remaining_amount = remaining_amount.saturating_sub(amount_in);
+            } else {
+                remaining_amount = remaining_amount.saturating_sub(amount_out);
+            }
+            protocol_fee_acc = protocol_fee_acc.saturating_add(bin_protocol_fee);
+            let ((fee_a, fee_b), (protocol_fee_a, protocol_fee_b)) = if a2b {
+                ((fee, 0), (bin_protocol_fee, 0))
+            } else {
+                ((0, fee), (0, bin_protocol_fee))
+            };
+            swap_result
+                .add_step(
+                    BinSwapU128 {
+                        bin_id: cur_bin.id,
+                        amount_in,
+                        amount_out,
+                        fee,
+                        var_fee_rate: dy_fee_rate,
+                        protocol_fee: bin_protocol_fee,
+                        price: cur_bin.price.as_raw(),
+                        execution_price: calculate_execution_price(
+                            cur_bin.price.as_raw(),
+                            amount_in,
+                            fee,
+                            a2b,
+                        ),
+                        fee_a,
+                        fee_b,
+                        protocol_fee_a,
+                        protocol_fee_b,
+                    },
+                    options.collect_steps,
+                )
+                .map_err(step_failed)?;
+            step_index += 1;
+            if by_amount_in {
+                rounding_shortfall = rounding_shortfall
+                    .saturating_add(step_rounding_shortfall(cur_bin.price.as_raw(), a2b));
+            }
+
+            // A swap that is filled exactly as it drains a bin leaves the
+            // active id on that bin, as the reference LB swap loop does. The
+            // next swap in the same direction passes over it as a bin
+            // without output liquidity.
+            if remaining_amount > 0 {
+                if let Some(next_idx) = op_next_bin_idx {
+                    active_id = self.bins[next_idx].id;
+                }
+            }
+        }
+
+        swap_result.finish(
+            protocol_fee_acc,
+            is_exceed,
+            remaining_amount,
+            rounding_shortfall,
+            active_id,
+            &v_parameters,
+        );
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            remaining = remaining_amount,
+            is_exceed,
+            active_id,
+            volatility_accumulator = v_parameters.volatility_accumulator,
+            "swap finished"
+        );
+
+        Ok(SwapSimulation {
+            result: swap_result,
+            active_id,
+            v_parameters,
+        })
+    }
+
+    fn find_first_swap_bin_index(
+        &self,
+        current_bin_index: i32,
+        a2b: bool,
+    ) -> (Option<usize>, Option<usize>) {
+        if self.bins.is_empty() {
+            return (None, None);
+        }
+
+        if a2b {
+            let mut left = 0;
+            let mut right = self.bins.len() - 1;
+            while left <= right {
+                let mid = left + ((right - left) >> 1);
+                if self.bins[mid].id <= current_bin_index {
+                    if mid == self.bins.len() - 1 || self.bins[mid + 1].id > current_bin_index {
+                        let next_idx = if mid > 0 { Some(mid - 1) } else { None };
+                        return (Some(mid), next_idx);
+                    } else {
+                        left = mid + 1;
+                    }
+                } else if mid == 0 {
+                    return (None, None);
+                } else {
+                    right = mid - 1;
+                }
+            }
+            (None, None)
+        } else {
+            let mut left = 0;
+            let mut right = self.bins.len() - 1;
+            while left <= right {
+                let mid = left + ((right - left) >> 1);
+                if self.bins[mid].id > current_bin_index {
+                    if mid == 0 || self.bins[mid - 1].id <= current_bin_index {
+                        let next_idx = if mid >= self.bins.len() - 1 {
+                            None
+                        } else {
+                            Some(mid + 1)
+                        };
+                        return (Some(mid), next_idx);
+                    } else {
+                        right = mid - 1;
+                    }
+                } else {
+                    left = mid + 1;
+                }
+            }
+            (None, None)
+        }
+    }
+}
+
+fn check_bin_id(id: i32) -> Result<(), Error> {
+    if (MIN_BIN_ID..=MAX_BIN_ID).contains(&id) {
+        Ok(())
+    } else {
+        Err(DlmmError::InvalidBinId)
+            .with_context(|| format!("bin id {id} is outside [{MIN_BIN_ID}, {MAX_BIN_ID}]"))
+    }
+}
+
+/// The bin invariants of [`Pool::validate`].
+pub(crate) fn check_bins(bins: &[Bin]) -> Result<(), Error> {
+    for pair in bins.windows(2) {
+        if pair[0].id >= pair[1].id {
+            return Err(anyhow!(
+                "bins are not strictly sorted: {} followed by {}",
+                pair[0].id,
+                pair[1].id
+            ));
+        }
+    }
+    if let Some(bin) = bins.iter().find(|bin| bin.price.as_raw() == 0) {
+        return Err(anyhow!("bin {} has zero price", bin.id));
+    }
+    // Sorted, so only the outermost bins can be out of range.
+    for bin in bins.first().into_iter().chain(bins.last()) {
+        check_bin_id(bin.id)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bin::Bin, builder::PoolBuilder, config::BinStepConfig};
+
+    fn make_bin(id: i32, amount_a: u64, amount_b: u64, price: u128) -> Bin {
+        Bin {
+            id,
+            amount_a,
+            amount_b,
+            price: price.into(),
+            liquidity_supply: 0,
+            rewards_growth_global: vec![],
+            fee_amount_a_growth_global: 0,
+            fee_amount_b_growth_global: 0,
+        }
+    }
+
+    fn default_bin_step() -> BinStepConfig {
+        BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 30_000)
+    }
+
+    #[test]
+    fn swap_exact_in_across_bins() {
+        let mut pool = Pool::new(
+            0,
+            30_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            vec![
+                make_bin(0, 1_000_000, 500_000, 1 << 64),
+                make_bin(1, 1_000_000, 2_000_000, (1 << 64) + 1000),
+            ],
+        );
+
+        let result = pool
+            .swap_exact_amount_in(200_000, true, 10)
+            .expect("swap succeeds");
 
         assert!(result.amount_out > 0);
         assert_eq!(result.steps.len(), 1);
     }
+
+    #[test]
+    fn ladder_matches_independent_quotes() {
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = (-3..=3)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(id, 1_000_000, 1_000_000, price)
+            })
+            .collect();
+        let pool = Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins);
+
+        for a2b in [true, false] {
+            // Include the exact cost of draining the active bin, where the walk
+            // stops without moving to the next bin.
+            let active_bin_cost = pool
+                .quote_exact_amount_out(1_000_000, a2b, 100)
+                .unwrap()
+                .amount_in;
+            let mut amounts = vec![
+                0,
+                1,
+                1_000,
+                active_bin_cost - 1,
+                active_bin_cost,
+                active_bin_cost + 1,
+                active_bin_cost + 1,
+                2_500_000,
+                4_000_001,
+                10_000_000,
+                u64::MAX / 2,
+            ];
+            amounts.sort_unstable();
+
+            let ladder = pool.quote_ladder(&amounts, a2b, 100).unwrap();
+            assert_eq!(ladder.len(), amounts.len());
+            for (amount, point) in amounts.iter().zip(&ladder) {
+                let quote = pool.quote_exact_amount_in(*amount, a2b, 100).unwrap();
+                let expected = LadderPoint::new(
+                    quote.amount_in,
+                    quote.amount_out,
+                    quote.fee,
+                    quote.is_exceed,
+                );
+                assert_eq!(*point, expected, "amount {amount}, a2b {a2b}");
+            }
+            assert!(ladder.last().unwrap().is_exceed);
+            assert!(ladder[2].effective_price > ladder[7].effective_price);
+        }
+
+        assert!(pool.quote_ladder(&[2, 1], true, 100).is_err());
+        let empty = Pool::new(0, 2_500_000, pool.v_parameters.clone(), vec![]);
+        assert!(empty
+            .quote_ladder(&[0, 5], true, 100)
+            .unwrap()
+            .iter()
+            .all(|p| p.is_exceed));
+    }
+
+    #[test]
+    fn draining_matches_an_exact_out_swap() {
+        let pool = PoolBuilder::new()
+            .config(BinStepConfig::new(
+                10, 1, 60, 600, 9000, 40_000, 350_000, 30_000,
+            ))
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-60, 60, 10_000, 10_000)
+            .bin(0, 5_000, 5_000)
+            .build()
+            .unwrap();
+
+        // Swaps from b to a start above the active bin, so its 5_000 of
+        // token a are out of reach.
+        for (a2b, reserve, bins) in [(true, 605_000, 61), (false, 600_000, 60)] {
+            let drain = pool.drain_quote(a2b, 100).unwrap();
+            assert_eq!(drain.max_amount_out, reserve);
+            assert_eq!(drain.bins_crossed, bins);
+
+            let mut swapped = pool.clone();
+            let swap = swapped
+                .swap_exact_amount_out(drain.max_amount_out as u64, a2b, 100)
+                .unwrap();
+            assert!(!swap.is_exceed);
+            assert_eq!(swap.amount_out as u128, drain.max_amount_out);
+            assert_eq!(swap.amount_in as u128, drain.required_amount_in);
+            assert_eq!(swap.fee as u128, drain.total_fee);
+            assert_eq!(swap.steps.len(), drain.bins_crossed);
+            // Nothing is left to take.
+            assert_eq!(swapped.drain_quote(a2b, 100).unwrap().max_amount_out, 0);
+        }
+
+        // A one-sided pool has nothing to give the other way.
+        let one_sided = Pool::new(
+            0,
+            2_500_000,
+            pool.v_parameters.clone(),
+            vec![make_bin(1, 10_000, 0, 1u128 << 64)],
+        );
+        assert_eq!(
+            one_sided.drain_quote(true, 100).unwrap(),
+            DrainQuote::default()
+        );
+        assert_eq!(
+            one_sided.drain_quote(false, 100).unwrap().max_amount_out,
+            10_000
+        );
+        let empty = Pool::new(0, 2_500_000, pool.v_parameters.clone(), vec![]);
+        assert_eq!(
+            empty.drain_quote(false, 100).unwrap(),
+            DrainQuote::default()
+        );
+    }
+
+    #[test]
+    fn max_input_fills_the_pool_without_exceeding() {
+        let pool = PoolBuilder::new()
+            .config(BinStepConfig::new(
+                10, 1, 60, 600, 9000, 40_000, 350_000, 30_000,
+            ))
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-40, 40, 10_000, 10_000)
+            .bin(0, 5_000, 5_000)
+            .bin(-3, 0, 0)
+            .build()
+            .unwrap();
+        for (a2b, max_output) in [(true, 395_000), (false, 400_000)] {
+            assert_eq!(pool.max_output(a2b), max_output);
+            let max_input = pool.max_input(a2b, 100).unwrap();
+
+            let full = pool.quote_exact_amount_in(max_input, a2b, 100).unwrap();
+            assert!(!full.is_exceed);
+            assert_eq!(full.amount_in, max_input);
+            assert_eq!(full.amount_out, max_output);
+            assert!(max_input as u128 >= pool.drain_quote(a2b, 100).unwrap().required_amount_in);
+            let over = pool.quote_exact_amount_in(max_input + 1, a2b, 100).unwrap();
+            assert!(over.is_exceed);
+            assert_eq!((over.amount_in, over.amount_remaining), (max_input, 1));
+        }
+
+        let mut huge = pool.clone();
+        for bin in &mut huge.bins {
+            bin.amount_a = u64::MAX;
+        }
+        assert_eq!(huge.max_output(false), u64::MAX);
+        let empty = Pool::new(0, 2_500_000, pool.v_parameters.clone(), vec![]);
+        assert_eq!(
+            (empty.max_output(true), empty.max_input(true, 100).unwrap()),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn trimmed_pools_quote_the_same_inside_the_window() {
+        let config = BinStepConfig::new(10, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = (-100..=100)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 10).unwrap();
+                make_bin(id, 10_000, 10_000, price)
+            })
+            .collect();
+        let pool = Pool::new(3, 2_500_000, VariableParameters::new(config, 3, 0), bins);
+        let trimmed = pool.trim_to_window(10);
+        assert_eq!(trimmed.bins.first().unwrap().id, -7);
+        assert_eq!(trimmed.bins.last().unwrap().id, 13);
+        let mut in_place = pool.clone();
+        in_place.trim_in_place(10);
+        assert_eq!(in_place, trimmed);
+
+        for a2b in [true, false] {
+            for amount in [1, 25_000, 70_000] {
+                assert_eq!(
+                    trimmed.quote_exact_amount_in(amount, a2b, 100).unwrap(),
+                    pool.quote_exact_amount_in(amount, a2b, 100).unwrap()
+                );
+            }
+
+            // Up to the edge of the window the quotes agree; one unit more
+            // needs the next bin, which only the full pool has.
+            let edge = trimmed.drain_quote(a2b, 100).unwrap().max_amount_out as u64;
+            let inside = trimmed.quote_exact_amount_out(edge, a2b, 100).unwrap();
+            assert!(!inside.is_exceed);
+            assert_eq!(inside, pool.quote_exact_amount_out(edge, a2b, 100).unwrap());
+
+            let outside = trimmed.quote_exact_amount_out(edge + 1, a2b, 100).unwrap();
+            let full = pool.quote_exact_amount_out(edge + 1, a2b, 100).unwrap();
+            assert!(outside.is_exceed && !full.is_exceed);
+            assert_eq!(outside.amount_out, edge);
+            assert_eq!(full.steps.len(), outside.steps.len() + 1);
+        }
+
+        // A window beyond every bin leaves none.
+        let mut far = Pool::new(
+            1_000,
+            2_500_000,
+            pool.v_parameters.clone(),
+            pool.bins.clone(),
+        );
+        far.trim_in_place(5);
+        assert!(far.bins.is_empty());
+        assert_eq!(pool.trim_to_window(u32::MAX).bins, pool.bins);
+    }
+
+    #[test]
+    fn exactly_draining_bins_keeps_the_last_one_active() {
+        let pool = PoolBuilder::new()
+            .config(BinStepConfig::new(
+                10, 1, 60, 600, 9000, 40_000, 350_000, 30_000,
+            ))
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-5, 0, 0, 10_000)
+            .build()
+            .unwrap();
+
+        // One bin's capacity, then two bins'.
+        for (amount_out, drained) in [(10_000, 0), (20_000, -1)] {
+            let mut swapped = pool.clone();
+            let result = swapped
+                .swap_exact_amount_out(amount_out, true, 100)
+                .unwrap();
+            assert_eq!((result.amount_out, result.is_exceed), (amount_out, false));
+            assert_eq!(result.steps.last().unwrap().bin_id, drained);
+            assert_eq!(swapped.get_bin(drained).unwrap().amount_b, 0);
+            assert_eq!(
+                (swapped.active_id, result.end_active_id),
+                (drained, drained)
+            );
+
+            // The same input as an exact-input swap ends on the same bin.
+            let exact_in = pool
+                .quote_exact_amount_in(result.amount_in, true, 100)
+                .unwrap();
+            assert_eq!(exact_in.amount_out, amount_out);
+            assert_eq!(exact_in.end_active_id, drained);
+
+            // The next swap starts below the drained bin and moves the
+            // active id there.
+            let next = swapped.swap_exact_amount_in(100, true, 100).unwrap();
+            assert_eq!(next.steps[0].bin_id, drained - 1);
+            assert_eq!(swapped.active_id, drained - 1);
+        }
+    }
+
+    #[test]
+    fn swaps_start_from_the_bin_on_their_side() {
+        let bins = [-4, -1, 2, 6]
+            .into_iter()
+            .map(|id| make_bin(id, 100, 100, 1 << 64))
+            .collect();
+        let mut pool = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+        let start = |pool: &Pool, a2b| {
+            pool.start_bin_for_swap(a2b)
+                .map(|(index, bin)| (index, bin.id))
+        };
+
+        // (active id, start from A to B, start from B to A)
+        for (active_id, a2b_start, b2a_start) in [
+            (-10, None, Some((0, -4))),
+            (10, Some((3, 6)), None),
+            (-1, Some((1, -1)), Some((2, 2))),
+            (0, Some((1, -1)), Some((2, 2))),
+            (6, Some((3, 6)), None),
+        ] {
+            pool.active_id = active_id;
+            assert_eq!(start(&pool, true), a2b_start, "active id {active_id}");
+            assert_eq!(start(&pool, false), b2a_start, "active id {active_id}");
+        }
+
+        let neighbor = |from_id, a2b| pool.neighbor_bin(from_id, a2b).map(|bin| bin.id);
+        assert_eq!((neighbor(2, true), neighbor(2, false)), (Some(-1), Some(6)));
+        assert_eq!((neighbor(0, true), neighbor(0, false)), (Some(-1), Some(2)));
+        assert_eq!((neighbor(-4, true), neighbor(6, false)), (None, None));
+        assert_eq!(
+            (neighbor(-10, true), neighbor(-10, false)),
+            (None, Some(-4))
+        );
+        assert_eq!((neighbor(10, true), neighbor(10, false)), (Some(6), None));
+        assert_eq!(neighbor(i32::MIN, true), None);
+        assert_eq!(
+            Pool::new(0, 0, pool.v_parameters.clone(), vec![]).start_bin_for_swap(true),
+            None
+        );
+    }
+
+    #[test]
+    fn bins_are_iterated_in_swap_order() {
+        let bins = [-4, -1, 2, 6]
+            .into_iter()
+            .map(|id| make_bin(id, 100, 100, 1 << 64))
+            .collect();
+        // The active id sits in the gap between -1 and 2.
+        let pool = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+        fn ids<'a>(bins: impl Iterator<Item = &'a Bin>) -> Vec<i32> {
+            bins.map(|bin| bin.id).collect()
+        }
+
+        assert_eq!(ids(pool.bins_toward(true)), [-1, -4]);
+        assert_eq!(ids(pool.bins_toward(false)), [2, 6]);
+        assert_eq!(ids(pool.bins_toward(true).rev()), [-4, -1]);
+        assert_eq!(pool.bins_toward(false).len(), 2);
+
+        assert_eq!(ids(pool.bins_from(2, true)), [2, -1, -4]);
+        assert_eq!(ids(pool.bins_from(2, false)), [6]);
+        assert_eq!(ids(pool.bins_from(-10, false)), [-4, -1, 2, 6]);
+        assert!(pool.bins_from(-10, true).next().is_none());
+        assert!(pool.bins_from(6, false).next().is_none());
+
+        // The first bin is where a swap starts.
+        let (_, start) = pool.start_bin_for_swap(true).unwrap();
+        assert!(core::ptr::eq(pool.bins_toward(true).next().unwrap(), start));
+    }
+
+    #[test]
+    fn skipping_steps_keeps_totals() {
+        let config = BinStepConfig::new(10, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = (-150..=150)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 10).unwrap();
+                make_bin(id, 10_000, 10_000, price)
+            })
+            .collect();
+        let pool = Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins);
+        let no_steps = SwapOptions {
+            collect_steps: false,
+            ..Default::default()
+        };
+
+        for (a2b, amount) in [(true, 1_200_000), (false, 900_000), (true, u64::MAX / 4)] {
+            let (mut with_steps, mut without_steps) = (pool.clone(), pool.clone());
+            let full = with_steps.swap_exact_amount_in(amount, a2b, 100).unwrap();
+            let summary = without_steps
+                .swap_exact_amount_in_with_options(amount, a2b, 100, no_steps)
+                .unwrap();
+            assert!(full.steps.len() > 50);
+            assert_eq!(
+                summary,
+                SwapResult {
+                    steps: vec![],
+                    ..full.clone()
+                }
+            );
+            assert_eq!(without_steps, with_steps);
+            assert_eq!(
+                pool.quote_exact_amount_in_with_options(amount, a2b, 100, no_steps)
+                    .unwrap(),
+                summary
+            );
+
+            let (mut with_steps, mut without_steps) = (pool.clone(), pool.clone());
+            let full = with_steps
+                .swap_exact_amount_out(full.amount_out, a2b, 100)
+                .unwrap();
+            let summary = without_steps
+                .swap_exact_amount_out_with_options(full.amount_out, a2b, 100, no_steps)
+                .unwrap();
+            assert_eq!(
+                summary,
+                SwapResult {
+                    steps: vec![],
+                    ..full.clone()
+                }
+            );
+            assert_eq!(without_steps, with_steps);
+            assert_eq!(
+                pool.quote_exact_amount_out_with_options(full.amount_out, a2b, 100, no_steps)
+                    .unwrap(),
+                summary
+            );
+        }
+    }
+
+    #[test]
+    fn stale_and_skewed_timestamps_are_rejected() {
+        let config = BinStepConfig::new(10, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = vec![make_bin(0, 10_000, 10_000, 1u128 << 64)];
+        let pool = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(config, 0, 1_000),
+            bins,
+        );
+        let options = SwapOptions {
+            max_staleness_seconds: Some(30),
+            max_clock_skew_seconds: Some(5),
+            ..Default::default()
+        };
+        let quote = |timestamp| {
+            pool.quote_exact_amount_in_with_options(100, true, timestamp, options)
+                .map_err(|err| err.downcast::<DlmmError>().unwrap())
+        };
+
+        assert!(quote(1_000).is_ok());
+        assert!(quote(1_030).is_ok());
+        assert!(quote(995).is_ok());
+        assert!(matches!(
+            quote(1_031),
+            Err(DlmmError::StaleState { age: 31 })
+        ));
+        assert!(matches!(
+            quote(994),
+            Err(DlmmError::TimestampInPast { behind: 6 })
+        ));
+
+        // The defaults accept any timestamp.
+        assert!(pool.quote_exact_amount_in(100, true, 0).is_ok());
+        assert!(pool.quote_exact_amount_in(100, true, u64::MAX / 2).is_ok());
+    }
+
+    #[test]
+    fn time_policies_handle_an_earlier_timestamp() {
+        let config = BinStepConfig::new(10, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = (-5..=5)
+            .map(|id| {
+                make_bin(
+                    id,
+                    10_000,
+                    10_000,
+                    crate::math::price_math::get_price_from_id(id, 10).unwrap(),
+                )
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(config, 0, 1_000);
+        v_parameters.volatility_accumulator = 50_000;
+        v_parameters.volatility_reference = 20_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+        let swap = |policy, timestamp| {
+            let options = SwapOptions {
+                time_policy: policy,
+                ..Default::default()
+            };
+            pool.quote_exact_amount_in_with_options(15_000, true, timestamp, options)
+                .map_err(|err| err.downcast::<DlmmError>().unwrap())
+        };
+
+        assert!(matches!(
+            swap(TimePolicy::Reject, 995),
+            Err(DlmmError::TimestampInPast { behind: 5 })
+        ));
+        assert!(swap(TimePolicy::Reject, 1_000).is_ok());
+
+        // Neither policy decays the reference or moves the last update back.
+        let at_last = swap(TimePolicy::ClampToLast, 1_000).unwrap();
+        for policy in [TimePolicy::ClampToLast, TimePolicy::Allow] {
+            let early = swap(policy, 995).unwrap();
+            assert_eq!(early, at_last, "{policy:?}");
+            assert_eq!(early.end_last_update_timestamp, 1_000);
+        }
+        assert_eq!(SwapOptions::default().time_policy, TimePolicy::ClampToLast);
+
+        // A later timestamp still decays, which the earlier one must not.
+        assert_ne!(swap(TimePolicy::Allow, 1_100).unwrap().fee, at_last.fee);
+    }
+
+    #[test]
+    fn fee_rates_follow_the_accumulator_on_long_swaps() {
+        // The reference sits 20 bins above the active bin, so a swap up first
+        // lowers the accumulator, then raises it until it is capped 35 bins
+        // past the reference; a swap down caps it after 15 bins.
+        let config = BinStepConfig::new(10, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = (-400..=400)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 10).unwrap();
+                make_bin(id, 10_000, 10_000, price)
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(config.clone(), 20, 100);
+        v_parameters.volatility_reference = 30_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        for a2b in [true, false] {
+            let result = pool.quote_exact_amount_in(3_000_000, a2b, 100).unwrap();
+            assert!(result.steps.len() > 200);
+            let mut params = pool.v_parameters.clone();
+            let mut remaining = 3_000_000u128;
+            let mut capped_steps = 0;
+            for (i, step) in result.steps.iter().enumerate() {
+                // Every bin holds both tokens, so after the first step the
+                // active bin is the one being filled.
+                let active_id = if i == 0 { pool.active_id } else { step.bin_id };
+                params.update_volatility_accumulator(active_id).unwrap();
+                if params.volatility_accumulator == config.max_volatility_accumulator {
+                    capped_steps += 1;
+                }
+                let (fee_rate, var_fee_rate) = params.get_total_fee(pool.base_fee_rate).unwrap();
+                assert_eq!(step.var_fee_rate, var_fee_rate, "step {i}");
+                let bin = pool.get_bin(step.bin_id).unwrap();
+                let fill = bin
+                    .quote_exact_amount_in_u128(remaining, a2b, fee_rate, config.protocol_fee_rate)
+                    .unwrap();
+                assert_eq!(
+                    fill,
+                    (
+                        step.amount_in as u128,
+                        step.amount_out as u128,
+                        step.fee as u128,
+                        step.protocol_fee as u128
+                    ),
+                    "step {i}"
+                );
+                remaining -= step.amount_in as u128;
+            }
+            assert!(capped_steps > 150);
+        }
+    }
+
+    #[test]
+    fn bin_lookup_tracks_mutations() {
+        fn assert_lookup_matches(pool: &Pool) {
+            let lookup = pool.bins_by_id();
+            for id in -40..=40 {
+                let expected = pool.bins.iter().position(|bin| bin.id == id);
+                assert_eq!(pool.bin_index(id), expected, "bin {id}");
+                assert_eq!(lookup(id), expected.map(|index| &pool.bins[index]));
+            }
+        }
+
+        let bins = [-30, -7, -2, 0, 1, 5, 20]
+            .into_iter()
+            .map(|id| make_bin(id, 100_000, 100_000, 1 << 64))
+            .collect();
+        let mut pool = Pool::new(
+            0,
+            30_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+        assert_lookup_matches(&pool);
+        assert_eq!(pool.get_bin(5).map(|bin| bin.id), Some(5));
+        assert!(pool.get_bin(4).is_none());
+
+        pool.swap_exact_amount_in(150_000, true, 10).unwrap();
+        assert_eq!(pool.get_bin(0).unwrap().amount_b, 0);
+        assert_lookup_matches(&pool);
+
+        pool.bins.insert(3, make_bin(-1, 0, 0, 1 << 64));
+        pool.bins.retain(|bin| bin.id != 20);
+        assert_lookup_matches(&pool);
+
+        let empty = Pool::new(0, 30_000, pool.v_parameters.clone(), vec![]);
+        assert!(empty.get_bin(0).is_none());
+    }
+
+    #[test]
+    fn state_comparison_can_ignore_the_timestamp() {
+        let bins = vec![
+            make_bin(0, 1_000, 1_000, 1 << 64),
+            make_bin(1, 1_000, 0, 1 << 64),
+        ];
+        let pool = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+
+        let mut touched = pool.clone();
+        touched.v_parameters.last_update_timestamp = 1_700_000_000;
+        assert_ne!(touched, pool);
+        assert!(touched.state_eq_ignoring_time(&pool));
+
+        let mut swapped = pool.clone();
+        swapped
+            .swap_exact_amount_in(100, false, 1_700_000_000)
+            .unwrap();
+        assert_ne!(swapped, touched);
+        assert!(!swapped.state_eq_ignoring_time(&touched));
+
+        let mut calmer = touched.clone();
+        calmer.v_parameters.volatility_reference += 1;
+        assert!(!calmer.state_eq_ignoring_time(&pool));
+        assert_eq!(pool.clone(), pool);
+    }
+
+    #[test]
+    fn fee_rate_at_matches_the_first_swap_step() {
+        // A b2a swap starts in the bin above the active one.
+        let above =
+            crate::math::price_math::get_price_from_id(1, default_bin_step().bin_step).unwrap();
+        let bins = vec![
+            make_bin(0, 1_000_000, 1_000_000, 1 << 64),
+            make_bin(1, 1_000_000, 0, above),
+        ];
+        let mut v_parameters = VariableParameters::new(default_bin_step(), -4, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        v_parameters.volatility_accumulator = 90_000;
+        v_parameters.volatility_reference = 30_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        let mut rates = Vec::new();
+        for timestamp in [100, 100 + 30, 100 + 600, 100 + 10_000] {
+            let fee_rate = pool.fee_rate_at(timestamp).unwrap();
+            for a2b in [true, false] {
+                let mut swapped = pool.clone();
+                let step = swapped
+                    .swap_exact_amount_in(1_000, a2b, timestamp)
+                    .unwrap()
+                    .steps[0]
+                    .clone();
+                assert_eq!(fee_rate, pool.base_fee_rate + step.var_fee_rate);
+            }
+            rates.push(fee_rate);
+        }
+        assert_eq!(pool.v_parameters.last_update_timestamp, 100);
+        assert_eq!(pool.v_parameters.volatility_reference, 30_000);
+        assert!(rates.windows(2).all(|pair| pair[0] >= pair[1]), "{rates:?}");
+        assert_eq!(rates.last(), Some(&pool.base_fee_rate));
+    }
+
+    #[test]
+    fn fill_quotes_at_a_bin_match_the_swap_step() {
+        let above =
+            crate::math::price_math::get_price_from_id(1, default_bin_step().bin_step).unwrap();
+        let bins = vec![
+            make_bin(0, 1_000_000, 1_000_000, 1 << 64),
+            make_bin(1, 1_000_000, 0, above),
+        ];
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        v_parameters.volatility_reference = 30_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        for (bin_id, a2b) in [(0, true), (1, false)] {
+            let fill = pool.fill_quote_at_bin(bin_id, 10_000, a2b, 130).unwrap();
+            let step = pool.quote_exact_amount_in(10_000, a2b, 130).unwrap().steps[0].clone();
+            assert_eq!(step.bin_id, bin_id);
+            assert_eq!(
+                (fill.amount_in, fill.amount_out, fill.fee, fill.protocol_fee),
+                (step.amount_in, step.amount_out, step.fee, step.protocol_fee)
+            );
+            assert_eq!(fill.maker_proceeds, fill.amount_in - fill.protocol_fee);
+            assert_eq!(
+                fill.reserve_filled_bps,
+                (fill.amount_out * 10_000 / 1_000_000) as u32
+            );
+        }
+        // The bin above holds no B for an a2b taker.
+        assert_eq!(
+            pool.fill_quote_at_bin(1, 10_000, true, 130)
+                .unwrap()
+                .amount_unfilled,
+            10_000
+        );
+
+        let err = pool.fill_quote_at_bin(2, 10_000, true, 130).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidBinId)));
+    }
+
+    #[test]
+    fn wait_time_for_fee_below_lands_on_the_decay_boundaries() {
+        let bins = (-5..=5)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        // The active id is 4 bins from the reference, so until the filter
+        // period passes the accumulator is 30_000 + 40_000; then 90% of
+        // 100_000; after the decay period 0.
+        let mut v_parameters = VariableParameters::new(default_bin_step(), -4, 1_000);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        v_parameters.volatility_accumulator = 100_000;
+        v_parameters.volatility_reference = 30_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+        let quoted_fee = |timestamp: u64| {
+            let step = pool
+                .quote_exact_amount_in(100, true, timestamp)
+                .unwrap()
+                .steps[0]
+                .clone();
+            pool.base_fee_rate + step.var_fee_rate
+        };
+        let (calm, decayed, reset) = (quoted_fee(1_010), quoted_fee(1_060), quoted_fee(1_600));
+        assert!(
+            calm < decayed && reset == pool.base_fee_rate,
+            "{calm} {decayed} {reset}"
+        );
+
+        for now in [900, 1_000, 1_030, 1_059, 1_060, 1_300, 1_599, 1_600, 5_000] {
+            for target in [
+                reset - 1,
+                reset,
+                calm - 1,
+                calm,
+                decayed - 1,
+                decayed,
+                MAX_FEE_RATE,
+            ] {
+                let Some(wait) = pool.wait_time_for_fee_below(target, now).unwrap() else {
+                    assert_eq!(target, reset - 1);
+                    continue;
+                };
+                assert!(
+                    quoted_fee(now + wait) <= target,
+                    "{target} at {now} + {wait}"
+                );
+                if wait > 0 {
+                    assert!(
+                        quoted_fee(now + wait - 1) > target,
+                        "{target} at {now} + {wait} - 1"
+                    );
+                }
+            }
+        }
+        // Before the filter period the fee is the calm one, so the reset
+        // is what a target between it and the base fee waits for.
+        assert_eq!(pool.wait_time_for_fee_below(calm, 1_030).unwrap(), Some(0));
+        assert_eq!(
+            pool.wait_time_for_fee_below(calm - 1, 1_030).unwrap(),
+            Some(570)
+        );
+        assert_eq!(
+            pool.wait_time_for_fee_below(calm - 1, 1_070).unwrap(),
+            Some(530)
+        );
+        assert_eq!(pool.wait_time_for_fee_below(reset, 2_000).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn refresh_then_swap_matches_direct_swap() {
+        let bins = (-5..=5)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(default_bin_step(), -3, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        v_parameters.volatility_accumulator = 80_000;
+        v_parameters.volatility_reference = 50_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        for later in [100, 130, 500, 5_000] {
+            let mut direct = pool.clone();
+            let direct_result = direct.swap_exact_amount_in(25_000, true, later).unwrap();
+
+            let mut refreshed = pool.clone();
+            refreshed.refresh(later).unwrap();
+            assert_eq!(refreshed.v_parameters.last_update_timestamp, later);
+            assert_eq!(refreshed.active_id, pool.active_id);
+            let refreshed_result = refreshed.swap_exact_amount_in(25_000, true, later).unwrap();
+
+            assert_eq!(refreshed_result, direct_result, "at {later}");
+            assert_eq!(refreshed, direct, "at {later}");
+        }
+
+        // An idle pool's reference decays to zero without any swap.
+        let mut idle = pool.clone();
+        idle.refresh(100 + 600).unwrap();
+        assert_eq!(idle.v_parameters.volatility_reference, 0);
+        assert_eq!(idle.v_parameters.index_reference, 0);
+    }
+
+    #[test]
+    fn millisecond_and_second_timestamps_swap_identically() {
+        let bins = (-3..=3)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        let last_update = 1_700_000_000;
+        let mut v_parameters = VariableParameters::new(default_bin_step(), -3, last_update);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        v_parameters.volatility_accumulator = 80_000;
+        v_parameters.volatility_reference = 50_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        // 30s, 5min and 20min after the last update: before the filter
+        // period, inside the decay period and past it.
+        for elapsed in [30, 300, 1_200] {
+            let seconds = Timestamp::from_seconds(last_update + elapsed);
+            let millis = Timestamp::from_millis((last_update + elapsed) * 1_000 + 250);
+
+            let (mut by_seconds, mut by_millis) = (pool.clone(), pool.clone());
+            let result = by_seconds
+                .swap_exact_amount_in(15_000, true, seconds)
+                .unwrap();
+            assert_eq!(
+                by_millis
+                    .swap_exact_amount_in(15_000, true, millis)
+                    .unwrap(),
+                result
+            );
+            assert_eq!(by_millis, by_seconds);
+            assert_eq!(
+                pool.fee_rate_at(millis).unwrap(),
+                pool.fee_rate_at(seconds).unwrap()
+            );
+        }
+
+        // A raw millisecond reading passed as seconds lands far past the
+        // decay period, resetting the volatility that should still apply.
+        let millis_as_seconds = (last_update + 300) * 1_000;
+        assert_ne!(
+            pool.fee_rate_at(millis_as_seconds).unwrap(),
+            pool.fee_rate_at(Timestamp::from_millis(millis_as_seconds))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn end_volatility_state_predicts_the_next_swap() {
+        let bins = (-10..=10)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        let mut pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        let quote = pool.quote_exact_amount_in(45_000, true, 200).unwrap();
+        let first = pool.swap_exact_amount_in(45_000, true, 200).unwrap();
+        assert_eq!(quote, first);
+        assert!(first.end_volatility_accumulator > 0);
+        assert_eq!(
+            first.end_volatility_accumulator,
+            pool.v_parameters.volatility_accumulator
+        );
+        assert_eq!(
+            first.end_volatility_reference,
+            pool.v_parameters.volatility_reference
+        );
+        assert_eq!(first.end_index_reference, pool.v_parameters.index_reference);
+
+        // Within the filter period the references stay put, so the next
+        // swap's first bin is priced at the reported accumulator.
+        let second = pool.swap_exact_amount_in(5_000, true, 210).unwrap();
+        let mut expected = pool.v_parameters.clone();
+        expected.volatility_accumulator = first.end_volatility_accumulator;
+        assert_eq!(
+            second.steps[0].var_fee_rate as u128,
+            expected.get_variable_fee().unwrap()
+        );
+        assert!(second.end_volatility_accumulator >= first.end_volatility_accumulator);
+    }
+
+    #[test]
+    fn applying_a_swap_result_reproduces_the_swap() {
+        let bins = (-10..=10)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        let mut swapped = pool.clone();
+        let mut replica = pool.clone();
+        for (amount, a2b, by_amount_in, timestamp) in [
+            (45_000, true, true, 200),
+            (30_000, false, false, 230),
+            (8_000, false, true, 900),
+        ] {
+            let quote = if by_amount_in {
+                replica.quote_exact_amount_in(amount, a2b, timestamp)
+            } else {
+                replica.quote_exact_amount_out(amount, a2b, timestamp)
+            }
+            .unwrap();
+            if by_amount_in {
+                swapped.swap_exact_amount_in(amount, a2b, timestamp)
+            } else {
+                swapped.swap_exact_amount_out(amount, a2b, timestamp)
+            }
+            .unwrap();
+            assert!(!quote.steps.is_empty());
+            replica.apply_swap_result(&quote, a2b).unwrap();
+            assert_eq!(replica, swapped);
+        }
+
+        let result = pool
+            .clone()
+            .swap_exact_amount_in(45_000, true, 200)
+            .unwrap();
+        let mut copy = pool.clone();
+        let mut missing = result.clone();
+        missing.steps[1].bin_id = 1_000;
+        assert!(matches!(
+            copy.apply_swap_result(&missing, true),
+            Err(DlmmError::InvalidBinId)
+        ));
+        let mut drained = result.clone();
+        drained.steps[0].amount_out += 1;
+        drained.amount_out += 1;
+        assert!(matches!(
+            copy.apply_swap_result(&drained, true),
+            Err(DlmmError::MathOverflow)
+        ));
+        let mut without_steps = result.clone();
+        without_steps.steps.clear();
+        assert!(matches!(
+            copy.apply_swap_result(&without_steps, true),
+            Err(DlmmError::InvalidInput)
+        ));
+        assert_eq!(copy, pool);
+    }
+
+    #[test]
+    fn restoring_a_checkpoint_undoes_tracked_swaps() {
+        let bins = (-10..=10)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        let original = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        let mut pool = original.clone();
+        let mut checkpoint = pool.checkpoint();
+        let first = pool
+            .swap_tracked(45_000, true, true, 200, &mut checkpoint)
+            .unwrap();
+        assert_eq!(
+            first,
+            original
+                .clone()
+                .swap_exact_amount_in(45_000, true, 200)
+                .unwrap()
+        );
+        pool.swap_tracked(20_000, false, false, 260, &mut checkpoint)
+            .unwrap();
+        assert_ne!(pool, original);
+        assert!(checkpoint.saved_bins() >= first.steps.len());
+        assert!(checkpoint.saved_bins() < pool.bins.len());
+
+        pool.restore(&checkpoint);
+        assert_eq!(pool, original);
+
+        // The checkpoint can be reused for the next branch of a search.
+        pool.swap_tracked(5_000, false, true, 300, &mut checkpoint)
+            .unwrap();
+        pool.restore(&checkpoint);
+        assert_eq!(pool, original);
+    }
+
+    #[test]
+    fn stats_sum_the_swap_results() {
+        let bins = (-10..=10)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        let mut pool = Pool::new(0, 2_500_000, v_parameters, bins);
+        let legacy_json = serde_json::to_string(&pool).unwrap();
+        assert!(!legacy_json.contains("stats"));
+
+        let results = [
+            (true, pool.swap_exact_amount_in(15_000, true, 110).unwrap()),
+            (
+                false,
+                pool.swap_exact_amount_out(4_000, false, 120).unwrap(),
+            ),
+            (true, pool.swap_exact_amount_in(2_500, true, 130).unwrap()),
+            (true, pool.swap_exact_amount_out(2_000, true, 140).unwrap()),
+        ];
+        pool.quote_exact_amount_in(1_000, true, 150).unwrap();
+
+        let mut expected = PoolStats {
+            swap_count: 4,
+            ..Default::default()
+        };
+        for (a2b, result) in &results {
+            let (volume, fee, protocol_fee) = if *a2b {
+                (
+                    &mut expected.cumulative_volume_a_in,
+                    &mut expected.cumulative_fee_a,
+                    &mut expected.cumulative_protocol_fee_a,
+                )
+            } else {
+                (
+                    &mut expected.cumulative_volume_b_in,
+                    &mut expected.cumulative_fee_b,
+                    &mut expected.cumulative_protocol_fee_b,
+                )
+            };
+            *volume += result.amount_in as u128;
+            *fee += result.fee as u128;
+            *protocol_fee += result.protocol_fee as u128;
+        }
+        assert!(expected.cumulative_protocol_fee_a > 0 && expected.cumulative_protocol_fee_b > 0);
+        assert_eq!(pool.stats(), expected);
+
+        // Stats survive JSON, and snapshots without them still load.
+        let json = serde_json::to_string(&pool).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Pool>(&json).unwrap().stats(),
+            expected
+        );
+        assert!(serde_json::from_str::<Pool>(&legacy_json)
+            .unwrap()
+            .stats()
+            .is_empty());
+
+        pool.reset_stats();
+        assert!(pool.stats().is_empty());
+    }
+
+    #[test]
+    fn amount_remaining_completes_the_request() {
+        let price = 1u128 << 64;
+        let bins = vec![
+            make_bin(-1, 0, 10_000, price),
+            make_bin(0, 10_000, 10_000, price),
+            make_bin(1, 10_000, 0, price),
+        ];
+        let pool = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+        let empty = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            Vec::new(),
+        );
+
+        for (pool, requested, filled) in [
+            (&pool, 5_000, true),
+            (&pool, 50_000, false),
+            (&empty, 5_000, false),
+        ] {
+            for a2b in [true, false] {
+                let exact_in = pool.quote_exact_amount_in(requested, a2b, 0).unwrap();
+                assert_eq!(exact_in.amount_in + exact_in.amount_remaining, requested);
+                let exact_out = pool.quote_exact_amount_out(requested, a2b, 0).unwrap();
+                assert_eq!(exact_out.amount_out + exact_out.amount_remaining, requested);
+                for result in [&exact_in, &exact_out] {
+                    assert_eq!(result.amount_remaining == 0, filled, "{result}");
+                    assert_eq!(result.is_exceed, !filled);
+                }
+                let wide = pool
+                    .quote_exact_amount_in_u128(requested as u128, a2b, 0)
+                    .unwrap();
+                assert_eq!(wide.amount_remaining, exact_in.amount_remaining as u128);
+            }
+        }
+    }
+
+    #[test]
+    fn protocol_fee_overrides_only_change_the_split() {
+        let pool = PoolBuilder::new()
+            .config(BinStepConfig::new(
+                10,
+                1,
+                60,
+                600,
+                9000,
+                40_000,
+                350_000,
+                300_000_000,
+            ))
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-20, 20, 10_000, 10_000)
+            .build()
+            .unwrap();
+        let with_rate = |rate: FeeRate| SwapOptions {
+            protocol_fee_rate_override: Some(rate),
+            ..Default::default()
+        };
+
+        for a2b in [true, false] {
+            let config = pool.quote_exact_amount_in(50_000, a2b, 0).unwrap();
+            assert!(config.protocol_fee > 0);
+            let same = pool
+                .quote_exact_amount_in_with_options(
+                    50_000,
+                    a2b,
+                    0,
+                    with_rate(FeeRate::from_bps(3_000)),
+                )
+                .unwrap();
+            assert_eq!(same, config);
+
+            let mut swapped = pool.clone();
+            let free = swapped
+                .swap_exact_amount_in_with_options(50_000, a2b, 0, with_rate(FeeRate::ZERO))
+                .unwrap();
+            assert_eq!(free.protocol_fee, 0);
+            assert!(free.steps.iter().all(|step| step.protocol_fee == 0));
+            let strip = |result: &SwapResult| {
+                let mut result = result.clone();
+                (
+                    result.protocol_fee,
+                    result.protocol_fee_a,
+                    result.protocol_fee_b,
+                ) = (0, 0, 0);
+                for step in &mut result.steps {
+                    (step.protocol_fee, step.protocol_fee_a, step.protocol_fee_b) = (0, 0, 0);
+                }
+                result
+            };
+            assert_eq!(strip(&free), strip(&config));
+            let stats = swapped.stats;
+            assert_eq!(
+                stats.cumulative_protocol_fee_a + stats.cumulative_protocol_fee_b,
+                0
+            );
+
+            let all = pool
+                .quote_exact_amount_out_with_options(
+                    5_000,
+                    a2b,
+                    0,
+                    with_rate(FeeRate::from_bps(10_000)),
+                )
+                .unwrap();
+            assert_eq!(all.protocol_fee, all.fee);
+        }
+
+        let err = pool
+            .quote_exact_amount_in_with_options(
+                100,
+                true,
+                0,
+                with_rate(FeeRate::from_raw(FEE_PRECISION + 1)),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast::<DlmmError>(),
+            Ok(DlmmError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn fee_discounts_scale_the_fee_and_its_protocol_share() {
+        let pool = PoolBuilder::new()
+            .config(BinStepConfig::new(10, 0, 0, 0, 0, 0, 0, 300_000_000))
+            .base_fee_rate(2_500_000)
+            .bin(0, 10_000_000, 10_000_000)
+            .build()
+            .unwrap();
+        let quote = |discount| {
+            let options = SwapOptions {
+                fee_discount_bps: discount,
+                ..Default::default()
+            };
+            pool.quote_exact_amount_in_with_options(1_000_000, true, 0, options)
+        };
+
+        let full = quote(None).unwrap();
+        assert_eq!((full.fee, full.protocol_fee), (2_500, 750));
+        assert_eq!(quote(Some(0)).unwrap(), full);
+
+        let free = quote(Some(BASIS_POINT_MAX)).unwrap();
+        assert_eq!(
+            (free.fee, free.protocol_fee, free.amount_out),
+            (0, 0, 1_000_000)
+        );
+
+        let half = quote(Some(5_000)).unwrap();
+        assert_eq!((half.fee, half.protocol_fee), (1_250, 375));
+        assert_eq!(half.amount_out - full.amount_out, full.fee - half.fee);
+        assert_eq!(half.amount_in, full.amount_in);
+
+        let err = quote(Some(BASIS_POINT_MAX + 1)).unwrap_err();
+        assert!(matches!(
+            err.downcast::<DlmmError>(),
+            Ok(DlmmError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn volatility_reference_resets_after_the_decay_period() {
+        use crate::timestamp::ManualClock;
+
+        let clock = ManualClock::new(1_700_000_000);
+        let config = BinStepConfig::new(25, 10_000, 30, 600, 5_000, 40_000, 350_000, 0);
+        let mut pool = PoolBuilder::new()
+            .config(config.clone())
+            .base_fee_rate(2_500_000)
+            .timestamp(clock.now_seconds())
+            .uniform_liquidity(-20, 20, 1_000_000, 1_000_000)
+            .build()
+            .unwrap();
+
+        let first = pool
+            .swap_exact_amount_in_with_clock(3_000_000, true, &clock)
+            .unwrap();
+        let accumulator = first.end_volatility_accumulator;
+        assert!(accumulator > 0);
+        assert_eq!(first.end_last_update_timestamp, clock.now_seconds());
+
+        // Within the decay period the reference keeps part of the volatility.
+        clock.advance(config.filter_period.into());
+        let quote = pool
+            .quote_exact_amount_in_with_clock(1, false, &clock)
+            .unwrap();
+        assert_eq!(quote.end_volatility_reference, accumulator / 2);
+        pool.swap_exact_amount_in_with_clock(1, false, &clock)
+            .unwrap();
+        assert_eq!(pool.v_parameters.volatility_reference, accumulator / 2);
+
+        // Past it the reference resets and fees fall back.
+        clock.advance(config.decay_period.into());
+        let fee_before = pool.fee_rate_at(clock.now()).unwrap();
+        pool.swap_exact_amount_out_with_clock(1, false, &clock)
+            .unwrap();
+        assert_eq!(pool.v_parameters.volatility_reference, 0);
+        assert_eq!(pool.v_parameters.index_reference, pool.active_id);
+        assert_eq!(pool.v_parameters.last_update_timestamp, clock.now_seconds());
+        assert_eq!(fee_before, 2_500_000);
+        let quote = pool
+            .quote_exact_amount_out_with_clock(1, false, &clock)
+            .unwrap();
+        assert_eq!(quote.end_volatility_reference, 0);
+    }
+
+    #[test]
+    fn steps_carry_their_execution_price() {
+        use ruint::aliases::U256;
+
+        let pool = PoolBuilder::new()
+            .bin_step(25)
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-5, 5, 1_000_000, 1_000_000)
+            .build()
+            .unwrap();
+        for a2b in [true, false] {
+            let result = pool.quote_exact_amount_in(3_500_000, a2b, 0).unwrap();
+            assert!(result.steps.len() > 2);
+            for step in &result.steps {
+                let price = pool.get_bin(step.bin_id).unwrap().price.as_raw();
+                assert_eq!(step.price, price);
+                // The price net of the fee, within one unit in the last place.
+                let gross = U256::from(step.amount_in);
+                let net = U256::from(step.amount_in - step.fee);
+                let (price, execution) = (U256::from(price), U256::from(step.execution_price));
+                if a2b {
+                    assert!(step.execution_price < step.price);
+                    assert!(execution * gross <= price * net);
+                    assert!(price * net - execution * gross < gross);
+                } else {
+                    assert!(step.execution_price > step.price);
+                    assert!(execution * net >= price * gross);
+                    assert!(execution * net - price * gross < net);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn step_protocol_fees_sum_to_the_total() {
+        let bins: Vec<Bin> = (-30..=30)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(
+                    id,
+                    if id >= 0 { 137 } else { 0 },
+                    if id <= 0 { 137 } else { 0 },
+                    price,
+                )
+            })
+            .collect();
+        for protocol_fee_rate in [1, 333_333_333, 999_999_999] {
+            let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 0);
+            v_parameters.bin_step_config.protocol_fee_rate = protocol_fee_rate;
+            v_parameters.bin_step_config.variable_fee_control = 40_000;
+            let pool = Pool::new(0, 3_333_333, v_parameters, bins.clone());
+            for (a2b, by_amount_in) in [(true, true), (false, true), (true, false), (false, false)]
+            {
+                let result = if by_amount_in {
+                    pool.quote_exact_amount_in(2_000, a2b, 0)
+                } else {
+                    pool.quote_exact_amount_out(2_000, a2b, 0)
+                }
+                .unwrap();
+                assert!(result.steps.len() > 5);
+                assert!(result
+                    .steps
+                    .iter()
+                    .all(|step| step.protocol_fee <= step.fee));
+                assert_eq!(
+                    result
+                        .steps
+                        .iter()
+                        .map(|step| step.protocol_fee)
+                        .sum::<u64>(),
+                    result.protocol_fee
+                );
+                let wide = if by_amount_in {
+                    pool.quote_exact_amount_in_u128(2_000, a2b, 0)
+                } else {
+                    pool.quote_exact_amount_out_u128(2_000, a2b, 0)
+                }
+                .unwrap();
+                assert_eq!(
+                    wide.steps
+                        .iter()
+                        .map(|step| step.protocol_fee)
+                        .sum::<u128>(),
+                    wide.protocol_fee
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fees_are_split_by_input_token() {
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 0);
+        v_parameters.bin_step_config.protocol_fee_rate = 300_000_000;
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        let pool = PoolBuilder::new()
+            .config(v_parameters.bin_step_config)
+            .base_fee_rate(FeeRate::from_bps(25))
+            .uniform_liquidity(-30, 30, 137, 137)
+            .build()
+            .unwrap();
+        for (a2b, by_amount_in) in [(true, true), (false, true), (true, false), (false, false)] {
+            for collect_steps in [true, false] {
+                let options = SwapOptions {
+                    collect_steps,
+                    ..Default::default()
+                };
+                let result = if by_amount_in {
+                    pool.quote_exact_amount_in_with_options(2_000, a2b, 0, options)
+                } else {
+                    pool.quote_exact_amount_out_with_options(2_000, a2b, 0, options)
+                }
+                .unwrap();
+                assert!(result.protocol_fee > 0);
+                let (paid, other) = if a2b {
+                    (
+                        (result.fee_a, result.protocol_fee_a),
+                        (result.fee_b, result.protocol_fee_b),
+                    )
+                } else {
+                    (
+                        (result.fee_b, result.protocol_fee_b),
+                        (result.fee_a, result.protocol_fee_a),
+                    )
+                };
+                assert_eq!(paid, (result.fee, result.protocol_fee));
+                assert_eq!(other, (0, 0));
+                assert_eq!(result.steps.len() > 5, collect_steps);
+                for step in &result.steps {
+                    let split = if a2b {
+                        (
+                            step.fee_a,
+                            step.protocol_fee_a,
+                            step.fee_b,
+                            step.protocol_fee_b,
+                        )
+                    } else {
+                        (
+                            step.fee_b,
+                            step.protocol_fee_b,
+                            step.fee_a,
+                            step.protocol_fee_a,
+                        )
+                    };
+                    assert_eq!(split, (step.fee, step.protocol_fee, 0, 0), "{step}");
+                }
+
+                let wide = if by_amount_in {
+                    pool.quote_exact_amount_in_u128(2_000, a2b, 0)
+                } else {
+                    pool.quote_exact_amount_out_u128(2_000, a2b, 0)
+                }
+                .unwrap();
+                assert_eq!(
+                    (
+                        wide.fee_a,
+                        wide.fee_b,
+                        wide.protocol_fee_a,
+                        wide.protocol_fee_b
+                    ),
+                    (
+                        result.fee_a.into(),
+                        result.fee_b.into(),
+                        result.protocol_fee_a.into(),
+                        result.protocol_fee_b.into()
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bins_without_output_liquidity_are_skipped() {
+        // Even bins other than the active one only hold the token a swap
+        // towards them pays in.
+        let has_output = |id: i32| id == 0 || id % 2 != 0;
+        let bin = |id: i32| {
+            let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+            if has_output(id) {
+                make_bin(
+                    id,
+                    if id >= 0 { 10_000 } else { 0 },
+                    if id <= 0 { 10_000 } else { 0 },
+                    price,
+                )
+            } else {
+                make_bin(
+                    id,
+                    if id < 0 { 5_000 } else { 0 },
+                    if id > 0 { 5_000 } else { 0 },
+                    price,
+                )
+            }
+        };
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        let pool = Pool::new(
+            0,
+            2_500_000,
+            v_parameters.clone(),
+            (-21..=21).map(bin).collect(),
+        );
+        let pruned = Pool::new(
+            0,
+            2_500_000,
+            v_parameters,
+            (-21..=21).filter(|id| has_output(*id)).map(bin).collect(),
+        );
+
+        for a2b in [true, false] {
+            for amount in [5_000, 45_000, 10_000_000] {
+                let result = pool.quote_exact_amount_in(amount, a2b, 200).unwrap();
+                assert!(
+                    result.steps.iter().all(|step| has_output(step.bin_id)),
+                    "{a2b} {amount}"
+                );
+                assert_eq!(
+                    result,
+                    pruned.quote_exact_amount_in(amount, a2b, 200).unwrap()
+                );
+                let result = pool.quote_exact_amount_out(amount, a2b, 200).unwrap();
+                assert!(
+                    result.steps.iter().all(|step| has_output(step.bin_id)),
+                    "{a2b} {amount}"
+                );
+                assert_eq!(
+                    result,
+                    pruned.quote_exact_amount_out(amount, a2b, 200).unwrap()
+                );
+            }
+        }
+
+        // The ladder passes over the same bins, from an active bin with or
+        // without output liquidity.
+        let amounts = [1, 5_000, 45_000, 113_327, 113_328, 10_000_000];
+        for active_id in [-2, 0, 1] {
+            let mut pool = pool.clone();
+            pool.active_id = active_id;
+            pool.v_parameters.index_reference = active_id;
+            for a2b in [true, false] {
+                let ladder = pool.quote_ladder(&amounts, a2b, 200).unwrap();
+                for (amount, point) in amounts.iter().zip(&ladder) {
+                    let quote = pool.quote_exact_amount_in(*amount, a2b, 200).unwrap();
+                    let expected = LadderPoint::new(
+                        quote.amount_in,
+                        quote.amount_out,
+                        quote.fee,
+                        quote.is_exceed,
+                    );
+                    assert_eq!(*point, expected, "active {active_id}, {a2b} {amount}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pruning_empty_bins_keeps_quotes() {
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 100);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        // The outermost bins hold liquidity: a swap that runs out of bins
+        // leaves `active_id` at the last bin it reached, which pruning moves.
+        let bins = (-13..=13)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                let mut bin = if id % 3 == 0 {
+                    make_bin(id, 0, 0, price)
+                } else {
+                    make_bin(
+                        id,
+                        if id >= 0 { 10_000 } else { 0 },
+                        if id <= 0 { 10_000 } else { 0 },
+                        price,
+                    )
+                };
+                bin.liquidity_supply = (bin.amount_a + bin.amount_b) as u128;
+                if id == 6 {
+                    bin.fee_amount_a_growth_global = 1;
+                }
+                if id == -6 {
+                    bin.rewards_growth_global = vec![0, 5];
+                }
+                bin
+            })
+            .collect();
+        let pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        let mut kept = pool.clone();
+        assert_eq!(kept.prune_empty_bins(true), 6);
+        assert!(kept.get_bin(6).is_some() && kept.get_bin(-6).is_some());
+        let mut pruned = pool.clone();
+        assert_eq!(pruned.prune_empty_bins(false), 8);
+        assert_eq!(pruned.prune_empty_bins(false), 0);
+        // The active bin is empty too, but stays.
+        assert!(pruned.get_bin(0).is_some());
+        assert!(pruned.bins.windows(2).all(|pair| pair[0].id < pair[1].id));
+        assert!(pruned.validate().is_ok());
+
+        for a2b in [true, false] {
+            for amount in [5_000, 45_000, 10_000_000] {
+                let expected = pool.quote_exact_amount_in(amount, a2b, 200).unwrap();
+                assert_eq!(
+                    kept.quote_exact_amount_in(amount, a2b, 200).unwrap(),
+                    expected
+                );
+                assert_eq!(
+                    pruned.quote_exact_amount_in(amount, a2b, 200).unwrap(),
+                    expected
+                );
+                let expected = pool.quote_exact_amount_out(amount, a2b, 200).unwrap();
+                assert_eq!(
+                    pruned.quote_exact_amount_out(amount, a2b, 200).unwrap(),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn display_formats_are_pinned() {
+        let bins = vec![
+            make_bin(-1, 0, 1_000_000, 1 << 64),
+            make_bin(0, 1_000_000, 1_000_000, 1 << 64),
+            make_bin(3, 1_000_000, 0, 1 << 64),
+        ];
+        let mut v_parameters = VariableParameters::new(default_bin_step(), 0, 0);
+        v_parameters.bin_step_config.variable_fee_control = 40_000;
+        v_parameters.volatility_accumulator = 10_000;
+        let mut pool = Pool::new(0, 2_500_000, v_parameters, bins);
+
+        assert_eq!(
+            pool.summary().to_string(),
+            "active_id=0 bins=3 ids=[-1, 3] reserves=(a=2000000, b=2000000) fee=25.25bps"
+        );
+        let result = pool.swap_exact_amount_in(1_500_000, true, 10).unwrap();
+        assert_eq!(
+            result.to_string(),
+            "in=1500000 out=1496236 fee=3764 protocol_fee=2 steps=2 exceed=false"
+        );
+        assert_eq!(
+            result.steps[0].to_string(),
+            "bin 0: 1002507 -> 1000000 fee=2507 var_fee=0.00bps"
+        );
+        assert_eq!(
+            result.steps[1].to_string(),
+            "bin -1: 497493 -> 496236 fee=1257 var_fee=0.25bps"
+        );
+        assert_eq!(
+            Pool::new(
+                7,
+                0,
+                VariableParameters::new(default_bin_step(), 0, 0),
+                vec![]
+            )
+            .summary()
+            .to_string(),
+            "active_id=7 bins=0 ids=none reserves=(a=0, b=0) fee=0.00bps"
+        );
+    }
+
+    #[test]
+    fn merge_from_matches_the_union() {
+        let v_parameters = |timestamp: u64| {
+            let mut v_parameters = VariableParameters::new(default_bin_step(), 0, timestamp);
+            v_parameters.bin_step_config.variable_fee_control = 40_000;
+            v_parameters
+        };
+        let window =
+            |active_id: i32, ids: core::ops::RangeInclusive<i32>, amount: u64, timestamp: u64| {
+                let bins = ids
+                    .map(|id| {
+                        let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                        let amount_a = if id >= active_id { amount } else { 0 };
+                        let amount_b = if id <= active_id { amount } else { 0 };
+                        Bin {
+                            liquidity_supply: amount as u128,
+                            ..make_bin(id, amount_a, amount_b, price)
+                        }
+                    })
+                    .collect();
+                Pool::new(active_id, 2_500_000, v_parameters(timestamp), bins)
+            };
+        let quotes_match = |merged: &Pool, union: &Pool| {
+            assert!(merged.validate().is_ok());
+            for a2b in [true, false] {
+                for amount in [500, 5_000, 50_000] {
+                    assert_eq!(
+                        merged.quote_exact_amount_in(amount, a2b, 2_000).unwrap(),
+                        union.quote_exact_amount_in(amount, a2b, 2_000).unwrap(),
+                        "{a2b} {amount}"
+                    );
+                }
+            }
+        };
+
+        // Disjoint windows, fetched outward from the active id.
+        let union = window(0, -20..=20, 10_000, 1_000);
+        let mut merged = window(0, -5..=5, 10_000, 1_000);
+        merged.merge_from(&window(0, 6..=20, 10_000, 1_000), MergePreference::Newer);
+        merged.merge_from(&window(0, -20..=-6, 10_000, 1_000), MergePreference::Other);
+        assert_eq!(merged, union);
+        quotes_match(&merged, &union);
+
+        // Fully overlapping: only the preferred side survives.
+        let stale = window(0, -20..=20, 7_000, 900);
+        let mut merged = stale.clone();
+        merged.merge_from(&union, MergePreference::Newer);
+        assert_eq!(merged, union);
+        let mut kept = union.clone();
+        kept.merge_from(&stale, MergePreference::Newer);
+        assert_eq!(kept, union);
+        let mut forced = union.clone();
+        forced.merge_from(&stale, MergePreference::Other);
+        assert_eq!(forced, stale);
+
+        // Conflicting active ids: the newer fetch saw a swap move the price
+        // down to -3, and overlaps the older one on -10..=0.
+        let moved = window(-3, -10..=0, 10_000, 1_500);
+        let older = window(0, -20..=20, 10_000, 1_000);
+        let mut union = older.clone();
+        union.active_id = -3;
+        union.v_parameters = moved.v_parameters.clone();
+        for bin in &moved.bins {
+            union.insert_bin(bin.clone()).unwrap();
+        }
+        for (first, second) in [(&older, &moved), (&moved, &older)] {
+            let mut merged = first.clone();
+            merged.merge_from(second, MergePreference::Newer);
+            assert_eq!(merged, union);
+            quotes_match(&merged, &union);
+        }
+        let mut merged = moved.clone();
+        merged.merge_from(&older, MergePreference::Other);
+        assert_eq!(
+            (merged.active_id, merged.v_parameters.last_update_timestamp),
+            (0, 1_000)
+        );
+        assert_eq!(merged.bins, older.bins);
+    }
+
+    #[test]
+    fn failed_steps_name_their_bin() {
+        let bins = (10..20)
+            .map(|id| {
+                let price = if id == 13 {
+                    0
+                } else {
+                    crate::math::price_math::get_price_from_id(id, 25).unwrap()
+                };
+                make_bin(id, 1_000, 0, price)
+            })
+            .collect();
+        let pool = Pool::new(
+            9,
+            0,
+            VariableParameters::new(default_bin_step(), 9, 0),
+            bins,
+        );
+
+        let err = pool.quote_exact_amount_in(10_000, false, 0).unwrap_err();
+        let Some(DlmmError::StepFailed {
+            bin_id,
+            step_index,
+            remaining_amount,
+            a2b,
+            source,
+        }) = err.downcast_ref()
+        else {
+            panic!("{err:#}");
+        };
+        assert_eq!((*bin_id, *step_index, *a2b), (13, 3, false));
+        assert!(*remaining_amount < 10_000 - 3 * 1_000, "{remaining_amount}");
+        assert!(matches!(&**source, DlmmError::SwapFailed(reason) if reason == "price is zero"));
+        assert_eq!(
+            err.to_string(),
+            format!("Swap step 3 failed in bin 13 (B to A, {remaining_amount} remaining): Swap failed: price is zero")
+        );
+        // A swap that stops before the bin never reaches it.
+        assert!(pool.quote_exact_amount_in(2_000, false, 0).is_ok());
+    }
+
+    #[test]
+    fn bin_accessors_keep_the_invariants() {
+        let bins = (0..5)
+            .map(|id| make_bin(id, 1_000, 1_000, 1 << 64))
+            .collect();
+        let mut pool = Pool::new(
+            2,
+            0,
+            VariableParameters::new(default_bin_step(), 2, 0),
+            bins,
+        );
+        let ids = |pool: &Pool| pool.bins().iter().map(|bin| bin.id).collect::<Vec<_>>();
+
+        pool.bins_mut_with(|bins| bins[1].amount_b = 7).unwrap();
+        assert_eq!(pool.get_bin(1).map(|bin| bin.amount_b), Some(7));
+
+        // Each broken invariant is rejected and rolled back.
+        let before = pool.clone();
+        let unsorted = pool.bins_mut_with(|bins| bins.push(make_bin(-1, 1, 1, 1 << 64)));
+        assert!(unsorted.unwrap_err().to_string().contains("sorted"));
+        let zero_price = pool.bins_mut_with(|bins| bins[3].price = 0.into());
+        assert!(zero_price.unwrap_err().to_string().contains("zero price"));
+        let out_of_range =
+            pool.bins_mut_with(|bins| bins.push(make_bin(MAX_BIN_ID + 1, 1, 1, 1 << 64)));
+        assert!(matches!(
+            out_of_range.unwrap_err().downcast_ref(),
+            Some(DlmmError::InvalidBinId)
+        ));
+        assert_eq!(pool, before);
+
+        assert_eq!(pool.remove_bin(3).map(|bin| bin.id), Some(3));
+        assert_eq!(pool.remove_bin(3), None);
+        assert_eq!(ids(&pool), [0, 1, 2, 4]);
+
+        let (active_id, base_fee_rate, v_parameters, bins) = pool.clone().into_parts();
+        assert_eq!(
+            Pool::new(active_id, base_fee_rate, v_parameters, bins),
+            pool
+        );
+    }
+
+    #[test]
+    fn combined_chunks_match_one_swap_only_on_the_threaded_pool() {
+        let pool = PoolBuilder::new()
+            .config(BinStepConfig::new(
+                10, 1, 60, 600, 9000, 40_000, 350_000, 30_000,
+            ))
+            .base_fee_rate(2_500_000)
+            .uniform_liquidity(-60, 60, 10_000, 10_000)
+            .build()
+            .unwrap();
+        let chunks = [20_000, 30_000, 40_000];
+        for a2b in [true, false] {
+            let full = pool.clone().swap_exact_amount_in(90_000, a2b, 100).unwrap();
+
+            let mut threaded = pool.clone();
+            let results =
+                chunks.map(|amount| threaded.swap_exact_amount_in(amount, a2b, 100).unwrap());
+            let mut combined = SwapResult::combine_all(&results).unwrap();
+            assert_eq!(combined.amount_in, full.amount_in);
+            assert_eq!(combined.end_active_id, full.end_active_id);
+            // Both round down from the same exact output, each within its
+            // own bound.
+            let bound = combined
+                .max_rounding_shortfall
+                .max(full.max_rounding_shortfall);
+            assert!(
+                combined.amount_out.abs_diff(full.amount_out) <= bound,
+                "{combined} vs {full}"
+            );
+
+            let steps = combined.steps.len();
+            let repeats = combined
+                .steps
+                .windows(2)
+                .filter(|pair| pair[0].bin_id == pair[1].bin_id)
+                .count();
+            // Selling A, each chunk starts in the bin the previous one ended in.
+            if a2b {
+                assert_eq!(repeats, 2);
+            }
+            combined.merge_consecutive_steps().unwrap();
+            assert_eq!(combined.steps.len(), steps - repeats);
+            assert!(combined
+                .steps
+                .windows(2)
+                .all(|pair| pair[0].bin_id != pair[1].bin_id));
+            assert_eq!(
+                combined
+                    .steps
+                    .iter()
+                    .map(|step| step.amount_in)
+                    .sum::<u64>(),
+                combined.amount_in
+            );
+            for step in &combined.steps {
+                let execution_price = calculate_execution_price(
+                    step.price,
+                    step.amount_in as u128,
+                    step.fee as u128,
+                    a2b,
+                );
+                assert_eq!(step.execution_price, execution_price);
+            }
+
+            // Quoting every chunk against the same state reuses the best
+            // bins.
+            let quotes = chunks.map(|amount| pool.quote_exact_amount_in(amount, a2b, 100).unwrap());
+            let unthreaded = SwapResult::combine_all(&quotes).unwrap();
+            assert!(
+                unthreaded.amount_out > full.amount_out + bound,
+                "{unthreaded} vs {full}"
+            );
+        }
+    }
+
+    #[test]
+    fn combine_adds_the_totals() {
+        let step = |bin_id, amount_in, fee| BinSwap {
+            bin_id,
+            amount_in,
+            amount_out: amount_in / 2,
+            fee,
+            fee_a: fee,
+            ..Default::default()
+        };
+        let first = SwapResult {
+            amount_in: 100,
+            amount_out: 50,
+            fee: 3,
+            ref_fee: 1,
+            protocol_fee: 2,
+            steps: vec![step(0, 40, 1), step(-1, 60, 2)],
+            is_exceed: false,
+            end_volatility_accumulator: 10,
+            end_volatility_reference: 11,
+            end_index_reference: 12,
+            end_active_id: -1,
+            end_last_update_timestamp: 13,
+            amount_remaining: 0,
+            fee_a: 3,
+            fee_b: 0,
+            protocol_fee_a: 2,
+            protocol_fee_b: 0,
+            max_rounding_shortfall: u64::MAX - 1,
+        };
+        let second = SwapResult {
+            amount_in: 7,
+            amount_out: 3,
+            fee: 1,
+            ref_fee: 0,
+            protocol_fee: 1,
+            steps: vec![step(-1, 7, 1)],
+            is_exceed: true,
+            end_volatility_accumulator: 20,
+            end_volatility_reference: 21,
+            end_index_reference: 22,
+            end_active_id: -2,
+            end_last_update_timestamp: 23,
+            amount_remaining: 5,
+            fee_a: 1,
+            fee_b: 0,
+            protocol_fee_a: 1,
+            protocol_fee_b: 0,
+            max_rounding_shortfall: 9,
+        };
+        assert_eq!(
+            first.combine(&second).unwrap(),
+            SwapResult {
+                amount_in: 107,
+                amount_out: 53,
+                fee: 4,
+                ref_fee: 1,
+                protocol_fee: 3,
+                steps: vec![step(0, 40, 1), step(-1, 60, 2), step(-1, 7, 1)],
+                amount_remaining: 5,
+                fee_a: 4,
+                protocol_fee_a: 3,
+                max_rounding_shortfall: u64::MAX,
+                ..second.clone()
+            }
+        );
+        assert_eq!(
+            SwapResult::combine_all([&first, &second]).unwrap(),
+            first.combine(&second).unwrap()
+        );
+        assert_eq!(SwapResult::combine_all([&first]).unwrap(), first);
+
+        // Adding steps one at a time keeps every fee total, as combine does.
+        let mut stepwise = SwapResult::default();
+        for (fee_a, fee_b) in [(3, 0), (0, 5)] {
+            stepwise.update_swap_result(BinSwap {
+                fee: fee_a + fee_b,
+                protocol_fee: 1,
+                fee_a,
+                fee_b,
+                protocol_fee_a: fee_a.min(1),
+                protocol_fee_b: fee_b.min(1),
+                ..step(0, 10, 0)
+            });
+        }
+        assert_eq!((stepwise.fee, stepwise.fee_a, stepwise.fee_b), (8, 3, 5));
+        assert_eq!(
+            (
+                stepwise.protocol_fee,
+                stepwise.protocol_fee_a,
+                stepwise.protocol_fee_b
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(SwapResult::combine_all([]).unwrap(), SwapResult::default());
+
+        let mut merged = first.combine(&second).unwrap();
+        merged.merge_consecutive_steps().unwrap();
+        assert_eq!(
+            merged.steps,
+            [
+                step(0, 40, 1),
+                BinSwap {
+                    amount_out: 33,
+                    ..step(-1, 67, 3)
+                }
+            ]
+        );
+
+        let full = SwapResult {
+            amount_in: u64::MAX,
+            ..Default::default()
+        };
+        assert!(matches!(full.combine(&first), Err(DlmmError::MathOverflow)));
+    }
+
+    #[test]
+    fn bin_ids_are_bounded() {
+        // Bin step 1, the only one that prices the bounds, so the pools also
+        // pass `check_invariants`.
+        let bin = |id: i32, amount: u64| Bin {
+            liquidity_supply: amount as u128,
+            ..make_bin(
+                id,
+                amount,
+                amount,
+                crate::math::price_math::get_price_from_id(id, 1).unwrap_or(1 << 64),
+            )
+        };
+        let pool_with = |ids: &[i32]| {
+            let bins = ids.iter().map(|&id| bin(id, 1_000)).collect();
+            let config = BinStepConfig {
+                bin_step: 1,
+                ..default_bin_step()
+            };
+            Pool::new_checked(ids[0], 0, VariableParameters::new(config, 0, 0), bins)
+        };
+        let invalid_bin_id =
+            |err: Error| matches!(err.downcast_ref(), Some(DlmmError::InvalidBinId));
+        assert!(pool_with(&[MIN_BIN_ID, 0, MAX_BIN_ID]).is_ok());
+        assert!(invalid_bin_id(pool_with(&[MIN_BIN_ID - 1, 0]).unwrap_err()));
+        assert!(invalid_bin_id(pool_with(&[0, MAX_BIN_ID + 1]).unwrap_err()));
+
+        let mut pool = pool_with(&[0]).unwrap();
+        for id in [MIN_BIN_ID, MAX_BIN_ID] {
+            assert_eq!(pool.insert_bin(bin(id, 1)).unwrap(), None);
+        }
+        let replaced = pool.insert_bin(bin(0, 7)).unwrap();
+        assert_eq!(replaced.map(|bin| bin.amount_a), Some(1_000));
+        assert!(invalid_bin_id(
+            pool.insert_bin(bin(MIN_BIN_ID - 1, 1)).unwrap_err()
+        ));
+        assert!(invalid_bin_id(
+            pool.insert_bin(bin(MAX_BIN_ID + 1, 1)).unwrap_err()
+        ));
+        let priceless = Bin {
+            price: 0.into(),
+            ..bin(0, 1)
+        };
+        let err = pool.insert_bin(priceless).unwrap_err();
+        assert!(err.to_string().contains("zero price"), "{err}");
+        assert_eq!(
+            pool.bins.iter().map(|bin| bin.id).collect::<Vec<_>>(),
+            [MIN_BIN_ID, 0, MAX_BIN_ID]
+        );
+        assert!(pool.validate().is_ok());
+        assert_eq!(pool.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn swap_stops_at_the_bin_id_bounds() {
+        for (a2b, edge, beyond) in [
+            (true, MIN_BIN_ID, MIN_BIN_ID - 1),
+            (false, MAX_BIN_ID, MAX_BIN_ID + 1),
+        ] {
+            let (mut ids, active_id) = if a2b {
+                (vec![beyond, edge], edge)
+            } else {
+                (vec![edge - 1, edge, beyond], edge - 1)
+            };
+            ids.sort();
+            let bins = ids
+                .iter()
+                .map(|&id| {
+                    let amount_a = if id > active_id { 1_000 } else { 0 };
+                    let amount_b = if id <= active_id { 1_000 } else { 0 };
+                    make_bin(id, amount_a, amount_b, 1 << 64)
+                })
+                .collect();
+            let pool = Pool::new(
+                active_id,
+                0,
+                VariableParameters::new(default_bin_step(), 0, 0),
+                bins,
+            );
+
+            let result = pool.quote_exact_amount_in(5_000, a2b, 10).unwrap();
+            assert!(result.is_exceed);
+            assert_eq!(
+                result
+                    .steps
+                    .iter()
+                    .map(|step| step.bin_id)
+                    .collect::<Vec<_>>(),
+                [edge]
+            );
+            assert_eq!(result.amount_out, 1_000);
+            let ladder = pool.quote_ladder(&[5_000], a2b, 10).unwrap();
+            assert_eq!(ladder[0].amount_out, result.amount_out);
+            assert!(ladder[0].is_exceed);
+        }
+    }
+
+    #[test]
+    fn u128_swap_totals_beyond_u64() {
+        // Two 18-decimal tokens: 12 bins of 10 B tokens each below the
+        // active bin, so the whole range holds more than u64::MAX units.
+        let per_bin = 10_000_000_000_000_000_000u64;
+        let bins = (-11..=0)
+            .map(|id| {
+                let price = crate::math::price_math::get_price_from_id(id, 25).unwrap();
+                make_bin(id, 0, per_bin, price)
+            })
+            .collect();
+        let pool = Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+        let amount_in = 100_000_000_000_000_000_000u128;
+
+        let quote = pool
+            .quote_exact_amount_in_u128(amount_in, true, 10)
+            .unwrap();
+        let mut swapped = pool.clone();
+        assert_eq!(
+            swapped
+                .swap_exact_amount_in_u128(amount_in, true, 10)
+                .unwrap(),
+            quote
+        );
+        assert!(!quote.is_exceed);
+        assert_eq!(quote.amount_in, amount_in);
+        assert!(quote.amount_out > u64::MAX as u128);
+        assert_eq!(
+            quote.amount_out,
+            quote.steps.iter().map(|s| s.amount_out).sum::<u128>()
+        );
+        assert_eq!(
+            swapped.total_amount_b(),
+            pool.total_amount_b() - quote.amount_out
+        );
+
+        let exact_out = pool
+            .quote_exact_amount_out_u128(quote.amount_out, true, 10)
+            .unwrap();
+        assert_eq!(exact_out.amount_out, quote.amount_out);
+        assert!(exact_out.amount_in <= amount_in);
+
+        // The u64 entry points report the overflow instead of wrapping:
+        // buying u64::MAX of B costs more than u64::MAX of A once fees are
+        // added.
+        let err = pool.quote_exact_amount_out(u64::MAX, true, 10).unwrap_err();
+        assert!(err.to_string().contains("overflow"), "{err}");
+        let wide = pool
+            .quote_exact_amount_out_u128(u64::MAX as u128, true, 10)
+            .unwrap();
+        assert!(wide.amount_in > u64::MAX as u128);
+    }
+
+    /// What `amount_in` buys along the bins of `result` without rounding,
+    /// times 2^32 and rounded up. Input the contract overpays for a drained
+    /// bin is spent at the rate of the last bin, which is at least what
+    /// the bins past it would pay.
+    fn exact_amount_out_scaled(
+        result: &SwapResult,
+        amount_in: u64,
+        base_fee_rate: u64,
+        a2b: bool,
+    ) -> u128 {
+        use crate::math::{full_math::mul_div, q64x64_math::ONE, Rounding};
+
+        const SCALE: u128 = 1 << 32;
+        let net_rate = |step: &BinSwap| {
+            FEE_PRECISION as u128 - (base_fee_rate + step.var_fee_rate).min(MAX_FEE_RATE) as u128
+        };
+        let (last, drained) = result.steps.split_last().unwrap();
+        let mut spent = 0u128;
+        let mut amount_out = 0u128;
+        for step in drained {
+            let reserve = step.amount_out as u128 * SCALE;
+            let net = if a2b {
+                mul_div(reserve, ONE, step.price, Rounding::Down)
+            } else {
+                mul_div(reserve, step.price, ONE, Rounding::Down)
+            };
+            spent += mul_div(
+                net.unwrap(),
+                FEE_PRECISION as u128,
+                net_rate(step),
+                Rounding::Down,
+            )
+            .unwrap();
+            amount_out += reserve;
+        }
+        let net = mul_div(
+            amount_in as u128 * SCALE - spent,
+            net_rate(last),
+            FEE_PRECISION as u128,
+            Rounding::Up,
+        )
+        .unwrap();
+        let last_out = if a2b {
+            mul_div(net, last.price, ONE, Rounding::Up)
+        } else {
+            mul_div(net, ONE, last.price, Rounding::Up)
+        };
+        amount_out + last_out.unwrap()
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn rounding_shortfall_bounds_the_exact_output(
+            pool in crate::arbitrary::small_pool(),
+            amount in 1..=crate::arbitrary::MAX_BIN_RESERVE,
+            a2b in proptest::prelude::any::<bool>(),
+        ) {
+            let now = pool.v_parameters.last_update_timestamp;
+            // Dust changes how every step rounds.
+            for amount in amount..amount + 4 {
+                let quote = pool.quote_exact_amount_in(amount, a2b, now).unwrap();
+                let executed = pool.clone().swap_exact_amount_in(amount, a2b, now).unwrap();
+                proptest::prop_assert_eq!(&executed, &quote);
+                if executed.is_exceed || executed.steps.is_empty() {
+                    // Every crossed bin was drained, as it would be without rounding.
+                    continue;
+                }
+                let exact = exact_amount_out_scaled(&executed, amount, pool.base_fee_rate, a2b);
+                let bound = (executed.amount_out + executed.max_rounding_shortfall) as u128;
+                proptest::prop_assert!(exact <= bound << 32, "{} > {} + {}", exact >> 32, executed.amount_out, executed.max_rounding_shortfall);
+            }
+            let exact_out = pool.quote_exact_amount_out(amount, a2b, now).unwrap();
+            proptest::prop_assert_eq!(exact_out.max_rounding_shortfall, 0);
+        }
+
+        #[test]
+        fn u64_path_matches_u128_path(
+            pool in crate::arbitrary::small_pool(),
+            amount in 1..=crate::arbitrary::MAX_BIN_RESERVE,
+            a2b in proptest::prelude::any::<bool>(),
+            by_amount_in in proptest::prelude::any::<bool>(),
+        ) {
+            let (narrow, wide) = if by_amount_in {
+                (
+                    pool.quote_exact_amount_in(amount, a2b, 10).unwrap(),
+                    pool.quote_exact_amount_in_u128(amount as u128, a2b, 10).unwrap(),
+                )
+            } else {
+                (
+                    pool.quote_exact_amount_out(amount, a2b, 10).unwrap(),
+                    pool.quote_exact_amount_out_u128(amount as u128, a2b, 10).unwrap(),
+                )
+            };
+            proptest::prop_assert_eq!(narrow.amount_in as u128, wide.amount_in);
+            proptest::prop_assert_eq!(narrow.amount_out as u128, wide.amount_out);
+            proptest::prop_assert_eq!(narrow.fee as u128, wide.fee);
+            proptest::prop_assert_eq!(narrow.protocol_fee as u128, wide.protocol_fee);
+            proptest::prop_assert_eq!(narrow.is_exceed, wide.is_exceed);
+            proptest::prop_assert_eq!(narrow.steps.len(), wide.steps.len());
+        }
+
+        #[test]
+        fn clone_into_matches_clone(
+            source in crate::arbitrary::pool(64, 100),
+            target in crate::arbitrary::pool(64, 100),
+        ) {
+            let mut reused = target.clone();
+            source.clone_into(&mut reused);
+            proptest::prop_assert_eq!(&reused, &source);
+            let mut reset = target;
+            reset.reset_from(&source);
+            proptest::prop_assert_eq!(&reset, &source);
+        }
+
+        #[test]
+        fn wait_time_for_fee_below_is_minimal(
+            pool in crate::arbitrary::small_pool(),
+            target in 0..=MAX_FEE_RATE,
+            since_update in 0u64..2_000,
+        ) {
+            let now = pool.v_parameters.last_update_timestamp + since_update;
+            let decay_period = pool.v_parameters.bin_step_config.decay_period as u64;
+            match pool.wait_time_for_fee_below(target, now).unwrap() {
+                Some(wait) => {
+                    proptest::prop_assert!(pool.fee_rate_at(now + wait).unwrap() <= target);
+                    if wait > 0 {
+                        proptest::prop_assert!(pool.fee_rate_at(now + wait - 1).unwrap() > target);
+                        proptest::prop_assert!(pool.fee_rate_at(now).unwrap() > target);
+                    }
+                }
+                None => proptest::prop_assert!(
+                    pool.fee_rate_at(now.max(pool.v_parameters.last_update_timestamp + decay_period)).unwrap() > target
+                ),
+            }
+        }
+
+        #[test]
+        fn max_input_is_the_exceed_boundary(
+            pool in crate::arbitrary::small_pool(),
+            a2b in proptest::prelude::any::<bool>(),
+        ) {
+            let max_input = pool.max_input(a2b, 10).unwrap();
+            proptest::prop_assume!(max_input < u64::MAX);
+            let full = pool.quote_exact_amount_in(max_input, a2b, 10).unwrap();
+            proptest::prop_assert!(!full.is_exceed || max_input == 0);
+            proptest::prop_assert_eq!(full.amount_in, max_input);
+            proptest::prop_assert!(full.amount_out <= pool.max_output(a2b));
+            let over = pool.quote_exact_amount_in(max_input + 1, a2b, 10).unwrap();
+            proptest::prop_assert!(over.is_exceed);
+        }
+    }
+
+    #[test]
+    fn totals_do_not_overflow_u64() {
+        let mut bins: Vec<Bin> = (-2..=2)
+            .map(|id| make_bin(id, u64::MAX, u64::MAX / 2, 1 << 64))
+            .collect();
+        for bin in bins.iter_mut() {
+            bin.liquidity_supply = u128::MAX / 4;
+        }
+        let pool = Pool::new(
+            0,
+            30_000,
+            VariableParameters::new(default_bin_step(), 0, 0),
+            bins,
+        );
+
+        assert_eq!(pool.total_amount_a(), u64::MAX as u128 * 5);
+        assert_eq!(pool.total_amount_b(), (u64::MAX / 2) as u128 * 5);
+        assert_eq!(pool.total_liquidity(), u128::MAX);
+
+        assert_eq!(
+            pool.reserves_in_range(-1, 1),
+            (u64::MAX as u128 * 3, (u64::MAX / 2) as u128 * 3)
+        );
+        assert_eq!(
+            pool.reserves_in_range(-10, 10),
+            (pool.total_amount_a(), pool.total_amount_b())
+        );
+        assert_eq!(
+            pool.reserves_in_range(2, 2),
+            (u64::MAX as u128, (u64::MAX / 2) as u128)
+        );
+        assert_eq!(pool.reserves_in_range(3, 10), (0, 0));
+        assert_eq!(pool.reserves_in_range(1, -1), (0, 0));
+    }
+
+    /// Golden encodings hand-assembled from the field order documented under
+    /// "Borsh layout" in the README. A failure here means the binary layout
+    /// changed and cached snapshots would no longer decode.
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn borsh_layout_is_pinned() {
+        #[rustfmt::skip]
+        const POOL_HEX: &str = concat!(
+            "ffffffff", "3075000000000000",
+            "07000000", "03000000", "feffffff", "00f1536500000000",
+            "1900", "0100", "3c00", "5802", "2823", "00000000", "40420f00", "3075000000000000",
+            "01000000",
+            "ffffffff", "0500000000000000", "0600000000000000",
+            "00000000000000000100000000000000", "09000000000000000000000000000000",
+            "01000000", "01000000000000000000000000000000",
+            "02000000000000000000000000000000", "03000000000000000000000000000000",
+        );
+        #[rustfmt::skip]
+        const SWAP_RESULT_HEX: &str = concat!(
+            "6400000000000000", "5a00000000000000", "0100000000000000",
+            "0000000000000000", "0000000000000000",
+            "01000000", "ffffffff", "6400000000000000", "5a00000000000000",
+            "0100000000000000", "0400000000000000", "0100000000000000",
+            "00000000000000000100000000000000",
+            "a3703d0ad7a370fd0000000000000000",
+            "0100000000000000", "0000000000000000", "0100000000000000", "0000000000000000",
+            "01", "07000000", "03000000", "fbffffff",
+            "0c000000", "e803000000000000", "0900000000000000",
+            "0100000000000000", "0000000000000000", "0000000000000000", "0000000000000000",
+            "0500000000000000",
+        );
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{b:02x}")).collect()
+        }
+
+        let mut v_parameters = VariableParameters::new(default_bin_step(), -2, 1_700_000_000);
+        v_parameters.volatility_accumulator = 7;
+        v_parameters.volatility_reference = 3;
+        let mut bin = make_bin(-1, 5, 6, 1 << 64);
+        bin.liquidity_supply = 9;
+        bin.rewards_growth_global = vec![1];
+        bin.fee_amount_a_growth_global = 2;
+        bin.fee_amount_b_growth_global = 3;
+        let pool = Pool::new(-1, 30_000, v_parameters, vec![bin]);
+
+        let bytes = borsh::to_vec(&pool).unwrap();
+        assert_eq!(hex(&bytes), POOL_HEX);
+        assert_eq!(borsh::from_slice::<Pool>(&bytes).unwrap(), pool);
+
+        let result = SwapResult {
+            amount_in: 100,
+            amount_out: 90,
+            fee: 1,
+            steps: vec![BinSwap {
+                bin_id: -1,
+                amount_in: 100,
+                amount_out: 90,
+                fee: 1,
+                var_fee_rate: 4,
+                protocol_fee: 1,
+                price: 1 << 64,
+                execution_price: (1 << 64) * 99 / 100,
+                fee_a: 1,
+                fee_b: 0,
+                protocol_fee_a: 1,
+                protocol_fee_b: 0,
+            }],
+            is_exceed: true,
+            end_volatility_accumulator: 7,
+            end_volatility_reference: 3,
+            end_index_reference: -5,
+            end_active_id: 12,
+            end_last_update_timestamp: 1_000,
+            amount_remaining: 9,
+            fee_a: 1,
+            max_rounding_shortfall: 5,
+            ..Default::default()
+        };
+        let bytes = borsh::to_vec(&result).unwrap();
+        assert_eq!(hex(&bytes), SWAP_RESULT_HEX);
+        assert_eq!(borsh::from_slice::<SwapResult>(&bytes).unwrap(), result);
+    }
+
+    #[cfg(feature = "borsh")]
+    mod borsh_json_agreement {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        fn bin_strategy() -> impl Strategy<Value = Bin> {
+            (
+                any::<i32>(),
+                any::<u64>(),
+                any::<u64>(),
+                any::<u128>(),
+                any::<u128>(),
+                proptest::collection::vec(any::<u128>(), 0..4),
+                any::<u128>(),
+                any::<u128>(),
+            )
+                .prop_map(
+                    |(id, amount_a, amount_b, price, liquidity_supply, rewards, fee_a, fee_b)| {
+                        Bin {
+                            id,
+                            amount_a,
+                            amount_b,
+                            price: price.into(),
+                            liquidity_supply,
+                            rewards_growth_global: rewards,
+                            fee_amount_a_growth_global: fee_a,
+                            fee_amount_b_growth_global: fee_b,
+                        }
+                    },
+                )
+        }
+
+        fn pool_strategy() -> impl Strategy<Value = Pool> {
+            (
+                any::<i32>(),
+                any::<u64>(),
+                any::<(u16, u16, u16, u16, u16, u32, u32, u64)>(),
+                any::<(u32, u32, i32, u64)>(),
+                proptest::collection::vec(bin_strategy(), 0..6),
+            )
+                .prop_map(|(active_id, base_fee_rate, c, v, bins)| {
+                    let config = BinStepConfig::new(c.0, c.1, c.2, c.3, c.4, c.5, c.6, c.7);
+                    let mut v_parameters = VariableParameters::new(config, v.2, v.3);
+                    v_parameters.volatility_accumulator = v.0;
+                    v_parameters.volatility_reference = v.1;
+                    Pool::new(active_id, base_fee_rate, v_parameters, bins)
+                })
+        }
+
+        fn swap_result_strategy() -> impl Strategy<Value = SwapResult> {
+            let step = any::<(
+                (i32, u64, u64, u64, u64, u64, u128, u128),
+                (u64, u64, u64, u64),
+            )>()
+            .prop_map(|(s, t)| BinSwap {
+                bin_id: s.0,
+                amount_in: s.1,
+                amount_out: s.2,
+                fee: s.3,
+                var_fee_rate: s.4,
+                protocol_fee: s.5,
+                price: s.6,
+                execution_price: s.7,
+                fee_a: t.0,
+                fee_b: t.1,
+                protocol_fee_a: t.2,
+                protocol_fee_b: t.3,
+            });
+            (
+                any::<(u64, u64, u64, u64, u64)>(),
+                proptest::collection::vec(step, 0..6),
+                any::<bool>(),
+                any::<(u32, u32, i32, i32, u64, u64)>(),
+                any::<(u64, u64, u64, u64, u64)>(),
+            )
+                .prop_map(|(a, steps, is_exceed, end, t)| SwapResult {
+                    amount_in: a.0,
+                    amount_out: a.1,
+                    fee: a.2,
+                    ref_fee: a.3,
+                    protocol_fee: a.4,
+                    steps,
+                    is_exceed,
+                    end_volatility_accumulator: end.0,
+                    end_volatility_reference: end.1,
+                    end_index_reference: end.2,
+                    end_active_id: end.3,
+                    end_last_update_timestamp: end.4,
+                    amount_remaining: end.5,
+                    fee_a: t.0,
+                    fee_b: t.1,
+                    protocol_fee_a: t.2,
+                    protocol_fee_b: t.3,
+                    max_rounding_shortfall: t.4,
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn pool_round_trips_identically(pool in pool_strategy()) {
+                let from_borsh: Pool = borsh::from_slice(&borsh::to_vec(&pool).unwrap()).unwrap();
+                let from_json: Pool = serde_json::from_str(&serde_json::to_string(&pool).unwrap()).unwrap();
+                prop_assert_eq!(&from_borsh, &pool);
+                prop_assert_eq!(&from_json, &from_borsh);
+            }
+
+            #[test]
+            fn swap_result_round_trips_identically(result in swap_result_strategy()) {
+                let from_borsh: SwapResult = borsh::from_slice(&borsh::to_vec(&result).unwrap()).unwrap();
+                let from_json: SwapResult = serde_json::from_str(&serde_json::to_string(&result).unwrap()).unwrap();
+                prop_assert_eq!(&from_borsh, &result);
+                prop_assert_eq!(&from_json, &from_borsh);
+            }
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_reports_each_swap_step() {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::{field::Field, span, Event, Metadata};
+
+        /// Records the message of every event.
+        struct Messages(Arc<Mutex<Vec<String>>>);
+
+        struct MessageVisitor<'a>(&'a mut String);
+
+        impl tracing::field::Visit for MessageVisitor<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                if field.name() == "message" {
+                    *self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Messages {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut message = String::new();
+                event.record(&mut MessageVisitor(&mut message));
+                self.0.lock().unwrap().push(message);
+            }
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 40_000, 350_000, 30_000);
+        let bins = (-4..=0)
+            .map(|id| make_bin(id, 0, if id == -2 { 0 } else { 100_000 }, 1 << 64))
+            .collect();
+        let mut pool = Pool::new(0, 30_000, VariableParameters::new(config, 0, 0), bins);
+
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let result = tracing::subscriber::with_default(Messages(messages.clone()), || {
+            pool.swap_exact_amount_in(350_000, true, 100).unwrap()
+        });
+        assert_eq!(result.steps.len(), 4);
+
+        let messages = messages.lock().unwrap();
+        let count = |message: &str| messages.iter().filter(|m| *m == message).count();
+        assert_eq!(count("swap"), 1);
+        assert_eq!(count("update references"), 1);
+        assert_eq!(count("skip bin without output liquidity"), 1);
+        assert_eq!(count("update volatility accumulator"), result.steps.len());
+        assert_eq!(count("swap step"), result.steps.len());
+        assert_eq!(count("swap step filled"), result.steps.len());
+        assert_eq!(count("swap finished"), 1);
+        assert_eq!(count("applying swap"), 1);
+    }
 }
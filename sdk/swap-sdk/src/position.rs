@@ -0,0 +1,177 @@
+//! Liquidity positions and the rewards they have accrued.
+//!
+//! Each bin keeps one cumulative reward growth per rewarder in
+//! [`Bin::rewards_growth_global`], indexed like the pool's rewarders. A
+//! position records the growth it was last paid up to in each of its bins,
+//! so what it can claim is the growth since then times its liquidity share.
+
+use alloc::vec::Vec;
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{bin::Bin, error::DlmmError, math::dlmm_math::calculate_amount_by_growth};
+
+/// One of a pool's rewarders, `cetusdlmm::reward::Reward` without its
+/// bookkeeping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewarderInfo {
+    /// Index into the pool's rewarders and each bin's
+    /// [`Bin::rewards_growth_global`].
+    pub index: usize,
+    /// Tokens emitted per second as a Q64.64 number, the contract's
+    /// `current_emission_rate`.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub emission_per_second: u128,
+    /// Growth per unit of liquidity accumulated so far, in the units of
+    /// [`Bin::rewards_growth_global`].
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub growth_global: u128,
+}
+
+/// A position's share of one bin, `cetusdlmm::position::BinStat` without
+/// the fee growth.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionBin {
+    pub bin_id: i32,
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    pub liquidity_share: u128,
+    /// The growth of each rewarder the position was last paid up to, indexed
+    /// like [`Bin::rewards_growth_global`]. Rewarders added since the
+    /// position was last updated have no entry.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::vec_u128_string")
+    )]
+    #[serde(default)]
+    pub rewards_growth: Vec<u128>,
+}
+
+/// A liquidity position, one entry per bin it holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub bins: Vec<PositionBin>,
+}
+
+impl Position {
+    /// The position's entry for `bin_id`.
+    pub fn bin(&self, bin_id: i32) -> Option<&PositionBin> {
+        self.bins.iter().find(|bin| bin.bin_id == bin_id)
+    }
+
+    /// The rewards the position can claim from `bin`, one amount per
+    /// rewarder.
+    ///
+    /// A rewarder missing from either side counts as zero growth there, so
+    /// one added after the position was last updated pays out all of its
+    /// growth in the bin. Growth counters wrap around, so the growth since
+    /// the checkpoint is their wrapping difference. Fails with
+    /// [`DlmmError::InvalidBinId`] if the position has no share in `bin`
+    /// and [`DlmmError::MathOverflow`] if an amount does not fit in a `u64`.
+    pub fn pending_rewards(&self, bin: &Bin) -> Result<Vec<u64>, Error> {
+        let share = self
+            .bin(bin.id)
+            .ok_or(DlmmError::InvalidBinId)
+            .with_context(|| alloc::format!("position has no share in bin {}", bin.id))?;
+        let rewarders = bin
+            .rewards_growth_global
+            .len()
+            .max(share.rewards_growth.len());
+        (0..rewarders)
+            .map(|index| {
+                let growth = bin.rewards_growth_global.get(index).copied().unwrap_or(0);
+                let checkpoint = share.rewards_growth.get(index).copied().unwrap_or(0);
+                calculate_amount_by_growth(growth.wrapping_sub(checkpoint), share.liquidity_share)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bin(id: i32, rewards_growth_global: Vec<u128>) -> Bin {
+        Bin {
+            id,
+            rewards_growth_global,
+            ..Default::default()
+        }
+    }
+
+    fn position(bin_id: i32, liquidity_share: u128, rewards_growth: Vec<u128>) -> Position {
+        Position {
+            bins: vec![PositionBin {
+                bin_id,
+                liquidity_share,
+                rewards_growth,
+            }],
+        }
+    }
+
+    #[test]
+    fn one_rewarder_pays_the_growth_since_the_checkpoint() {
+        // Growth is per unit of liquidity with 128 fractional bits, so this
+        // is 1.25 and the bin's 1.75.
+        let position = position(3, 4_000, vec![5 << 126]);
+        let rewards = position.pending_rewards(&bin(3, vec![7 << 126])).unwrap();
+        assert_eq!(rewards, [2_000]);
+        assert_eq!(
+            position.pending_rewards(&bin(3, vec![5 << 126])).unwrap(),
+            [0]
+        );
+
+        let err = position.pending_rewards(&bin(4, vec![1])).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(DlmmError::InvalidBinId)));
+    }
+
+    #[test]
+    fn growth_is_counted_across_a_wrap() {
+        // The checkpoint was taken 0.25 before the counter wrapped, and the
+        // bin has grown 0.25 past the wrap since.
+        let quarter = 1u128 << 126;
+        let position = position(3, 4_000, vec![quarter.wrapping_neg()]);
+        let rewards = position.pending_rewards(&bin(3, vec![quarter])).unwrap();
+        assert_eq!(rewards, [2_000]);
+    }
+
+    #[test]
+    fn rewarders_added_later_start_from_zero() {
+        let quarter = 1u128 << 126;
+        // The position was last updated when only the first rewarder
+        // existed; the bin has seen all three.
+        let position = position(-2, 1_000, vec![quarter]);
+        let bin = bin(-2, vec![3 * quarter, quarter, 2 * quarter]);
+        assert_eq!(position.pending_rewards(&bin).unwrap(), [500, 250, 500]);
+
+        // A bin not updated since the third rewarder was added.
+        let position = Position {
+            bins: vec![PositionBin {
+                rewards_growth: vec![quarter, 0, 0],
+                ..position.bins[0].clone()
+            }],
+        };
+        let bin = Bin {
+            rewards_growth_global: vec![2 * quarter, quarter],
+            ..bin
+        };
+        assert_eq!(position.pending_rewards(&bin).unwrap(), [250, 250, 0]);
+
+        let empty = Position {
+            bins: vec![PositionBin {
+                liquidity_share: 0,
+                ..position.bins[0].clone()
+            }],
+        };
+        assert_eq!(empty.pending_rewards(&bin).unwrap(), [0, 0, 0]);
+    }
+}
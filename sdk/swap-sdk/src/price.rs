@@ -0,0 +1,605 @@
+//! Conversions between Q64.64 prices and human-readable decimal prices.
+//!
+//! A Q64.64 price is the amount of token B base units paid per base unit of
+//! token A. The decimal price is quoted in whole tokens, so it is the Q64.64
+//! price scaled by `10^(decimals_a - decimals_b)`. The string conversions use
+//! only integer and digit arithmetic, so they are exact up to the documented
+//! truncation.
+//!
+//! [`PriceQ64`] wraps a raw Q64.64 price so it cannot be mixed up with the
+//! other `u128` values of a pool, such as liquidity supply or fee growth.
+//! [`PriceTable`] prices a whole range of bins at once, for callers that
+//! would otherwise call [`get_price_from_id`] bin by bin.
+
+use alloc::{
+    borrow::ToOwned,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, ops::RangeInclusive};
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::DlmmError,
+    math::{
+        full_math::mul_div,
+        price_math::{bin_base, get_price_from_id},
+        q64x64_math::{PowError, PowLadder, ONE, SCALE_OFFSET},
+        Rounding,
+    },
+    MAX_BIN_ID, MIN_BIN_ID,
+};
+
+/// A Q64.64 price: token B base units per base unit of token A, times
+/// `2^64`, as stored on chain and in [`Bin::price`](crate::Bin::price).
+///
+/// Serializes exactly like the raw `u128`, a number or, with the
+/// `serde-string-numbers` feature, a decimal string. Methods taking a price
+/// take `impl Into<PriceQ64>`, so raw `u128` prices are still accepted;
+/// what the newtype rules out is using a price as another `u128` value, or
+/// another value as a price, without saying so:
+///
+/// ```compile_fail
+/// # use cetus_swap_sdk::Bin;
+/// let bin = Bin::default();
+/// let supply: u128 = bin.price;
+/// ```
+///
+/// ```compile_fail
+/// # use cetus_swap_sdk::Bin;
+/// let mut bin = Bin::default();
+/// bin.price = bin.liquidity_supply;
+/// ```
+///
+/// ```
+/// # use cetus_swap_sdk::{Bin, PriceQ64};
+/// let mut bin = Bin::default();
+/// bin.price = PriceQ64::from_raw(1 << 64);
+/// let raw: u128 = bin.price.into();
+/// assert_eq!(raw, bin.price.as_raw());
+/// ```
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+#[cfg_attr(feature = "schemars", schemars(transparent, inline))]
+pub struct PriceQ64(
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u128_string")
+    )]
+    #[cfg_attr(
+        all(feature = "schemars", feature = "serde-string-numbers"),
+        schemars(schema_with = "crate::schema::decimal_string")
+    )]
+    u128,
+);
+
+impl PriceQ64 {
+    /// A price of one base unit of B per base unit of A.
+    pub const ONE: Self = Self(ONE);
+
+    pub const fn from_raw(raw: u128) -> Self {
+        Self(raw)
+    }
+
+    pub const fn as_raw(self) -> u128 {
+        self.0
+    }
+
+    /// The price of bin `bin_id` at `bin_step`, see [`get_price_from_id`].
+    pub fn from_id(bin_id: i32, bin_step: u16) -> Result<Self, DlmmError> {
+        get_price_from_id(bin_id, bin_step).map(Self)
+    }
+
+    /// Parses a decimal price, see [`decimal_str_to_q64`].
+    pub fn from_decimal_str(s: &str, decimals_a: u8, decimals_b: u8) -> Result<Self, Error> {
+        decimal_str_to_q64(s, decimals_a, decimals_b).map(Self)
+    }
+
+    /// Formats the price as a decimal, see [`q64_to_decimal_string`].
+    pub fn to_decimal_string(self, decimals_a: u8, decimals_b: u8, precision: usize) -> String {
+        q64_to_decimal_string(self, decimals_a, decimals_b, precision)
+    }
+
+    /// The token B `amount` of token A is worth, `amount * price`, rounded
+    /// as `rounding` says. Fails with [`DlmmError::MathOverflow`] if it
+    /// does not fit in a `u64`.
+    pub fn mul_amount(self, amount: u64, rounding: Rounding) -> Result<u64, Error> {
+        mul_div(amount.into(), self.0, ONE, rounding)
+            .and_then(|value| u64::try_from(value).ok())
+            .ok_or_else(|| DlmmError::MathOverflow.into())
+    }
+
+    /// The token A `amount` of token B is worth, `amount / price`, rounded
+    /// as `rounding` says. Fails with [`DlmmError::InvalidInput`] for a
+    /// zero price and [`DlmmError::MathOverflow`] if the result does not
+    /// fit in a `u64`.
+    pub fn div_amount(self, amount: u64, rounding: Rounding) -> Result<u64, Error> {
+        if self.0 == 0 {
+            return Err(DlmmError::InvalidInput).context("price is zero");
+        }
+        mul_div(amount.into(), ONE, self.0, rounding)
+            .and_then(|value| u64::try_from(value).ok())
+            .ok_or_else(|| DlmmError::MathOverflow.into())
+    }
+
+    /// The price of A in B, `1 / price`, rounded down. Fails with
+    /// [`DlmmError::InvalidInput`] for a zero price and
+    /// [`DlmmError::MathOverflow`] for a price of `1` raw unit, whose
+    /// inverse is `2^128`.
+    pub fn inverse(self) -> Result<Self, Error> {
+        if self.0 == 0 {
+            return Err(DlmmError::InvalidInput).context("price is zero");
+        }
+        mul_div(ONE, ONE, self.0, Rounding::Down)
+            .map(Self)
+            .ok_or_else(|| DlmmError::MathOverflow.into())
+    }
+}
+
+impl From<u128> for PriceQ64 {
+    fn from(raw: u128) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<PriceQ64> for u128 {
+    fn from(price: PriceQ64) -> Self {
+        price.0
+    }
+}
+
+/// The raw `u128`, as the field printed before it was typed.
+impl fmt::Display for PriceQ64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// The prices of the bins `lo_id..=hi_id` at one bin step, equal bit for
+/// bit to [`get_price_from_id`].
+///
+/// Every price is still a full power of the bin base rather than the
+/// previous price times the base: the contract's `pow` rounds differently
+/// for each exponent, and its error against the exact power jumps by up to
+/// hundreds of units in the last place between adjacent ids, so prices
+/// chained by multiplication drift from the on-chain ones after a single
+/// step. What the table saves is the repeated squaring of the base, which
+/// depends only on the bin step: it squares once for the whole range and
+/// then only multiplies the squares each id selects, about three times as
+/// fast as calling [`get_price_from_id`] per bin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceTable {
+    bin_step: u16,
+    lo_id: i32,
+    prices: Vec<u128>,
+}
+
+impl PriceTable {
+    /// Prices the bins `lo_id..=hi_id`, 16 bytes per bin. Fails with
+    /// [`DlmmError::InvalidInput`] if `lo_id > hi_id`, and otherwise as
+    /// [`get_price_from_id`] fails for the first bin it fails for.
+    pub fn build(bin_step: u16, lo_id: i32, hi_id: i32) -> Result<PriceTable, Error> {
+        if lo_id > hi_id {
+            return Err(DlmmError::InvalidInput)
+                .with_context(|| format!("bin range {lo_id}..={hi_id} is empty"));
+        }
+        if !(MIN_BIN_ID..=MAX_BIN_ID).contains(&lo_id)
+            || !(MIN_BIN_ID..=MAX_BIN_ID).contains(&hi_id)
+        {
+            return Err(DlmmError::InvalidBinId)
+                .with_context(|| format!("bin range {lo_id}..={hi_id}"));
+        }
+        let base = bin_base(bin_step);
+        let ladder = PowLadder::new(base);
+        let mut prices = Vec::with_capacity((hi_id - lo_id) as usize + 1);
+        for bin_id in lo_id..=hi_id {
+            let price = ladder
+                .pow(bin_id)
+                .map_err(|kind| DlmmError::PriceOutOfRange {
+                    bin_id,
+                    bin_step,
+                    error: PowError {
+                        base,
+                        exp: bin_id,
+                        kind,
+                    },
+                })?;
+            prices.push(price);
+        }
+        Ok(PriceTable {
+            bin_step,
+            lo_id,
+            prices,
+        })
+    }
+
+    /// The price of bin `id`, or `None` outside the table's range.
+    pub fn price(&self, id: i32) -> Option<u128> {
+        let index = usize::try_from(id.checked_sub(self.lo_id)?).ok()?;
+        self.prices.get(index).copied()
+    }
+
+    pub fn bin_step(&self) -> u16 {
+        self.bin_step
+    }
+
+    /// The bin ids the table prices.
+    pub fn ids(&self) -> RangeInclusive<i32> {
+        self.lo_id..=self.lo_id + (self.prices.len() - 1) as i32
+    }
+}
+
+/// Formats `price` as a decimal price with exactly `precision` fractional
+/// digits, truncating (rounding down) any further digits.
+pub fn q64_to_decimal_string(
+    price: impl Into<PriceQ64>,
+    decimals_a: u8,
+    decimals_b: u8,
+    precision: usize,
+) -> String {
+    let price = price.into().as_raw();
+    // A Q64.64 fraction has at most 64 decimal digits, since 2^64 divides
+    // 10^64, so its expansion is exact.
+    let mut digits = (price >> SCALE_OFFSET).to_string();
+    let integer_len = digits.len() as isize;
+    let mut fraction = price & (ONE - 1);
+    for _ in 0..SCALE_OFFSET {
+        fraction *= 10;
+        digits.push(char::from(b'0' + (fraction >> SCALE_OFFSET) as u8));
+        fraction &= ONE - 1;
+    }
+
+    let point = integer_len + decimals_a as isize - decimals_b as isize;
+    let (integer, fraction) = split_at_point(&digits, point);
+    let integer = integer.trim_start_matches('0');
+
+    let mut out = String::with_capacity(integer.len() + precision + 2);
+    out.push_str(if integer.is_empty() { "0" } else { integer });
+    if precision > 0 {
+        out.push('.');
+        out.extend(
+            fraction
+                .chars()
+                .chain(core::iter::repeat('0'))
+                .take(precision),
+        );
+    }
+    out
+}
+
+/// Parses a decimal price such as `"2500.75"` into a Q64.64 price, rounding
+/// down to the nearest representable value.
+///
+/// Fractional digits below the Q64.64 resolution are truncated, so prices
+/// smaller than `2^-64` base units parse as `0`. Fails with
+/// [`DlmmError::InvalidInput`] unless `s` is a non-negative decimal number
+/// without sign or exponent, and with [`DlmmError::MathOverflow`] when the
+/// price does not fit in a `u128`.
+pub fn decimal_str_to_q64(s: &str, decimals_a: u8, decimals_b: u8) -> Result<u128, Error> {
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if integer.len() + fraction.len() == 0 || !is_digits(integer) || !is_digits(fraction) {
+        return Err(DlmmError::InvalidInput)
+            .with_context(|| format!("invalid decimal price {s:?}"));
+    }
+
+    let digits = [integer, fraction].concat();
+    let point = integer.len() as isize + decimals_b as isize - decimals_a as isize;
+    let (integer, fraction) = split_at_point(&digits, point);
+
+    let integer = integer.trim_start_matches('0');
+    let integer = if integer.is_empty() {
+        0
+    } else {
+        integer
+            .parse::<u64>()
+            .map_err(|_| DlmmError::MathOverflow)
+            .with_context(|| format!("decimal price {s:?} overflows Q64.64"))?
+    };
+
+    Ok(((integer as u128) << SCALE_OFFSET) | fraction_bits(&fraction))
+}
+
+/// Converts `price` to a decimal price as an `f64`.
+///
+/// Lossy: `f64` keeps about 16 significant digits, so use this for display
+/// and charts only, never to compute amounts.
+#[cfg(feature = "std")]
+pub fn q64_to_f64(price: impl Into<PriceQ64>, decimals_a: u8, decimals_b: u8) -> f64 {
+    price.into().as_raw() as f64 / ONE as f64 * 10f64.powi(decimals_a as i32 - decimals_b as i32)
+}
+
+/// Splits `digits` into integer and fractional digits with the decimal point
+/// `point` digits from the left, padding with zeros when the point falls
+/// outside the digits.
+fn split_at_point(digits: &str, point: isize) -> (String, String) {
+    if point <= 0 {
+        ("0".to_owned(), "0".repeat(point.unsigned_abs()) + digits)
+    } else if point as usize >= digits.len() {
+        (
+            digits.to_owned() + &"0".repeat(point as usize - digits.len()),
+            String::new(),
+        )
+    } else {
+        let (integer, fraction) = digits.split_at(point as usize);
+        (integer.to_owned(), fraction.to_owned())
+    }
+}
+
+/// The first 64 bits of the binary expansion of the decimal fraction
+/// `0.fraction`, found by doubling it and collecting the carries.
+fn fraction_bits(fraction: &str) -> u128 {
+    let mut digits: Vec<u8> = fraction
+        .trim_end_matches('0')
+        .bytes()
+        .map(|b| b - b'0')
+        .collect();
+    let mut bits = 0u128;
+    for _ in 0..SCALE_OFFSET {
+        let mut carry = 0;
+        for digit in digits.iter_mut().rev() {
+            let doubled = *digit * 2 + carry;
+            *digit = doubled % 10;
+            carry = doubled / 10;
+        }
+        bits = (bits << 1) | carry as u128;
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::math::dlmm_math::{calculate_amount_in, calculate_amount_out};
+
+    // Expected values were computed exactly with Python's `fractions`.
+
+    fn dlmm_error(err: &Error) -> Option<&DlmmError> {
+        err.downcast_ref::<DlmmError>()
+    }
+
+    #[test]
+    fn formats_exact_truncated_decimals() {
+        assert_eq!(q64_to_decimal_string(ONE, 9, 9, 4), "1.0000");
+        assert_eq!(q64_to_decimal_string(ONE / 4, 9, 9, 0), "0");
+        assert_eq!(q64_to_decimal_string(ONE / 4, 9, 9, 3), "0.250");
+        assert_eq!(
+            q64_to_decimal_string(1, 6, 6, 25),
+            "0.0000000000000000000542101"
+        );
+        assert_eq!(
+            q64_to_decimal_string(u128::MAX, 0, 0, 30),
+            "18446744073709551615.999999999999999999945789891375"
+        );
+        assert_eq!(
+            q64_to_decimal_string(u128::MAX, 18, 6, 3),
+            "18446744073709551615999999999999.999"
+        );
+        assert_eq!(
+            q64_to_decimal_string(12_345_678_901_234_567_890_123_456_789, 9, 6, 12),
+            "669260594276.348691766747"
+        );
+        // One base unit of an 18-decimal token B per base unit of a
+        // 6-decimal token A.
+        assert_eq!(q64_to_decimal_string(ONE, 6, 18, 14), "0.00000000000100");
+    }
+
+    #[test]
+    fn parses_to_the_largest_price_not_above_the_decimal() {
+        assert_eq!(decimal_str_to_q64("1", 9, 9).unwrap(), ONE);
+        assert_eq!(decimal_str_to_q64("0.25", 9, 9).unwrap(), ONE / 4);
+        assert_eq!(decimal_str_to_q64(".25", 9, 9).unwrap(), ONE / 4);
+        assert_eq!(
+            decimal_str_to_q64("0.1", 6, 6).unwrap(),
+            1_844_674_407_370_955_161
+        );
+        assert_eq!(
+            decimal_str_to_q64(
+                "0.1000000000000000000000000000000000000000000000000000001",
+                6,
+                6
+            )
+            .unwrap(),
+            1_844_674_407_370_955_161
+        );
+        assert_eq!(
+            decimal_str_to_q64("1.5", 6, 18).unwrap(),
+            27_670_116_110_564_327_424_000_000_000_000
+        );
+        assert_eq!(decimal_str_to_q64("0.000000000000001", 18, 6).unwrap(), 0);
+        let max =
+            "18446744073709551615.9999999999999999999457898913757247782996273599565029144287109375";
+        assert_eq!(decimal_str_to_q64(max, 0, 0).unwrap(), u128::MAX);
+        assert_eq!(decimal_str_to_q64(&max[..51], 0, 0).unwrap(), u128::MAX - 1);
+    }
+
+    #[test]
+    fn round_trips_between_strings_and_prices() {
+        // 2,500 of a 6-decimal token B per 18-decimal token A.
+        let price = decimal_str_to_q64("2500", 18, 6).unwrap();
+        assert_eq!(price, 46_116_860_184);
+        assert_eq!(q64_to_decimal_string(price, 18, 6, 2), "2499.99");
+        assert_eq!(
+            q64_to_decimal_string(price, 18, 6, 20),
+            "2499.99999998515298749168"
+        );
+        assert!((q64_to_f64(price, 18, 6) - 2500.0).abs() < 1e-6);
+
+        for text in ["0.000123", "1", "42.5", "98765.4321"] {
+            let price = decimal_str_to_q64(text, 6, 6).unwrap();
+            let back = q64_to_decimal_string(price + 1, 6, 6, text.len());
+            assert!(back.starts_with(text), "{text} -> {back}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_and_oversized_strings() {
+        for text in ["", ".", "1.2.3", "-1", "+1", "1e5", " 1", "0x10", "1,5"] {
+            let err = decimal_str_to_q64(text, 6, 6).unwrap_err();
+            assert!(
+                matches!(dlmm_error(&err), Some(DlmmError::InvalidInput)),
+                "{text:?}: {err:#}"
+            );
+        }
+        for (text, decimals_a, decimals_b) in [("18446744073709551616", 0, 0), ("1", 0, 20)] {
+            let err = decimal_str_to_q64(text, decimals_a, decimals_b).unwrap_err();
+            assert!(
+                matches!(dlmm_error(&err), Some(DlmmError::MathOverflow)),
+                "{text:?}: {err:#}"
+            );
+        }
+    }
+
+    #[test]
+    fn typed_prices_match_the_raw_ones() {
+        for (id, bin_step) in [(0, 25), (-1_000, 10), (7, 1), (443_636, 1)] {
+            assert_eq!(
+                PriceQ64::from_id(id, bin_step).unwrap().as_raw(),
+                get_price_from_id(id, bin_step).unwrap()
+            );
+        }
+        assert!(PriceQ64::from_id(i32::MAX, 100).is_err());
+        assert_eq!(
+            PriceQ64::from_decimal_str("0.25", 9, 9).unwrap(),
+            PriceQ64::from_raw(ONE / 4)
+        );
+        assert_eq!(PriceQ64::ONE.to_decimal_string(9, 9, 2), "1.00");
+        assert_eq!(PriceQ64::ONE.to_string(), "18446744073709551616");
+    }
+
+    #[test]
+    fn inverts_rounding_down() {
+        assert_eq!(PriceQ64::ONE.inverse().unwrap(), PriceQ64::ONE);
+        assert_eq!(
+            PriceQ64::from_raw(4 * ONE).inverse().unwrap(),
+            PriceQ64::from_raw(ONE / 4)
+        );
+        // 1/3 is 0x5555...5 in the last place, truncated.
+        assert_eq!(
+            PriceQ64::from_raw(3 * ONE).inverse().unwrap().as_raw(),
+            0x5555_5555_5555_5555
+        );
+        assert_eq!(PriceQ64::from_raw(2).inverse().unwrap().as_raw(), 1 << 127);
+
+        let err = PriceQ64::default().inverse().unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::InvalidInput)));
+        let err = PriceQ64::from_raw(1).inverse().unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::MathOverflow)));
+    }
+
+    #[test]
+    fn amounts_fail_on_overflow_and_zero_prices() {
+        let max = PriceQ64::from_raw(u128::MAX);
+        let err = max.mul_amount(2, Rounding::Down).unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::MathOverflow)));
+        let err = PriceQ64::default().div_amount(1, Rounding::Up).unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::InvalidInput)));
+        assert_eq!(PriceQ64::default().mul_amount(5, Rounding::Up).unwrap(), 0);
+    }
+
+    #[test]
+    fn encodes_like_the_raw_integer() {
+        let price = PriceQ64::from_raw(u128::MAX - 1);
+        let json = serde_json::to_string(&price).unwrap();
+        #[cfg(not(feature = "serde-string-numbers"))]
+        assert_eq!(json, (u128::MAX - 1).to_string());
+        #[cfg(feature = "serde-string-numbers")]
+        assert_eq!(json, format!("\"{}\"", u128::MAX - 1));
+        assert_eq!(serde_json::from_str::<PriceQ64>(&json).unwrap(), price);
+        #[cfg(feature = "borsh")]
+        assert_eq!(
+            borsh::to_vec(&price).unwrap(),
+            borsh::to_vec(&(u128::MAX - 1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn table_matches_per_id_prices() {
+        for bin_step in [1, 5, 25, 50] {
+            let table = PriceTable::build(bin_step, -5_000, 4_999).unwrap();
+            assert_eq!(table.ids(), -5_000..=4_999);
+            assert_eq!(table.bin_step(), bin_step);
+            for id in -5_000..=4_999 {
+                assert_eq!(
+                    table.price(id),
+                    Some(get_price_from_id(id, bin_step).unwrap()),
+                    "{bin_step}: {id}"
+                );
+            }
+            assert_eq!(table.price(-5_001), None);
+            assert_eq!(table.price(5_000), None);
+            assert_eq!(table.price(i32::MIN), None);
+        }
+
+        let edges = PriceTable::build(1, MIN_BIN_ID, MIN_BIN_ID + 1).unwrap();
+        assert_eq!(
+            edges.price(MIN_BIN_ID),
+            get_price_from_id(MIN_BIN_ID, 1).ok()
+        );
+        let single = PriceTable::build(1, MAX_BIN_ID, MAX_BIN_ID).unwrap();
+        assert_eq!(single.ids(), MAX_BIN_ID..=MAX_BIN_ID);
+        assert_eq!(
+            single.price(MAX_BIN_ID),
+            get_price_from_id(MAX_BIN_ID, 1).ok()
+        );
+    }
+
+    #[test]
+    fn table_fails_like_per_id_prices() {
+        let err = PriceTable::build(1, 1, 0).unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::InvalidInput)));
+        let err = PriceTable::build(1, MIN_BIN_ID - 1, 0).unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::InvalidBinId)));
+        let err = PriceTable::build(1, 0, MAX_BIN_ID + 1).unwrap_err();
+        assert!(matches!(dlmm_error(&err), Some(DlmmError::InvalidBinId)));
+
+        // 1.01 ^ -5000 underflows, and the first failing id is reported
+        // with the same error.
+        let err = PriceTable::build(100, -5_000, 0).unwrap_err();
+        let DlmmError::PriceOutOfRange { bin_id, .. } = dlmm_error(&err).unwrap() else {
+            panic!("{err}");
+        };
+        assert_eq!(*bin_id, -5_000);
+        assert_eq!(
+            err.to_string(),
+            get_price_from_id(-5_000, 100).unwrap_err().to_string()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn amounts_match_the_swap_math(raw in 1..=u128::MAX, amount: u64) {
+            // Outputs round down and inputs up. Selling A multiplies by the
+            // price and buying A divides.
+            let price = PriceQ64::from_raw(raw);
+            prop_assert_eq!(
+                price.mul_amount(amount, Rounding::Down).ok(),
+                calculate_amount_out(amount, raw, true).ok()
+            );
+            prop_assert_eq!(
+                price.mul_amount(amount, Rounding::Up).ok(),
+                calculate_amount_in(amount, raw, false).ok()
+            );
+            prop_assert_eq!(
+                price.div_amount(amount, Rounding::Down).ok(),
+                calculate_amount_out(amount, raw, false).ok()
+            );
+            prop_assert_eq!(
+                price.div_amount(amount, Rounding::Up).ok(),
+                calculate_amount_in(amount, raw, true).ok()
+            );
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Types mirroring the on-chain JSON layout returned by Sui RPC.
+//!
+//! `sui_getObject` with `showContent` renders Move structs as
+//! `{ "type": "...", "fields": { ... } }`, encodes `u64`/`u128` values as
+//! strings and `integer_mate::i32::I32` as `{ "bits": <u32> }`. The `Raw*`
+//! types accept that shape (as well as the unwrapped `fields` object) so the
+//! RPC content can be fed straight into `serde_json::from_value` and then
+//! converted into the SDK types.
+
+use alloc::{string::String, vec::Vec};
+
+use anyhow::Error;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    bin::Bin,
+    config::{BinStepConfig, VariableParameters},
+    metadata::{coin_types_from_pool_type, PoolMetadata},
+    pool::Pool,
+    serde_utils::{u128_string, u64_string, vec_u128_string},
+};
+
+/// A Move struct that may or may not be wrapped in `{ "type", "fields" }`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoveStruct<T> {
+    Wrapped {
+        #[serde(default, rename = "type")]
+        type_name: Option<String>,
+        fields: T,
+    },
+    Bare(T),
+}
+
+impl<T> MoveStruct<T> {
+    fn into_inner(self) -> T {
+        self.into_parts().1
+    }
+
+    /// The struct and its Move type, if the wrapper names one.
+    fn into_parts(self) -> (Option<String>, T) {
+        match self {
+            MoveStruct::Wrapped { type_name, fields } => (type_name, fields),
+            MoveStruct::Bare(inner) => (None, inner),
+        }
+    }
+}
+
+pub(crate) fn move_struct<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    MoveStruct::<T>::deserialize(deserializer).map(MoveStruct::into_inner)
+}
+
+#[derive(Deserialize)]
+struct I32Bits {
+    bits: u32,
+}
+
+/// Decodes an `integer_mate::i32::I32` (two's complement bits).
+pub(crate) fn move_i32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    move_struct::<D, I32Bits>(deserializer).map(|value| value.bits as i32)
+}
+
+#[derive(Deserialize)]
+struct Uid {
+    id: String,
+}
+
+fn move_uid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    move_struct::<D, Uid>(deserializer).map(|uid| uid.id)
+}
+
+/// `cetusdlmm::config::BinStepConfig` as rendered by RPC.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawBinStepConfig {
+    pub bin_step: u16,
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    pub max_volatility_accumulator: u32,
+    #[serde(with = "u64_string")]
+    pub protocol_fee_rate: u64,
+}
+
+/// `cetusdlmm::parameters::VariableParameters` as rendered by RPC.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawVariableParameters {
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    #[serde(deserialize_with = "move_i32")]
+    pub index_reference: i32,
+    #[serde(with = "u64_string")]
+    pub last_update_timestamp: u64,
+    #[serde(deserialize_with = "move_struct")]
+    pub bin_step_config: RawBinStepConfig,
+}
+
+/// `cetusdlmm::bin::Bin` as rendered by RPC.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawBin {
+    #[serde(deserialize_with = "move_i32")]
+    pub id: i32,
+    #[serde(with = "u64_string")]
+    pub amount_a: u64,
+    #[serde(with = "u64_string")]
+    pub amount_b: u64,
+    #[serde(with = "u128_string")]
+    pub price: u128,
+    #[serde(with = "u128_string")]
+    pub liquidity_share: u128,
+    #[serde(with = "vec_u128_string", default)]
+    pub rewards_growth_global: Vec<u128>,
+    #[serde(with = "u128_string")]
+    pub fee_a_growth_global: u128,
+    #[serde(with = "u128_string")]
+    pub fee_b_growth_global: u128,
+}
+
+/// `cetusdlmm::pool::Pool` as rendered by RPC.
+///
+/// Bins live in dynamic fields under the bin manager and are not part of the
+/// pool object itself; callers that fetched them separately can place them in
+/// `bins` before converting.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawPool {
+    #[serde(deserialize_with = "move_uid")]
+    pub id: String,
+    #[serde(with = "u64_string")]
+    pub index: u64,
+    #[serde(deserialize_with = "move_struct")]
+    pub v_parameters: RawVariableParameters,
+    #[serde(deserialize_with = "move_i32")]
+    pub active_id: i32,
+    #[serde(with = "u64_string")]
+    pub base_fee_rate: u64,
+    #[serde(with = "u64_string")]
+    pub balance_a: u64,
+    #[serde(with = "u64_string")]
+    pub balance_b: u64,
+    #[serde(with = "u64_string")]
+    pub protocol_fee_a: u64,
+    #[serde(with = "u64_string")]
+    pub protocol_fee_b: u64,
+    #[serde(default)]
+    pub bins: Vec<RawBin>,
+    /// The object's Move type, `<package>::pool::Pool<A, B>`, taken from
+    /// the envelope by [`RawPool::from_rpc_content`].
+    #[serde(skip)]
+    pub pool_type: Option<String>,
+}
+
+impl RawPool {
+    /// Parses the `content` of an RPC object response, i.e. either the
+    /// `{ "dataType", "type", "fields" }` envelope or the bare `fields` map.
+    pub fn from_rpc_content<'de, D: Deserializer<'de>>(content: D) -> Result<Self, D::Error> {
+        let (pool_type, raw) = MoveStruct::<RawPool>::deserialize(content)?.into_parts();
+        Ok(RawPool { pool_type, ..raw })
+    }
+}
+
+impl From<RawBinStepConfig> for BinStepConfig {
+    fn from(raw: RawBinStepConfig) -> Self {
+        BinStepConfig::new(
+            raw.bin_step,
+            raw.base_factor,
+            raw.filter_period,
+            raw.decay_period,
+            raw.reduction_factor,
+            raw.variable_fee_control,
+            raw.max_volatility_accumulator,
+            raw.protocol_fee_rate,
+        )
+    }
+}
+
+impl From<RawVariableParameters> for VariableParameters {
+    fn from(raw: RawVariableParameters) -> Self {
+        VariableParameters {
+            volatility_accumulator: raw.volatility_accumulator,
+            volatility_reference: raw.volatility_reference,
+            index_reference: raw.index_reference,
+            last_update_timestamp: raw.last_update_timestamp,
+            bin_step_config: raw.bin_step_config.into(),
+        }
+    }
+}
+
+impl From<RawBin> for Bin {
+    fn from(raw: RawBin) -> Self {
+        Bin {
+            id: raw.id,
+            amount_a: raw.amount_a,
+            amount_b: raw.amount_b,
+            price: raw.price.into(),
+            liquidity_supply: raw.liquidity_share,
+            rewards_growth_global: raw.rewards_growth_global,
+            fee_amount_a_growth_global: raw.fee_a_growth_global,
+            fee_amount_b_growth_global: raw.fee_b_growth_global,
+        }
+    }
+}
+
+impl TryFrom<RawPool> for Pool {
+    type Error = Error;
+
+    /// Converts and validates the pool (see [`Pool::validate`]), with
+    /// metadata holding its id, bin step and, if `pool_type` is set, coin
+    /// types. The decimals are not part of the pool object.
+    fn try_from(raw: RawPool) -> Result<Self, Self::Error> {
+        let (coin_type_a, coin_type_b) = raw
+            .pool_type
+            .as_deref()
+            .and_then(coin_types_from_pool_type)
+            .unzip();
+        let metadata = PoolMetadata {
+            pool_id: raw.id,
+            coin_type_a,
+            coin_type_b,
+            decimals_a: None,
+            decimals_b: None,
+            bin_step: raw.v_parameters.bin_step_config.bin_step,
+        };
+        let pool = Pool::new(
+            raw.active_id,
+            raw.base_fee_rate,
+            raw.v_parameters.into(),
+            raw.bins.into_iter().map(Bin::from).collect(),
+        )
+        .with_metadata(metadata);
+        pool.validate()?;
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RPC_POOL: &str = include_str!("../tests/fixtures/synthetic_rpc_pool_object.json");
+
+    fn rpc_content() -> serde_json::Value {
+        let response: serde_json::Value = serde_json::from_str(RPC_POOL).unwrap();
+        response["data"]["content"].clone()
+    }
+
+    #[test]
+    fn parses_rpc_object_content() {
+        let raw = RawPool::from_rpc_content(rpc_content()).unwrap();
+        assert_eq!(
+            raw.id,
+            "0x4fe3a2a0f4e1ac2e4a0e5d4fc2bdb46a3f1c3c05a6b0c7e8e7bba45b1b6d1c2e"
+        );
+        assert_eq!(raw.active_id, -12);
+        assert_eq!(raw.v_parameters.index_reference, -10);
+        assert_eq!(raw.v_parameters.bin_step_config.bin_step, 25);
+
+        let pool = Pool::try_from(raw).unwrap();
+        assert_eq!(pool.active_id, -12);
+        assert_eq!(pool.base_fee_rate, 2_500_000);
+        assert_eq!(pool.v_parameters.volatility_accumulator, 20_000);
+        assert_eq!(pool.v_parameters.last_update_timestamp, 1_726_017_305);
+        assert_eq!(
+            pool.v_parameters.bin_step_config.protocol_fee_rate,
+            200_000_000
+        );
+        assert!(pool.bins.is_empty());
+
+        let metadata = pool.metadata().unwrap();
+        assert_eq!(
+            metadata.pool_id,
+            "0x4fe3a2a0f4e1ac2e4a0e5d4fc2bdb46a3f1c3c05a6b0c7e8e7bba45b1b6d1c2e"
+        );
+        assert_eq!(metadata.coin_type_a.as_deref(), Some("0x2::sui::SUI"));
+        assert_eq!(
+            metadata.coin_type_b.as_deref(),
+            Some("0xdba34672e30cb065b1f93e3ab55318768fd6fef66c15942c9f7cb846e2f900e7::usdc::USDC")
+        );
+        assert_eq!((metadata.decimals(), metadata.bin_step), (None, 25));
+    }
+
+    #[test]
+    fn bare_fields_with_bins_convert() {
+        let mut fields = rpc_content()["fields"].clone();
+        fields["bins"] = serde_json::json!([
+            {
+                "id": { "type": "0x714a63a0dba6da4f017b42d5d0fb78867f18bcde904868e51d951a5a6f5b7f57::i32::I32", "fields": { "bits": 4294967284u32 } },
+                "amount_a": "1200000",
+                "amount_b": "5400000",
+                "price": "18215001129347432448",
+                "liquidity_share": "99530000000000000000000000",
+                "rewards_growth_global": ["12", "0"],
+                "fee_a_growth_global": "340282366920938463463374607431768211455",
+                "fee_b_growth_global": "0"
+            },
+            {
+                "id": { "bits": 4294967285u32 },
+                "amount_a": "0",
+                "amount_b": "700000",
+                "price": "18260538632170800128",
+                "liquidity_share": "12782377042519560089600000",
+                "rewards_growth_global": [],
+                "fee_a_growth_global": "0",
+                "fee_b_growth_global": "5"
+            }
+        ]);
+        let pool = Pool::try_from(serde_json::from_value::<RawPool>(fields).unwrap()).unwrap();
+        assert_eq!(pool.bins.len(), 2);
+        // The bare fields have no type to take the coin types from.
+        assert_eq!(pool.metadata().unwrap().coin_type_a, None);
+        assert_eq!(pool.bins[0].id, -12);
+        assert_eq!(pool.bins[1].id, -11);
+        assert_eq!(
+            pool.bins[0].liquidity_supply,
+            99_530_000_000_000_000_000_000_000
+        );
+        assert_eq!(pool.bins[0].fee_amount_a_growth_global, u128::MAX);
+        assert_eq!(pool.bins[0].rewards_growth_global, vec![12, 0]);
+        assert_eq!(pool.bins[1].fee_amount_b_growth_global, 5);
+    }
+
+    #[test]
+    fn conversion_rejects_invalid_pools() {
+        let mut fields = rpc_content()["fields"].clone();
+        let bin = |bits: u32| {
+            serde_json::json!({
+                "id": { "bits": bits },
+                "amount_a": "1", "amount_b": "1", "price": "18446744073709551616",
+                "liquidity_share": "1", "fee_a_growth_global": "0", "fee_b_growth_global": "0"
+            })
+        };
+        fields["bins"] = serde_json::json!([bin(3), bin(1)]);
+        let raw: RawPool = serde_json::from_value(fields.clone()).unwrap();
+        assert!(Pool::try_from(raw).is_err());
+
+        fields["bins"] = serde_json::json!([]);
+        fields["base_fee_rate"] = serde_json::json!("100000001");
+        let raw: RawPool = serde_json::from_value(fields).unwrap();
+        assert!(Pool::try_from(raw).is_err());
+    }
+
+    #[test]
+    fn bin_accepts_on_chain_field_names() {
+        let bin: Bin = serde_json::from_value(serde_json::json!({
+            "id": 3,
+            "amount_a": 1,
+            "amount_b": 2,
+            "price": 1000,
+            "liquidity_share": 9,
+            "rewards_growth_global": [],
+            "fee_a_growth_global": 4,
+            "fee_b_growth_global": 5
+        }))
+        .unwrap();
+        assert_eq!(bin.liquidity_supply, 9);
+        assert_eq!(bin.fee_amount_a_growth_global, 4);
+        assert_eq!(bin.fee_amount_b_growth_global, 5);
+    }
+}
@@ -0,0 +1,289 @@
+//! Replaying a log of recorded swaps against a snapshot, see [`replay_log`].
+//!
+//! Where [`conformance`](crate::conformance) checks one swap against the
+//! pool right before it, a replay starts from one snapshot and executes a
+//! whole log on it, e.g. a day of swaps. Each swap runs on the pool the
+//! previous ones left behind, so a small divergence early on shows up as
+//! drift in every later output, and the final pool can be compared with an
+//! end-of-day snapshot.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{pool::Pool, timestamp::Timestamp};
+
+/// One exact-input swap from the log, with the output it paid on chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedSwap {
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_in: u64,
+    pub a2b: bool,
+    pub timestamp: Timestamp,
+    /// The output observed on chain.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount_out: u64,
+    /// Where the swap comes from, e.g. a transaction digest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// How [`replay_log_with_options`] judges and handles drift.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayOptions {
+    /// The largest absolute cumulative drift, in output token units, that
+    /// is not a divergence. Zero by default.
+    pub tolerance: u64,
+    /// Stop after the first divergence instead of replaying the rest.
+    pub stop_on_divergence: bool,
+}
+
+/// The outcome of one [`RecordedSwap`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplayEntry {
+    /// Position in the log.
+    pub index: usize,
+    /// What the SDK paid out, or `None` if the swap failed.
+    pub sdk_amount_out: Option<u64>,
+    pub recorded_amount_out: u64,
+    /// `sdk_amount_out - recorded_amount_out`, counting a failed swap as
+    /// paying nothing.
+    pub drift: i128,
+    /// The sum of `drift` over this entry and the ones before it.
+    pub cumulative_drift: i128,
+    /// Why the swap failed.
+    pub error: Option<String>,
+}
+
+/// The result of [`replay_log`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplayReport {
+    /// One entry per replayed swap, in log order. Shorter than the log if
+    /// the replay stopped at a divergence.
+    pub entries: Vec<ReplayEntry>,
+    /// `cumulative_drift` of the last entry.
+    pub cumulative_drift: i128,
+    /// The index of the first entry whose swap failed or whose cumulative
+    /// drift exceeded the tolerance.
+    pub first_divergence: Option<usize>,
+    /// The [`Pool::state_hash`] of the pool after the replay.
+    pub final_state_hash: [u8; 32],
+}
+
+impl ReplayReport {
+    /// Whether every swap replayed within the tolerance.
+    pub fn passed(&self) -> bool {
+        self.first_divergence.is_none()
+    }
+}
+
+/// `replayed 12 swap(s), cumulative drift +0`, followed by the first
+/// divergence if there is one.
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "replayed {} swap(s), cumulative drift {:+}",
+            self.entries.len(),
+            self.cumulative_drift
+        )?;
+        let Some(entry) = self.first_divergence.map(|index| &self.entries[index]) else {
+            return Ok(());
+        };
+        match &entry.error {
+            Some(error) => write!(f, "\n  swap {} failed: {error}", entry.index),
+            None => write!(
+                f,
+                "\n  swap {} diverged: expected {}, got {} (cumulative {:+})",
+                entry.index,
+                entry.recorded_amount_out,
+                entry.sdk_amount_out.unwrap_or_default(),
+                entry.cumulative_drift
+            ),
+        }
+    }
+}
+
+/// [`replay_log_with_options`] with zero tolerance, replaying the whole log.
+pub fn replay_log(pool: &mut Pool, log: &[RecordedSwap]) -> ReplayReport {
+    replay_log_with_options(pool, log, ReplayOptions::default())
+}
+
+/// Executes the swaps of `log` in order on `pool`, each at its recorded
+/// timestamp, and compares each output with the recorded one.
+///
+/// `pool` is left as the last replayed swap left it, ready to be compared
+/// with an end snapshot, e.g. with [`Pool::diff`]. A swap the SDK fails
+/// leaves the pool unchanged and counts as a divergence whatever the
+/// tolerance, since the recorded swap succeeded.
+pub fn replay_log_with_options(
+    pool: &mut Pool,
+    log: &[RecordedSwap],
+    options: ReplayOptions,
+) -> ReplayReport {
+    let mut entries = Vec::with_capacity(log.len());
+    let mut cumulative_drift = 0i128;
+    let mut first_divergence = None;
+    for (index, swap) in log.iter().enumerate() {
+        let (sdk_amount_out, error) =
+            match pool.swap_exact_amount_in(swap.amount_in, swap.a2b, swap.timestamp) {
+                Ok(result) => (Some(result.amount_out), None),
+                Err(err) => (None, Some(format!("{err:#}"))),
+            };
+        let drift = i128::from(sdk_amount_out.unwrap_or_default()) - i128::from(swap.amount_out);
+        cumulative_drift += drift;
+        let diverged =
+            error.is_some() || cumulative_drift.unsigned_abs() > u128::from(options.tolerance);
+        if diverged && first_divergence.is_none() {
+            first_divergence = Some(index);
+        }
+        entries.push(ReplayEntry {
+            index,
+            sdk_amount_out,
+            recorded_amount_out: swap.amount_out,
+            drift,
+            cumulative_drift,
+            error,
+        });
+        if diverged && options.stop_on_divergence {
+            break;
+        }
+    }
+    ReplayReport {
+        entries,
+        cumulative_drift,
+        first_divergence,
+        final_state_hash: pool.state_hash(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::PriceQ64;
+
+    const START_POOL: &str = include_str!("../tests/fixtures/synthetic_events_start_pool.json");
+    const LOG: &str = include_str!("../tests/fixtures/replay_log.json");
+
+    fn start_pool() -> Pool {
+        serde_json::from_str(START_POOL).unwrap()
+    }
+
+    /// The index of the fixture's hand-entered swap, whose recorded output
+    /// is 500 below the SDK's. The swaps before it were recorded from the
+    /// SDK.
+    const HAND_ENTERED: usize = 8;
+
+    fn log() -> Vec<RecordedSwap> {
+        serde_json::from_str(LOG).unwrap()
+    }
+
+    /// The swaps recorded from the SDK, which replay without drift.
+    fn sdk_log() -> Vec<RecordedSwap> {
+        let mut log = log();
+        log.truncate(HAND_ENTERED);
+        log
+    }
+
+    #[test]
+    fn fixture_diverges_at_the_hand_entered_swap() {
+        let log = log();
+        let mut pool = start_pool();
+        let report = replay_log(&mut pool, &log);
+        assert_eq!(report.first_divergence, Some(HAND_ENTERED));
+        assert_eq!(report.entries.len(), log.len());
+        assert!(report.entries[..HAND_ENTERED]
+            .iter()
+            .all(|entry| entry.drift == 0));
+        let entry = &report.entries[HAND_ENTERED];
+        assert_eq!(
+            (entry.sdk_amount_out, entry.recorded_amount_out, entry.drift),
+            (Some(997_500), 997_000, 500)
+        );
+        assert_eq!(
+            report.to_string(),
+            "replayed 9 swap(s), cumulative drift +500\n  \
+             swap 8 diverged: expected 997000, got 997500 (cumulative +500)"
+        );
+        let lenient = ReplayOptions {
+            tolerance: 500,
+            ..Default::default()
+        };
+        assert!(replay_log_with_options(&mut start_pool(), &log, lenient).passed());
+
+        // The pool is the one the swaps leave behind.
+        let mut expected = start_pool();
+        for swap in &log {
+            expected
+                .swap_exact_amount_in(swap.amount_in, swap.a2b, swap.timestamp)
+                .unwrap();
+        }
+        assert_eq!(pool, expected);
+        assert_eq!(report.final_state_hash, expected.state_hash());
+        assert!(!start_pool().diff(&pool).is_empty());
+    }
+
+    #[test]
+    fn drift_past_the_tolerance_is_a_divergence() {
+        let mut log = sdk_log();
+        log[2].amount_out += 3;
+        log[5].amount_out -= 1;
+
+        let report = replay_log(&mut start_pool(), &log);
+        assert_eq!(report.first_divergence, Some(2));
+        assert_eq!(report.entries.len(), log.len());
+        assert_eq!(
+            (report.entries[2].drift, report.entries[2].cumulative_drift),
+            (-3, -3)
+        );
+        assert_eq!(report.cumulative_drift, -2);
+        let text = report.to_string();
+        assert!(text.contains("swap 2 diverged: expected"), "{text}");
+
+        let lenient = ReplayOptions {
+            tolerance: 3,
+            ..Default::default()
+        };
+        assert!(replay_log_with_options(&mut start_pool(), &log, lenient).passed());
+
+        let strict = ReplayOptions {
+            tolerance: 2,
+            stop_on_divergence: true,
+        };
+        let mut pool = start_pool();
+        let report = replay_log_with_options(&mut pool, &log, strict);
+        assert_eq!(report.first_divergence, Some(2));
+        assert_eq!(report.entries.len(), 3);
+        assert_eq!(report.final_state_hash, pool.state_hash());
+    }
+
+    #[test]
+    fn failed_swaps_diverge_whatever_the_tolerance() {
+        let log = log();
+        // A bin without a price cannot be swapped through.
+        let mut pool = start_pool();
+        for bin in &mut pool.bins {
+            bin.price = PriceQ64::from_raw(0);
+        }
+        let before = pool.clone();
+        let options = ReplayOptions {
+            tolerance: u64::MAX,
+            stop_on_divergence: true,
+        };
+        let report = replay_log_with_options(&mut pool, &log, options);
+        assert_eq!(report.first_divergence, Some(0));
+        let entry = &report.entries[0];
+        assert_eq!(entry.sdk_amount_out, None);
+        assert_eq!(entry.drift, -i128::from(log[0].amount_out));
+        assert!(entry.error.is_some());
+        assert!(report.to_string().contains("swap 0 failed"), "{report}");
+        assert_eq!(pool, before);
+    }
+}
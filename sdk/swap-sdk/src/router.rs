@@ -0,0 +1,969 @@
+//! Quoting swaps that span several pools.
+
+use alloc::{vec, vec::Vec};
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pool::{Pool, SwapResult},
+    swappable::Swappable,
+    timestamp::Timestamp,
+};
+
+/// One hop of a route.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HopQuote {
+    pub a2b: bool,
+    pub result: SwapResult,
+}
+
+/// The first hop that ran out of liquidity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExceededHop {
+    /// Index of the hop in the route.
+    pub hop: usize,
+    /// Amount fed into the hop, or for [`quote_route_exact_out`] the
+    /// output asked of it.
+    pub requested: u64,
+    /// Amount the hop actually consumed, or for [`quote_route_exact_out`]
+    /// the output it can deliver.
+    pub filled: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteQuote {
+    /// Input consumed by the first hop.
+    pub amount_in: u64,
+    /// Output of the last hop.
+    pub amount_out: u64,
+    pub hops: Vec<HopQuote>,
+    /// Relative shortfall of `amount_out` against the output at the pools'
+    /// active-bin prices before the swap, fees included.
+    pub price_impact: f64,
+    pub exceeded: Option<ExceededHop>,
+}
+
+impl RouteQuote {
+    /// Total fee charged by each hop, in that hop's input token.
+    pub fn fees(&self) -> Vec<u64> {
+        self.hops.iter().map(|hop| hop.result.fee).collect()
+    }
+
+    /// Protocol fee charged by each hop, in that hop's input token.
+    pub fn protocol_fees(&self) -> Vec<u64> {
+        self.hops
+            .iter()
+            .map(|hop| hop.result.protocol_fee)
+            .collect()
+    }
+}
+
+/// Swaps `amount_in` through `pools` in order, feeding each hop's output into
+/// the next. The pools are updated as if the route had been executed; pass
+/// clones to keep the originals.
+///
+/// If a hop runs out of liquidity, the route keeps going with whatever it
+/// produced and the first such hop is reported in
+/// [`RouteQuote::exceeded`].
+///
+/// The pools can be any [`Swappable`] venue; use
+/// `&mut dyn Swappable<Quote = SwapResult>` to mix venues in one route.
+pub fn quote_route_exact_in<P: Swappable<Quote = SwapResult> + ?Sized>(
+    pools: &mut [(&mut P, bool)],
+    amount_in: u64,
+    timestamp: impl Into<Timestamp>,
+) -> Result<RouteQuote, Error> {
+    let timestamp = timestamp.into();
+    if pools.is_empty() {
+        return Err(anyhow!("route has no hops"));
+    }
+
+    let mut spot = 1.0;
+    let mut hops = Vec::with_capacity(pools.len());
+    let mut exceeded = None;
+    let mut amount = amount_in;
+    for (index, (pool, a2b)) in pools.iter_mut().enumerate() {
+        spot *= pool.spot_rate(*a2b)?;
+        let result = pool.execute_exact_in(amount, *a2b, timestamp)?;
+        if result.is_exceed && exceeded.is_none() {
+            exceeded = Some(ExceededHop {
+                hop: index,
+                requested: amount,
+                filled: result.amount_in,
+            });
+        }
+        amount = result.amount_out;
+        hops.push(HopQuote { a2b: *a2b, result });
+    }
+
+    let amount_in = hops[0].result.amount_in;
+    let ideal = amount_in as f64 * spot;
+    let price_impact = if ideal > 0.0 {
+        1.0 - amount as f64 / ideal
+    } else {
+        0.0
+    };
+    Ok(RouteQuote {
+        amount_in,
+        amount_out: amount,
+        hops,
+        price_impact,
+        exceeded,
+    })
+}
+
+/// Times [`quote_route_exact_out`] raises its input before giving up.
+const EXACT_OUT_BUMPS: usize = 16;
+
+/// Swaps through `pools` in order so that the last hop delivers at least
+/// `amount_out`, with as little input as the pools' rounding allows. The
+/// pools are updated as if the route had been executed; pass clones to
+/// keep the originals.
+///
+/// Each hop's required input is found back to front with its exact-out
+/// quote. Exact-out and exact-in quotes round differently, so the input
+/// found this way can come up a few units short when the route is run
+/// forward. It is therefore checked with exact-in quotes first, and while
+/// it falls short it is raised by the shortfall converted at the route's
+/// average rate, rounded up and at least one unit, up to 16 times. The
+/// route is then executed exact-in like [`quote_route_exact_in`], so
+/// `amount_out` of the result is at least the one requested.
+///
+/// If a hop cannot deliver what the next one needs, the hops before it are
+/// sized for what it can deliver and the route delivers less than
+/// requested. The first such hop is reported in [`RouteQuote::exceeded`],
+/// with output amounts.
+pub fn quote_route_exact_out<P: Swappable<Quote = SwapResult> + ?Sized>(
+    pools: &mut [(&mut P, bool)],
+    amount_out: u64,
+    timestamp: impl Into<Timestamp>,
+) -> Result<RouteQuote, Error> {
+    let timestamp = timestamp.into();
+    if pools.is_empty() {
+        return Err(anyhow!("route has no hops"));
+    }
+
+    let mut required = amount_out;
+    let mut exceeded = None;
+    for (index, (pool, a2b)) in pools.iter().enumerate().rev() {
+        let result = pool.quote_exact_out(required, *a2b, timestamp)?;
+        if result.is_exceed {
+            exceeded = Some(ExceededHop {
+                hop: index,
+                requested: required,
+                filled: result.amount_out,
+            });
+        }
+        required = result.amount_in;
+    }
+
+    let mut amount_in = required;
+    if exceeded.is_none() {
+        let mut bumps = 0;
+        loop {
+            let delivered = forward_amount_out(pools, amount_in, timestamp)?;
+            if delivered >= amount_out {
+                break;
+            }
+            if bumps == EXACT_OUT_BUMPS {
+                return Err(anyhow!(
+                    "route still delivers {delivered} of {amount_out} after {bumps} bumps"
+                ));
+            }
+            let shortfall = (amount_out - delivered) as u128;
+            let bump = (shortfall * amount_in as u128).div_ceil(delivered.max(1) as u128);
+            amount_in = u64::try_from(bump.max(1))
+                .ok()
+                .and_then(|bump| amount_in.checked_add(bump))
+                .ok_or_else(|| anyhow!("route input overflows u64"))?;
+            bumps += 1;
+        }
+    }
+
+    let mut quote = quote_route_exact_in(pools, amount_in, timestamp)?;
+    if exceeded.is_some() {
+        quote.exceeded = exceeded;
+    }
+    Ok(quote)
+}
+
+/// What the last hop delivers for `amount_in`, from exact-in quotes.
+fn forward_amount_out<P: Swappable<Quote = SwapResult> + ?Sized>(
+    pools: &[(&mut P, bool)],
+    amount_in: u64,
+    timestamp: Timestamp,
+) -> Result<u64, Error> {
+    let mut amount = amount_in;
+    for (pool, a2b) in pools {
+        amount = pool.quote_exact_in(amount, *a2b, timestamp)?.amount_out;
+    }
+    Ok(amount)
+}
+
+/// Input amounts to send to each pool so the combined output is maximal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitQuote {
+    /// Input assigned to each pool, in the order the pools were given.
+    pub allocations: Vec<u64>,
+    /// Quote of each pool for its allocation.
+    pub results: Vec<SwapResult>,
+    /// Input consumed across all pools.
+    pub amount_in: u64,
+    /// Output summed across all pools.
+    pub amount_out: u64,
+    /// Set when the pools together cannot absorb the whole input.
+    pub is_exceed: bool,
+}
+
+/// Number of chunks the input is cut into before the allocation is refined.
+const SPLIT_CHUNKS: u64 = 64;
+
+/// Output of one pool as a function of its input, quoted without touching
+/// the pool.
+struct SplitLeg<'a, P: ?Sized> {
+    pool: &'a P,
+    a2b: bool,
+    timestamp: Timestamp,
+    /// Largest input the pool can take before it runs out of liquidity.
+    capacity: u64,
+}
+
+impl<P: Swappable<Quote = SwapResult> + ?Sized> SplitLeg<'_, P> {
+    fn quote(&self, amount_in: u64) -> Result<SwapResult, Error> {
+        if amount_in == 0 {
+            return Ok(SwapResult::default());
+        }
+        Ok(self
+            .pool
+            .quote_exact_in(amount_in, self.a2b, self.timestamp)?)
+    }
+
+    fn amount_out(&self, amount_in: u64) -> Result<u64, Error> {
+        Ok(self.quote(amount_in)?.amount_out)
+    }
+}
+
+/// Splits `amount_in` across pools trading the same pair in the same
+/// direction so that the summed output is as large as possible. The pools
+/// are only quoted, never modified.
+///
+/// The input is first handed out in chunks to whichever pool pays the most
+/// for the next chunk, then amounts are moved between pools in halving steps
+/// for as long as that increases the output. A pool that never beats the
+/// others gets an allocation of 0. If the pools together cannot absorb
+/// `amount_in`, each is allocated what it can take and
+/// [`SplitQuote::is_exceed`] is set. The same goes for input that no pool
+/// pays anything more for: it is left unallocated.
+///
+/// As with [`quote_route_exact_in`], the pools can be any [`Swappable`]
+/// venue.
+pub fn optimal_split_exact_in<P: Swappable<Quote = SwapResult> + ?Sized>(
+    pools: &[&P],
+    amount_in: u64,
+    a2b: bool,
+    timestamp: impl Into<Timestamp>,
+) -> Result<SplitQuote, Error> {
+    let timestamp = timestamp.into();
+    if pools.is_empty() {
+        return Err(anyhow!("no pools to split across"));
+    }
+
+    let mut legs = Vec::with_capacity(pools.len());
+    let mut full_quotes = Vec::with_capacity(pools.len());
+    for &pool in pools {
+        let mut leg = SplitLeg {
+            pool,
+            a2b,
+            timestamp,
+            capacity: amount_in,
+        };
+        let full = leg.quote(amount_in)?;
+        if full.is_exceed {
+            leg.capacity = full.amount_in;
+        }
+        legs.push(leg);
+        full_quotes.push(full);
+    }
+
+    let total_capacity = legs
+        .iter()
+        .fold(0u64, |acc, leg| acc.saturating_add(leg.capacity));
+    let (allocations, results) = if total_capacity <= amount_in {
+        // Every pool takes all it can; their full-amount quotes already say
+        // so.
+        (legs.iter().map(|leg| leg.capacity).collect(), full_quotes)
+    } else {
+        let allocations = allocate(&legs, amount_in)?;
+        let mut results = Vec::with_capacity(legs.len());
+        for (leg, allocation) in legs.iter().zip(&allocations) {
+            results.push(leg.quote(*allocation)?);
+        }
+        (allocations, results)
+    };
+    let consumed = results.iter().map(|r| r.amount_in).sum();
+    let amount_out = results.iter().map(|r| r.amount_out).sum();
+    Ok(SplitQuote {
+        allocations,
+        results,
+        amount_in: consumed,
+        amount_out,
+        is_exceed: consumed < amount_in,
+    })
+}
+
+/// Distributes `amount_in` when the pools have more capacity than needed.
+fn allocate<P: Swappable<Quote = SwapResult> + ?Sized>(
+    legs: &[SplitLeg<P>],
+    amount_in: u64,
+) -> Result<Vec<u64>, Error> {
+    let chunk = (amount_in / SPLIT_CHUNKS).max(1);
+    let mut allocations = vec![0u64; legs.len()];
+    let mut outs = vec![0u64; legs.len()];
+    let mut stopped = vec![false; legs.len()];
+
+    // Greedy pass: each chunk goes to the pool with the best output per unit
+    // of input for it.
+    let mut remaining = amount_in;
+    while remaining > 0 {
+        let mut best: Option<(usize, u64, u64)> = None;
+        for (i, leg) in legs.iter().enumerate() {
+            let size = chunk.min(remaining).min(leg.capacity - allocations[i]);
+            if size == 0 || stopped[i] {
+                continue;
+            }
+            let out = leg.amount_out(allocations[i] + size)?;
+            let gain = out.saturating_sub(outs[i]);
+            if gain == 0 {
+                // Stop at a pool that pays nothing more even for all the
+                // input left, rather than for this chunk only.
+                let rest = remaining.min(leg.capacity - allocations[i]);
+                if leg.amount_out(allocations[i] + rest)? <= outs[i] {
+                    stopped[i] = true;
+                    continue;
+                }
+            }
+            let better = best.is_none_or(|(j, best_size, best_out)| {
+                let best_gain = best_out - outs[j];
+                gain as u128 * best_size as u128 > best_gain as u128 * size as u128
+            });
+            if better {
+                best = Some((i, size, out));
+            }
+        }
+        let Some((i, size, out)) = best else {
+            if stopped.contains(&true) {
+                break;
+            }
+            return Err(anyhow!("pools ran out of capacity"));
+        };
+        allocations[i] += size;
+        outs[i] = out;
+        remaining -= size;
+    }
+
+    // Refinement: move input between pools while it pays off, halving the
+    // amount moved once no move improves the total.
+    let mut step = chunk / 2;
+    while step > 0 {
+        let mut improved = false;
+        for from in 0..legs.len() {
+            for to in 0..legs.len() {
+                if from == to {
+                    continue;
+                }
+                let moved = step
+                    .min(allocations[from])
+                    .min(legs[to].capacity - allocations[to]);
+                if moved == 0 {
+                    continue;
+                }
+                let from_out = legs[from].amount_out(allocations[from] - moved)?;
+                let to_out = legs[to].amount_out(allocations[to] + moved)?;
+                if from_out as u128 + to_out as u128 > outs[from] as u128 + outs[to] as u128 {
+                    allocations[from] -= moved;
+                    allocations[to] += moved;
+                    outs[from] = from_out;
+                    outs[to] = to_out;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            step /= 2;
+        }
+    }
+
+    Ok(allocations)
+}
+
+/// A round trip through two pools for the same pair that buys token A
+/// where it is cheap and sells it where it is dear, see
+/// [`close_arbitrage`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArbPlan {
+    /// Token B spent on the buy leg.
+    pub amount_in: u64,
+    /// Whether the buy leg trades on `pool_x` and the sell leg on `pool_y`,
+    /// rather than the other way around.
+    pub buy_on_x: bool,
+    /// Buys token A with `amount_in` of token B, so `a2b` is false.
+    pub buy: HopQuote,
+    /// Sells all the token A the buy leg returns, so `a2b` is true. The
+    /// buy is sized so that this leg is never `is_exceed`.
+    pub sell: HopQuote,
+    /// Token B the sell leg returns minus `amount_in`, before gas.
+    pub profit: u64,
+}
+
+/// Divisor of the probe offset in [`close_arbitrage`]: profit is compared
+/// between `x` and `x + x / ARB_PROBE_DIVISOR`.
+const ARB_PROBE_DIVISOR: u64 = 1024;
+
+/// The round trip that closes the price gap between two pools trading the
+/// same pair, or `None` if no size is profitable after fees. The pools are
+/// only quoted, never modified.
+///
+/// Token A is bought on the pool whose active bin is cheaper and sold on
+/// the other, and the plan's amounts and profit are in token B. The profit
+/// is concave in the size, so whether a slightly larger trade pays more is
+/// monotonic in the size: a binary search finds where it stops paying,
+/// which is the optimum to within about a thousandth. The search only
+/// covers buys whose token A the dear pool can take whole, up to its
+/// [`Pool::max_input`], so the sell leg is never `is_exceed`. Both legs are
+/// quoted at `timestamp`.
+pub fn close_arbitrage(
+    pool_x: &Pool,
+    pool_y: &Pool,
+    timestamp: impl Into<Timestamp>,
+) -> Result<Option<ArbPlan>, Error> {
+    let timestamp = timestamp.into();
+    let (price_x, price_y) = (pool_x.spot_rate(true)?, pool_y.spot_rate(true)?);
+    if price_x == price_y {
+        return Ok(None);
+    }
+    let buy_on_x = price_x < price_y;
+    let (cheap, dear) = if buy_on_x {
+        (pool_x, pool_y)
+    } else {
+        (pool_y, pool_x)
+    };
+
+    let round_trip = |amount_in: u64| -> Result<(SwapResult, SwapResult, i128), Error> {
+        let buy = cheap.quote_exact_amount_in(amount_in, false, timestamp)?;
+        let sell = dear.quote_exact_amount_in(buy.amount_out, true, timestamp)?;
+        let profit = sell.amount_out as i128 - buy.amount_in as i128;
+        Ok((buy, sell, profit))
+    };
+    // The largest buy whose output the sell leg takes whole. Past it the
+    // sell leg is exceeded and would leave token A unsold, which `profit`
+    // does not count.
+    let sell_max_in = dear.max_input(true, timestamp)?;
+    let (mut lo, mut hi) = (0, cheap.max_input(false, timestamp)?);
+    while lo < hi {
+        let mid = hi - (hi - lo) / 2;
+        let bought = cheap
+            .quote_exact_amount_in(mid, false, timestamp)?
+            .amount_out;
+        if bought <= sell_max_in {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    let max_in = lo;
+    if max_in == 0 {
+        return Ok(None);
+    }
+    let larger = |amount_in: u64| {
+        amount_in
+            .saturating_add((amount_in / ARB_PROBE_DIVISOR).max(1))
+            .min(max_in)
+    };
+
+    // The first size at which a larger trade no longer pays more.
+    (lo, hi) = (1, max_in);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if round_trip(larger(mid))?.2 > round_trip(mid)?.2 {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let (buy, sell, profit) = round_trip(lo)?;
+    if profit <= 0 {
+        return Ok(None);
+    }
+    Ok(Some(ArbPlan {
+        amount_in: buy.amount_in,
+        buy_on_x,
+        buy: HopQuote {
+            a2b: false,
+            result: buy,
+        },
+        sell: HopQuote {
+            a2b: true,
+            result: sell,
+        },
+        profit: profit as u64,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bin::Bin,
+        config::{BinStepConfig, VariableParameters},
+        error::DlmmError,
+        math::price_math::get_price_from_id,
+        pool::Pool,
+    };
+
+    /// A pool with `amount` of each token in every bin from `-width` to
+    /// `width`, priced around the active bin 0.
+    fn pool(bin_step: u16, width: i32, amount: u64) -> Pool {
+        pool_at(0, bin_step, width, amount)
+    }
+
+    /// [`pool`] around the active bin `active_id` instead of 0.
+    fn pool_at(active_id: i32, bin_step: u16, width: i32, amount: u64) -> Pool {
+        let config = BinStepConfig::new(bin_step, 1, 60, 600, 9000, 0, 1_000_000, 30_000);
+        let bins = (active_id - width..=active_id + width)
+            .map(|id| Bin {
+                id,
+                amount_a: if id >= active_id { amount } else { 0 },
+                amount_b: if id <= active_id { amount } else { 0 },
+                price: get_price_from_id(id, bin_step).unwrap().into(),
+                ..Default::default()
+            })
+            .collect();
+        let mut v_parameters = VariableParameters::new(config, 0, 0);
+        v_parameters.index_reference = active_id;
+        Pool::new(active_id, 2_500_000, v_parameters, bins)
+    }
+
+    #[test]
+    fn two_hop_route_chains_amounts() {
+        let (mut ab, mut bc) = (pool(25, 5, 1_000_000), pool(10, 5, 1_000_000));
+        let (ab_copy, bc_copy) = (ab.clone(), bc.clone());
+        let quote =
+            quote_route_exact_in(&mut [(&mut ab, true), (&mut bc, true)], 500_000, 10).unwrap();
+
+        assert!(quote.exceeded.is_none());
+        assert_eq!(quote.hops.len(), 2);
+        assert_eq!(quote.amount_in, 500_000);
+        assert_eq!(
+            quote.hops[1].result.amount_in,
+            quote.hops[0].result.amount_out
+        );
+        assert_eq!(quote.amount_out, quote.hops[1].result.amount_out);
+        assert_eq!(
+            quote.fees(),
+            vec![quote.hops[0].result.fee, quote.hops[1].result.fee]
+        );
+
+        // Chaining by hand on the untouched pools gives the same numbers.
+        let (mut ab, mut bc) = (ab_copy, bc_copy);
+        let first = ab.swap_exact_amount_in(500_000, true, 10).unwrap();
+        let second = bc.swap_exact_amount_in(first.amount_out, true, 10).unwrap();
+        assert_eq!(quote.amount_out, second.amount_out);
+
+        assert!(quote.price_impact > 0.0 && quote.price_impact < 0.05);
+    }
+
+    #[test]
+    fn three_hop_route_reports_exceeding_hop() {
+        let mut ab = pool(25, 5, 1_000_000);
+        let mut bc = pool(25, 2, 100_000);
+        let mut cd = pool(25, 5, 1_000_000);
+        let quote = quote_route_exact_in(
+            &mut [(&mut ab, true), (&mut bc, false), (&mut cd, true)],
+            800_000,
+            10,
+        )
+        .unwrap();
+
+        let exceeded = quote.exceeded.expect("middle hop runs dry");
+        assert_eq!(exceeded.hop, 1);
+        assert_eq!(exceeded.requested, quote.hops[0].result.amount_out);
+        assert_eq!(exceeded.filled, quote.hops[1].result.amount_in);
+        assert!(exceeded.filled < exceeded.requested);
+        assert!(quote.hops[1].result.is_exceed);
+        assert!(!quote.hops[2].result.is_exceed);
+        assert_eq!(
+            quote.hops[2].result.amount_in,
+            quote.hops[1].result.amount_out
+        );
+        assert_eq!(quote.amount_out, quote.hops[2].result.amount_out);
+        assert_eq!(quote.protocol_fees().len(), 3);
+    }
+
+    #[test]
+    fn empty_route_is_rejected() {
+        assert!(quote_route_exact_in::<Pool>(&mut [], 1, 0).is_err());
+        assert!(quote_route_exact_out::<Pool>(&mut [], 1, 0).is_err());
+    }
+
+    #[test]
+    fn exact_out_routes_deliver_the_requested_amount() {
+        for amount_out in [1, 500, 123_457, 900_000] {
+            let (mut ab, mut bc) = (pool(25, 5, 1_000_000), pool(10, 5, 1_000_000));
+            let mut cd = pool(25, 5, 1_000_000);
+            let originals = [ab.clone(), bc.clone(), cd.clone()];
+            let two =
+                quote_route_exact_out(&mut [(&mut ab, true), (&mut bc, false)], amount_out, 10)
+                    .unwrap();
+            let (mut ab, mut bc) = (originals[0].clone(), originals[1].clone());
+            let three = quote_route_exact_out(
+                &mut [(&mut ab, true), (&mut bc, false), (&mut cd, true)],
+                amount_out,
+                10,
+            )
+            .unwrap();
+
+            for (quote, hops) in [(&two, 2), (&three, 3)] {
+                assert!(quote.exceeded.is_none());
+                assert_eq!(quote.hops.len(), hops);
+                assert!(
+                    quote.amount_out >= amount_out,
+                    "{} < {amount_out}",
+                    quote.amount_out
+                );
+
+                // Running the input forward on the untouched pools delivers
+                // the same amount.
+                let mut pools: Vec<Pool> = originals[..hops].to_vec();
+                let mut amount = quote.amount_in;
+                for (pool, hop) in pools.iter_mut().zip(&quote.hops) {
+                    let result = pool.swap_exact_amount_in(amount, hop.a2b, 10).unwrap();
+                    assert_eq!(result, hop.result);
+                    amount = result.amount_out;
+                }
+                assert_eq!(amount, quote.amount_out);
+            }
+            assert_eq!(cd, {
+                let mut cd = originals[2].clone();
+                cd.swap_exact_amount_in(three.hops[1].result.amount_out, true, 10)
+                    .unwrap();
+                cd
+            });
+        }
+    }
+
+    /// Pays 999 per 1,000 in, but quotes exact outputs rounding the input
+    /// down, so forwarding its exact-out input falls short.
+    struct RoundsDown;
+
+    impl Swappable for RoundsDown {
+        type Quote = SwapResult;
+
+        fn quote_exact_in(
+            &self,
+            amount_in: u64,
+            _: bool,
+            _: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            Ok(SwapResult {
+                amount_in,
+                amount_out: amount_in * 999 / 1_000,
+                ..Default::default()
+            })
+        }
+
+        fn quote_exact_out(
+            &self,
+            amount_out: u64,
+            _: bool,
+            _: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            Ok(SwapResult {
+                amount_in: amount_out * 1_000 / 999,
+                amount_out,
+                ..Default::default()
+            })
+        }
+
+        fn execute_exact_in(
+            &mut self,
+            amount_in: u64,
+            a2b: bool,
+            timestamp: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            self.quote_exact_in(amount_in, a2b, timestamp)
+        }
+
+        fn spot_rate(&self, _: bool) -> Result<f64, DlmmError> {
+            Ok(0.999)
+        }
+    }
+
+    #[test]
+    fn exact_out_routes_bump_an_input_that_falls_short() {
+        let (mut first, mut second) = (RoundsDown, RoundsDown);
+        let quote = quote_route_exact_out(&mut [(&mut first, true), (&mut second, true)], 1_000, 0)
+            .unwrap();
+        // Back to front, 1,000 needs 1,001, which needs 1,002. Forward,
+        // 1,002 gives 1,000 and then 999, a shortfall of one, and the bump
+        // converts it at 1,002 per 999 into two more units. 1,003 would not
+        // have been enough either: it gives 1,001 and then 999.
+        assert_eq!(quote.amount_in, 1_004);
+        assert_eq!(quote.hops[0].result.amount_out, 1_002);
+        assert_eq!(quote.amount_out, 1_000);
+    }
+
+    #[test]
+    fn exact_out_routes_flag_the_hop_without_liquidity() {
+        let mut ab = pool(25, 5, 1_000_000);
+        let mut bc = pool(25, 2, 100_000);
+        let mut cd = pool(25, 5, 1_000_000);
+        let quote = quote_route_exact_out(
+            &mut [(&mut ab, true), (&mut bc, false), (&mut cd, true)],
+            500_000,
+            10,
+        )
+        .unwrap();
+
+        let exceeded = quote.exceeded.expect("middle hop runs dry");
+        assert_eq!(exceeded.hop, 1);
+        assert!(exceeded.filled < exceeded.requested);
+        // The last hop needs more than the middle one can deliver.
+        assert_eq!(
+            exceeded.requested,
+            pool(25, 5, 1_000_000)
+                .quote_exact_amount_out(500_000, true, 10)
+                .unwrap()
+                .amount_in
+        );
+        assert!(quote.amount_out < 500_000);
+        assert_eq!(quote.hops[1].result.amount_out, exceeded.filled);
+    }
+
+    #[test]
+    fn quote_leaves_pool_untouched() {
+        let pool = pool(25, 5, 1_000_000);
+        let quote = pool.quote_exact_amount_in(1_500_000, true, 10).unwrap();
+        let mut swapped = pool.clone();
+        assert_eq!(
+            swapped.swap_exact_amount_in(1_500_000, true, 10).unwrap(),
+            quote
+        );
+        assert_ne!(swapped, pool);
+    }
+
+    #[test]
+    fn split_fills_bins_in_price_order() {
+        // Both active bins trade at 1. Below them, pool 1's bins 1 and 2 (at
+        // 1/1.001 and 1/1.001^2) pay more than pool 2's next bin (1/1.0025),
+        // so pool 2 should stop after its active bin and pool 1 should take
+        // the rest, ending halfway through its third bin.
+        let liquidity = 1_000_000;
+        let (fine, coarse) = (pool(10, 5, liquidity), pool(25, 5, liquidity));
+        let coarse_share = coarse
+            .quote_exact_amount_out(liquidity, true, 10)
+            .unwrap()
+            .amount_in;
+        let two_bins = fine
+            .quote_exact_amount_out(2 * liquidity, true, 10)
+            .unwrap()
+            .amount_in;
+        let three_bins = fine
+            .quote_exact_amount_out(3 * liquidity, true, 10)
+            .unwrap()
+            .amount_in;
+        let amount_in = coarse_share + (two_bins + three_bins) / 2;
+
+        let split = optimal_split_exact_in(&[&fine, &coarse], amount_in, true, 10).unwrap();
+
+        assert!(!split.is_exceed);
+        assert_eq!(split.amount_in, amount_in);
+        assert_eq!(split.allocations.iter().sum::<u64>(), amount_in);
+        let tolerance = amount_in / 200;
+        assert!(
+            split.allocations[1].abs_diff(coarse_share) <= tolerance,
+            "{:?} vs {coarse_share}",
+            split.allocations
+        );
+        for single in [&fine, &coarse] {
+            let alone = single.quote_exact_amount_in(amount_in, true, 10).unwrap();
+            assert!(split.amount_out > alone.amount_out);
+        }
+        assert_eq!(
+            split.amount_out,
+            split.results.iter().map(|r| r.amount_out).sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn dominated_pool_gets_nothing() {
+        let cheap = pool(10, 5, 1_000_000);
+        let mut expensive = pool(10, 5, 1_000_000);
+        expensive.base_fee_rate = 50_000_000;
+
+        let split = optimal_split_exact_in(&[&expensive, &cheap], 500_000, false, 10).unwrap();
+        assert_eq!(split.allocations, vec![0, 500_000]);
+        assert_eq!(split.results[0], SwapResult::default());
+        assert_eq!(
+            split.amount_out,
+            cheap
+                .quote_exact_amount_in(500_000, false, 10)
+                .unwrap()
+                .amount_out
+        );
+    }
+
+    /// Pays 1:1 up to `.0`, then less for every unit beyond.
+    struct Peaks(u64);
+
+    impl Swappable for Peaks {
+        type Quote = SwapResult;
+
+        fn quote_exact_in(
+            &self,
+            amount_in: u64,
+            _: bool,
+            _: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            let amount_out = if amount_in <= self.0 {
+                amount_in
+            } else {
+                (2 * self.0).saturating_sub(amount_in)
+            };
+            Ok(SwapResult {
+                amount_in,
+                amount_out,
+                ..Default::default()
+            })
+        }
+
+        fn quote_exact_out(
+            &self,
+            amount_out: u64,
+            _: bool,
+            _: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            Ok(SwapResult {
+                amount_in: amount_out,
+                amount_out,
+                ..Default::default()
+            })
+        }
+
+        fn execute_exact_in(
+            &mut self,
+            amount_in: u64,
+            a2b: bool,
+            timestamp: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            self.quote_exact_in(amount_in, a2b, timestamp)
+        }
+
+        fn spot_rate(&self, _: bool) -> Result<f64, DlmmError> {
+            Ok(1.0)
+        }
+    }
+
+    #[test]
+    fn split_stops_at_pools_that_pay_less_for_more() {
+        let (high, low) = (Peaks(500), Peaks(300));
+        let split = optimal_split_exact_in::<Peaks>(&[&high, &low], 1_000, true, 0).unwrap();
+
+        // Chunks of 15: a further chunk would lower either pool's output.
+        assert_eq!(split.allocations, [495, 300]);
+        assert_eq!(split.amount_out, 795);
+        assert_eq!(split.amount_in, 795);
+        assert!(split.is_exceed);
+    }
+
+    #[test]
+    fn split_reports_when_every_pool_runs_dry() {
+        let (a, b) = (pool(10, 1, 1_000), pool(25, 1, 1_000));
+        let split = optimal_split_exact_in(&[&a, &b], 1_000_000, true, 10).unwrap();
+
+        assert!(split.is_exceed);
+        assert!(split.results.iter().all(|r| r.is_exceed));
+        assert!(split.amount_in < 1_000_000);
+        // Each pool holds token B in bins -1 and 0.
+        assert_eq!(split.amount_out, 4_000);
+        assert!(optimal_split_exact_in::<Pool>(&[], 1, true, 10).is_err());
+    }
+
+    #[test]
+    fn arbitrage_closes_a_known_mispricing() {
+        // Token A trades about 2% higher in `dear` (20 bins of 0.1%), more
+        // than the two 0.275% fees. The fees fall between two bin steps:
+        // were they a whole number of them, some pair of bins would price
+        // out exactly at the fees and the profit would be flat across it.
+        let mut cheap = pool(10, 30, 1_000_000);
+        let mut dear = pool_at(20, 10, 30, 1_000_000);
+        cheap.base_fee_rate = 2_750_000;
+        dear.base_fee_rate = 2_750_000;
+        let plan = close_arbitrage(&cheap, &dear, 10).unwrap().unwrap();
+
+        assert!(plan.buy_on_x);
+        assert!(!plan.buy.a2b && plan.sell.a2b);
+        assert_eq!(plan.buy.result.amount_in, plan.amount_in);
+        assert_eq!(plan.sell.result.amount_in, plan.buy.result.amount_out);
+        assert!(!plan.buy.result.is_exceed && !plan.sell.result.is_exceed);
+        assert!(plan.profit > 0);
+        assert_eq!(plan.sell.result.amount_out - plan.amount_in, plan.profit);
+        assert_eq!(
+            plan.buy.result,
+            cheap
+                .quote_exact_amount_in(plan.amount_in, false, 10)
+                .unwrap()
+        );
+
+        let profit = |amount_in: u64| {
+            let bought = cheap.quote_exact_amount_in(amount_in, false, 10).unwrap();
+            let sold = dear
+                .quote_exact_amount_in(bought.amount_out, true, 10)
+                .unwrap();
+            sold.amount_out as i128 - amount_in as i128
+        };
+        assert!(profit(plan.amount_in * 9 / 10) < plan.profit as i128);
+        assert!(profit(plan.amount_in * 11 / 10) < plan.profit as i128);
+
+        // The same gap the other way around.
+        let flipped = close_arbitrage(&dear, &cheap, 10).unwrap().unwrap();
+        assert!(!flipped.buy_on_x);
+        assert_eq!(
+            (flipped.amount_in, flipped.profit),
+            (plan.amount_in, plan.profit)
+        );
+    }
+
+    #[test]
+    fn arbitrage_sells_no_more_than_a_thin_dear_pool_takes() {
+        // Token A trades about 22% higher in `dear`, far above the fees, but
+        // `dear` only holds 100,000 of token B in each of bins 199 and 200:
+        // the best trade sells nearly all the token A it can take.
+        let cheap = pool(10, 30, 1_000_000);
+        let dear = pool_at(200, 10, 1, 100_000);
+        let plan = close_arbitrage(&cheap, &dear, 10).unwrap().unwrap();
+        let sell_max_in = dear.max_input(true, 10).unwrap();
+        assert!(
+            dear.quote_exact_amount_in(sell_max_in + 1, true, 10)
+                .unwrap()
+                .is_exceed
+        );
+
+        assert!(!plan.sell.result.is_exceed);
+        assert_eq!(plan.sell.result.amount_in, plan.buy.result.amount_out);
+        assert!(plan.sell.result.amount_in <= sell_max_in);
+        assert!((199_000..=200_000).contains(&plan.sell.result.amount_out));
+        assert_eq!(plan.sell.result.amount_out - plan.amount_in, plan.profit);
+    }
+
+    #[test]
+    fn no_plan_without_a_profitable_gap() {
+        let base = pool(10, 30, 1_000_000);
+        assert!(close_arbitrage(&base, &base.clone(), 10).unwrap().is_none());
+        // One bin apart is 0.1%, less than the fees of both legs.
+        let near = pool_at(1, 10, 30, 1_000_000);
+        assert!(close_arbitrage(&base, &near, 10).unwrap().is_none());
+        assert!(close_arbitrage(&near, &base, 10).unwrap().is_none());
+    }
+}
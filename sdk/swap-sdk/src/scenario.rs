@@ -0,0 +1,241 @@
+//! Replaying a sequence of timestamped swaps against a [`Pool`], e.g. to
+//! backtest fee revenue with the volatility decay between trades applied.
+
+use alloc::{collections::BTreeMap, format, vec::Vec};
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pool::{Pool, PoolSummary, SwapResult},
+    timestamp::Timestamp,
+};
+
+/// One swap of a scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapInstruction {
+    /// Input amount if `by_amount_in`, otherwise the output amount.
+    #[cfg_attr(
+        feature = "serde-string-numbers",
+        serde(with = "crate::serde_utils::u64_string")
+    )]
+    pub amount: u64,
+    pub a2b: bool,
+    pub by_amount_in: bool,
+    pub timestamp: Timestamp,
+}
+
+/// Results of [`Pool::run_scenario`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioReport {
+    /// One result per instruction, in order.
+    pub results: Vec<SwapResult>,
+    /// Token A moved by all swaps: paid in by `a2b` swaps and paid out by
+    /// the others.
+    pub volume_a: u128,
+    /// Token B moved by all swaps.
+    pub volume_b: u128,
+    /// Fees charged in token A, i.e. on `a2b` swaps, including the protocol
+    /// share.
+    pub fee_a: u128,
+    /// Fees charged in token B.
+    pub fee_b: u128,
+    pub protocol_fee_a: u128,
+    pub protocol_fee_b: u128,
+    /// Number of swaps by the number of bins each crossed.
+    pub bins_crossed: BTreeMap<usize, usize>,
+    /// The pool after the last instruction.
+    pub final_pool: PoolSummary,
+}
+
+impl Pool {
+    /// Executes `instructions` in order, each at its own timestamp, so the
+    /// volatility of each swap decays from the previous one exactly as on
+    /// chain.
+    ///
+    /// Fails if the timestamps are not in non-decreasing order or if any
+    /// swap fails; the pool is only updated if every instruction succeeds.
+    pub fn run_scenario(
+        &mut self,
+        instructions: &[SwapInstruction],
+    ) -> Result<ScenarioReport, Error> {
+        if let Some(index) = instructions
+            .windows(2)
+            .position(|pair| pair[0].timestamp > pair[1].timestamp)
+        {
+            return Err(anyhow!(
+                "instruction {} at {} precedes instruction {} at {}",
+                index + 1,
+                instructions[index + 1].timestamp.as_seconds(),
+                index,
+                instructions[index].timestamp.as_seconds()
+            ));
+        }
+
+        let mut pool = self.clone();
+        let mut report = ScenarioReport {
+            results: Vec::with_capacity(instructions.len()),
+            volume_a: 0,
+            volume_b: 0,
+            fee_a: 0,
+            fee_b: 0,
+            protocol_fee_a: 0,
+            protocol_fee_b: 0,
+            bins_crossed: BTreeMap::new(),
+            final_pool: self.summary(),
+        };
+        for (index, instruction) in instructions.iter().enumerate() {
+            let SwapInstruction {
+                amount,
+                a2b,
+                by_amount_in,
+                timestamp,
+            } = *instruction;
+            let result = if by_amount_in {
+                pool.swap_exact_amount_in(amount, a2b, timestamp)
+            } else {
+                pool.swap_exact_amount_out(amount, a2b, timestamp)
+            }
+            .map_err(|err| err.context(format!("instruction {index}")))?;
+
+            let (amount_a, amount_b) = if a2b {
+                (result.amount_in, result.amount_out)
+            } else {
+                (result.amount_out, result.amount_in)
+            };
+            report.volume_a += amount_a as u128;
+            report.volume_b += amount_b as u128;
+            report.fee_a += result.fee_a as u128;
+            report.fee_b += result.fee_b as u128;
+            report.protocol_fee_a += result.protocol_fee_a as u128;
+            report.protocol_fee_b += result.protocol_fee_b as u128;
+            *report.bins_crossed.entry(result.steps.len()).or_default() += 1;
+            report.results.push(result);
+        }
+
+        report.final_pool = pool.summary();
+        *self = pool;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bin::Bin,
+        config::{BinStepConfig, VariableParameters},
+        math::price_math::get_price_from_id,
+    };
+
+    fn pool() -> Pool {
+        let bins = (-20..=20)
+            .map(|id| Bin {
+                id,
+                amount_a: if id >= 0 { 50_000 } else { 0 },
+                amount_b: if id <= 0 { 50_000 } else { 0 },
+                price: get_price_from_id(id, 25).unwrap().into(),
+                ..Default::default()
+            })
+            .collect();
+        let config = BinStepConfig::new(25, 1, 30, 600, 5_000, 40_000, 350_000, 100_000_000);
+        Pool::new(
+            0,
+            2_500_000,
+            VariableParameters::new(config, 0, 1_000),
+            bins,
+        )
+    }
+
+    fn instruction(amount: u64, a2b: bool, by_amount_in: bool, seconds: u64) -> SwapInstruction {
+        SwapInstruction {
+            amount,
+            a2b,
+            by_amount_in,
+            timestamp: Timestamp::from_seconds(seconds),
+        }
+    }
+
+    #[test]
+    fn scenario_matches_chained_swaps() {
+        let instructions = [
+            instruction(120_000, true, true, 1_000),
+            instruction(40_000, false, false, 1_010),
+            instruction(90_000, false, true, 1_050),
+            instruction(10_000, true, true, 1_050),
+            instruction(60_000, true, false, 2_000),
+        ];
+
+        let mut manual = pool();
+        let mut expected = Vec::new();
+        for i in &instructions {
+            expected.push(if i.by_amount_in {
+                manual
+                    .swap_exact_amount_in(i.amount, i.a2b, i.timestamp)
+                    .unwrap()
+            } else {
+                manual
+                    .swap_exact_amount_out(i.amount, i.a2b, i.timestamp)
+                    .unwrap()
+            });
+        }
+
+        let mut pool = pool();
+        let report = pool.run_scenario(&instructions).unwrap();
+        assert_eq!(report.results, expected);
+        assert_eq!(pool, manual);
+        assert_eq!(report.final_pool, manual.summary());
+
+        let total_fee: u128 = expected.iter().map(|r| r.fee as u128).sum();
+        assert_eq!(report.fee_a + report.fee_b, total_fee);
+        let fee_a: u128 = expected
+            .iter()
+            .zip(&instructions)
+            .filter(|(_, i)| i.a2b)
+            .map(|(r, _)| r.fee as u128)
+            .sum();
+        assert_eq!(report.fee_a, fee_a);
+        assert!(report.protocol_fee_a > 0 && report.protocol_fee_b > 0);
+        assert_eq!(
+            report.bins_crossed.values().sum::<usize>(),
+            instructions.len()
+        );
+        assert_eq!(
+            report.bins_crossed.keys().max(),
+            expected.iter().map(|r| r.steps.len()).max().as_ref()
+        );
+    }
+
+    #[test]
+    fn out_of_order_or_failing_scenarios_leave_the_pool_untouched() {
+        let mut pool = pool();
+        let original = pool.clone();
+
+        let err = pool
+            .run_scenario(&[
+                instruction(1_000, true, true, 1_100),
+                instruction(1_000, true, true, 1_050),
+            ])
+            .unwrap_err();
+        assert!(err.to_string().contains("precedes"), "{err}");
+        assert_eq!(pool, original);
+
+        // The second swap would overflow the active bin's token A reserve.
+        let mut pool = original.clone();
+        pool.bins[20].amount_a = u64::MAX - 10;
+        let before = pool.clone();
+        let err = pool
+            .run_scenario(&[
+                instruction(1_000, false, true, 1_100),
+                instruction(1_000, true, true, 1_200),
+            ])
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("instruction 1"), "{err:#}");
+        assert_eq!(pool, before);
+        let mut pool = original.clone();
+
+        let report = pool.run_scenario(&[]).unwrap();
+        assert!(report.results.is_empty());
+        assert_eq!(report.final_pool, original.summary());
+    }
+}
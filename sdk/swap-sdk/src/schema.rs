@@ -0,0 +1,109 @@
+//! JSON Schema for the public data types.
+//!
+//! The schemas describe the JSON produced by this crate's `Serialize`
+//! impls. With the `serde-string-numbers` feature, `u64`/`u128` fields are
+//! described as decimal strings instead of integers.
+
+use schemars::{json_schema, schema_for, JsonSchema, Schema, SchemaGenerator};
+use serde_json::{Map, Value};
+
+use crate::{
+    bin::Bin,
+    config::{BinStepConfig, VariableParameters},
+    pool::{BinSwap, BinSwapU128, Pool, SwapResult, SwapResultU128},
+};
+
+/// Schema of an unsigned integer encoded as a decimal string.
+pub fn decimal_string(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "string",
+        "pattern": "^[0-9]+$"
+    })
+}
+
+/// Schema of an array of unsigned integers encoded as decimal strings.
+pub fn decimal_string_array(generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": "array",
+        "items": decimal_string(generator)
+    })
+}
+
+fn export<T: JsonSchema>(schemas: &mut Map<String, Value>) {
+    schemas.insert(T::schema_name().into_owned(), schema_for!(T).to_value());
+}
+
+/// Returns a self-contained schema for each public data type, keyed by type
+/// name.
+pub fn export_all() -> Value {
+    let mut schemas = Map::new();
+    export::<Pool>(&mut schemas);
+    export::<Bin>(&mut schemas);
+    export::<BinStepConfig>(&mut schemas);
+    export::<VariableParameters>(&mut schemas);
+    export::<SwapResult>(&mut schemas);
+    export::<BinSwap>(&mut schemas);
+    export::<SwapResultU128>(&mut schemas);
+    export::<BinSwapU128>(&mut schemas);
+    Value::Object(schemas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY_POOL: &str = include_str!("../tests/fixtures/pool_legacy_numbers.json");
+
+    /// Goes through a string because `Value` cannot hold integers above
+    /// `u64::MAX` without `arbitrary_precision`.
+    fn to_json_value<T: serde::Serialize>(value: &T) -> Value {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    fn assert_valid(schema: &Value, instance: &Value) {
+        let validator = jsonschema::validator_for(schema).unwrap();
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .map(|e| e.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{errors:#?}");
+    }
+
+    #[test]
+    fn swap_result_matches_its_schema() {
+        let mut pool: Pool = serde_json::from_str(LEGACY_POOL).unwrap();
+        let result = pool.swap_exact_amount_in(5_000, true, 10).unwrap();
+        assert!(!result.steps.is_empty());
+
+        let schemas = export_all();
+        assert_valid(&schemas["SwapResult"], &to_json_value(&result));
+        assert_valid(&schemas["Pool"], &to_json_value(&pool));
+
+        let mut bad = to_json_value(&result);
+        bad["is_exceed"] = Value::from("no");
+        assert!(!jsonschema::is_valid(&schemas["SwapResult"], &bad));
+    }
+
+    #[test]
+    fn exports_every_public_type() {
+        let schemas = export_all();
+        for name in [
+            "Pool",
+            "Bin",
+            "BinStepConfig",
+            "VariableParameters",
+            "SwapResult",
+            "BinSwap",
+            "SwapResultU128",
+            "BinSwapU128",
+        ] {
+            assert!(schemas[name].is_object(), "missing schema for {name}");
+        }
+        let price = &schemas["Bin"]["properties"]["price"];
+        if cfg!(feature = "serde-string-numbers") {
+            assert_eq!(price["type"], "string");
+        } else {
+            assert_eq!(price["type"], "integer");
+        }
+    }
+}
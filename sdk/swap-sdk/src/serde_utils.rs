@@ -0,0 +1,319 @@
+//! Serde adapters that encode `u64`/`u128` fields as decimal strings.
+//!
+//! JSON consumers such as JavaScript parse every number as an `f64`, so amounts
+//! above 2^53 and Q64.64 prices silently lose precision. With the
+//! `serde-string-numbers` feature the public data types serialize these fields
+//! as strings in human-readable formats, and accept either a string or a number
+//! when deserializing so snapshots written by older versions still load.
+//!
+//! Non human-readable formats keep the plain integer encoding.
+//!
+//! `serde_json` hands integers above `u64::MAX` to `deserialize_any` as
+//! floats, already rounded, so `u128` fields are read from the raw JSON token
+//! instead. Other human-readable formats are therefore not supported for them.
+
+use alloc::{format, vec::Vec};
+use core::{fmt, marker::PhantomData, str::FromStr};
+
+use serde::{
+    de::{self, Visitor},
+    Deserializer, Serializer,
+};
+
+struct StringOrNumber<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for StringOrNumber<T>
+where
+    T: TryFrom<u128> + FromStr,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an unsigned integer or a decimal string")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        self.visit_u128(v as u128)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<T, E> {
+        T::try_from(v).map_err(|_| E::custom(format!("integer {v} out of range")))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        let v = u128::try_from(v).map_err(|_| E::custom(format!("integer {v} is negative")))?;
+        self.visit_u128(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        T::from_str(v).map_err(|_| E::custom(format!("invalid integer string {v:?}")))
+    }
+
+    /// Integers too large for the format's integer types arrive rounded, so
+    /// they are rejected rather than loaded wrong.
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+        Err(E::custom(format!("number {v} is not an exact integer")))
+    }
+}
+
+fn serialize_display<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: fmt::Display,
+{
+    serializer.collect_str(value)
+}
+
+/// `#[serde(with = "...")]` adapter for `u64` fields.
+pub mod u64_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serialize_display(value, serializer)
+        } else {
+            serializer.serialize_u64(*value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(StringOrNumber(PhantomData))
+        } else {
+            deserializer.deserialize_u64(StringOrNumber(PhantomData))
+        }
+    }
+}
+
+/// `#[serde(with = "...")]` adapter for `u128` fields.
+pub mod u128_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serialize_display(value, serializer)
+        } else {
+            serializer.serialize_u128(*value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        if deserializer.is_human_readable() {
+            json_u128(deserializer)
+        } else {
+            deserializer.deserialize_u128(StringOrNumber(PhantomData))
+        }
+    }
+}
+
+/// A `u128` JSON number or decimal string, read from the raw token so that
+/// numbers above `u64::MAX` keep every digit.
+#[cfg(feature = "serde-string-numbers")]
+fn json_u128<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    use alloc::{boxed::Box, string::String};
+
+    use serde::Deserialize;
+    use serde_json::value::RawValue;
+
+    let raw = Box::<RawValue>::deserialize(deserializer)?;
+    let visitor = StringOrNumber::<u128>(PhantomData);
+    if raw.get().starts_with('"') {
+        let digits: String = serde_json::from_str(raw.get()).map_err(de::Error::custom)?;
+        visitor.visit_str(&digits)
+    } else {
+        visitor.visit_str(raw.get())
+    }
+}
+
+#[cfg(not(feature = "serde-string-numbers"))]
+fn json_u128<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    deserializer.deserialize_any(StringOrNumber(PhantomData))
+}
+
+/// `#[serde(with = "...")]` adapter for `Option<u64>` fields.
+pub mod option_u64_string {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    struct Item(#[serde(with = "super::u64_string")] u64);
+
+    pub fn serialize<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(Item).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        Ok(Option::<Item>::deserialize(deserializer)?.map(|item| item.0))
+    }
+}
+
+/// `#[serde(with = "...")]` adapter for `Vec<u128>` fields.
+pub mod vec_u128_string {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    struct Item(#[serde(with = "super::u128_string")] u128);
+
+    pub fn serialize<S: Serializer>(values: &[u128], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(|v| Item(*v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u128>, D::Error> {
+        let items = Vec::<Item>::deserialize(deserializer)?;
+        Ok(items.into_iter().map(|item| item.0).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Bin, BinStepConfig, Pool, PriceQ64, VariableParameters};
+
+    const LEGACY_POOL: &str = include_str!("../tests/fixtures/pool_legacy_numbers.json");
+
+    fn sample_pool() -> Pool {
+        let bins = vec![
+            Bin {
+                id: -1,
+                amount_a: u64::MAX,
+                amount_b: (1 << 53) + 1,
+                price: (u128::MAX - 1).into(),
+                liquidity_supply: u128::MAX,
+                rewards_growth_global: vec![u128::MAX, 1],
+                fee_amount_a_growth_global: u128::MAX - 7,
+                fee_amount_b_growth_global: 3,
+            },
+            Bin {
+                id: 0,
+                amount_a: 1,
+                amount_b: 2,
+                price: PriceQ64::ONE,
+                ..Default::default()
+            },
+        ];
+        let config = BinStepConfig::new(25, 1, 60, 600, 9000, 0, 1_000_000, 30_000);
+        Pool::new(
+            0,
+            30_000,
+            VariableParameters::new(config, 0, 1_700_000_000),
+            bins,
+        )
+    }
+
+    #[test]
+    fn legacy_numeric_fixture_loads() {
+        let pool: Pool = serde_json::from_str(LEGACY_POOL).unwrap();
+        assert_eq!(pool.active_id, 1);
+        assert_eq!(pool.bins.len(), 3);
+        assert_eq!(pool.bins[0].price.as_raw(), 18_400_000_000_000_000_001);
+        assert_eq!(pool.bins[1].price.as_raw(), 18_446_744_073_709_551_616);
+        assert_eq!(pool.bins[2].price.as_raw(), 18_492_860_933_893_825_496);
+        assert_eq!(pool.bins[2].amount_b, 9_007_199_254_740_993);
+        assert_eq!(
+            pool.bins[2].rewards_growth_global,
+            vec![340_282_366_920_938_463_463_374_607_431_768_211_455]
+        );
+    }
+
+    #[cfg(feature = "serde-string-numbers")]
+    #[test]
+    fn numbers_serialize_as_strings() {
+        let pool = sample_pool();
+        let value = serde_json::to_value(&pool).unwrap();
+        assert_eq!(
+            value["bins"][0]["price"],
+            serde_json::json!((u128::MAX - 1).to_string())
+        );
+        assert_eq!(
+            value["bins"][0]["amount_a"],
+            serde_json::json!(u64::MAX.to_string())
+        );
+        assert_eq!(value["base_fee_rate"], serde_json::json!("30000"));
+        assert_eq!(value["active_id"], serde_json::json!(0));
+
+        let json = serde_json::to_string(&pool).unwrap();
+        let back: Pool = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert_eq!(back.bins[0].price.as_raw(), u128::MAX - 1);
+        assert_eq!(back.bins[0].rewards_growth_global, vec![u128::MAX, 1]);
+    }
+
+    #[cfg(feature = "serde-string-numbers")]
+    #[test]
+    fn swap_result_round_trips() {
+        use crate::{BinSwap, SwapResult};
+
+        let result = SwapResult {
+            amount_in: u64::MAX,
+            amount_out: (1 << 53) + 1,
+            fee: 7,
+            steps: vec![BinSwap {
+                bin_id: -3,
+                amount_in: u64::MAX,
+                amount_out: 1,
+                fee: 2,
+                var_fee_rate: 3,
+                protocol_fee: 1,
+                price: u128::MAX,
+                execution_price: 1 << 64,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.contains("\"amount_out\":\"9007199254740993\""));
+        let back: SwapResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.amount_out, result.amount_out);
+        assert_eq!(back.steps[0].amount_in, u64::MAX);
+        assert_eq!(back.steps[0].price, u128::MAX);
+    }
+
+    #[cfg(feature = "serde-string-numbers")]
+    #[test]
+    fn rejects_malformed_strings() {
+        let json = serde_json::to_string(&sample_pool())
+            .unwrap()
+            .replace("\"30000\"", "\"30_000\"");
+        assert!(serde_json::from_str::<Pool>(&json).is_err());
+        let json = serde_json::to_string(&sample_pool())
+            .unwrap()
+            .replace("\"30000\"", "30000.5");
+        assert!(serde_json::from_str::<Pool>(&json).is_err());
+    }
+
+    #[cfg(feature = "serde-string-numbers")]
+    #[test]
+    fn large_numbers_load_exactly() {
+        // serde_json's own numbers are untouched: without
+        // `arbitrary_precision` this one is a rounded float.
+        let big = (u128::MAX - 1).to_string();
+        assert!(serde_json::from_str::<serde_json::Value>(&big)
+            .unwrap()
+            .is_f64());
+
+        let json = serde_json::to_string(&sample_pool()).unwrap();
+        let numeric = json.replace(&format!("\"{big}\""), &big);
+        assert_ne!(numeric, json);
+        let pool: Pool = serde_json::from_str(&numeric).unwrap();
+        assert_eq!(pool.bins[0].price.as_raw(), u128::MAX - 1);
+        let value = serde_json::to_value(sample_pool()).unwrap();
+        assert_eq!(
+            serde_json::from_value::<Pool>(value).unwrap(),
+            sample_pool()
+        );
+    }
+
+    #[cfg(not(feature = "serde-string-numbers"))]
+    #[test]
+    fn numbers_serialize_as_numbers() {
+        let json = serde_json::to_string(&sample_pool()).unwrap();
+        assert!(json.contains("\"base_fee_rate\":30000"));
+        assert!(json.contains(&format!("\"price\":{}", u128::MAX - 1)));
+    }
+}
@@ -0,0 +1,141 @@
+//! A deterministic fingerprint of the pool state that quotes depend on, e.g.
+//! to key a quote cache.
+//!
+//! [`Pool::state_hash`] is SHA-256 over a fixed little-endian encoding of:
+//!
+//! 1. the tag `cetus-dlmm-pool-state-v1`
+//! 2. `active_id: i32`, `base_fee_rate: u64`
+//! 3. `v_parameters`: `volatility_accumulator: u32`,
+//!    `volatility_reference: u32`, `index_reference: i32`,
+//!    `last_update_timestamp: u64`, then the `bin_step_config` fields in
+//!    declaration order
+//! 4. the bin count as `u64`, then `(id: i32, amount_a: u64, amount_b: u64,
+//!    price: u128, liquidity_supply: u128)` for each bin in `bins` order,
+//!    which is ascending id for a valid pool
+//!
+//! The fee and reward growth fields, the local swap `stats`,
+//! `schema_version` and `metadata` are left out: they do not affect quotes,
+//! so pools that differ only there hash the same. The encoding is part of
+//! the public contract; changing it means a new tag.
+
+use sha2::{Digest, Sha256};
+
+use crate::pool::Pool;
+
+const TAG: &[u8] = b"cetus-dlmm-pool-state-v1";
+
+impl Pool {
+    /// SHA-256 of the quote-relevant state; see the [module docs](self).
+    ///
+    /// Computed in one pass over the bins with a fixed-size buffer.
+    pub fn state_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(TAG);
+        hasher.update(self.active_id.to_le_bytes());
+        hasher.update(self.base_fee_rate.to_le_bytes());
+
+        let v = &self.v_parameters;
+        hasher.update(v.volatility_accumulator.to_le_bytes());
+        hasher.update(v.volatility_reference.to_le_bytes());
+        hasher.update(v.index_reference.to_le_bytes());
+        hasher.update(v.last_update_timestamp.to_le_bytes());
+        let config = &v.bin_step_config;
+        hasher.update(config.bin_step.to_le_bytes());
+        hasher.update(config.base_factor.to_le_bytes());
+        hasher.update(config.filter_period.to_le_bytes());
+        hasher.update(config.decay_period.to_le_bytes());
+        hasher.update(config.reduction_factor.to_le_bytes());
+        hasher.update(config.variable_fee_control.to_le_bytes());
+        hasher.update(config.max_volatility_accumulator.to_le_bytes());
+        hasher.update(config.protocol_fee_rate.to_le_bytes());
+
+        hasher.update((self.bins.len() as u64).to_le_bytes());
+        for bin in &self.bins {
+            hasher.update(bin.id.to_le_bytes());
+            hasher.update(bin.amount_a.to_le_bytes());
+            hasher.update(bin.amount_b.to_le_bytes());
+            hasher.update(bin.price.as_raw().to_le_bytes());
+            hasher.update(bin.liquidity_supply.to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bin::Bin,
+        config::{BinStepConfig, VariableParameters},
+    };
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn pool() -> Pool {
+        let config = BinStepConfig::new(25, 1, 30, 600, 5_000, 40_000, 350_000, 100_000_000);
+        let mut v_parameters = VariableParameters::new(config, -3, 1_700_000_000);
+        v_parameters.volatility_accumulator = 12_345;
+        v_parameters.volatility_reference = 678;
+        let bins = (-2..=2)
+            .map(|id| Bin {
+                id,
+                amount_a: if id >= 0 { 1_000_000 + id as u64 } else { 0 },
+                amount_b: if id <= 0 { (2_000_000 - id) as u64 } else { 0 },
+                price: (((1i128 << 64) + id as i128 * 1_000) as u128).into(),
+                liquidity_supply: (3_000_000_000 + id as i64) as u128,
+                rewards_growth_global: vec![7; 2],
+                fee_amount_a_growth_global: 11,
+                fee_amount_b_growth_global: 13,
+            })
+            .collect();
+        Pool::new(-1, 2_500_000, v_parameters, bins)
+    }
+
+    #[test]
+    fn state_hash_is_pinned() {
+        // Computed independently with Python's hashlib over the encoding in
+        // the module docs.
+        assert_eq!(
+            hex(&pool().state_hash()),
+            "6a5e02e285b3830616a6d015ceedab94f02d04c09611a74e38eb943b45dc1b26"
+        );
+    }
+
+    #[test]
+    fn state_hash_tracks_included_fields_only() {
+        let base = pool();
+        let hash = base.state_hash();
+
+        let mutations: [fn(&mut Pool); 13] = [
+            |p| p.active_id += 1,
+            |p| p.base_fee_rate += 1,
+            |p| p.v_parameters.volatility_accumulator += 1,
+            |p| p.v_parameters.volatility_reference += 1,
+            |p| p.v_parameters.index_reference += 1,
+            |p| p.v_parameters.last_update_timestamp += 1,
+            |p| p.v_parameters.bin_step_config.protocol_fee_rate += 1,
+            |p| p.bins[1].id -= 10,
+            |p| p.bins[2].amount_a += 1,
+            |p| p.bins[3].amount_b += 1,
+            |p| p.bins[4].price = (p.bins[4].price.as_raw() + 1).into(),
+            |p| p.bins[0].liquidity_supply += 1,
+            |p| {
+                p.bins.pop();
+            },
+        ];
+        for (i, mutate) in mutations.iter().enumerate() {
+            let mut pool = base.clone();
+            mutate(&mut pool);
+            assert_ne!(pool.state_hash(), hash, "mutation {i}");
+        }
+        let mut pool = base.clone();
+        for bin in &mut pool.bins {
+            bin.fee_amount_a_growth_global += 1;
+            bin.fee_amount_b_growth_global += 1;
+            bin.rewards_growth_global.push(1);
+        }
+        assert_eq!(pool.state_hash(), hash);
+    }
+}
@@ -0,0 +1,241 @@
+//! A venue-independent swap interface, so routing code can work with pools
+//! from other SDKs next to [`Pool`].
+//!
+//! The [`router`](crate::router) helpers are written against [`Swappable`].
+//! A pool from another venue joins a route by implementing it, and pools of
+//! different types can be mixed as `dyn Swappable<Quote = SwapResult>`.
+
+use crate::{
+    error::DlmmError,
+    math::{price_math::get_price_from_id, q64x64_math::ONE},
+    pool::{Pool, SwapResult},
+    timestamp::Timestamp,
+};
+
+/// Quoting and executing swaps on a single pool.
+///
+/// The methods take a [`Timestamp`] rather than `impl Into<Timestamp>` so
+/// that the trait stays object safe.
+pub trait Swappable {
+    type Quote;
+
+    /// Quotes a swap of `amount_in` without changing the pool.
+    fn quote_exact_in(
+        &self,
+        amount_in: u64,
+        a2b: bool,
+        timestamp: Timestamp,
+    ) -> Result<Self::Quote, DlmmError>;
+
+    /// Quotes a swap for `amount_out` without changing the pool.
+    fn quote_exact_out(
+        &self,
+        amount_out: u64,
+        a2b: bool,
+        timestamp: Timestamp,
+    ) -> Result<Self::Quote, DlmmError>;
+
+    /// Swaps `amount_in`, updating the pool.
+    fn execute_exact_in(
+        &mut self,
+        amount_in: u64,
+        a2b: bool,
+        timestamp: Timestamp,
+    ) -> Result<Self::Quote, DlmmError>;
+
+    /// Output per unit of input at the current marginal price, before fees.
+    /// Routers compare it with the quoted output to report price impact.
+    fn spot_rate(&self, a2b: bool) -> Result<f64, DlmmError>;
+}
+
+impl Swappable for Pool {
+    type Quote = SwapResult;
+
+    fn quote_exact_in(
+        &self,
+        amount_in: u64,
+        a2b: bool,
+        timestamp: Timestamp,
+    ) -> Result<SwapResult, DlmmError> {
+        self.quote_exact_amount_in(amount_in, a2b, timestamp)
+            .map_err(DlmmError::from_anyhow)
+    }
+
+    fn quote_exact_out(
+        &self,
+        amount_out: u64,
+        a2b: bool,
+        timestamp: Timestamp,
+    ) -> Result<SwapResult, DlmmError> {
+        self.quote_exact_amount_out(amount_out, a2b, timestamp)
+            .map_err(DlmmError::from_anyhow)
+    }
+
+    fn execute_exact_in(
+        &mut self,
+        amount_in: u64,
+        a2b: bool,
+        timestamp: Timestamp,
+    ) -> Result<SwapResult, DlmmError> {
+        self.swap_exact_amount_in(amount_in, a2b, timestamp)
+            .map_err(DlmmError::from_anyhow)
+    }
+
+    /// The active bin's price.
+    fn spot_rate(&self, a2b: bool) -> Result<f64, DlmmError> {
+        let price = get_price_from_id(self.active_id, self.v_parameters.bin_step_config.bin_step)?;
+        let price = price as f64 / ONE as f64;
+        Ok(if a2b { price } else { 1.0 / price })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bin::Bin,
+        config::{BinStepConfig, VariableParameters},
+        router::{optimal_split_exact_in, quote_route_exact_in},
+    };
+
+    /// A venue that fills any amount at a fixed rate, with no fee.
+    struct FixedRate {
+        rate: u64,
+    }
+
+    impl Swappable for FixedRate {
+        type Quote = SwapResult;
+
+        fn quote_exact_in(
+            &self,
+            amount_in: u64,
+            _: bool,
+            _: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            let amount_out = amount_in
+                .checked_mul(self.rate)
+                .ok_or(DlmmError::MathOverflow)?;
+            Ok(SwapResult {
+                amount_in,
+                amount_out,
+                ..Default::default()
+            })
+        }
+
+        fn quote_exact_out(
+            &self,
+            amount_out: u64,
+            _: bool,
+            _: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            Ok(SwapResult {
+                amount_in: amount_out.div_ceil(self.rate),
+                amount_out,
+                ..Default::default()
+            })
+        }
+
+        fn execute_exact_in(
+            &mut self,
+            amount_in: u64,
+            a2b: bool,
+            timestamp: Timestamp,
+        ) -> Result<SwapResult, DlmmError> {
+            self.quote_exact_in(amount_in, a2b, timestamp)
+        }
+
+        fn spot_rate(&self, _: bool) -> Result<f64, DlmmError> {
+            Ok(self.rate as f64)
+        }
+    }
+
+    fn pool() -> Pool {
+        let config = BinStepConfig::new(10, 1, 60, 600, 9000, 0, 1_000_000, 30_000);
+        let bins = (-5..=5)
+            .map(|id| Bin {
+                id,
+                amount_a: if id >= 0 { 1_000_000 } else { 0 },
+                amount_b: if id <= 0 { 1_000_000 } else { 0 },
+                price: get_price_from_id(id, 10).unwrap().into(),
+                ..Default::default()
+            })
+            .collect();
+        Pool::new(0, 2_500_000, VariableParameters::new(config, 0, 0), bins)
+    }
+
+    #[test]
+    fn pool_implements_the_trait_with_its_own_methods() {
+        let pool = pool();
+        let ts = Timestamp::from_seconds(10);
+        assert_eq!(
+            Swappable::quote_exact_in(&pool, 500_000, true, ts).unwrap(),
+            pool.quote_exact_amount_in(500_000, true, ts).unwrap()
+        );
+        assert_eq!(
+            Swappable::quote_exact_out(&pool, 500_000, false, ts).unwrap(),
+            pool.quote_exact_amount_out(500_000, false, ts).unwrap()
+        );
+        let mut executed = pool.clone();
+        let result = executed.execute_exact_in(500_000, true, ts).unwrap();
+        assert_eq!(
+            result,
+            pool.clone()
+                .swap_exact_amount_in(500_000, true, ts)
+                .unwrap()
+        );
+        assert_ne!(executed, pool);
+        assert_eq!(Swappable::spot_rate(&pool, true).unwrap(), 1.0);
+
+        // Errors raised by the swap path keep their variant.
+        let mut out_of_range = pool.clone();
+        out_of_range.active_id = crate::MAX_BIN_ID + 1;
+        assert!(matches!(
+            out_of_range.spot_rate(true),
+            Err(DlmmError::InvalidBinId)
+        ));
+        // Others are wrapped with their message, inside the failed step.
+        let mut overflowing = pool.clone();
+        overflowing.bins[6].amount_b = u64::MAX - 10;
+        let err = Swappable::quote_exact_in(&overflowing, 1_000, false, ts).unwrap_err();
+        let DlmmError::StepFailed { source, .. } = &err else {
+            panic!("{err}");
+        };
+        assert!(
+            matches!(&**source, DlmmError::SwapFailed(reason) if reason.contains("overflow")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn routers_accept_mixed_venues() {
+        let mut dlmm = pool();
+        let mut fixed = FixedRate { rate: 2 };
+        let quote = quote_route_exact_in(
+            &mut [
+                (&mut dlmm as &mut dyn Swappable<Quote = SwapResult>, true),
+                (&mut fixed, true),
+            ],
+            100_000,
+            10,
+        )
+        .unwrap();
+        assert_eq!(quote.amount_out, 2 * quote.hops[0].result.amount_out);
+        assert!(quote.price_impact > 0.0);
+
+        let dlmm = pool();
+        let split = optimal_split_exact_in(
+            &[
+                &dlmm as &dyn Swappable<Quote = SwapResult>,
+                &FixedRate { rate: 1 },
+            ],
+            3_000_000,
+            true,
+            10,
+        )
+        .unwrap();
+        // The fixed venue pays 1 with no fee, so it takes everything the
+        // pool cannot beat.
+        assert_eq!(split.allocations.iter().sum::<u64>(), 3_000_000);
+        assert!(split.allocations[1] > split.allocations[0]);
+    }
+}
@@ -0,0 +1,177 @@
+//! Unit-safe timestamps for the swap and quote entry points.
+//!
+//! `BinStepConfig::filter_period` and `decay_period` are in seconds, while
+//! the Sui clock reports milliseconds. [`Timestamp`] is always stored in
+//! seconds, and its constructors say which unit the caller has.
+//!
+//! Components that quote repeatedly can hold a [`Clock`] instead and call
+//! the `*_with_clock` methods, such as
+//! [`Pool::swap_exact_amount_in_with_clock`](crate::Pool::swap_exact_amount_in_with_clock).
+//! [`ManualClock`] lets tests and simulations move time forward.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// A point in time, in whole seconds since the Unix epoch.
+///
+/// Swap and quote methods take `impl Into<Timestamp>`. A plain `u64` is
+/// still accepted and read as seconds, which is deprecated: it is what made
+/// millisecond clocks silently disable fee decay. Use
+/// [`Timestamp::from_seconds`] or [`Timestamp::from_millis`] instead.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_seconds(seconds: u64) -> Self {
+        Self(seconds)
+    }
+
+    /// Converts a millisecond clock reading, such as Sui's
+    /// `clock::timestamp_ms`, truncating to whole seconds.
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(millis / 1_000)
+    }
+
+    /// The current system time, or the epoch if the clock is before it.
+    #[cfg(feature = "std")]
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    pub const fn as_seconds(self) -> u64 {
+        self.0
+    }
+}
+
+/// Reads `seconds` as seconds, matching the old `u64` parameters.
+impl From<u64> for Timestamp {
+    fn from(seconds: u64) -> Self {
+        Self::from_seconds(seconds)
+    }
+}
+
+/// A duration since the Unix epoch, truncated to whole seconds.
+impl From<Duration> for Timestamp {
+    fn from(since_epoch: Duration) -> Self {
+        Self::from_seconds(since_epoch.as_secs())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        time.duration_since(UNIX_EPOCH).unwrap_or_default().into()
+    }
+}
+
+/// A source of the current time, in seconds since the Unix epoch.
+pub trait Clock {
+    fn now_seconds(&self) -> u64;
+
+    fn now(&self) -> Timestamp {
+        Timestamp::from_seconds(self.now_seconds())
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_seconds(&self) -> u64 {
+        (**self).now_seconds()
+    }
+}
+
+/// The system clock, see [`Timestamp::now`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_seconds(&self) -> u64 {
+        Timestamp::now().as_seconds()
+    }
+}
+
+/// A clock that only moves when set or advanced, for tests and
+/// simulations. It is shared by reference, so whatever reads it sees the
+/// changes.
+#[derive(Debug, Default)]
+pub struct ManualClock(AtomicU64);
+
+impl ManualClock {
+    pub const fn new(seconds: u64) -> Self {
+        Self(AtomicU64::new(seconds))
+    }
+
+    pub fn set(&self, seconds: u64) {
+        self.0.store(seconds, Ordering::Relaxed);
+    }
+
+    /// Moves the clock forward by `seconds`, saturating at `u64::MAX`.
+    pub fn advance(&self, seconds: u64) {
+        // The closure always returns `Some`, so this cannot fail.
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |now| {
+                Some(now.saturating_add(seconds))
+            });
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_seconds(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constructors_agree_on_the_same_instant() {
+        let seconds = Timestamp::from_seconds(1_700_000_030);
+        assert_eq!(Timestamp::from_millis(1_700_000_030_000), seconds);
+        assert_eq!(Timestamp::from_millis(1_700_000_030_999), seconds);
+        assert_eq!(
+            Timestamp::from(Duration::from_millis(1_700_000_030_500)),
+            seconds
+        );
+        assert_eq!(
+            Timestamp::from(UNIX_EPOCH + Duration::from_secs(1_700_000_030)),
+            seconds
+        );
+        assert_eq!(Timestamp::from(1_700_000_030u64), seconds);
+        assert_eq!(seconds.as_seconds(), 1_700_000_030);
+        assert_eq!(
+            Timestamp::from(UNIX_EPOCH - Duration::from_secs(1)),
+            Timestamp::default()
+        );
+    }
+
+    #[test]
+    fn manual_clocks_move_when_told() {
+        let clock = ManualClock::new(1_700_000_000);
+        let reader: &dyn Clock = &clock;
+        assert_eq!(reader.now(), Timestamp::from_seconds(1_700_000_000));
+        clock.advance(30);
+        assert_eq!(reader.now_seconds(), 1_700_000_030);
+        clock.set(5);
+        // Borrowed clocks are clocks too.
+        fn read(clock: impl Clock) -> u64 {
+            clock.now_seconds()
+        }
+        assert_eq!(read(&clock), 5);
+        clock.advance(u64::MAX);
+        assert_eq!(clock.now_seconds(), u64::MAX);
+        assert!(SystemClock.now_seconds() > 1_700_000_000);
+    }
+}
@@ -0,0 +1,114 @@
+//! Consistency checks between the exact-in and exact-out swap paths.
+//!
+//! Both paths round against the trader: exact-out rounds the required input
+//! up and exact-in rounds the output down. Chaining them therefore never
+//! produces a better deal than the quote it started from, which
+//! [`verify_round_trip`] checks for a given pool and amount.
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pool::{Pool, SwapResult},
+    timestamp::Timestamp,
+};
+
+/// Both round trips of [`verify_round_trip`] and how far each one moved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundTripReport {
+    /// Exact-out quote for the requested amount.
+    pub exact_out: SwapResult,
+    /// Exact-in quote for `exact_out.amount_in`.
+    pub exact_in_of_quoted_input: SwapResult,
+    /// Output gained by the exact-in leg over `exact_out.amount_out`.
+    /// Negative values break the rounding invariant.
+    pub output_surplus: i128,
+    /// Exact-in quote for the requested amount.
+    pub exact_in: SwapResult,
+    /// Exact-out quote for `exact_in.amount_out`.
+    pub exact_out_of_quoted_output: SwapResult,
+    /// Input the exact-out leg needs less than `exact_in.amount_in`.
+    /// Negative values break the rounding invariant.
+    pub input_saving: i128,
+}
+
+impl RoundTripReport {
+    /// Whether both round trips rounded in the trader's disfavor.
+    pub fn holds(&self) -> bool {
+        self.output_surplus >= 0 && self.input_saving >= 0
+    }
+}
+
+/// Quotes `amount` both ways on `pool` and feeds each result into the
+/// opposite path:
+///
+/// - exact-out for `amount`, then exact-in with the input it required, must
+///   return at least the same output;
+/// - exact-in for `amount`, then exact-out for the output it returned, must
+///   require at most the same input.
+///
+/// The pool is only quoted, never modified.
+pub fn verify_round_trip(
+    pool: &Pool,
+    amount: u64,
+    a2b: bool,
+    timestamp: impl Into<Timestamp>,
+) -> Result<RoundTripReport, Error> {
+    let timestamp = timestamp.into();
+    let exact_out = pool.quote_exact_amount_out(amount, a2b, timestamp)?;
+    let exact_in_of_quoted_input =
+        pool.quote_exact_amount_in(exact_out.amount_in, a2b, timestamp)?;
+    let output_surplus = exact_in_of_quoted_input.amount_out as i128 - exact_out.amount_out as i128;
+
+    let exact_in = pool.quote_exact_amount_in(amount, a2b, timestamp)?;
+    let exact_out_of_quoted_output =
+        pool.quote_exact_amount_out(exact_in.amount_out, a2b, timestamp)?;
+    let input_saving = exact_in.amount_in as i128 - exact_out_of_quoted_output.amount_in as i128;
+
+    Ok(RoundTripReport {
+        exact_out,
+        exact_in_of_quoted_input,
+        output_surplus,
+        exact_in,
+        exact_out_of_quoted_output,
+        input_saving,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::arbitrary::{small_pool, sparse_pool, MAX_BIN_RESERVE};
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(512))]
+
+        #[test]
+        fn round_trips_round_against_the_trader(
+            pool in prop_oneof![4 => small_pool(), 1 => sparse_pool()],
+            amount in prop_oneof![1u64..=1_000, 1..=MAX_BIN_RESERVE],
+            a2b in any::<bool>(),
+            timestamp in 0u64..=1 << 41,
+        ) {
+            let report = verify_round_trip(&pool, amount, a2b, timestamp).unwrap();
+            prop_assert!(report.holds(), "{report:#?}");
+        }
+    }
+
+    #[test]
+    fn report_for_a_single_bin() {
+        let pool: Pool =
+            serde_json::from_str(include_str!("../tests/fixtures/pool_legacy_numbers.json"))
+                .unwrap();
+        let report = verify_round_trip(&pool, 1_000, true, 10).unwrap();
+        assert!(report.holds());
+        assert_eq!(report.exact_out.amount_out, 1_000);
+        assert_eq!(report.exact_in.amount_in, 1_000);
+        assert_eq!(
+            report.output_surplus,
+            report.exact_in_of_quoted_input.amount_out as i128 - 1_000
+        );
+    }
+}
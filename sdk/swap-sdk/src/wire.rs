@@ -0,0 +1,479 @@
+//! A compact binary encoding of [`SwapResult`] for passing quotes between
+//! processes, see [`SwapResult::to_bytes`].
+//!
+//! Unsigned integers are LEB128 varints and signed ones are zigzag encoded
+//! first, so small amounts take one or two bytes. The layout, version 4:
+//!
+//! 1. the version byte `4`
+//! 2. `amount_in`, `amount_out`, `fee`, `ref_fee`, `protocol_fee`,
+//!    `amount_remaining`, `fee_a`, `fee_b`, `protocol_fee_a`,
+//!    `protocol_fee_b`, `max_rounding_shortfall`
+//! 3. `is_exceed` as one byte, `0` or `1`
+//! 4. `end_volatility_accumulator`, `end_volatility_reference`,
+//!    `end_index_reference`, `end_active_id`, `end_last_update_timestamp`
+//! 5. the step count, then `(bin_id, amount_in, amount_out, fee,
+//!    var_fee_rate, protocol_fee, price, execution_price, fee_a, fee_b,
+//!    protocol_fee_a, protocol_fee_b)` for each step, where `bin_id` is the
+//!    difference from the previous step's id (from 0 for the first), since
+//!    a swap crosses neighboring bins
+//!
+//! Version 3 is the same without `max_rounding_shortfall`, version 2 also
+//! without the per-token fees, and version 1 also without the two prices.
+//! All still decode, with the missing fields zero. Decoding rejects unknown
+//! versions, truncated input, trailing bytes and varints longer than they
+//! need to be, so each result has exactly one encoding. A layout change
+//! means a new version byte.
+
+use alloc::{format, vec::Vec};
+
+use anyhow::Error;
+
+use crate::{
+    error::DlmmError,
+    pool::{BinSwap, SwapResult},
+};
+
+const VERSION: u8 = 4;
+
+/// The version before results carried their rounding bound.
+const VERSION_3: u8 = 3;
+
+/// The version before fees were split per token.
+const VERSION_2: u8 = 2;
+
+/// The version before steps carried their prices.
+const VERSION_1: u8 = 1;
+
+impl SwapResult {
+    /// Encodes the result in the layout of the [module docs](self).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(32 + self.steps.len() * 48);
+        out.push(VERSION);
+        for value in [
+            self.amount_in,
+            self.amount_out,
+            self.fee,
+            self.ref_fee,
+            self.protocol_fee,
+            self.amount_remaining,
+            self.fee_a,
+            self.fee_b,
+            self.protocol_fee_a,
+            self.protocol_fee_b,
+            self.max_rounding_shortfall,
+        ] {
+            put_u64(&mut out, value);
+        }
+        out.push(self.is_exceed as u8);
+        put_u64(&mut out, self.end_volatility_accumulator.into());
+        put_u64(&mut out, self.end_volatility_reference.into());
+        put_i64(&mut out, self.end_index_reference.into());
+        put_i64(&mut out, self.end_active_id.into());
+        put_u64(&mut out, self.end_last_update_timestamp);
+
+        put_u64(&mut out, self.steps.len() as u64);
+        let mut prev_id = 0i64;
+        for step in &self.steps {
+            put_i64(&mut out, step.bin_id as i64 - prev_id);
+            prev_id = step.bin_id.into();
+            for value in [
+                step.amount_in,
+                step.amount_out,
+                step.fee,
+                step.var_fee_rate,
+                step.protocol_fee,
+            ] {
+                put_u64(&mut out, value);
+            }
+            put_u128(&mut out, step.price);
+            put_u128(&mut out, step.execution_price);
+            for value in [
+                step.fee_a,
+                step.fee_b,
+                step.protocol_fee_a,
+                step.protocol_fee_b,
+            ] {
+                put_u64(&mut out, value);
+            }
+        }
+        out
+    }
+
+    /// Decodes bytes written by [`to_bytes`](Self::to_bytes). Malformed
+    /// input fails with [`DlmmError::InvalidLayout`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<SwapResult, Error> {
+        let mut reader = Reader(bytes);
+        let version = reader.u8()?;
+        if ![VERSION, VERSION_3, VERSION_2, VERSION_1].contains(&version) {
+            return Err(invalid(format!("unknown SwapResult version {version}")));
+        }
+        let has_prices = version != VERSION_1;
+        let has_token_fees = version >= VERSION_3;
+        let mut result = SwapResult {
+            amount_in: reader.u64()?,
+            amount_out: reader.u64()?,
+            fee: reader.u64()?,
+            ref_fee: reader.u64()?,
+            protocol_fee: reader.u64()?,
+            amount_remaining: reader.u64()?,
+            ..Default::default()
+        };
+        if has_token_fees {
+            result.fee_a = reader.u64()?;
+            result.fee_b = reader.u64()?;
+            result.protocol_fee_a = reader.u64()?;
+            result.protocol_fee_b = reader.u64()?;
+        }
+        if version == VERSION {
+            result.max_rounding_shortfall = reader.u64()?;
+        }
+        result.is_exceed = match reader.u8()? {
+            0 => false,
+            1 => true,
+            byte => return Err(invalid(format!("invalid is_exceed byte {byte}"))),
+        };
+        result.end_volatility_accumulator = reader.u32()?;
+        result.end_volatility_reference = reader.u32()?;
+        result.end_index_reference = reader.i32()?;
+        result.end_active_id = reader.i32()?;
+        result.end_last_update_timestamp = reader.u64()?;
+
+        let len = reader.u64()?;
+        // Checked against the bytes left before allocating, at one byte
+        // per field.
+        let min_step_len = match (has_prices, has_token_fees) {
+            (true, true) => 12,
+            (true, false) => 8,
+            _ => 6,
+        };
+        if len > (reader.0.len() / min_step_len) as u64 {
+            return Err(invalid(format!("{len} steps do not fit in the input")));
+        }
+        result.steps = Vec::with_capacity(len as usize);
+        let mut prev_id = 0i64;
+        for _ in 0..len {
+            let bin_id = prev_id
+                .checked_add(reader.i64()?)
+                .and_then(|id| i32::try_from(id).ok())
+                .ok_or_else(|| invalid("step bin_id out of range".into()))?;
+            prev_id = bin_id.into();
+            let mut step = BinSwap {
+                bin_id,
+                amount_in: reader.u64()?,
+                amount_out: reader.u64()?,
+                fee: reader.u64()?,
+                var_fee_rate: reader.u64()?,
+                protocol_fee: reader.u64()?,
+                ..Default::default()
+            };
+            if has_prices {
+                step.price = reader.u128()?;
+                step.execution_price = reader.u128()?;
+            }
+            if has_token_fees {
+                step.fee_a = reader.u64()?;
+                step.fee_b = reader.u64()?;
+                step.protocol_fee_a = reader.u64()?;
+                step.protocol_fee_b = reader.u64()?;
+            }
+            result.steps.push(step);
+        }
+        if !reader.0.is_empty() {
+            return Err(invalid(format!("{} trailing bytes", reader.0.len())));
+        }
+        Ok(result)
+    }
+}
+
+fn invalid(message: alloc::string::String) -> Error {
+    DlmmError::InvalidLayout(message).into()
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    put_u128(out, value.into());
+}
+
+fn put_u128(out: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_i64(out: &mut Vec<u8>, value: i64) {
+    put_u64(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Result<u8, Error> {
+        let (&byte, rest) = self
+            .0
+            .split_first()
+            .ok_or_else(|| invalid("unexpected end of input".into()))?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn u128(&mut self) -> Result<u128, Error> {
+        let mut value = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = self.u8()?;
+            let bits = (byte & 0x7f) as u128;
+            // The nineteenth byte only has room for the top two bits.
+            if shift == 126 && bits > 3 {
+                break;
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                // A zero last byte adds nothing: the encoder stops before it.
+                if byte == 0 && shift > 0 {
+                    return Err(invalid("over-long varint".into()));
+                }
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint overflows u128".into()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        u64::try_from(self.u128()?).map_err(|_| invalid("varint overflows u64".into()))
+    }
+
+    fn i64(&mut self) -> Result<i64, Error> {
+        let value = self.u64()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        u32::try_from(self.u64()?).map_err(|_| invalid("varint overflows u32".into()))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        i32::try_from(self.i64()?).map_err(|_| invalid("varint overflows i32".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(bin_id: i32, value: u64) -> BinSwap {
+        BinSwap {
+            bin_id,
+            amount_in: value,
+            amount_out: value,
+            fee: value,
+            var_fee_rate: value,
+            protocol_fee: value,
+            price: (value as u128) << 64,
+            execution_price: value.into(),
+            fee_a: value,
+            fee_b: 0,
+            protocol_fee_a: value,
+            protocol_fee_b: 0,
+        }
+    }
+
+    fn round_trip(result: &SwapResult) {
+        let bytes = result.to_bytes();
+        assert_eq!(&SwapResult::from_bytes(&bytes).unwrap(), result);
+    }
+
+    #[test]
+    fn results_round_trip() {
+        round_trip(&SwapResult::default());
+
+        let steps = (0..500)
+            .map(|i| step(-443_636 + i, (i as u64) << 40))
+            .collect();
+        let many = SwapResult {
+            amount_in: 123_456_789,
+            steps,
+            end_active_id: -443_137,
+            ..Default::default()
+        };
+        round_trip(&many);
+        // Each step is a one-byte id delta, eight amounts of at most seven
+        // bytes, two zeros and a price of at most seventeen.
+        assert!(many.to_bytes().len() < 500 * 77);
+
+        let max = SwapResult {
+            amount_in: u64::MAX,
+            amount_out: u64::MAX,
+            fee: u64::MAX,
+            ref_fee: u64::MAX,
+            protocol_fee: u64::MAX,
+            steps: vec![
+                BinSwap {
+                    price: u128::MAX,
+                    execution_price: u128::MAX,
+                    ..step(i32::MAX, u64::MAX)
+                },
+                step(i32::MIN, u64::MAX),
+            ],
+            is_exceed: true,
+            end_volatility_accumulator: u32::MAX,
+            end_volatility_reference: u32::MAX,
+            end_index_reference: i32::MIN,
+            end_active_id: i32::MAX,
+            end_last_update_timestamp: u64::MAX,
+            amount_remaining: u64::MAX,
+            fee_a: u64::MAX,
+            fee_b: u64::MAX,
+            protocol_fee_a: u64::MAX,
+            protocol_fee_b: u64::MAX,
+            max_rounding_shortfall: u64::MAX,
+        };
+        round_trip(&max);
+    }
+
+    #[test]
+    fn layout_is_pinned() {
+        let result = SwapResult {
+            amount_in: 1_000,
+            amount_out: 997,
+            fee: 3,
+            ref_fee: 0,
+            protocol_fee: 1,
+            steps: vec![step(-1, 1), step(-2, 300)],
+            is_exceed: false,
+            end_volatility_accumulator: 10_000,
+            end_volatility_reference: 0,
+            end_index_reference: 0,
+            end_active_id: -2,
+            end_last_update_timestamp: 1_700_000_000,
+            amount_remaining: 0,
+            fee_a: 3,
+            fee_b: 0,
+            protocol_fee_a: 1,
+            protocol_fee_b: 0,
+            max_rounding_shortfall: 4,
+        };
+        #[rustfmt::skip]
+        let golden = [
+            4,
+            0xe8, 0x07, 0xe5, 0x07, 3, 0, 1, 0, 3, 0, 1, 0, 4,
+            0,
+            0x90, 0x4e, 0, 0, 3, 0x80, 0xe2, 0xcf, 0xaa, 0x06,
+            2,
+            1, 1, 1, 1, 1, 1,
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x02,
+            1,
+            1, 0, 1, 0,
+            1, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02,
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xd8, 0x04,
+            0xac, 0x02,
+            0xac, 0x02, 0, 0xac, 0x02, 0,
+        ];
+        assert_eq!(result.to_bytes(), golden);
+        assert_eq!(SwapResult::from_bytes(&golden).unwrap(), result);
+
+        // Version 3 had no rounding bound.
+        let mut version_3 = golden.to_vec();
+        version_3[0] = 3;
+        version_3.remove(13);
+        let result = SwapResult {
+            max_rounding_shortfall: 0,
+            ..result
+        };
+        assert_eq!(SwapResult::from_bytes(&version_3).unwrap(), result);
+
+        // Version 2 had no per-token fees.
+        #[rustfmt::skip]
+        let version_2 = [
+            2,
+            0xe8, 0x07, 0xe5, 0x07, 3, 0, 1, 0,
+            0,
+            0x90, 0x4e, 0, 0, 3, 0x80, 0xe2, 0xcf, 0xaa, 0x06,
+            2,
+            1, 1, 1, 1, 1, 1,
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x02,
+            1,
+            1, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02,
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xd8, 0x04,
+            0xac, 0x02,
+        ];
+        let mut result = SwapResult {
+            fee_a: 0,
+            protocol_fee_a: 0,
+            ..result
+        };
+        for step in &mut result.steps {
+            step.fee_a = 0;
+            step.protocol_fee_a = 0;
+        }
+        assert_eq!(SwapResult::from_bytes(&version_2).unwrap(), result);
+
+        // Version 1 had no prices either.
+        #[rustfmt::skip]
+        let version_1 = [
+            1,
+            0xe8, 0x07, 0xe5, 0x07, 3, 0, 1, 0,
+            0,
+            0x90, 0x4e, 0, 0, 3, 0x80, 0xe2, 0xcf, 0xaa, 0x06,
+            2,
+            1, 1, 1, 1, 1, 1,
+            1, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02, 0xac, 0x02,
+        ];
+        let mut unpriced = result;
+        for step in &mut unpriced.steps {
+            step.price = 0;
+            step.execution_price = 0;
+        }
+        assert_eq!(SwapResult::from_bytes(&version_1).unwrap(), unpriced);
+    }
+
+    #[test]
+    fn malformed_bytes_are_rejected() {
+        let bytes = SwapResult {
+            steps: vec![step(5, 7)],
+            ..Default::default()
+        }
+        .to_bytes();
+        let rejects = |bytes: &[u8]| {
+            let err = SwapResult::from_bytes(bytes).unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(DlmmError::InvalidLayout(_))),
+                "{err}"
+            );
+        };
+
+        rejects(&[]);
+        rejects(&[VERSION]);
+        rejects(&[VERSION + 1]);
+        for len in 1..bytes.len() {
+            rejects(&bytes[..len]);
+        }
+        rejects(&[bytes.as_slice(), &[0]].concat());
+
+        let mut exceed = bytes.clone();
+        exceed[12] = 2;
+        rejects(&exceed);
+        let mut steps = bytes.clone();
+        // One step claimed as 127.
+        steps[18] = 127;
+        rejects(&steps);
+        // An eleven-byte varint.
+        let mut long = vec![VERSION];
+        long.extend([0xff; 10]);
+        long.push(1);
+        rejects(&long);
+        // Zero and 7 as `amount_in` padded to two and three bytes.
+        for padded in [&[0x80, 0][..], &[0x87, 0x80, 0]] {
+            let over_long = [&bytes[..1], padded, &bytes[2..]].concat();
+            rejects(&over_long);
+        }
+        // The shortest two-byte varint is canonical.
+        let two_bytes = [&bytes[..1], &[0x80, 1], &bytes[2..]].concat();
+        assert_eq!(SwapResult::from_bytes(&two_bytes).unwrap().amount_in, 128);
+        // A twenty-byte varint for a price, followed by the per-token fees.
+        let mut price = bytes.clone();
+        price.truncate(bytes.len() - 5);
+        price.extend([0xff; 19]);
+        price.extend([0, 7, 0, 7, 0]);
+        rejects(&price);
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "cli")]
+
+use cetus_swap_sdk::{
+    cli::{self, QuoteArgs},
+    error::DlmmError,
+    SwapResult,
+};
+
+const POOL: &str = include_str!("../examples/pool.json");
+
+fn args(amount: u64, a2b: bool, exact_out: bool, json: bool) -> QuoteArgs {
+    QuoteArgs {
+        pool_path: "examples/pool.json".into(),
+        amount,
+        a2b,
+        exact_out,
+        timestamp: None,
+        json,
+    }
+}
+
+#[test]
+fn quotes_the_example_pool() {
+    let pool = cli::load_pool(POOL).unwrap();
+    for (a2b, exact_out) in [(true, false), (false, false), (true, true), (false, true)] {
+        let args = args(1_500_000_000, a2b, exact_out, true);
+        let expected = if exact_out {
+            pool.quote_exact_amount_out(args.amount, a2b, 1_700_000_000)
+        } else {
+            pool.quote_exact_amount_in(args.amount, a2b, 1_700_000_000)
+        }
+        .unwrap();
+        assert!(expected.steps.len() > 1);
+
+        let json = cli::run(POOL, &args).unwrap();
+        let printed: SwapResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(printed, expected);
+
+        let summary = cli::run(
+            POOL,
+            &QuoteArgs {
+                json: false,
+                ..args
+            },
+        )
+        .unwrap();
+        let mut lines = summary.lines();
+        assert_eq!(lines.next().unwrap(), expected.to_string());
+        assert_eq!(lines.count(), expected.steps.len());
+    }
+}
+
+#[test]
+fn failures_carry_the_error_name() {
+    let err = cli::run("{}", &args(1, true, false, false)).unwrap_err();
+    assert_eq!(err.name(), "InvalidLayout");
+
+    let unsorted = POOL.replacen("\"id\": -5", "\"id\": 7", 1);
+    let err = cli::run(&unsorted, &args(1, true, false, false)).unwrap_err();
+    assert!(matches!(err, DlmmError::InvalidLayout(ref reason) if reason.contains("sorted")));
+
+    // Reserves that overflow mid-swap fail the quote itself.
+    let mut pool = cli::load_pool(POOL).unwrap();
+    pool.bins_mut_with(|bins| bins[6].amount_b = u64::MAX - 10)
+        .unwrap();
+    let overflowing = serde_json::to_string(&pool).unwrap();
+    let err = cli::run(&overflowing, &args(1_000, false, false, false)).unwrap_err();
+    assert_eq!(err.name(), "StepFailed");
+    assert!(
+        matches!(err, DlmmError::StepFailed { ref source, .. } if source.name() == "SwapFailed"),
+        "{err}"
+    );
+}